@@ -0,0 +1,151 @@
+//! Choose-your-own-adventure sessions for `eg!adventure`: a story told a paragraph at a time, with
+//! the channel voting on which way it goes next.
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::bot::types::{ActionRow, Button, ButtonStyle, Id};
+
+pub const BUTTON_PREFIX: &str = "adventure:";
+pub const LABELS: [&str; 3] = ["A", "B", "C"];
+/// Sessions end on their own once the story is this long
+pub const MAX_PARAGRAPHS: usize = 12;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Session {
+    pub guild: Option<Id>,
+    pub style: Option<String>,
+    pub started_by: Id,
+    pub paragraphs: Vec<String>,
+    pub options: Vec<String>,
+    /// The message with the current vote, once it's been posted
+    pub message: Option<Id>,
+    /// Which option everyone voted for, by user
+    votes: HashMap<Id, usize>,
+}
+
+impl Session {
+    pub fn new(guild: Option<Id>, style: Option<String>, started_by: Id, opening: String) -> Self {
+        Session {
+            guild,
+            style,
+            started_by,
+            paragraphs: vec![opening],
+            options: Vec::new(),
+            message: None,
+            votes: HashMap::new(),
+        }
+    }
+
+    /// Starts the next vote, on `options`. Call `posted` once `render` is sent.
+    pub fn next_round(&mut self, options: Vec<String>) {
+        self.options = options;
+        self.message = None;
+        self.votes.clear();
+    }
+
+    pub fn posted(&mut self, message: Id) {
+        self.message = Some(message);
+    }
+
+    /// The option with the most votes, ties (and no votes at all) broken at random.
+    pub fn winner(&self, rng: &mut impl Rng) -> usize {
+        let mut counts = vec![0; self.options.len()];
+        for &option in self.votes.values() {
+            counts[option] += 1;
+        }
+        let best = counts.iter().copied().max().unwrap_or(0);
+        let tied: Vec<_> = (0..counts.len()).filter(|&i| counts[i] == best).collect();
+        tied.choose(rng).copied().unwrap_or(0)
+    }
+
+    /// Continues the story with option `choice`, returning what the vote message should say now.
+    pub fn advance(&mut self, choice: usize) -> String {
+        let votes = self.votes.values().filter(|&&v| v == choice).count();
+        let result = format!(
+            "{}\n\n➡️ **{}** won with {} vote{}",
+            self.render(),
+            LABELS[choice],
+            votes,
+            if votes == 1 { "" } else { "s" }
+        );
+        self.paragraphs.push(self.options.swap_remove(choice));
+        self.options.clear();
+        result
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.paragraphs.len() >= MAX_PARAGRAPHS
+    }
+
+    /// The story so far, as its latest paragraph, and the options for what comes next.
+    pub fn render(&self) -> String {
+        let mut text = format!(
+            "📖 *Part {}*\n{}",
+            self.paragraphs.len(),
+            self.paragraphs.last().map_or("", String::as_str)
+        );
+        for (label, option) in LABELS.iter().zip(&self.options) {
+            text += &format!("\n**{}**: {}", label, option);
+        }
+        text
+    }
+
+    pub fn buttons(&self) -> Vec<ActionRow> {
+        let buttons = LABELS
+            .iter()
+            .take(self.options.len())
+            .enumerate()
+            .map(|(i, label)| {
+                Button::new(
+                    ButtonStyle::Primary,
+                    *label,
+                    format!("{}{}", BUTTON_PREFIX, i),
+                )
+            })
+            .collect();
+        vec![ActionRow::new(buttons)]
+    }
+
+    /// The whole story, for uploading once it's over.
+    pub fn export(&self) -> String {
+        self.paragraphs.join("\n\n") + "\n"
+    }
+}
+
+/// Running sessions, one per channel.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Adventures {
+    sessions: HashMap<Id, Session>,
+}
+
+impl Adventures {
+    pub fn get_mut(&mut self, channel: Id) -> Option<&mut Session> {
+        self.sessions.get_mut(&channel)
+    }
+
+    pub fn is_running(&self, channel: Id) -> bool {
+        self.sessions.contains_key(&channel)
+    }
+
+    pub fn start(&mut self, channel: Id, session: Session) {
+        self.sessions.insert(channel, session);
+    }
+
+    pub fn end(&mut self, channel: Id) -> Option<Session> {
+        self.sessions.remove(&channel)
+    }
+
+    /// Records `user`'s vote from a button on `message`. Returns the label they voted for, or
+    /// `None` if that vote is already over.
+    pub fn vote(&mut self, channel: Id, message: Id, user: Id, option: usize) -> Option<&str> {
+        let session = self
+            .sessions
+            .get_mut(&channel)
+            .filter(|s| s.message == Some(message) && option < s.options.len())?;
+        session.votes.insert(user, option);
+        Some(LABELS[option])
+    }
+}
@@ -0,0 +1,116 @@
+//! Servers' own names for commands, like `eg!vibe` for
+//! `eg!generate --temperature 1.5 --style memes`. An alias is expanded before its command runs,
+//! with whatever came after it on the end, and can stand for another alias, just not in a loop.
+//! The bot's config can give every server some, which a server's own take the place of.
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use crate::bot::types::Id;
+
+/// How many aliases deep one can go before it's taken to be a loop
+const MAX_DEPTH: usize = 8;
+const MAX_PER_GUILD: usize = 50;
+const MAX_LEN: usize = 300;
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Aliases {
+    guilds: HashMap<Id, BTreeMap<String, String>>,
+}
+
+impl Aliases {
+    /// Makes `name` stand for `expansion` in `guild`. Built-in `commands` can't be taken, and
+    /// neither can an alias that would end up standing for itself.
+    pub fn set(
+        &mut self,
+        guild: Id,
+        name: &str,
+        expansion: &str,
+        prefix: &str,
+        defaults: &BTreeMap<String, String>,
+        commands: &[&str],
+    ) -> Result<()> {
+        let name = name.strip_prefix(prefix).unwrap_or(name).to_lowercase();
+        let expansion = expansion.trim();
+        let expansion = expansion.strip_prefix(prefix).unwrap_or(expansion);
+        if commands.contains(&name.as_str()) {
+            bail!("`{}` is already a command", name);
+        }
+        if expansion.is_empty() {
+            bail!("expected a command for `{}` to stand for", name);
+        }
+        if expansion.len() > MAX_LEN {
+            bail!("aliases can be up to {} characters long", MAX_LEN);
+        }
+        let aliases = self.guilds.entry(guild).or_default();
+        if aliases.len() >= MAX_PER_GUILD && !aliases.contains_key(&name) {
+            bail!("a server can have up to {} aliases", MAX_PER_GUILD);
+        }
+        let old = aliases.insert(name.clone(), String::from(expansion));
+        if let Err(e) = self.expand(Some(guild), defaults, vec![name.clone()], commands) {
+            let aliases = self.guilds.entry(guild).or_default();
+            match old {
+                Some(old) => aliases.insert(name, old),
+                None => aliases.remove(&name),
+            };
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Returns whether `guild` had `name`.
+    pub fn remove(&mut self, guild: Id, name: &str) -> bool {
+        self.guilds
+            .get_mut(&guild)
+            .is_some_and(|a| a.remove(&name.to_lowercase()).is_some())
+    }
+
+    /// `guild`'s aliases and the config's it doesn't take the place of, for `eg!alias list`.
+    pub fn list(&self, guild: Id, defaults: &BTreeMap<String, String>, prefix: &str) -> String {
+        let own = self.guilds.get(&guild);
+        let mut all: BTreeMap<_, _> = defaults.iter().collect();
+        all.extend(own.into_iter().flatten());
+        if all.is_empty() {
+            return String::from("There aren't any aliases here");
+        }
+        let mut text = String::new();
+        for (name, expansion) in all {
+            let _ = writeln!(text, "`{}{}` → `{}{}`", prefix, name, prefix, expansion);
+        }
+        text
+    }
+
+    /// `words`, a command and its arguments, with the alias they start with expanded, and any
+    /// that one starts with, until they start with one of `commands` or something that isn't an
+    /// alias. `None` if they don't start with an alias. Errors if aliases stand for each other in
+    /// a loop.
+    pub fn expand(
+        &self,
+        guild: Option<Id>,
+        defaults: &BTreeMap<String, String>,
+        mut words: Vec<String>,
+        commands: &[&str],
+    ) -> Result<Option<Vec<String>>> {
+        let mut seen: Vec<String> = Vec::new();
+        loop {
+            let name = match words.first() {
+                Some(name) if !commands.contains(&name.as_str()) => name.to_lowercase(),
+                _ => break,
+            };
+            let own = guild.and_then(|g| self.guilds.get(&g)?.get(&name));
+            let expansion = match own.or_else(|| defaults.get(&name)) {
+                Some(e) => e,
+                None => break,
+            };
+            if seen.contains(&name) || seen.len() >= MAX_DEPTH {
+                seen.push(name);
+                bail!("these aliases go in a loop: {}", seen.join(" → "));
+            }
+            seen.push(name);
+            words.splice(..1, expansion.split_whitespace().map(String::from));
+        }
+        Ok(Some(words).filter(|_| !seen.is_empty()))
+    }
+}
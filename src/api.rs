@@ -0,0 +1,293 @@
+//! The HTTP generation API: `GET /generate` with a key a server's owner issued with `eg!apikey`
+//! gets a sentence from that server's model, and only that one. Requests are handed to the Discord
+//! handler like bridged messages, so generation goes through moderation as usual.
+
+use anyhow::{anyhow, bail, Result};
+use async_io::Timer;
+use chrono::{TimeZone, Utc};
+use futures::channel::mpsc::{unbounded, UnboundedSender};
+use futures::future::{self, Either};
+use futures::StreamExt;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::bot::types::Id;
+use crate::bridge::BridgeMessage;
+use crate::schedule::now_millis;
+use crate::store::Scope;
+
+/// Keys a guild can have at once
+const MAX_KEYS: usize = 10;
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a request waits for the handler before giving up
+const REPLY_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct ApiConfig {
+    /// The `host:port` to serve the API on
+    pub listen: String,
+    /// For each key
+    #[serde(default = "default_requests_per_minute")]
+    pub requests_per_minute: u32,
+}
+
+fn default_requests_per_minute() -> u32 {
+    30
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ApiKey {
+    pub guild: Id,
+    pub name: String,
+    pub created_by: Id,
+    /// In milliseconds since the epoch, like `last_used`
+    pub created: i64,
+    pub uses: u64,
+    pub last_used: Option<i64>,
+}
+
+impl Display for ApiKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let date = |at: i64| Utc.timestamp_millis(at).format("%Y-%m-%d");
+        write!(
+            f,
+            "`{}`, issued by <@{}> on {}, used {} times",
+            self.name,
+            self.created_by,
+            date(self.created),
+            self.uses
+        )?;
+        if let Some(at) = self.last_used {
+            write!(f, ", last on {}", date(at))?;
+        }
+        Ok(())
+    }
+}
+
+/// Every guild's keys, shared between the handler, which issues and revokes them, and the API.
+pub type SharedKeys = Arc<Mutex<ApiKeys>>;
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct ApiKeys {
+    /// By the key's hash, since the key itself is never kept
+    keys: HashMap<String, ApiKey>,
+    /// When each key's current minute started and how many requests it's made in it. Starts over
+    /// on a restart
+    #[serde(skip)]
+    windows: HashMap<String, (i64, u32)>,
+}
+
+/// What a request's key gets it.
+pub enum Access {
+    Granted(ApiKey),
+    Unknown,
+    /// Out of requests for this minute, for this many more seconds
+    Limited(u64),
+}
+
+impl ApiKeys {
+    /// A new key for `guild`, only ever shown this once.
+    pub fn issue(
+        &mut self,
+        rng: &mut impl Rng,
+        guild: Id,
+        name: &str,
+        created_by: Id,
+    ) -> Result<String> {
+        let guild_keys = self.of_guild(guild);
+        if guild_keys.iter().any(|k| k.name == name) {
+            bail!("this server already has a key called `{}`", name);
+        }
+        if guild_keys.len() >= MAX_KEYS {
+            bail!("a server can have at most {} keys", MAX_KEYS);
+        }
+        let key: String = (0..24)
+            .map(|_| format!("{:02x}", rng.gen::<u8>()))
+            .collect();
+        let key = format!("taco_{}", key);
+        self.keys.insert(
+            hash(&key),
+            ApiKey {
+                guild,
+                name: String::from(name),
+                created_by,
+                created: now_millis(),
+                uses: 0,
+                last_used: None,
+            },
+        );
+        Ok(key)
+    }
+
+    pub fn revoke(&mut self, guild: Id, name: &str) -> Result<()> {
+        let before = self.keys.len();
+        self.keys.retain(|_, k| k.guild != guild || k.name != name);
+        if self.keys.len() == before {
+            bail!("this server has no key called `{}`", name);
+        }
+        Ok(())
+    }
+
+    /// `guild`'s keys, by name.
+    pub fn of_guild(&self, guild: Id) -> Vec<&ApiKey> {
+        let mut keys: Vec<_> = self.keys.values().filter(|k| k.guild == guild).collect();
+        keys.sort_by(|a, b| a.name.cmp(&b.name));
+        keys
+    }
+
+    /// Whether `key` can make a request now, counting it if so.
+    pub fn check(&mut self, key: &str, requests_per_minute: u32) -> Access {
+        let hash = hash(key);
+        let entry = match self.keys.get_mut(&hash) {
+            Some(entry) => entry,
+            None => return Access::Unknown,
+        };
+        let now = now_millis();
+        let (start, count) = self.windows.entry(hash).or_insert((now, 0));
+        if now - *start >= 60_000 {
+            *start = now;
+            *count = 0;
+        }
+        if *count >= requests_per_minute {
+            return Access::Limited(((*start + 60_000 - now) as u64).div_ceil(1000));
+        }
+        *count += 1;
+        entry.uses += 1;
+        entry.last_used = Some(now);
+        Access::Granted(entry.clone())
+    }
+}
+
+fn hash(key: &str) -> String {
+    blake3::hash(key.as_bytes()).to_hex().to_string()
+}
+
+/// Serves the API on `cfg.listen`, each connection on its own thread. `prefix` is the command
+/// prefix, for asking the handler to generate.
+pub fn spawn(
+    cfg: ApiConfig,
+    keys: SharedKeys,
+    per_guild: bool,
+    prefix: String,
+    events: UnboundedSender<BridgeMessage>,
+) -> Result<()> {
+    let listener = TcpListener::bind(&cfg.listen)?;
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(s) => s,
+                Err(e) => {
+                    eprintln!("api: {}", e);
+                    continue;
+                }
+            };
+            let (cfg, keys, prefix, events) =
+                (cfg.clone(), keys.clone(), prefix.clone(), events.clone());
+            thread::spawn(move || {
+                if let Err(e) = handle(stream, &cfg, &keys, per_guild, &prefix, &events) {
+                    eprintln!("api: {}", e);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+fn handle(
+    mut stream: TcpStream,
+    cfg: &ApiConfig,
+    keys: &SharedKeys,
+    per_guild: bool,
+    prefix: &str,
+    events: &UnboundedSender<BridgeMessage>,
+) -> Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new((&stream).take(8192));
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    let mut key = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("authorization") {
+                key = value.trim().strip_prefix("Bearer ").map(String::from);
+            }
+        }
+    }
+
+    let path = match request.split_whitespace().collect::<Vec<_>>()[..] {
+        ["GET", path, _] => path.split('?').next().unwrap_or(path),
+        _ => return respond(&mut stream, "405 Method Not Allowed", error("only GET")),
+    };
+    if path != "/generate" {
+        return respond(&mut stream, "404 Not Found", error("try /generate"));
+    }
+    // without a model per guild, a key would generate from every guild's messages
+    if !per_guild {
+        let body = error("the bot has no model per server for a key to generate from");
+        return respond(&mut stream, "403 Forbidden", body);
+    }
+    let key = match key {
+        Some(key) => key,
+        None => return respond(&mut stream, "401 Unauthorized", error("needs a key")),
+    };
+    let access = keys
+        .lock()
+        .map_err(|_| anyhow!("the keys are poisoned"))?
+        .check(&key, cfg.requests_per_minute);
+    let key = match access {
+        Access::Granted(key) => key,
+        Access::Unknown => return respond(&mut stream, "401 Unauthorized", error("unknown key")),
+        Access::Limited(seconds) => {
+            let body = error(&format!("too many requests, try again in {}s", seconds));
+            return respond(&mut stream, "429 Too Many Requests", body);
+        }
+    };
+    eprintln!("api: key `{}` of guild {} generating", key.name, key.guild);
+
+    let (reply, mut replies) = unbounded();
+    events.unbounded_send(BridgeMessage {
+        scope: Scope::for_guild(Some(key.guild), per_guild),
+        author: format!("api key {}", key.name),
+        text: format!("{}mimic", prefix),
+        learn: false,
+        reply,
+    })?;
+    let text = async_io::block_on(future::select(replies.next(), Timer::after(REPLY_TIMEOUT)));
+    match text {
+        Either::Left((Some(text), _)) => {
+            respond(&mut stream, "200 OK", serde_json::json!({ "text": text }))
+        }
+        _ => respond(
+            &mut stream,
+            "503 Service Unavailable",
+            error("couldn't generate anything"),
+        ),
+    }
+}
+
+fn error(text: &str) -> serde_json::Value {
+    serde_json::json!({ "error": text })
+}
+
+fn respond(stream: &mut TcpStream, status: &str, body: serde_json::Value) -> Result<()> {
+    let body = body.to_string();
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
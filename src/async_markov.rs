@@ -0,0 +1,129 @@
+//! `AsyncMarkov`, a model any number of tasks and threads can learn into and generate from at
+//! once, without a lock around the whole thing. The model lives on its own thread and everything
+//! else sends it commands: learning never waits, and generating only waits for its own sentence.
+//! Messages that come in while it's busy are learned together, in parallel, before whatever's
+//! asked next, so generated text always knows everything learned before it was asked for.
+//!
+//! The bot doesn't use it. Its handler takes one event at a time, so there's never a second
+//! learner or generator waiting on a model, and it keeps using its models directly. This is for
+//! code that learns and generates from threads of its own, like `taco_bot parbench`.
+
+use anyhow::{anyhow, Result};
+use futures::channel::oneshot;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use crate::markov::Markov;
+
+/// The most messages learned at once, so a steady stream of them can't hold generation up
+const MAX_BATCH: usize = 1000;
+
+enum Command {
+    Learn(Vec<String>),
+    Generate {
+        seed: u64,
+        max_words: usize,
+        reply: oneshot::Sender<Vec<String>>,
+    },
+    Snapshot(oneshot::Sender<Markov>),
+    Stop(oneshot::Sender<Markov>),
+}
+
+/// A handle to a model on its own thread. Handles can be cloned and sent anywhere; the model is
+/// dropped along with the last one, unless it's `stop`ped first.
+#[derive(Clone)]
+pub struct AsyncMarkov {
+    commands: Sender<Command>,
+}
+
+impl AsyncMarkov {
+    pub fn spawn(model: Markov) -> Self {
+        let (commands, received) = mpsc::channel();
+        thread::spawn(move || serve(model, received));
+        AsyncMarkov { commands }
+    }
+
+    /// Learns `words` as one message, after everything asked before. Never waits.
+    pub fn learn(&self, words: Vec<String>) {
+        let _ = self.commands.send(Command::Learn(words));
+    }
+
+    /// Up to `max_words` generated from `seed`.
+    pub async fn generate(&self, seed: u64, max_words: usize) -> Result<Vec<String>> {
+        self.ask(|reply| Command::Generate {
+            seed,
+            max_words,
+            reply,
+        })
+        .await
+    }
+
+    /// A copy of the model as it is now, for saving.
+    pub async fn snapshot(&self) -> Result<Markov> {
+        self.ask(Command::Snapshot).await
+    }
+
+    /// Stops the model's thread once everything asked before is done, and gives the model back.
+    /// Any other handles fail from then on.
+    pub async fn stop(self) -> Result<Markov> {
+        self.ask(Command::Stop).await
+    }
+
+    async fn ask<T>(&self, command: impl FnOnce(oneshot::Sender<T>) -> Command) -> Result<T> {
+        let (reply, answer) = oneshot::channel();
+        let stopped = || anyhow!("the model's thread has stopped");
+        self.commands.send(command(reply)).map_err(|_| stopped())?;
+        answer.await.map_err(|_| stopped())
+    }
+}
+
+/// Runs `model`'s commands until it's stopped or every handle is gone.
+fn serve(mut model: Markov, commands: Receiver<Command>) {
+    let mut pending = Vec::new();
+    loop {
+        // only waits once there's nothing left to learn
+        let command = match pending.is_empty() {
+            true => commands.recv().ok(),
+            false => commands.try_recv().ok(),
+        };
+        let command = match command {
+            Some(Command::Learn(words)) if pending.len() < MAX_BATCH => {
+                pending.push(words);
+                continue;
+            }
+            Some(Command::Learn(words)) => {
+                model.insert_sequences(&pending);
+                pending.clear();
+                pending.push(words);
+                continue;
+            }
+            Some(command) => command,
+            None if pending.is_empty() => return,
+            None => {
+                model.insert_sequences(&pending);
+                pending.clear();
+                continue;
+            }
+        };
+        model.insert_sequences(&pending);
+        pending.clear();
+        match command {
+            Command::Generate {
+                seed,
+                max_words,
+                reply,
+            } => {
+                let words = model.generate_with_seed(seed);
+                let _ = reply.send(words.take(max_words).collect());
+            }
+            Command::Snapshot(reply) => {
+                let _ = reply.send(model.clone());
+            }
+            Command::Stop(reply) => {
+                let _ = reply.send(model);
+                return;
+            }
+            Command::Learn(_) => unreachable!("learning is handled above"),
+        }
+    }
+}
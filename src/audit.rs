@@ -0,0 +1,45 @@
+use anyhow::Result;
+use regex::Regex;
+use std::collections::BTreeMap;
+
+use crate::markov::Markov;
+
+/// Words in a model that match the blocklist or look like personal information, with why.
+pub fn audit(model: &Markov, blocklist: &[String]) -> Result<BTreeMap<String, &'static str>> {
+    let blocklist = blocklist
+        .iter()
+        .map(|p| Regex::new(&format!("(?i){}", p)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let pii = [
+        ("email", Regex::new(r"^[^@\s`]+@[^@\s`]+\.[A-Za-z]{2,}$")?),
+        ("phone number", Regex::new(r"^\+?\d[\d\-.()]{7,}\d$")?),
+        ("ip address", Regex::new(r"^\d{1,3}(\.\d{1,3}){3}$")?),
+        ("user tag", Regex::new(r"^`?[^`#\s]+#\d{4}`?$")?),
+        ("mention", Regex::new(r"^`?<@!?\d+>`?$")?),
+    ];
+
+    let mut findings = BTreeMap::new();
+    for word in model.words() {
+        let trimmed = word.trim_matches(|c: char| c.is_ascii_punctuation() && c != '`');
+        let kind = if blocklist.iter().any(|r| r.is_match(word)) {
+            Some("blocklist")
+        } else {
+            pii.iter()
+                .find(|(_, r)| r.is_match(trimmed))
+                .map(|(kind, _)| *kind)
+        };
+        if let Some(kind) = kind {
+            findings.insert(String::from(word), kind);
+        }
+    }
+    Ok(findings)
+}
+
+/// Enough of a word to recognize it without repeating it.
+pub fn redact(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => format!("{}{}", first, "•".repeat(chars.count().min(12))),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,91 @@
+//! Keeping learning in line with Discord's AutoMod: messages it flags aren't learned, and the
+//! keywords it matched can be blocked from learning too.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+use crate::bot::types::Id;
+
+/// How many flagged messages to remember in case they haven't arrived yet
+const FLAGGED_KEPT: usize = 100;
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Automod {
+    /// Keywords each guild won't have learned, in AutoMod's syntax
+    blocklists: HashMap<Id, BTreeSet<String>>,
+    /// Messages AutoMod flagged that haven't been learned
+    #[serde(skip)]
+    flagged: VecDeque<Id>,
+}
+
+impl Automod {
+    /// Keeps `message` from being learned if it shows up later.
+    pub fn flag(&mut self, message: Id) {
+        if self.flagged.len() >= FLAGGED_KEPT {
+            self.flagged.pop_front();
+        }
+        self.flagged.push_back(message);
+    }
+
+    pub fn is_flagged(&self, message: Id) -> bool {
+        self.flagged.contains(&message)
+    }
+
+    /// Whether `text` has any of `guild`'s blocked keywords.
+    pub fn blocks(&self, guild: Id, text: &str) -> bool {
+        let keywords = match self.blocklists.get(&guild) {
+            Some(k) => k,
+            None => return false,
+        };
+        let text = text.to_lowercase();
+        keywords.iter().any(|k| matches(k, &text))
+    }
+
+    pub fn keywords(&self, guild: Id) -> impl Iterator<Item = &str> {
+        self.blocklists
+            .get(&guild)
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+    }
+
+    /// Returns whether `keyword` wasn't already blocked.
+    pub fn add(&mut self, guild: Id, keyword: &str) -> bool {
+        self.blocklists
+            .entry(guild)
+            .or_default()
+            .insert(keyword.to_lowercase())
+    }
+
+    /// Returns whether `keyword` was blocked.
+    pub fn remove(&mut self, guild: Id, keyword: &str) -> bool {
+        let removed = match self.blocklists.get_mut(&guild) {
+            Some(keywords) => keywords.remove(&keyword.to_lowercase()),
+            None => false,
+        };
+        if self.blocklists.get(&guild).is_some_and(BTreeSet::is_empty) {
+            self.blocklists.remove(&guild);
+        }
+        removed
+    }
+}
+
+/// Matches the way AutoMod keywords do: whole words, unless `*` at either end lets the word go on.
+/// Keywords with spaces match anywhere.
+fn matches(keyword: &str, text: &str) -> bool {
+    if keyword.contains(char::is_whitespace) {
+        return text.contains(keyword.trim_matches('*'));
+    }
+    let core = keyword.trim_matches('*');
+    if core.is_empty() {
+        return false;
+    }
+    let (prefix, suffix) = (keyword.starts_with('*'), keyword.ends_with('*'));
+    text.split(|c: char| !c.is_alphanumeric())
+        .any(|word| match (prefix, suffix) {
+            (true, true) => word.contains(core),
+            (true, false) => word.ends_with(core),
+            (false, true) => word.starts_with(core),
+            (false, false) => word == core,
+        })
+}
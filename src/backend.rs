@@ -0,0 +1,325 @@
+//! Where models are kept between runs. `Files` writes each model out whole, `Sled` (with the `sled`
+//! feature) keeps every entry on its own so saving only writes what changed.
+
+use anyhow::Result;
+use serde::Deserialize;
+use std::fs;
+use std::io::ErrorKind;
+
+use crate::markov::Markov;
+use crate::persist::{load_sealed, save_sealed_to, Key};
+use crate::store::Scope;
+
+/// Which backend to use, from the config.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Storage {
+    #[default]
+    Files,
+    Sled,
+}
+
+pub trait MarkovBackend: Send + Sync {
+    /// `scope`'s model, or `None` if it hasn't saved one.
+    fn load(&self, scope: &Scope, key: Option<&Key>) -> Result<Option<Markov>>;
+    /// Just the part of `scope`'s model that's used the most, to generate from while the rest is
+    /// loaded with `load`. `None` if it can't load part of a model, or has nothing worth loading
+    /// first.
+    fn load_hot(&self, _scope: &Scope, _key: Option<&Key>) -> Result<Option<Markov>> {
+        Ok(None)
+    }
+    /// Saves what changed in `markov` since it was last saved, returning how many bytes that took.
+    fn save(&self, scope: &Scope, markov: &Markov, key: Option<&Key>) -> Result<u64>;
+    fn exists(&self, scope: &Scope) -> bool;
+    fn remove(&self, scope: &Scope) -> Result<()>;
+    /// The names of the models it has that aren't in files, see `Scope::name`.
+    fn names(&self) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Every model in its own file, under `models/`.
+pub struct Files;
+
+impl MarkovBackend for Files {
+    fn load(&self, scope: &Scope, key: Option<&Key>) -> Result<Option<Markov>> {
+        if !scope.path().exists() {
+            return Ok(None);
+        }
+        load_sealed(scope.path(), key).map(Some)
+    }
+
+    fn save(&self, scope: &Scope, markov: &Markov, key: Option<&Key>) -> Result<u64> {
+        if let Some(dir) = scope.path().parent() {
+            fs::create_dir_all(dir)?;
+        }
+        save_sealed_to(scope.path(), markov, key)
+    }
+
+    fn exists(&self, scope: &Scope) -> bool {
+        scope.path().exists()
+    }
+
+    fn remove(&self, scope: &Scope) -> Result<()> {
+        match fs::remove_file(scope.path()) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Every model in its own tree of one sled database, one entry per key. With a key, entries are
+/// encrypted and what they're keyed by is hashed, so the words don't show.
+///
+/// Beside each model's tree is one of when its entries were last learned or unlearned. The ones
+/// from the last `HOT_DAYS` days, and the sentence starts, are its hot entries, which `load_hot`
+/// loads on their own.
+#[cfg(feature = "sled")]
+pub struct Sled {
+    db: sled::Db,
+}
+
+#[cfg(feature = "sled")]
+impl Sled {
+    const PATH: &'static str = "models.sled";
+    const HOT_DAYS: i64 = 3;
+
+    pub fn open() -> Result<Self> {
+        Ok(Sled {
+            db: sled::open(Self::PATH)?,
+        })
+    }
+
+    fn hot_tree(&self, scope: &Scope) -> Result<sled::Tree> {
+        Ok(self.db.open_tree(format!("{}#hot", scope.name()))?)
+    }
+
+    /// The model's order, kept by its name, or `None` if it was never saved here.
+    fn order(&self, scope: &Scope) -> Result<Option<usize>> {
+        use anyhow::anyhow;
+        use std::convert::TryInto;
+
+        self.db
+            .get(scope.name())?
+            .map(|order| {
+                let order: [u8; 8] = order
+                    .as_ref()
+                    .try_into()
+                    .map_err(|_| anyhow!("bad order for {}", scope.name()))?;
+                Ok(u64::from_be_bytes(order) as usize)
+            })
+            .transpose()
+    }
+
+    fn decode(
+        value: &[u8],
+        key: Option<&Key>,
+    ) -> Result<(Vec<crate::markov::Word>, crate::markov::Entry)> {
+        Ok(match key {
+            Some(key) => crate::persist::unseal(value, key)?,
+            None => bincode::deserialize(value)?,
+        })
+    }
+
+    fn entry_key(index: &[crate::markov::Word], key: Option<&Key>) -> Result<Vec<u8>> {
+        let index = bincode::serialize(index)?;
+        Ok(match key {
+            Some(key) => blake3::keyed_hash(key, &index).as_bytes().to_vec(),
+            None => index,
+        })
+    }
+}
+
+#[cfg(feature = "sled")]
+impl MarkovBackend for Sled {
+    /// Models that were never saved here are taken from their file, the first time.
+    fn load(&self, scope: &Scope, key: Option<&Key>) -> Result<Option<Markov>> {
+        let order = match self.order(scope)? {
+            Some(order) => order,
+            None => {
+                let mut markov = Files.load(scope, key)?;
+                if let Some(markov) = &mut markov {
+                    markov.mark_all_changed();
+                }
+                return Ok(markov);
+            }
+        };
+        let mut entries = Vec::new();
+        for item in self.db.open_tree(scope.name())?.iter() {
+            let (_, value) = item?;
+            entries.push(Self::decode(&value, key)?);
+        }
+        Markov::from_entries(order, entries).map(Some)
+    }
+
+    /// The sentence starts and whatever changed in the last `HOT_DAYS` days. What changed before
+    /// that is forgotten from the hot tree as it goes.
+    fn load_hot(&self, scope: &Scope, key: Option<&Key>) -> Result<Option<Markov>> {
+        use crate::markov::Word;
+        use crate::schedule::now_millis;
+        use std::convert::TryInto;
+
+        let order = match self.order(scope)? {
+            Some(order) => order,
+            None => return Ok(None),
+        };
+        let since = now_millis() - Self::HOT_DAYS * 24 * 60 * 60_000;
+        let hot = self.hot_tree(scope)?;
+        let mut keys = Vec::new();
+        let mut cooled = sled::Batch::default();
+        for item in hot.iter() {
+            let (entry_key, at) = item?;
+            match at.as_ref().try_into().map(i64::from_be_bytes) {
+                Ok(at) if at >= since => keys.push(entry_key),
+                _ => cooled.remove(entry_key),
+            }
+        }
+        hot.apply_batch(cooled)?;
+        if keys.is_empty() {
+            return Ok(None);
+        }
+        let tree = self.db.open_tree(scope.name())?;
+        let start = Self::entry_key(&vec![Word::Start; order], key)?;
+        let mut entries = Vec::new();
+        for entry_key in keys.iter().map(|k| k.as_ref()).chain([&start[..]]) {
+            if let Some(value) = tree.get(entry_key)? {
+                entries.push(Self::decode(&value, key)?);
+            }
+        }
+        Markov::from_entries(order, entries).map(Some)
+    }
+
+    fn save(&self, scope: &Scope, markov: &Markov, key: Option<&Key>) -> Result<u64> {
+        use crate::markov::{Changes, Entry, Word};
+        use crate::schedule::now_millis;
+        use std::collections::HashSet;
+
+        let encode = |index: &Vec<Word>, entry: &Entry| match key {
+            Some(key) => crate::persist::seal(&(index, entry), key),
+            None => Ok(bincode::serialize(&(index, entry))?),
+        };
+        let tree = self.db.open_tree(scope.name())?;
+        let hot = self.hot_tree(scope)?;
+        let mut batch = sled::Batch::default();
+        let mut hot_batch = sled::Batch::default();
+        let now = now_millis().to_be_bytes();
+        let mut size = 0;
+        let (changed, all): (Vec<_>, _) = match markov.changes() {
+            Changes::All => {
+                // what's gone is removed in the same batch the rest is written in, so a crash
+                // leaves the old model or the new one rather than an empty one
+                let mut kept = HashSet::new();
+                for (index, _) in markov.entries() {
+                    kept.insert(Self::entry_key(index, key)?);
+                }
+                for entry_key in tree.iter().keys() {
+                    let entry_key = entry_key?;
+                    if !kept.contains(entry_key.as_ref()) {
+                        batch.remove(entry_key.clone());
+                        hot_batch.remove(entry_key);
+                    }
+                }
+                (markov.entries().map(|(index, _)| index).collect(), true)
+            }
+            Changes::Entries(changed) => (changed.iter().collect(), false),
+        };
+        for index in changed {
+            let entry_key = Self::entry_key(index, key)?;
+            match markov.entry(index) {
+                Some(entry) => {
+                    let value = encode(index, entry)?;
+                    size += value.len() as u64;
+                    // a whole model saved at once isn't a sign any of it's used more
+                    if !all {
+                        hot_batch.insert(entry_key.clone(), &now[..]);
+                    }
+                    batch.insert(entry_key, value);
+                }
+                None => {
+                    hot_batch.remove(entry_key.clone());
+                    batch.remove(entry_key);
+                }
+            }
+        }
+        tree.apply_batch(batch)?;
+        hot.apply_batch(hot_batch)?;
+        self.db
+            .insert(scope.name(), &(markov.order() as u64).to_be_bytes())?;
+        self.db.flush()?;
+        Ok(size)
+    }
+
+    fn exists(&self, scope: &Scope) -> bool {
+        self.db.contains_key(scope.name()).unwrap_or(false) || Files.exists(scope)
+    }
+
+    fn remove(&self, scope: &Scope) -> Result<()> {
+        self.db.drop_tree(scope.name())?;
+        self.db.drop_tree(format!("{}#hot", scope.name()))?;
+        self.db.remove(scope.name())?;
+        Files.remove(scope)
+    }
+
+    /// Each model's order is kept by its name.
+    fn names(&self) -> Result<Vec<String>> {
+        self.db
+            .iter()
+            .keys()
+            .map(|name| Ok(String::from_utf8_lossy(&name?).into_owned()))
+            .collect()
+    }
+}
+
+#[cfg(all(test, feature = "sled"))]
+mod tests {
+    use super::*;
+
+    fn sled() -> Sled {
+        Sled {
+            db: sled::Config::new().temporary(true).open().unwrap(),
+        }
+    }
+
+    fn learn(markov: &mut Markov, text: &str) {
+        markov.insert_sequence(text.split(' ').map(String::from));
+    }
+
+    #[test]
+    fn saving_a_whole_model_drops_what_it_forgot() {
+        let (sled, scope) = (sled(), Scope::Corpus(String::from("cats")));
+        let mut markov = Markov::new();
+        learn(&mut markov, "the cat sat on the mat");
+        learn(&mut markov, "a dog ran off");
+        sled.save(&scope, &markov, None).unwrap();
+        markov.mark_saved();
+
+        markov.remove_sequence("a dog ran off".split(' ').map(String::from));
+        markov.mark_all_changed();
+        sled.save(&scope, &markov, None).unwrap();
+        let loaded = sled.load(&scope, None).unwrap().unwrap();
+        assert_eq!(loaded.entry_count(), markov.entry_count());
+        assert_eq!(loaded.fingerprint(), markov.fingerprint());
+    }
+
+    #[test]
+    fn hot_entries_are_what_was_learned_lately() {
+        let (sled, scope) = (sled(), Scope::Corpus(String::from("cats")));
+        assert!(sled.load_hot(&scope, None).unwrap().is_none());
+        let mut markov = Markov::new();
+        learn(&mut markov, "the cat sat on the mat");
+        markov.mark_all_changed();
+        sled.save(&scope, &markov, None).unwrap();
+        markov.mark_saved();
+        // saved whole, nothing stands out
+        assert!(sled.load_hot(&scope, None).unwrap().is_none());
+
+        learn(&mut markov, "a dog ran off");
+        sled.save(&scope, &markov, None).unwrap();
+        let hot = sled.load_hot(&scope, None).unwrap().unwrap();
+        assert!(hot.entry_count() < markov.entry_count());
+        assert_eq!(
+            sled.load(&scope, None).unwrap().unwrap().entry_count(),
+            markov.entry_count()
+        );
+    }
+}
@@ -0,0 +1,55 @@
+//! The biases generation can be steered with. See `markov::Bias`.
+
+use std::collections::{BTreeSet, HashSet};
+
+use crate::markov::{Bias, Word};
+
+/// Makes some words (lowercase) `factor` times as likely, for the `suppress` setting.
+pub struct Suppress {
+    pub words: BTreeSet<String>,
+    pub factor: f64,
+}
+
+impl Bias for Suppress {
+    fn weight(&self, _: &[String], candidate: &Word) -> f64 {
+        match candidate {
+            Word::Word(w) if self.words.contains(&w.to_lowercase()) => self.factor,
+            _ => 1.0,
+        }
+    }
+}
+
+/// Divides a word's weight by `penalty` for every time it's already been generated.
+pub struct RepetitionPenalty {
+    pub penalty: f64,
+}
+
+impl Bias for RepetitionPenalty {
+    fn weight(&self, so_far: &[String], candidate: &Word) -> f64 {
+        match candidate {
+            Word::Word(w) => {
+                let seen = so_far.iter().filter(|s| s.as_str() == w.as_str()).count();
+                self.penalty.powi(-(seen as i32))
+            }
+            _ => 1.0,
+        }
+    }
+}
+
+/// Makes some words (lowercase) `factor` times as likely to start with, so sentences don't keep
+/// opening with "the" or "and".
+pub struct AvoidStarts {
+    pub words: HashSet<String>,
+    pub factor: f64,
+}
+
+impl Bias for AvoidStarts {
+    fn weight(&self, so_far: &[String], candidate: &Word) -> f64 {
+        match candidate {
+            Word::Word(w) if so_far.is_empty() && self.words.contains(&w.to_lowercase()) => {
+                self.factor
+            }
+            _ => 1.0,
+        }
+    }
+}
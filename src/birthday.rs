@@ -0,0 +1,192 @@
+use crate::bot::types::Id;
+use chrono::{Datelike, Duration, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryInto;
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Occasion {
+    Birthday,
+    Anniversary,
+}
+
+impl Occasion {
+    pub fn greeting(self) -> &'static str {
+        match self {
+            Occasion::Birthday => "🎂 Happy birthday",
+            Occasion::Anniversary => "🎉 Happy anniversary",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Celebration {
+    pub user: Id,
+    pub channel: Id,
+    pub occasion: Occasion,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl Celebration {
+    /// What to generate the wish for `date` from, the same every time it's tried.
+    pub fn seed(&self, guild: Id, date: NaiveDate) -> u64 {
+        let what = format!("{} {} {:?} {}", guild, self.user, self.occasion, date);
+        let hash = blake3::hash(what.as_bytes());
+        u64::from_le_bytes(hash.as_bytes()[..8].try_into().unwrap())
+    }
+}
+
+/// How many days late a celebration that couldn't be sent on the day is still sent
+const LATE_DAYS: i64 = 3;
+
+/// What's been sent on days not done yet, by date, guild, user and occasion. Kept in a file of its
+/// own, so a restart while the rest are retried doesn't send them again.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Sent(HashSet<(NaiveDate, Id, Id, Occasion)>);
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Birthdays {
+    guilds: HashMap<Id, Vec<Celebration>>,
+    /// The last date everything due was sent
+    last_run: Option<NaiveDate>,
+    /// See `Sent`
+    #[serde(skip)]
+    sent: Sent,
+}
+
+impl Birthdays {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&mut self, guild: Id, celebration: Celebration) {
+        let entries = self.guilds.entry(guild).or_default();
+        entries.retain(|c| c.user != celebration.user || c.occasion != celebration.occasion);
+        entries.push(celebration);
+    }
+
+    pub fn of_guild(&self, guild: Id) -> &[Celebration] {
+        self.guilds.get(&guild).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn clear(&mut self, guild: Id, user: Id, occasion: Occasion) -> bool {
+        match self.guilds.get_mut(&guild) {
+            Some(entries) => {
+                let old_len = entries.len();
+                entries.retain(|c| c.user != user || c.occasion != occasion);
+                old_len != entries.len()
+            }
+            None => false,
+        }
+    }
+
+    /// What's been sent on days not done yet, to save.
+    pub fn sent_so_far(&self) -> &Sent {
+        &self.sent
+    }
+
+    /// Picks up what was sent before a restart.
+    pub fn restore_sent(&mut self, sent: Sent) {
+        self.sent = sent;
+    }
+
+    /// Celebrations that haven't been sent yet, with the day each is for: `date`'s, and those of
+    /// the days since everything was last sent, up to `LATE_DAYS` back. See `sent` and `done`.
+    pub fn due(&self, date: NaiveDate) -> Vec<(NaiveDate, Id, Celebration)> {
+        let first = match self.last_run {
+            Some(last) => (last + Duration::days(1)).max(date - Duration::days(LATE_DAYS)),
+            None => date,
+        };
+        let mut due = Vec::new();
+        let mut day = first;
+        while day <= date {
+            due.extend(self.falling_on(day).map(|(guild, c)| (day, guild, c)));
+            day += Duration::days(1);
+        }
+        due
+    }
+
+    /// Celebrations falling on `date` that haven't been sent for it. Feb 29 entries are celebrated
+    /// on Feb 28 in non-leap years.
+    fn falling_on(&self, date: NaiveDate) -> impl Iterator<Item = (Id, Celebration)> + '_ {
+        let leap_fallback = date.month() == 2
+            && date.day() == 28
+            && NaiveDate::from_ymd_opt(date.year(), 2, 29).is_none();
+        self.guilds
+            .iter()
+            .flat_map(|(&guild, entries)| entries.iter().map(move |c| (guild, c)))
+            .filter(move |(_, c)| {
+                (c.month == date.month() && c.day == date.day())
+                    || (leap_fallback && c.month == 2 && c.day == 29)
+            })
+            .filter(move |(guild, c)| !self.sent.0.contains(&(date, *guild, c.user, c.occasion)))
+            .map(|(guild, c)| (guild, c.clone()))
+    }
+
+    /// Notes that `celebration` was sent for `date`, or given up on, so it isn't sent again while
+    /// the rest are retried.
+    pub fn sent(&mut self, date: NaiveDate, guild: Id, celebration: &Celebration) {
+        self.sent
+            .0
+            .insert((date, guild, celebration.user, celebration.occasion));
+    }
+
+    /// Notes that everything due up to `date` was sent.
+    pub fn done(&mut self, date: NaiveDate) {
+        self.last_run = Some(date);
+        self.sent.0.retain(|&(d, ..)| d > date);
+    }
+}
+
+pub fn parse_month_day(s: &str) -> Option<(u32, u32)> {
+    let mut parts = s.splitn(2, '-');
+    let month = parts.next()?.parse().ok()?;
+    let day = parts.next()?.parse().ok()?;
+    // 2000 is a leap year, so Feb 29 is accepted
+    NaiveDate::from_ymd_opt(2000, month, day).map(|_| (month, day))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, month, day).unwrap()
+    }
+
+    fn birthdays() -> (Birthdays, Id, Celebration) {
+        let guild: Id = "81384788765712384".parse().unwrap();
+        let celebration = Celebration {
+            user: "175928847299117063".parse().unwrap(),
+            channel: guild,
+            occasion: Occasion::Birthday,
+            month: 3,
+            day: 14,
+        };
+        let mut birthdays = Birthdays::new();
+        birthdays.set(guild, celebration.clone());
+        (birthdays, guild, celebration)
+    }
+
+    #[test]
+    fn what_was_sent_isnt_due_again() {
+        let (mut birthdays, guild, celebration) = birthdays();
+        assert_eq!(birthdays.due(date(3, 14)).len(), 1);
+        birthdays.sent(date(3, 14), guild, &celebration);
+        assert!(birthdays.due(date(3, 14)).is_empty());
+        birthdays.done(date(3, 14));
+        assert!(birthdays.due(date(3, 14)).is_empty());
+    }
+
+    #[test]
+    fn what_couldnt_be_sent_carries_over() {
+        let (mut birthdays, ..) = birthdays();
+        birthdays.done(date(3, 13));
+        let due = birthdays.due(date(3, 16));
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].0, date(3, 14));
+        // far enough behind, it's too late
+        assert!(birthdays.due(date(3, 20)).is_empty());
+    }
+}
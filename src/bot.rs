@@ -4,7 +4,7 @@ use std::time::Duration;
 use anyhow::{anyhow, bail, ensure, Result};
 use async_io::{Async, Timer};
 use async_tungstenite::{tungstenite::Message, WebSocketStream};
-use futures::{future::FusedFuture, prelude::*, select};
+use futures::{future::FusedFuture, prelude::*, select, stream::FusedStream};
 use serde::Deserialize;
 use std::pin::Pin;
 use url::Url;
@@ -16,6 +16,7 @@ pub mod client;
 pub mod message;
 pub mod types;
 
+use crate::secrets::redact;
 use client::Client;
 
 type WebSocket = WebSocketStream<async_tungstenite::async_tls::ClientStream<Async<TcpStream>>>;
@@ -169,7 +170,7 @@ impl Bot {
                     }
                     state.seq = d.seq;
                     if let Err(e) = handler.handle_message(d.payload, &self.client).await {
-                        eprintln!("{}", e);
+                        eprintln!("{}", redact(&e.to_string()));
                     }
                 }
                 Ok(Event::HeartbeatAck) => {
@@ -192,16 +193,30 @@ impl Bot {
         Ok(())
     }
 
-    async fn run_loop(
+    async fn run_loop<H: AsyncDispatchHandler>(
         &self,
         ws: &mut WebSocket,
         mut state: State,
-        mut handler: impl AsyncDispatchHandler,
+        mut handler: H,
+        mut external: impl FusedStream<Item = H::External> + Unpin,
     ) -> Result<()> {
+        const TICK_INTERVAL: u64 = 60_000;
         let mut timer = wait(state.heartbeat_interval);
+        let mut tick = wait(TICK_INTERVAL);
         loop {
             let mut ws_fut = ws.next().fuse();
             select! {
+                _ = tick => {
+                    if let Err(e) = handler.handle_tick(&self.client).await {
+                        eprintln!("{}", redact(&e.to_string()));
+                    }
+                    tick = wait(TICK_INTERVAL);
+                }
+                event = external.select_next_some() => {
+                    if let Err(e) = handler.handle_external(event, &self.client).await {
+                        eprintln!("{}", redact(&e.to_string()));
+                    }
+                }
                 _ = timer => {
                     if !state.heartbeat_acked {
                         println!("disconnecting (heartbeat ack missed)");
@@ -222,14 +237,24 @@ impl Bot {
                     }
                 }
             }
+            if handler.restart_requested() {
+                self.disconnect(ws).await?;
+                bail!("restarting at the handler's request");
+            }
         }
     }
 
-    pub fn run(&self, mut handler: impl AsyncDispatchHandler) -> Result<()> {
+    /// Runs until the connection fails. Items from `external` are passed to
+    /// [`AsyncDispatchHandler::handle_external`] alongside gateway events.
+    pub fn run<H: AsyncDispatchHandler>(
+        &self,
+        mut handler: H,
+        external: impl FusedStream<Item = H::External> + Unpin,
+    ) -> Result<()> {
         async_io::block_on(async move {
             let mut ws = self.connect_to_gateway().await?;
             let state = dbg!(self.opening_handshake(&mut ws, &mut handler).await?);
-            self.run_loop(&mut ws, state, handler).await
+            self.run_loop(&mut ws, state, handler, external).await
         })
     }
 }
@@ -237,14 +262,38 @@ impl Bot {
 pub type AsyncDispatchFuture<'a> = Pin<Box<dyn Future<Output = Result<()>> + 'a>>;
 
 pub trait AsyncDispatchHandler {
+    /// Events coming from somewhere other than the gateway.
+    type External;
+
     fn handle_message<'a>(
         &'a mut self,
         payload: DispatchPayload<'a>,
         client: &'a Client,
     ) -> AsyncDispatchFuture<'a>;
+
+    /// Called periodically from the gateway loop, for work that isn't driven by an event.
+    fn handle_tick<'a>(&'a mut self, _client: &'a Client) -> AsyncDispatchFuture<'a> {
+        Box::pin(future::ready(Ok(())))
+    }
+
+    fn handle_external<'a>(
+        &'a mut self,
+        _event: Self::External,
+        _client: &'a Client,
+    ) -> AsyncDispatchFuture<'a> {
+        Box::pin(future::ready(Ok(())))
+    }
+
+    /// Checked after every event; returning `true` ends [`Bot::run`] with an error, so the
+    /// caller can start over, e.g. with a new token.
+    fn restart_requested(&self) -> bool {
+        false
+    }
 }
 
 impl<T: AsyncDispatchHandler> AsyncDispatchHandler for &'_ mut T {
+    type External = T::External;
+
     fn handle_message<'a>(
         &'a mut self,
         payload: DispatchPayload<'a>,
@@ -252,6 +301,22 @@ impl<T: AsyncDispatchHandler> AsyncDispatchHandler for &'_ mut T {
     ) -> AsyncDispatchFuture<'a> {
         T::handle_message(*self, payload, client)
     }
+
+    fn handle_tick<'a>(&'a mut self, client: &'a Client) -> AsyncDispatchFuture<'a> {
+        T::handle_tick(*self, client)
+    }
+
+    fn handle_external<'a>(
+        &'a mut self,
+        event: Self::External,
+        client: &'a Client,
+    ) -> AsyncDispatchFuture<'a> {
+        T::handle_external(*self, event, client)
+    }
+
+    fn restart_requested(&self) -> bool {
+        T::restart_requested(*self)
+    }
 }
 
 #[derive(Debug)]
@@ -1,9 +1,12 @@
-use anyhow::Result;
+use anyhow::{ensure, Result};
 use futures::prelude::*;
 use isahc::HttpClientBuilder;
 use serde::{Deserialize, Serialize};
 
 use crate::bot::types::*;
+use crate::commands::{Command, Registered};
+use crate::otel;
+use crate::perf;
 use serde::de::DeserializeOwned;
 use std::marker::PhantomData;
 use std::str::FromStr;
@@ -15,10 +18,27 @@ pub struct Client {
 
 pub struct Response<T> {
     inner: ResponseInner,
+    status: http::StatusCode,
     rate_limit_end: Option<Instant>,
     _phantom: PhantomData<T>,
 }
 
+/// Discord turned a request down with a 4xx other than a rate limit, like for a channel that's
+/// gone or one the bot can't post in, so trying it again won't help.
+#[derive(Debug)]
+pub struct Rejected {
+    pub status: u16,
+    pub body: String,
+}
+
+impl std::fmt::Display for Rejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Discord refused with {}: {}", self.status, self.body)
+    }
+}
+
+impl std::error::Error for Rejected {}
+
 enum ResponseInner {
     Response(isahc::Body),
     Bytes(Vec<u8>),
@@ -43,30 +63,45 @@ impl Client {
         format!("{}/{}", Self::DISCORD_ROOT, endpoint)
     }
 
+    /// A span for a request to `path`, named for its route with ids and tokens left out, see
+    /// `otel`, and its timing for `eg!perf`.
+    fn span(method: &str, path: &str) -> (otel::Span, perf::Timer) {
+        let path = path.split('?').next().unwrap_or(path);
+        let route: Vec<_> = path
+            .split('/')
+            .filter(|part| !part.is_empty() && *part != "api")
+            .map(|part| match part {
+                p if p.bytes().all(|b| b.is_ascii_digit()) => "{id}",
+                // interaction tokens
+                p if p.len() > 40 => "{token}",
+                p => p,
+            })
+            .collect();
+        let mut span = otel::Span::client(&format!("discord {} /{}", method, route.join("/")));
+        span.attribute("http.request.method", method);
+        (span, perf::time(perf::Op::Discord))
+    }
+
+    /// Sends `request`, in a span named for its route.
+    async fn send<B: Into<isahc::Body>>(
+        &self,
+        request: http::Request<B>,
+    ) -> Result<http::Response<isahc::Body>> {
+        let _span = Self::span(request.method().as_str(), request.uri().path());
+        Ok(self.http.send_async(request).await?)
+    }
+
     pub async fn make_get_request<T>(&self, endpoint: &str) -> Result<Response<T>> {
+        let _span = Self::span("GET", endpoint);
         let response = self
             .http
             .get_async(dbg!(Self::get_discord_endpoint(endpoint)))
             .await?;
-
-        let rate_limit_end = get_from_response::<usize, _>(&response, "X-RateLimit-Remaining")
-            .and_then(|remaining| {
-                if remaining == 0 {
-                    let limit_end_after = get_from_response(&response, "X-RateLimit-Reset-After")?;
-                    Some(Instant::now() + Duration::from_secs(limit_end_after))
-                } else {
-                    None
-                }
-            });
-
-        Ok(Response {
-            inner: ResponseInner::Response(response.into_body()),
-            rate_limit_end: dbg!(rate_limit_end),
-            _phantom: PhantomData,
-        })
+        Ok(Response::new(response))
     }
 
     pub async fn make_put_request(&self, endpoint: &str, body: String) -> Result<()> {
+        let _span = Self::span("PUT", endpoint);
         let response = self
             .http
             .put_async(Self::get_discord_endpoint(endpoint), dbg!(body))
@@ -75,27 +110,524 @@ impl Client {
         Ok(())
     }
 
-    pub async fn make_post_request(&self, endpoint: &str, body: String) -> Result<()> {
+    pub async fn make_post_request<T>(&self, endpoint: &str, body: String) -> Result<Response<T>> {
+        let _span = Self::span("POST", endpoint);
         let response = self
             .http
             .post_async(Self::get_discord_endpoint(endpoint), dbg!(body))
             .await?;
-        dbg!(response);
-        Ok(())
+        Ok(Response::new(dbg!(response)))
     }
 
     pub async fn create_message(&self, channel_id: Id, content: &str) -> Result<()> {
+        self.post_message(channel_id, content).await?;
+        Ok(())
+    }
+
+    pub async fn post_message(&self, channel_id: Id, content: &str) -> Result<Id> {
+        self.post_message_with_components(channel_id, content, &[])
+            .await
+    }
+
+    pub async fn post_message_with_components(
+        &self,
+        channel_id: Id,
+        content: &str,
+        components: &[ActionRow],
+    ) -> Result<Id> {
         #[derive(Serialize)]
         struct CreateMessage<'a> {
             content: &'a str,
+            #[serde(skip_serializing_if = "<[_]>::is_empty")]
+            components: &'a [ActionRow],
+        }
+        #[derive(Deserialize)]
+        struct Created {
+            id: Id,
         }
-        self.make_post_request(
+        let created = self
+            .make_post_request::<Created>(
+                &format!("/channels/{}/messages", channel_id),
+                serde_json::to_string(&CreateMessage {
+                    content,
+                    components,
+                })
+                .expect("Cannot format message to create "),
+            )
+            .await?
+            .get_response_owned()?;
+
+        Ok(created.id)
+    }
+
+    /// Posts a native poll of `question` and `answers`, open for `hours`.
+    pub async fn post_poll(
+        &self,
+        channel_id: Id,
+        question: &str,
+        answers: &[String],
+        hours: u64,
+    ) -> Result<Id> {
+        #[derive(Serialize)]
+        struct Text<'a> {
+            text: &'a str,
+        }
+        #[derive(Serialize)]
+        struct Answer<'a> {
+            poll_media: Text<'a>,
+        }
+        #[derive(Serialize)]
+        struct CreatePoll<'a> {
+            question: Text<'a>,
+            answers: Vec<Answer<'a>>,
+            duration: u64,
+            allow_multiselect: bool,
+        }
+        #[derive(Serialize)]
+        struct CreateMessage<'a> {
+            poll: CreatePoll<'a>,
+        }
+        #[derive(Deserialize)]
+        struct Created {
+            id: Id,
+        }
+        let poll = CreatePoll {
+            question: Text { text: question },
+            answers: answers
+                .iter()
+                .map(|a| Answer {
+                    poll_media: Text { text: a },
+                })
+                .collect(),
+            duration: hours,
+            allow_multiselect: false,
+        };
+        let created = self
+            .make_post_request::<Created>(
+                &format!("/channels/{}/messages", channel_id),
+                serde_json::to_string(&CreateMessage { poll })
+                    .expect("Cannot format message to create "),
+            )
+            .await?
+            .get_response_owned()?;
+        Ok(created.id)
+    }
+
+    /// Closes a native poll now, so its results are final.
+    pub async fn end_poll(&self, channel: Id, message: Id) -> Result<()> {
+        self.make_post_request::<serde::de::IgnoredAny>(
+            &format!("/channels/{}/polls/{}/expire", channel, message),
+            String::default(),
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub async fn post_embed(&self, channel_id: Id, embed: &Embed) -> Result<()> {
+        #[derive(Serialize)]
+        struct CreateMessage<'a> {
+            embeds: [&'a Embed; 1],
+        }
+        self.make_post_request::<serde::de::IgnoredAny>(
             &format!("/channels/{}/messages", channel_id),
-            serde_json::to_string(&CreateMessage { content })
+            serde_json::to_string(&CreateMessage { embeds: [embed] })
                 .expect("Cannot format message to create "),
         )
+        .await?
+        .get_response_owned()?;
+        Ok(())
+    }
+
+    /// Sends `content` with up to 3 of `stickers`, which have to be the guild's or Discord's own.
+    pub async fn post_message_with_stickers(
+        &self,
+        channel_id: Id,
+        content: &str,
+        stickers: &[Id],
+    ) -> Result<Id> {
+        #[derive(Serialize)]
+        struct CreateMessage<'a> {
+            content: &'a str,
+            sticker_ids: &'a [Id],
+        }
+        #[derive(Deserialize)]
+        struct Created {
+            id: Id,
+        }
+        let created = self
+            .make_post_request::<Created>(
+                &format!("/channels/{}/messages", channel_id),
+                serde_json::to_string(&CreateMessage {
+                    content,
+                    sticker_ids: &stickers[..stickers.len().min(3)],
+                })
+                .expect("Cannot format message to create "),
+            )
+            .await?
+            .get_response_owned()?;
+        Ok(created.id)
+    }
+
+    /// Replaces a message's content and components. Returns when the rate limit lets up, if this
+    /// used it up.
+    pub async fn edit_message(
+        &self,
+        channel_id: Id,
+        message_id: Id,
+        content: &str,
+        components: &[ActionRow],
+    ) -> Result<Option<Instant>> {
+        #[derive(Serialize)]
+        struct EditMessage<'a> {
+            content: &'a str,
+            components: &'a [ActionRow],
+        }
+        let request = http::Request::patch(Self::get_discord_endpoint(&format!(
+            "/channels/{}/messages/{}",
+            channel_id, message_id
+        )))
+        .body(
+            serde_json::to_string(&EditMessage {
+                content,
+                components,
+            })
+            .expect("Cannot format message edit"),
+        )?;
+        let response = self.send(request).await?;
+        ensure!(
+            response.status().is_success(),
+            "edit failed with {}",
+            response.status()
+        );
+        Ok(Response::<()>::new(response).rate_limit_end())
+    }
+
+    /// Starts a public thread off of `message`, returning the thread's ID.
+    pub async fn create_thread(&self, channel: Id, message: Id, name: &str) -> Result<Id> {
+        #[derive(Serialize)]
+        struct StartThread<'a> {
+            name: &'a str,
+            /// Minutes without messages before Discord archives it anyway
+            auto_archive_duration: u32,
+        }
+        #[derive(Deserialize)]
+        struct Channel {
+            id: Id,
+        }
+        let thread = self
+            .make_post_request::<Channel>(
+                &format!("/channels/{}/messages/{}/threads", channel, message),
+                serde_json::to_string(&StartThread {
+                    name,
+                    auto_archive_duration: 60,
+                })
+                .expect("Cannot format thread request"),
+            )
+            .await?
+            .get_response_owned()?;
+
+        Ok(thread.id)
+    }
+
+    pub async fn archive_thread(&self, thread: Id) -> Result<()> {
+        let request =
+            http::Request::patch(Self::get_discord_endpoint(&format!("/channels/{}", thread)))
+                .body(String::from(r#"{"archived":true}"#))?;
+        let response = self.send(request).await?;
+        ensure!(
+            response.status().is_success(),
+            "archiving failed with {}",
+            response.status()
+        );
+        Ok(())
+    }
+
+    /// The category `channel` is in, if any.
+    pub async fn get_channel_parent(&self, channel: Id) -> Result<Option<Id>> {
+        #[derive(Deserialize)]
+        struct Channel {
+            parent_id: Option<Id>,
+        }
+        let channel = self
+            .make_get_request::<Channel>(&format!("/channels/{}", channel))
+            .await?
+            .get_response_owned()?;
+        Ok(channel.parent_id)
+    }
+
+    pub async fn get_guild_owner(&self, guild: Id) -> Result<Id> {
+        #[derive(Deserialize)]
+        struct Guild {
+            owner_id: Id,
+        }
+        let guild = self
+            .make_get_request::<Guild>(&format!("/guilds/{}", guild))
+            .await?
+            .get_response_owned()?;
+        Ok(guild.owner_id)
+    }
+
+    /// The ids and names of `guild`'s own stickers.
+    pub async fn get_guild_stickers(&self, guild: Id) -> Result<Vec<(Id, String)>> {
+        #[derive(Deserialize)]
+        struct Sticker {
+            id: Id,
+            name: String,
+        }
+        let stickers = self
+            .make_get_request::<Vec<Sticker>>(&format!("/guilds/{}/stickers", guild))
+            .await?
+            .get_response_owned()?;
+        Ok(stickers.into_iter().map(|s| (s.id, s.name)).collect())
+    }
+
+    /// Every channel in `guild`, categories included.
+    pub async fn get_guild_channels(&self, guild: Id) -> Result<Vec<Id>> {
+        #[derive(Deserialize)]
+        struct Channel {
+            id: Id,
+        }
+        let channels = self
+            .make_get_request::<Vec<Channel>>(&format!("/guilds/{}/channels", guild))
+            .await?
+            .get_response_owned()?;
+        Ok(channels.into_iter().map(|c| c.id).collect())
+    }
+
+    fn commands_endpoint(application: Id, guild: Option<Id>) -> String {
+        match guild {
+            Some(guild) => format!("/applications/{}/guilds/{}/commands", application, guild),
+            None => format!("/applications/{}/commands", application),
+        }
+    }
+
+    /// The slash commands registered globally, or in `guild` only.
+    pub async fn get_application_commands(
+        &self,
+        application: Id,
+        guild: Option<Id>,
+    ) -> Result<Vec<Registered>> {
+        self.make_get_request::<Vec<Registered>>(&Self::commands_endpoint(application, guild))
+            .await?
+            .get_response_owned()
+    }
+
+    pub async fn create_application_command(
+        &self,
+        application: Id,
+        guild: Option<Id>,
+        command: &Command,
+    ) -> Result<()> {
+        self.make_post_request::<serde::de::IgnoredAny>(
+            &Self::commands_endpoint(application, guild),
+            serde_json::to_string(command).expect("Cannot format command"),
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub async fn edit_application_command(
+        &self,
+        application: Id,
+        guild: Option<Id>,
+        id: Id,
+        command: &Command,
+    ) -> Result<()> {
+        let endpoint = format!("{}/{}", Self::commands_endpoint(application, guild), id);
+        let request = http::Request::patch(Self::get_discord_endpoint(&endpoint))
+            .body(serde_json::to_string(command).expect("Cannot format command"))?;
+        let response = self.send(request).await?;
+        ensure!(
+            response.status().is_success(),
+            "editing /{} failed with {}",
+            command.name,
+            response.status()
+        );
+        Ok(())
+    }
+
+    pub async fn delete_application_command(
+        &self,
+        application: Id,
+        guild: Option<Id>,
+        id: Id,
+    ) -> Result<()> {
+        let endpoint = format!("{}/{}", Self::commands_endpoint(application, guild), id);
+        let _span = Self::span("DELETE", &endpoint);
+        let response = self
+            .http
+            .delete_async(Self::get_discord_endpoint(&endpoint))
+            .await?;
+        ensure!(
+            response.status().is_success(),
+            "deleting command {} failed with {}",
+            id,
+            response.status()
+        );
+        Ok(())
+    }
+
+    /// The guild `channel` is in, or `None` for DMs.
+    pub async fn get_channel_guild(&self, channel: Id) -> Result<Option<Id>> {
+        #[derive(Deserialize)]
+        struct Channel {
+            guild_id: Option<Id>,
+        }
+        let channel = self
+            .make_get_request::<Channel>(&format!("/channels/{}", channel))
+            .await?
+            .get_response_owned()?;
+        Ok(channel.guild_id)
+    }
+
+    /// The DM channel with `user`, opened if there isn't one yet.
+    pub async fn create_dm(&self, user: Id) -> Result<Id> {
+        #[derive(Serialize)]
+        struct CreateDm {
+            recipient_id: Id,
+        }
+        #[derive(Deserialize)]
+        struct Channel {
+            id: Id,
+        }
+        let channel = self
+            .make_post_request::<Channel>(
+                "/users/@me/channels",
+                serde_json::to_string(&CreateDm { recipient_id: user })
+                    .expect("Cannot format DM request"),
+            )
+            .await?
+            .get_response_owned()?;
+
+        Ok(channel.id)
+    }
+
+    /// Posts a message with a single file attached.
+    pub async fn upload_file(
+        &self,
+        channel_id: Id,
+        content: &str,
+        filename: &str,
+        file: &[u8],
+    ) -> Result<()> {
+        const BOUNDARY: &str = "taco-bot-upload";
+        #[derive(Serialize)]
+        struct Payload<'a> {
+            content: &'a str,
+        }
+        let payload = serde_json::to_string(&Payload { content }).expect("Cannot format upload");
+        let mut body = format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"payload_json\"\r\n\
+             Content-Type: application/json\r\n\r\n{p}\r\n\
+             --{b}\r\nContent-Disposition: form-data; name=\"files[0]\"; filename=\"{f}\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\n",
+            b = BOUNDARY,
+            p = payload,
+            f = filename
+        )
+        .into_bytes();
+        body.extend_from_slice(file);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", BOUNDARY).as_bytes());
+
+        // the per-request Content-Type takes precedence over the client's JSON default
+        let request = http::Request::post(Self::get_discord_endpoint(&format!(
+            "/channels/{}/messages",
+            channel_id
+        )))
+        .header(
+            "Content-Type",
+            format!("multipart/form-data; boundary={}", BOUNDARY),
+        )
+        .body(body)?;
+        let response = self.send(request).await?;
+        ensure!(
+            response.status().is_success(),
+            "upload failed with {}",
+            response.status()
+        );
+        Ok(())
+    }
+
+    /// Acknowledges `interaction` without answering yet. Responses to it after this go where
+    /// Discord expects them for a deferred interaction.
+    pub async fn defer_interaction(&self, interaction: &Interaction<'_>) -> Result<()> {
+        let kind = if interaction.kind == Interaction::COMPONENT {
+            InteractionResponse::DEFERRED_UPDATE_MESSAGE
+        } else {
+            InteractionResponse::DEFERRED_CHANNEL_MESSAGE
+        };
+        self.create_interaction_response(interaction, &InteractionResponse::deferred(kind))
+            .await?;
+        interaction.deferred.set(Some(kind));
+        Ok(())
+    }
+
+    pub async fn create_interaction_response(
+        &self,
+        interaction: &Interaction<'_>,
+        response: &InteractionResponse<'_>,
+    ) -> Result<()> {
+        if let Some(deferred) = interaction.deferred.get() {
+            return self
+                .respond_to_deferred(interaction, deferred, response)
+                .await;
+        }
+        self.make_post_request::<serde::de::IgnoredAny>(
+            &format!(
+                "/interactions/{}/{}/callback",
+                interaction.id, interaction.token
+            ),
+            serde_json::to_string(response).expect("Cannot format interaction response"),
+        )
         .await?;
+        Ok(())
+    }
 
+    /// Updates and command answers replace the deferred message, anything else follows up on it.
+    /// Ephemeral answers to commands take the deferred message's place, since it can't be made
+    /// ephemeral after the fact.
+    async fn respond_to_deferred(
+        &self,
+        interaction: &Interaction<'_>,
+        deferred: u8,
+        response: &InteractionResponse<'_>,
+    ) -> Result<()> {
+        let webhook = format!(
+            "/webhooks/{}/{}",
+            interaction.application_id, interaction.token
+        );
+        let body =
+            serde_json::to_string(&response.data).expect("Cannot format interaction response");
+        if response.is_ephemeral() && deferred == InteractionResponse::DEFERRED_CHANNEL_MESSAGE {
+            let endpoint = format!("{}/messages/@original", webhook);
+            let span = Self::span("DELETE", &endpoint);
+            let deleted = self
+                .http
+                .delete_async(Self::get_discord_endpoint(&endpoint))
+                .await?;
+            ensure!(
+                deleted.status().is_success(),
+                "deleting the deferred response failed with {}",
+                deleted.status()
+            );
+            drop(span);
+            self.make_post_request::<serde::de::IgnoredAny>(&webhook, body)
+                .await?;
+        } else if response.kind == InteractionResponse::UPDATE_MESSAGE
+            || deferred == InteractionResponse::DEFERRED_CHANNEL_MESSAGE
+        {
+            let endpoint = format!("{}/messages/@original", webhook);
+            let request = http::Request::patch(Self::get_discord_endpoint(&endpoint)).body(body)?;
+            let response = self.send(request).await?;
+            ensure!(
+                response.status().is_success(),
+                "editing the deferred response failed with {}",
+                response.status()
+            );
+        } else {
+            self.make_post_request::<serde::de::IgnoredAny>(&webhook, body)
+                .await?;
+        }
         Ok(())
     }
 
@@ -122,12 +654,48 @@ impl Client {
         }
         self.make_get_request(&endpoint).await
     }
+
+    pub async fn get_message<'a>(&self, channel: Id, message: Id) -> Result<Response<Message<'a>>> {
+        self.make_get_request(&format!("/channels/{}/messages/{}", channel, message))
+            .await
+    }
 }
 
 impl<T> Response<T> {
+    fn new(response: http::Response<isahc::Body>) -> Self {
+        let rate_limit_end = get_from_response::<usize, _>(&response, "X-RateLimit-Remaining")
+            .and_then(|remaining| {
+                if remaining == 0 {
+                    let limit_end_after = get_from_response(&response, "X-RateLimit-Reset-After")?;
+                    Some(Instant::now() + Duration::from_secs(limit_end_after))
+                } else {
+                    None
+                }
+            });
+
+        Response {
+            status: response.status(),
+            inner: ResponseInner::Response(response.into_body()),
+            rate_limit_end: dbg!(rate_limit_end),
+            _phantom: PhantomData,
+        }
+    }
+
     pub fn rate_limit_end(&self) -> Option<Instant> {
         self.rate_limit_end
     }
+
+    /// A `Rejected` error if Discord refused the request, with what it said about it.
+    fn check(&self, body: &[u8]) -> Result<()> {
+        if self.status.is_client_error() && self.status != http::StatusCode::TOO_MANY_REQUESTS {
+            return Err(Rejected {
+                status: self.status.as_u16(),
+                body: String::from_utf8_lossy(body).into_owned(),
+            }
+            .into());
+        }
+        Ok(())
+    }
 }
 
 impl<'a, 'de, T> Response<T>
@@ -148,6 +716,7 @@ where
         match &self.inner {
             ResponseInner::Bytes(bytes) => {
                 dbg!(String::from_utf8_lossy(bytes));
+                self.check(bytes)?;
                 Ok(serde_json::from_slice(bytes)?)
             }
             _ => unreachable!(),
@@ -157,6 +726,16 @@ where
 
 impl<T: DeserializeOwned> Response<T> {
     pub fn get_response_owned(&mut self) -> Result<T> {
+        if let ResponseInner::Response(body) = &mut self.inner {
+            if self.status.is_client_error() {
+                let mut bytes = Vec::new();
+                std::io::Read::read_to_end(body, &mut bytes)?;
+                self.inner = ResponseInner::Bytes(bytes);
+            }
+        }
+        if let ResponseInner::Bytes(bytes) = &self.inner {
+            self.check(bytes)?;
+        }
         Ok(match &mut self.inner {
             ResponseInner::Response(body) => serde_json::from_reader(body)?,
             ResponseInner::Bytes(bytes) => serde_json::from_slice(bytes)?,
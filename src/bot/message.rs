@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
 
 use crate::bot::types::*;
+use crate::strings::StrCow;
 
 pub mod command {
     use serde::ser::SerializeStruct;
@@ -111,10 +112,37 @@ pub mod event {
 
     #[derive(Debug)]
     pub enum DispatchPayload<'a> {
-        MessageCreate(Message<'a>),
+        MessageCreate(Box<Message<'a>>),
         // more to be added later
         Ready(Ready<'a>),
         TypingStart(TypingStart<'a>),
+        MessageReactionAdd(MessageReaction<'a>),
+        MessageReactionRemove(MessageReaction<'a>),
+        InteractionCreate(Box<Interaction<'a>>),
+        ChannelUpdate(ChannelUpdate),
+        MessageDeleteBulk(MessageDeleteBulk),
+        AutoModerationActionExecution(AutoModerationAction<'a>),
+        GuildScheduledEventCreate(ScheduledEvent<'a>),
+    }
+
+    impl DispatchPayload<'_> {
+        /// The event's name, as the gateway sends it.
+        pub fn name(&self) -> &'static str {
+            match self {
+                DispatchPayload::MessageCreate(_) => "MESSAGE_CREATE",
+                DispatchPayload::Ready(_) => "READY",
+                DispatchPayload::TypingStart(_) => "TYPING_START",
+                DispatchPayload::MessageReactionAdd(_) => "MESSAGE_REACTION_ADD",
+                DispatchPayload::MessageReactionRemove(_) => "MESSAGE_REACTION_REMOVE",
+                DispatchPayload::InteractionCreate(_) => "INTERACTION_CREATE",
+                DispatchPayload::ChannelUpdate(_) => "CHANNEL_UPDATE",
+                DispatchPayload::MessageDeleteBulk(_) => "MESSAGE_DELETE_BULK",
+                DispatchPayload::AutoModerationActionExecution(_) => {
+                    "AUTO_MODERATION_ACTION_EXECUTION"
+                }
+                DispatchPayload::GuildScheduledEventCreate(_) => "GUILD_SCHEDULED_EVENT_CREATE",
+            }
+        }
     }
 
     #[derive(Deserialize)]
@@ -127,6 +155,12 @@ pub mod event {
         #[serde(borrow)]
         pub user: User<'a>,
         pub session_id: &'a str,
+        pub application: Application,
+    }
+
+    #[derive(Deserialize, Debug)]
+    pub struct Application {
+        pub id: Id,
     }
 
     #[derive(Deserialize, Debug)]
@@ -140,6 +174,55 @@ pub mod event {
         member: Option<Member<'a>>,
     }
 
+    #[derive(Deserialize, Debug)]
+    pub struct MessageReaction<'a> {
+        pub user_id: Id,
+        pub channel_id: Id,
+        pub message_id: Id,
+        pub guild_id: Option<Id>,
+
+        #[serde(borrow)]
+        pub emoji: Emoji<'a>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    pub struct MessageDeleteBulk {
+        pub ids: Vec<Id>,
+        pub channel_id: Id,
+        pub guild_id: Option<Id>,
+    }
+
+    /// One action AutoMod took on a message. Blocking, alerting and timing out are each their own.
+    #[derive(Deserialize, Debug)]
+    pub struct AutoModerationAction<'a> {
+        pub guild_id: Id,
+        pub user_id: Id,
+        pub channel_id: Option<Id>,
+        /// Missing if the message was blocked before it was sent
+        pub message_id: Option<Id>,
+        #[serde(borrow)]
+        pub matched_keyword: Option<StrCow<'a>>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    pub struct ScheduledEvent<'a> {
+        pub id: Id,
+        pub guild_id: Id,
+        /// Missing for events from before Discord kept track
+        pub creator_id: Option<Id>,
+        #[serde(borrow)]
+        pub name: StrCow<'a>,
+        #[serde(borrow)]
+        pub description: Option<StrCow<'a>>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    pub struct ChannelUpdate {
+        pub id: Id,
+        /// The category it's in
+        pub parent_id: Option<Id>,
+    }
+
     #[derive(Deserialize)]
     struct RawEvent<'a> {
         op: u8,
@@ -162,19 +245,34 @@ pub mod event {
             where
                 D: Deserializer<'de, Error = serde_json::Error>,
             {
-                let payload = match t {
-                    "MESSAGE_CREATE" => {
-                        Message::deserialize(de).map(DispatchPayload::MessageCreate)
-                    }
-                    "READY" => Ready::deserialize(de).map(DispatchPayload::Ready),
-                    "TYPING_START" => {
-                        TypingStart::deserialize(de).map(DispatchPayload::TypingStart)
-                    }
-                    s => Err(serde_json::Error::invalid_value(
-                        Unexpected::Str(s),
-                        &"valid gateway message type",
-                    )),
-                }?;
+                let payload =
+                    match t {
+                        "MESSAGE_CREATE" => Message::deserialize(de)
+                            .map(|m| DispatchPayload::MessageCreate(Box::new(m))),
+                        "READY" => Ready::deserialize(de).map(DispatchPayload::Ready),
+                        "TYPING_START" => {
+                            TypingStart::deserialize(de).map(DispatchPayload::TypingStart)
+                        }
+                        "MESSAGE_REACTION_ADD" => MessageReaction::deserialize(de)
+                            .map(DispatchPayload::MessageReactionAdd),
+                        "MESSAGE_REACTION_REMOVE" => MessageReaction::deserialize(de)
+                            .map(DispatchPayload::MessageReactionRemove),
+                        "INTERACTION_CREATE" => Interaction::deserialize(de)
+                            .map(|i| DispatchPayload::InteractionCreate(Box::new(i))),
+                        "MESSAGE_DELETE_BULK" => MessageDeleteBulk::deserialize(de)
+                            .map(DispatchPayload::MessageDeleteBulk),
+                        "AUTO_MODERATION_ACTION_EXECUTION" => AutoModerationAction::deserialize(de)
+                            .map(DispatchPayload::AutoModerationActionExecution),
+                        "GUILD_SCHEDULED_EVENT_CREATE" => ScheduledEvent::deserialize(de)
+                            .map(DispatchPayload::GuildScheduledEventCreate),
+                        "CHANNEL_UPDATE" => {
+                            ChannelUpdate::deserialize(de).map(DispatchPayload::ChannelUpdate)
+                        }
+                        s => Err(serde_json::Error::invalid_value(
+                            Unexpected::Str(s),
+                            &"valid gateway message type",
+                        )),
+                    }?;
                 Ok(Dispatch { seq, payload })
             }
             fn deserialize_null<'de, D>(de: D, ret: Event) -> Result<Event, D::Error>
@@ -1,13 +1,21 @@
 use crate::strings::StrCow;
 use chrono::{DateTime, Utc};
 use serde::{de::Error, Deserialize, Deserializer, Serialize};
+use std::cell::Cell;
 use std::fmt::{Display, Formatter};
 use std::ops::Deref;
 use std::str::FromStr;
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct TokenBuf(String);
 
+// tokens never show up in debug output
+impl std::fmt::Debug for TokenBuf {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&**self, f)
+    }
+}
+
 impl<T: Into<String>> From<T> for TokenBuf {
     fn from(s: T) -> Self {
         TokenBuf(s.into())
@@ -22,10 +30,16 @@ impl Deref for TokenBuf {
     }
 }
 
-#[derive(Serialize, Debug, PartialEq, Eq)]
+#[derive(Serialize, PartialEq, Eq)]
 #[repr(transparent)]
 pub struct Token(str);
 
+impl std::fmt::Debug for Token {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Token([redacted])")
+    }
+}
+
 impl Token {
     pub fn as_str(&self) -> &str {
         &self.0
@@ -38,10 +52,20 @@ impl Display for Token {
     }
 }
 
-#[derive(Serialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Serialize, Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[serde(into = "String")]
 pub struct Id(u64);
 
+impl Id {
+    pub fn mention_user(self) -> String {
+        format!("<@{}>", self)
+    }
+
+    pub fn mention_channel(self) -> String {
+        format!("<#{}>", self)
+    }
+}
+
 impl<'de> Deserialize<'de> for Id {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -92,6 +116,7 @@ pub struct Message<'a> {
 
     pub id: Id,
     pub channel_id: Id,
+    pub guild_id: Option<Id>,
 
     #[serde(deserialize_with = "deserialize_datetime_into_millis")]
     pub timestamp: i64,
@@ -101,11 +126,127 @@ pub struct Message<'a> {
 
     #[serde(borrow)]
     pub mentions: Vec<User<'a>>,
+
+    #[serde(borrow, default)]
+    pub reactions: Vec<Reaction<'a>>,
+
+    #[serde(borrow, default)]
+    pub attachments: Vec<Attachment<'a>>,
+
+    #[serde(default)]
+    pub message_reference: Option<MessageReference>,
+
+    /// What it replies to, if that's still there
+    #[serde(default)]
+    pub referenced_message: Option<Box<RepliedTo>>,
+
+    #[serde(default)]
+    pub flags: u64,
+
+    #[serde(borrow, default)]
+    pub sticker_items: Vec<StickerItem<'a>>,
+
+    /// When it's a native poll
+    #[serde(default)]
+    pub poll: Option<MessagePoll>,
+}
+
+impl Message<'_> {
+    const IS_CROSSPOST: u64 = 1 << 1;
+
+    /// Whether it's an announcement another server's channel published to one this one follows.
+    pub fn is_crosspost(&self) -> bool {
+        self.flags & Self::IS_CROSSPOST != 0
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MessageReference {
+    pub message_id: Option<Id>,
+}
+
+/// As much of a native poll as is needed: its votes, once Discord has counted them.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MessagePoll {
+    #[serde(default)]
+    pub results: Option<PollResults>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PollResults {
+    pub answer_counts: Vec<AnswerCount>,
+}
+
+/// The votes for one answer, by its id, which count up from 1 in the order the answers were given.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AnswerCount {
+    pub id: usize,
+    pub count: usize,
+}
+
+/// As much of a replied-to message as is needed: who wrote it.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RepliedTo {
+    pub author: Author,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Author {
+    pub id: Id,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Attachment<'a> {
+    pub id: Id,
+    pub size: u64,
+
+    #[serde(borrow)]
+    pub filename: StrCow<'a>,
+    #[serde(borrow)]
+    pub url: StrCow<'a>,
+    /// Alt text
+    #[serde(borrow, default)]
+    pub description: Option<StrCow<'a>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StickerItem<'a> {
+    pub id: Id,
+    #[serde(borrow)]
+    pub name: StrCow<'a>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Reaction<'a> {
+    pub count: usize,
+    pub me: bool,
+
+    #[serde(borrow)]
+    pub emoji: Emoji<'a>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Emoji<'a> {
+    pub id: Option<Id>,
+
+    #[serde(borrow)]
+    pub name: Option<StrCow<'a>>,
+}
+
+impl Emoji<'_> {
+    /// The form used in reaction endpoints: `name:id` for custom emoji, the character itself otherwise.
+    pub fn to_reaction_string(&self) -> Option<String> {
+        let name = self.name.as_ref()?.as_str();
+        Some(match self.id {
+            Some(id) => format!("{}:{}", name, id),
+            None => String::from(name),
+        })
+    }
 }
 
 #[derive(Serialize, Deserialize, Copy, Clone)]
 #[serde(rename_all = "snake_case")]
-#[repr(u16)]
+#[repr(u32)]
 pub enum Intent {
     Guilds = 1 << 0,
     GuildMembers = 1 << 1,
@@ -122,17 +263,19 @@ pub enum Intent {
     DirectMessages = 1 << 12,
     DirectMessageReactions = 1 << 13,
     DirectMessageTyping = 1 << 14,
+    GuildScheduledEvents = 1 << 16,
+    AutoModerationExecution = 1 << 21,
 }
 
 impl Intent {
     pub const fn and(self, other: Intent) -> Intents {
-        Intents(self as u16 | other as u16)
+        Intents(self as u32 | other as u32)
     }
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone)]
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
 #[serde(from = "Vec<Intent>")]
-pub struct Intents(u16);
+pub struct Intents(u32);
 
 impl<I: IntoIterator<Item = Intent>> From<I> for Intents {
     fn from(iter: I) -> Self {
@@ -142,13 +285,13 @@ impl<I: IntoIterator<Item = Intent>> From<I> for Intents {
 
 impl Intents {
     pub const fn and(self, other: Intent) -> Intents {
-        Intents(self.0 | other as u16)
+        Intents(self.0 | other as u32)
     }
 }
 
 impl From<Intent> for Intents {
     fn from(intent: Intent) -> Intents {
-        Intents(intent as u16)
+        Intents(intent as u32)
     }
 }
 
@@ -165,6 +308,295 @@ pub struct User<'a> {
     pub id: Id,
     pub username: &'a str, // might need Cow
     pub discriminator: &'a str,
+    #[serde(default)]
+    pub bot: bool,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ActionRow {
+    #[serde(rename = "type")]
+    kind: u8,
+    components: Vec<Component>,
+}
+
+impl ActionRow {
+    pub fn new(buttons: Vec<Button>) -> Self {
+        ActionRow {
+            kind: 1,
+            components: buttons.into_iter().map(Component::Button).collect(),
+        }
+    }
+
+    /// A row with just `menu`, since a select menu takes up a whole row.
+    pub fn select(menu: SelectMenu) -> Self {
+        ActionRow {
+            kind: 1,
+            components: vec![Component::Select(menu)],
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(untagged)]
+enum Component {
+    Button(Button),
+    Select(SelectMenu),
+}
+
+#[derive(Serialize, Copy, Clone, Debug)]
+#[repr(u8)]
+pub enum ButtonStyle {
+    Primary = 1,
+    Secondary = 2,
+    Success = 3,
+    Danger = 4,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Button {
+    #[serde(rename = "type")]
+    kind: u8,
+    style: u8,
+    label: String,
+    custom_id: String,
+}
+
+impl Button {
+    pub fn new(style: ButtonStyle, label: impl Into<String>, custom_id: impl Into<String>) -> Self {
+        Button {
+            kind: 2,
+            style: style as u8,
+            label: label.into(),
+            custom_id: custom_id.into(),
+        }
+    }
+}
+
+/// A menu to pick one option from, which answers with the option's value.
+#[derive(Serialize, Clone, Debug)]
+pub struct SelectMenu {
+    #[serde(rename = "type")]
+    kind: u8,
+    custom_id: String,
+    placeholder: String,
+    options: Vec<SelectOption>,
+}
+
+impl SelectMenu {
+    pub fn new(custom_id: impl Into<String>, placeholder: impl Into<String>) -> Self {
+        SelectMenu {
+            kind: 3,
+            custom_id: custom_id.into(),
+            placeholder: placeholder.into(),
+            options: Vec::new(),
+        }
+    }
+
+    /// Adds an option, shown already picked if `default`.
+    pub fn option(
+        mut self,
+        label: impl Into<String>,
+        value: impl Into<String>,
+        default: bool,
+    ) -> Self {
+        self.options.push(SelectOption {
+            label: label.into(),
+            value: value.into(),
+            default,
+        });
+        self
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct SelectOption {
+    label: String,
+    value: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    default: bool,
+}
+
+/// A rich embed, as much of one as the bot sends.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct Embed {
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<EmbedField>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<u32>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct EmbedField {
+    pub name: String,
+    pub value: String,
+    pub inline: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Interaction<'a> {
+    pub id: Id,
+    pub application_id: Id,
+    pub token: &'a str,
+    #[serde(rename = "type")]
+    pub kind: u8,
+    pub channel_id: Option<Id>,
+    pub guild_id: Option<Id>,
+
+    #[serde(borrow)]
+    pub member: Option<Member<'a>>,
+    #[serde(borrow)]
+    pub user: Option<User<'a>>,
+    #[serde(borrow)]
+    pub data: Option<InteractionData<'a>>,
+    #[serde(borrow)]
+    pub message: Option<Message<'a>>,
+    /// How it was deferred, if it was, so the response can go where Discord expects it
+    #[serde(skip)]
+    pub deferred: Cell<Option<u8>>,
+}
+
+impl Interaction<'_> {
+    pub const APPLICATION_COMMAND: u8 = 2;
+    pub const COMPONENT: u8 = 3;
+
+    /// Guild interactions carry the user inside `member`, DMs carry it directly.
+    pub fn author(&self) -> Option<&User<'_>> {
+        self.member
+            .as_ref()
+            .and_then(|m| m.user.as_ref())
+            .or(self.user.as_ref())
+    }
+
+    pub fn custom_id(&self) -> Option<&str> {
+        self.data.as_ref()?.custom_id.as_ref().map(|s| s.as_str())
+    }
+
+    pub fn command_name(&self) -> Option<&str> {
+        self.data.as_ref()?.name.as_ref().map(|s| s.as_str())
+    }
+
+    /// What was picked from a select menu.
+    pub fn selected(&self) -> Option<&str> {
+        self.data.as_ref()?.values.first().map(|s| s.as_str())
+    }
+
+    /// The value of the slash command's option called `name`, if it was given. Users and
+    /// channels come as their ids.
+    pub fn option(&self, name: &str) -> Option<&str> {
+        self.option_value(name)?.as_str()
+    }
+
+    pub fn integer_option(&self, name: &str) -> Option<i64> {
+        self.option_value(name)?.as_i64()
+    }
+
+    fn option_value(&self, name: &str) -> Option<&serde_json::Value> {
+        self.data
+            .as_ref()?
+            .options
+            .iter()
+            .find(|o| o.name.as_str() == name)?
+            .value
+            .as_ref()
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct InteractionData<'a> {
+    #[serde(borrow)]
+    pub custom_id: Option<StrCow<'a>>,
+    /// The slash command's, for those
+    #[serde(borrow)]
+    pub name: Option<StrCow<'a>>,
+    #[serde(borrow, default)]
+    pub options: Vec<InteractionOption<'a>>,
+    /// What was picked, for select menus
+    #[serde(borrow, default)]
+    pub values: Vec<StrCow<'a>>,
+}
+
+/// An option given to a slash command.
+#[derive(Deserialize, Debug)]
+pub struct InteractionOption<'a> {
+    #[serde(borrow)]
+    pub name: StrCow<'a>,
+    /// A string, or a number for numbers
+    pub value: Option<serde_json::Value>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct InteractionResponse<'a> {
+    #[serde(rename = "type")]
+    pub(super) kind: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) data: Option<InteractionResponseData<'a>>,
+}
+
+#[derive(Serialize, Debug)]
+pub(super) struct InteractionResponseData<'a> {
+    content: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    components: Option<&'a [ActionRow]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flags: Option<u32>,
+}
+
+impl<'a> InteractionResponse<'a> {
+    pub(super) const CHANNEL_MESSAGE: u8 = 4;
+    pub(super) const DEFERRED_CHANNEL_MESSAGE: u8 = 5;
+    pub(super) const DEFERRED_UPDATE_MESSAGE: u8 = 6;
+    pub(super) const UPDATE_MESSAGE: u8 = 7;
+    const EPHEMERAL: u32 = 1 << 6;
+
+    pub fn message(content: &'a str) -> Self {
+        InteractionResponse {
+            kind: Self::CHANNEL_MESSAGE,
+            data: Some(InteractionResponseData {
+                content,
+                components: None,
+                flags: None,
+            }),
+        }
+    }
+
+    pub fn ephemeral(content: &'a str) -> Self {
+        InteractionResponse {
+            kind: Self::CHANNEL_MESSAGE,
+            data: Some(InteractionResponseData {
+                content,
+                components: None,
+                flags: Some(Self::EPHEMERAL),
+            }),
+        }
+    }
+
+    pub(super) fn is_ephemeral(&self) -> bool {
+        self.data
+            .as_ref()
+            .and_then(|d| d.flags)
+            .is_some_and(|f| f & Self::EPHEMERAL != 0)
+    }
+
+    /// Holds off on answering, which gives the bot 15 minutes instead of 3 seconds. Commands get a
+    /// "thinking…" message, components just keep their message as it is.
+    pub(super) fn deferred(kind: u8) -> Self {
+        InteractionResponse { kind, data: None }
+    }
+
+    /// Replaces the message the component was attached to.
+    pub fn update(content: &'a str, components: &'a [ActionRow]) -> Self {
+        InteractionResponse {
+            kind: Self::UPDATE_MESSAGE,
+            data: Some(InteractionResponseData {
+                content,
+                components: Some(components),
+                flags: None,
+            }),
+        }
+    }
 }
 
 fn deserialize_datetime_into_millis<'de, D>(deserializer: D) -> Result<i64, D::Error>
@@ -0,0 +1,14 @@
+use futures::channel::mpsc::UnboundedSender;
+
+use crate::store::Scope;
+
+/// A chat message from a bridged platform, handed to the Discord handler.
+/// Anything sent on `reply` is posted back to where the message came from.
+pub struct BridgeMessage {
+    pub scope: Scope,
+    pub author: String,
+    pub text: String,
+    /// Whether it can be learned from, which it can't when its room or author is blacklisted
+    pub learn: bool,
+    pub reply: UnboundedSender<String>,
+}
@@ -0,0 +1,87 @@
+use crate::bot::types::{Id, Message};
+use crate::store::Scope;
+use std::collections::{HashMap, VecDeque};
+
+pub struct CachedMessage {
+    pub channel: Id,
+    pub guild: Option<Id>,
+    pub author: Id,
+    pub timestamp: i64,
+    pub content: String,
+    /// What it was learned as, if it was
+    pub learned: Option<Learned>,
+}
+
+/// The words a message was learned as and the models they went into, so it can be unlearned.
+pub struct Learned {
+    pub scopes: Vec<Scope>,
+    pub words: Vec<String>,
+}
+
+pub struct MessageCache {
+    capacity: usize,
+    order: VecDeque<Id>,
+    messages: HashMap<Id, CachedMessage>,
+}
+
+impl MessageCache {
+    pub fn new(capacity: usize) -> Self {
+        MessageCache {
+            capacity,
+            order: VecDeque::with_capacity(capacity),
+            messages: HashMap::with_capacity(capacity),
+        }
+    }
+
+    pub fn insert(&mut self, message: &Message<'_>) {
+        if self.order.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.messages.remove(&oldest);
+            }
+        }
+        self.order.push_back(message.id);
+        self.messages.insert(
+            message.id,
+            CachedMessage {
+                channel: message.channel_id,
+                guild: message.guild_id,
+                author: message.author.id,
+                timestamp: message.timestamp,
+                content: String::from(message.content.as_str()),
+                learned: None,
+            },
+        );
+    }
+
+    pub fn get(&self, id: Id) -> Option<&CachedMessage> {
+        self.messages.get(&id)
+    }
+
+    /// How many of the cached messages were sent in `channel` since `since`.
+    pub fn recent(&self, channel: Id, since: i64) -> usize {
+        self.messages
+            .values()
+            .filter(|m| m.channel == channel && m.timestamp >= since)
+            .count()
+    }
+
+    /// Every cached message `author` sent in `guild`.
+    pub fn by_author(&self, author: Id, guild: Option<Id>) -> Vec<Id> {
+        self.messages
+            .iter()
+            .filter(|(_, m)| m.author == author && m.guild == guild)
+            .map(|(&id, _)| id)
+            .collect()
+    }
+
+    pub fn set_learned(&mut self, id: Id, learned: Learned) {
+        if let Some(message) = self.messages.get_mut(&id) {
+            message.learned = Some(learned);
+        }
+    }
+
+    /// What `id` was learned as, which it won't be anymore once it's unlearned.
+    pub fn take_learned(&mut self, id: Id) -> Option<Learned> {
+        self.messages.get_mut(&id)?.learned.take()
+    }
+}
@@ -0,0 +1,55 @@
+use serde::{Deserialize, Serialize};
+
+use crate::bot::types::Id;
+
+/// Sentences admins pinned so they never fade out of generation.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Pinned {
+    pub guild: Option<Id>,
+    pub channel: Id,
+    pub text: String,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Canon {
+    pinned: Vec<Pinned>,
+}
+
+impl Canon {
+    /// Returns `false` if the sentence was already canon there.
+    pub fn pin(&mut self, guild: Option<Id>, channel: Id, text: String) -> bool {
+        if self
+            .pinned
+            .iter()
+            .any(|p| p.guild == guild && p.text == text)
+        {
+            return false;
+        }
+        self.pinned.push(Pinned {
+            guild,
+            channel,
+            text,
+        });
+        true
+    }
+
+    /// Unpins the `index`th (from 1) sentence of `guild`.
+    pub fn unpin(&mut self, guild: Option<Id>, index: usize) -> Option<Pinned> {
+        let i = self
+            .pinned
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| p.guild == guild)
+            .nth(index.checked_sub(1)?)?
+            .0;
+        Some(self.pinned.remove(i))
+    }
+
+    pub fn guild(&self, guild: Option<Id>) -> impl Iterator<Item = &Pinned> {
+        self.pinned.iter().filter(move |p| p.guild == guild)
+    }
+
+    pub fn all(&self) -> &[Pinned] {
+        &self.pinned
+    }
+}
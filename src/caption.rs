@@ -0,0 +1,99 @@
+//! Messages that are only stickers or pictures, learned as tags like `` `sticker:wave` `` and
+//! `` `alt:a_cat_asleep` `` so they say something too, and stickers sent as themselves when
+//! they're generated.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::bot::types::{Id, Message};
+
+const STICKER: &str = "`sticker:";
+const ALT_TEXT: &str = "`alt:";
+
+/// What gets learned besides the text.
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+pub struct CaptionConfig {
+    #[serde(default)]
+    pub stickers: bool,
+    /// Attachments' descriptions, which people write for screen readers
+    #[serde(default)]
+    pub alt_text: bool,
+}
+
+/// The tags for `message`'s stickers and attachments that `cfg` learns.
+pub fn tags(message: &Message<'_>, cfg: &CaptionConfig) -> Vec<String> {
+    let mut tags = Vec::new();
+    if cfg.stickers {
+        tags.extend(
+            message
+                .sticker_items
+                .iter()
+                .map(|s| tag(STICKER, s.name.as_str())),
+        );
+    }
+    if cfg.alt_text {
+        tags.extend(
+            message
+                .attachments
+                .iter()
+                .filter_map(|a| a.description.as_ref())
+                .map(|d| tag(ALT_TEXT, d.as_str())),
+        );
+    }
+    tags
+}
+
+fn tag(kind: &str, text: &str) -> String {
+    let text: Vec<_> = text.split_whitespace().collect();
+    format!("{}{}`", kind, text.join("_").replace('`', "'"))
+}
+
+/// Whether `word` is one of the tags, which mean as much on their own as a sentence does.
+pub fn is_tag(word: &str) -> bool {
+    word.starts_with(STICKER) || word.starts_with(ALT_TEXT)
+}
+
+/// `text` without its sticker tags, and the names of the stickers they were.
+pub fn take_stickers(text: &str) -> (String, Vec<String>) {
+    let mut names = Vec::new();
+    let rest: Vec<_> = text
+        .split(' ')
+        .filter(
+            |word| match word.strip_prefix(STICKER).and_then(|w| w.strip_suffix('`')) {
+                Some(name) => {
+                    names.push(name.replace('_', " "));
+                    false
+                }
+                None => true,
+            },
+        )
+        .collect();
+    (rest.join(" "), names)
+}
+
+/// The stickers of every guild that's been asked about, by lowercase name. They're fetched again
+/// once they're an hour old, for ones added since.
+#[derive(Default)]
+pub struct GuildStickers {
+    guilds: HashMap<Id, (Instant, HashMap<String, Id>)>,
+}
+
+impl GuildStickers {
+    const MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+    pub fn fresh(&self, guild: Id) -> Option<&HashMap<String, Id>> {
+        match self.guilds.get(&guild) {
+            Some((fetched, stickers)) if fetched.elapsed() < Self::MAX_AGE => Some(stickers),
+            _ => None,
+        }
+    }
+
+    pub fn insert(&mut self, guild: Id, stickers: Vec<(Id, String)>) {
+        let stickers = stickers
+            .into_iter()
+            .map(|(id, name)| (name.to_lowercase(), id))
+            .collect();
+        self.guilds.insert(guild, (Instant::now(), stickers));
+    }
+}
@@ -0,0 +1,252 @@
+//! Content-addressed storage for snapshots and backups. A file is cut into chunks where a rolling
+//! hash of its bytes says to, so an edit only changes the chunks around it and everything else
+//! still cuts the same way. Each chunk is kept once, under its hash, however many snapshots and
+//! backups have it, and those are just manifests listing their chunks in order.
+
+use anyhow::{bail, Context, Result};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
+
+pub const DIR: &str = "chunks";
+pub const BACKUPS_DIR: &str = "backups";
+/// Starts every manifest, so they can be told apart from whole files
+const MAGIC: &[u8] = b"taco-chunks1\n";
+const MIN_CHUNK: usize = 16 * 1024;
+const MAX_CHUNK: usize = 256 * 1024;
+/// Cuts after about 64 KiB past `MIN_CHUNK` on average
+const MASK: u64 = (1 << 16) - 1;
+/// Chunks used more recently than this are never collected, since whatever's using them may not
+/// have written its manifest yet
+const GRACE: Duration = Duration::from_secs(60 * 60);
+
+/// A random number for each byte, for the rolling hash. Fixed, so files cut the same way every
+/// time.
+static GEAR: [u64; 256] = gear();
+
+const fn gear() -> [u64; 256] {
+    // splitmix64
+    let mut table = [0; 256];
+    let mut state: u64 = 0x7461_636f_5f62_6f74;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+/// Set when a manifest is dropped, so its chunks may not be needed any more
+static DROPPED: AtomicBool = AtomicBool::new(false);
+
+/// Where the first chunk of `bytes` ends.
+fn cut(bytes: &[u8]) -> usize {
+    if bytes.len() <= MIN_CHUNK {
+        return bytes.len();
+    }
+    let mut hash: u64 = 0;
+    for (i, &byte) in bytes.iter().enumerate().take(MAX_CHUNK).skip(MIN_CHUNK) {
+        hash = (hash << 1).wrapping_add(GEAR[byte as usize]);
+        if hash & MASK == 0 {
+            return i + 1;
+        }
+    }
+    bytes.len().min(MAX_CHUNK)
+}
+
+fn chunk_path(hash: &str) -> PathBuf {
+    Path::new(DIR).join(&hash[..2]).join(hash)
+}
+
+/// Stores whatever chunks of `bytes` aren't stored already. Returns the manifest to keep in
+/// place of them, and how many bytes of new chunks were written.
+pub fn store(mut bytes: &[u8]) -> Result<(Vec<u8>, u64)> {
+    let mut manifest = MAGIC.to_vec();
+    let mut written = 0;
+    while !bytes.is_empty() {
+        let (chunk, rest) = bytes.split_at(cut(bytes));
+        let hash = blake3::hash(chunk).to_hex();
+        let path = chunk_path(&hash);
+        match File::options().append(true).open(&path) {
+            // so it isn't collected before the manifest that needs it is written
+            Ok(file) => file.set_modified(SystemTime::now())?,
+            Err(_) => {
+                fs::create_dir_all(path.parent().unwrap())?;
+                let temp = path.with_extension("tmp");
+                let mut file = File::create(&temp)?;
+                file.write_all(chunk)?;
+                file.sync_all()?;
+                fs::rename(&temp, &path)?;
+                written += chunk.len() as u64;
+            }
+        }
+        manifest.extend_from_slice(hash.as_bytes());
+        manifest.push(b'\n');
+        bytes = rest;
+    }
+    Ok((manifest, written))
+}
+
+/// The chunks `manifest` lists, or `None` if it's a whole file rather than a manifest.
+fn hashes(manifest: &[u8]) -> Option<impl Iterator<Item = &str>> {
+    let list = std::str::from_utf8(manifest.strip_prefix(MAGIC)?).ok()?;
+    Some(list.lines())
+}
+
+/// What's in the file at `path`, put back together if it's a manifest.
+pub fn read(path: &Path) -> Result<Vec<u8>> {
+    let file = fs::read(path).with_context(|| format!("couldn't read {}", path.display()))?;
+    if !file.starts_with(MAGIC) {
+        return Ok(file);
+    }
+    let hashes =
+        hashes(&file).with_context(|| format!("{} isn't a valid manifest", path.display()))?;
+    let mut bytes = Vec::new();
+    for hash in hashes {
+        if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+            bail!("{} isn't a valid manifest", path.display());
+        }
+        let chunk = fs::read(chunk_path(hash))
+            .with_context(|| format!("{} is missing chunk {}", path.display(), hash))?;
+        if blake3::hash(&chunk).to_hex().as_str() != hash {
+            bail!("chunk {} of {} is corrupted", hash, path.display());
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+/// Notes that a manifest was dropped, for `collect_if_dropped`.
+pub fn dropped() {
+    DROPPED.store(true, Ordering::Relaxed);
+}
+
+/// `collect` if a manifest was dropped since the last time.
+pub fn collect_if_dropped() -> Result<()> {
+    if DROPPED.swap(false, Ordering::Relaxed) {
+        collect()?;
+    }
+    Ok(())
+}
+
+/// Deletes every chunk no manifest under the working directory lists any more. Returns how many
+/// and how many bytes.
+pub fn collect() -> Result<(usize, u64)> {
+    let mut used = HashSet::new();
+    find_used(Path::new("."), &mut used)?;
+    let (mut count, mut size) = (0, 0);
+    for dir in fs::read_dir(DIR).into_iter().flatten() {
+        for chunk in fs::read_dir(dir?.path())? {
+            let chunk = chunk?;
+            let metadata = chunk.metadata()?;
+            let name = chunk.file_name();
+            let recent = metadata
+                .modified()?
+                .elapsed()
+                .map_or(true, |age| age < GRACE);
+            if recent || name.to_str().is_some_and(|n| used.contains(n)) {
+                continue;
+            }
+            fs::remove_file(chunk.path())?;
+            count += 1;
+            size += metadata.len();
+        }
+    }
+    Ok((count, size))
+}
+
+/// Adds the chunks of every manifest in `dir` and below to `used`.
+fn find_used(dir: &Path, used: &mut HashSet<String>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() && path != Path::new(".").join(DIR) {
+            find_used(&path, used)?;
+            continue;
+        }
+        if !file_type.is_file() {
+            continue;
+        }
+        let mut start = [0; MAGIC.len()];
+        let is_manifest = File::open(&path)
+            .and_then(|mut f| f.read_exact(&mut start))
+            .is_ok_and(|()| start == MAGIC);
+        if is_manifest {
+            let manifest = fs::read(&path)?;
+            used.extend(hashes(&manifest).into_iter().flatten().map(String::from));
+        }
+    }
+    Ok(())
+}
+
+/// `taco_bot backup NAME`: keeps what every `.dat` file has now in `backups/NAME`, sharing chunks
+/// with snapshots and earlier backups.
+pub fn backup(args: &[String]) -> Result<bool> {
+    let name = match args.first() {
+        Some(name) if !name.contains(['/', '\\']) && !name.starts_with('.') => name,
+        _ => bail!("usage: taco_bot backup NAME"),
+    };
+    let dir = Path::new(BACKUPS_DIR).join(name);
+    if dir.exists() {
+        bail!("there's already a backup called {}", name);
+    }
+    let mut files = Vec::new();
+    find_data(Path::new("."), &mut files)?;
+    let (mut size, mut written) = (0, 0);
+    for file in &files {
+        let bytes = fs::read(file)?;
+        let (manifest, new) = store(&bytes)?;
+        let path = dir.join(file.strip_prefix(".").unwrap_or(file));
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, manifest)?;
+        size += bytes.len() as u64;
+        written += new;
+    }
+    println!(
+        "backed up {} files ({:.1} MB) to {}, {:.1} MB of it new",
+        files.len(),
+        size as f64 / 1e6,
+        dir.display(),
+        written as f64 / 1e6
+    );
+    Ok(true)
+}
+
+/// Every `.dat` file in `dir` and below, besides backups and chunks.
+fn find_data(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            let name = entry.file_name();
+            if ![DIR, BACKUPS_DIR].iter().any(|d| name == *d) {
+                find_data(&path, files)?;
+            }
+        } else if path.extension().is_some_and(|e| e == "dat") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// `taco_bot restore FROM TO`: writes the snapshot or backed up file `FROM` to `TO`.
+pub fn restore(args: &[String]) -> Result<bool> {
+    let (from, to) = match args {
+        [from, to] => (Path::new(from), Path::new(to)),
+        _ => bail!("usage: taco_bot restore SNAPSHOT FILE"),
+    };
+    let bytes = read(from)?;
+    let temp = to.with_extension("restoring");
+    fs::write(&temp, &bytes)?;
+    fs::rename(&temp, to)?;
+    println!("restored {} to {}", from.display(), to.display());
+    Ok(true)
+}
@@ -0,0 +1,140 @@
+//! The slash commands the bot has, and keeping what's registered with Discord in line with them.
+//! On startup what's registered is diffed against `defined()`, and only the difference is sent.
+
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+
+use crate::bot::types::Id;
+
+const STRING_OPTION: u8 = 3;
+const INTEGER_OPTION: u8 = 4;
+const USER_OPTION: u8 = 6;
+const CHANNEL_OPTION: u8 = 7;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Command {
+    pub name: String,
+    pub description: String,
+    #[serde(default)]
+    pub options: Vec<CommandOption>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct CommandOption {
+    #[serde(rename = "type")]
+    pub kind: u8,
+    pub name: String,
+    pub description: String,
+    #[serde(default)]
+    pub required: bool,
+}
+
+/// A command as Discord has it, with its id.
+#[derive(Deserialize, Debug)]
+pub struct Registered {
+    pub id: Id,
+    #[serde(flatten)]
+    pub command: Command,
+}
+
+/// Every slash command the bot handles.
+pub fn defined() -> Vec<Command> {
+    fn command(name: &str, description: &str, options: Vec<CommandOption>) -> Command {
+        Command {
+            name: String::from(name),
+            description: String::from(description),
+            options,
+        }
+    }
+    fn option(kind: u8, name: &str, description: &str, required: bool) -> CommandOption {
+        CommandOption {
+            kind,
+            name: String::from(name),
+            description: String::from(description),
+            required,
+        }
+    }
+    let style = option(STRING_OPTION, "style", "A style to talk in", false);
+    let channel = |description| option(CHANNEL_OPTION, "channel", description, true);
+    vec![
+        command("mimic", "Say something like this server would", Vec::new()),
+        command("generate", "Say something, maybe in a style", vec![style]),
+        command(
+            "speak",
+            "Say something like a channel would",
+            vec![channel("The channel to sound like")],
+        ),
+        command(
+            "imitate",
+            "Say something like somebody would",
+            vec![option(USER_OPTION, "user", "Who to sound like", true)],
+        ),
+        command(
+            "train",
+            "Learn a channel's history",
+            vec![
+                channel("The channel to learn"),
+                option(
+                    INTEGER_OPTION,
+                    "limit",
+                    "How many messages, newest first",
+                    false,
+                ),
+            ],
+        ),
+        command("stats", "How much the bot has learned here", Vec::new()),
+    ]
+}
+
+/// What it takes to get from what's registered to what's defined.
+#[derive(Default, Debug)]
+pub struct Plan {
+    pub create: Vec<Command>,
+    pub update: Vec<(Id, Command)>,
+    pub delete: Vec<(Id, String)>,
+}
+
+impl Plan {
+    pub fn new(registered: Vec<Registered>, defined: &[Command]) -> Self {
+        let mut plan = Plan::default();
+        for command in defined {
+            match registered.iter().find(|r| r.command.name == command.name) {
+                Some(r) if r.command == *command => {}
+                Some(r) => plan.update.push((r.id, command.clone())),
+                None => plan.create.push(command.clone()),
+            }
+        }
+        for r in registered {
+            if !defined.iter().any(|c| c.name == r.command.name) {
+                plan.delete.push((r.id, r.command.name));
+            }
+        }
+        plan
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.create.is_empty() && self.update.is_empty() && self.delete.is_empty()
+    }
+}
+
+impl Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "up to date");
+        }
+        let mut parts = Vec::new();
+        if !self.create.is_empty() {
+            let names: Vec<_> = self.create.iter().map(|c| c.name.as_str()).collect();
+            parts.push(format!("create {}", names.join(", ")));
+        }
+        if !self.update.is_empty() {
+            let names: Vec<_> = self.update.iter().map(|(_, c)| c.name.as_str()).collect();
+            parts.push(format!("update {}", names.join(", ")));
+        }
+        if !self.delete.is_empty() {
+            let names: Vec<_> = self.delete.iter().map(|(_, n)| n.as_str()).collect();
+            parts.push(format!("delete {}", names.join(", ")));
+        }
+        write!(f, "{}", parts.join("; "))
+    }
+}
@@ -0,0 +1,52 @@
+//! Where the bot's configuration comes from: `bot.toml`, or `bot.json` if there's no `bot.toml`.
+//! Both turn into the same thing, so every setting is written the same way in either, just in
+//! TOML's syntax instead of JSON's (ids are still quoted).
+//!
+//! The config can be reloaded while the bot runs, with `eg!reload` or by sending it SIGHUP.
+
+use anyhow::{anyhow, Result};
+use serde::de::DeserializeOwned;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock};
+
+pub const TOML_PATH: &str = "bot.toml";
+pub const JSON_PATH: &str = "bot.json";
+
+/// Set by SIGHUP, until the reload it asks for happens
+static RELOAD: LazyLock<Arc<AtomicBool>> = LazyLock::new(Arc::default);
+
+/// Which file the config is read from.
+pub fn path() -> &'static str {
+    match Path::new(TOML_PATH).exists() {
+        true => TOML_PATH,
+        false => JSON_PATH,
+    }
+}
+
+pub fn load<T: DeserializeOwned>() -> Result<T> {
+    let path = path();
+    let text = fs::read_to_string(path).map_err(|e| anyhow!("{}: {}", path, e))?;
+    match path {
+        TOML_PATH => toml::from_str(&text).map_err(|e| anyhow!("{}: {}", path, e)),
+        _ => serde_json::from_str(&text).map_err(|e| anyhow!("{}: {}", path, e)),
+    }
+}
+
+/// Makes SIGHUP ask for the config to be reloaded, see `reload_requested`.
+#[cfg(unix)]
+pub fn reload_on_sighup() {
+    let sighup = signal_hook::consts::SIGHUP;
+    if let Err(e) = signal_hook::flag::register(sighup, Arc::clone(&RELOAD)) {
+        eprintln!("couldn't listen for SIGHUP: {}", e);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn reload_on_sighup() {}
+
+/// Whether SIGHUP has asked for a reload since this was last checked.
+pub fn reload_requested() -> bool {
+    RELOAD.swap(false, Ordering::SeqCst)
+}
@@ -0,0 +1,54 @@
+//! Where generated messages left off, so a "Continue" button can carry on the same walk through
+//! the model instead of starting a new one.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+use crate::bot::types::{ActionRow, Button, ButtonStyle, Id};
+use crate::markov::ChainState;
+use crate::settings::Settings;
+
+pub const CONTINUE_ID: &str = "story:continue";
+/// How many messages can still be continued
+const KEPT: usize = 200;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Continuation {
+    pub guild: Option<Id>,
+    pub style: Option<String>,
+    pub settings: Settings,
+    pub state: ChainState,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Continuations {
+    order: VecDeque<Id>,
+    by_message: HashMap<Id, Continuation>,
+}
+
+impl Continuations {
+    pub fn insert(&mut self, message: Id, continuation: Continuation) {
+        if self.order.len() >= KEPT {
+            if let Some(oldest) = self.order.pop_front() {
+                self.by_message.remove(&oldest);
+            }
+        }
+        self.order.push_back(message);
+        self.by_message.insert(message, continuation);
+    }
+
+    /// Where `message` left off. It can only be continued once.
+    pub fn take(&mut self, message: Id) -> Option<Continuation> {
+        let continuation = self.by_message.remove(&message)?;
+        self.order.retain(|&m| m != message);
+        Some(continuation)
+    }
+}
+
+pub fn button() -> Vec<ActionRow> {
+    vec![ActionRow::new(vec![Button::new(
+        ButtonStyle::Secondary,
+        "Continue",
+        CONTINUE_ID,
+    )])]
+}
@@ -0,0 +1,148 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::bot::types::Id;
+use crate::store::{is_valid_name, Scope};
+
+/// What a guild's own model is called in a mix.
+pub const SERVER: &str = "server";
+
+/// Imported text kept in its own model so it can be mixed into generation, or taken back out,
+/// without retraining anything else.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CorpusInfo {
+    pub enabled: bool,
+    pub weight: f64,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Corpora {
+    corpora: BTreeMap<String, CorpusInfo>,
+}
+
+impl Corpora {
+    pub fn scope(name: &str) -> Scope {
+        Scope::Corpus(String::from(name))
+    }
+
+    /// Registers a corpus if it isn't already, returning its scope to import into.
+    pub fn create(&mut self, name: &str) -> Result<Scope> {
+        if !is_valid_name(name) {
+            bail!("corpus names can only have letters, numbers and `_`");
+        }
+        self.corpora
+            .entry(String::from(name))
+            .or_insert(CorpusInfo {
+                enabled: true,
+                weight: 1.0,
+            });
+        Ok(Self::scope(name))
+    }
+
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> Result<()> {
+        self.get_mut(name)?.enabled = enabled;
+        Ok(())
+    }
+
+    pub fn set_weight(&mut self, name: &str, weight: f64) -> Result<()> {
+        if !(weight.is_finite() && weight >= 0.0) {
+            bail!("weights can't be negative");
+        }
+        self.get_mut(name)?.weight = weight;
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<Scope> {
+        match self.corpora.remove(name) {
+            Some(_) => Ok(Self::scope(name)),
+            None => bail!("there's no corpus called `{}`", name),
+        }
+    }
+
+    pub fn list(&self) -> impl Iterator<Item = (&str, &CorpusInfo)> {
+        self.corpora.iter().map(|(n, c)| (n.as_str(), c))
+    }
+
+    /// The models generation in `scope` draws from, with their weights. A guild's mix replaces
+    /// the corpora's own settings entirely.
+    pub fn blend(&self, scope: &Scope, mix: Option<&Mix>) -> Vec<(Scope, f64)> {
+        // corpora and styles are only ever generated from on their own
+        if matches!(
+            scope,
+            Scope::Corpus(_) | Scope::Style(_) | Scope::GuildStyle(..) | Scope::Events(_)
+        ) {
+            return vec![(scope.clone(), 1.0)];
+        }
+        let blend: Vec<_> = match mix {
+            Some(mix) => mix
+                .iter()
+                .filter_map(|(name, &w)| match name.as_str() {
+                    SERVER => Some((scope.clone(), w)),
+                    n if self.corpora.contains_key(n) => Some((Self::scope(n), w)),
+                    _ => None,
+                })
+                .filter(|(_, w)| *w > 0.0)
+                .collect(),
+            None => std::iter::once((scope.clone(), 1.0))
+                .chain(
+                    self.corpora
+                        .iter()
+                        .filter(|(_, c)| c.enabled && c.weight > 0.0)
+                        .map(|(n, c)| (Self::scope(n), c.weight)),
+                )
+                .collect(),
+        };
+        if blend.is_empty() {
+            vec![(scope.clone(), 1.0)]
+        } else {
+            blend
+        }
+    }
+
+    fn get_mut(&mut self, name: &str) -> Result<&mut CorpusInfo> {
+        match self.corpora.get_mut(name) {
+            Some(c) => Ok(c),
+            None => bail!("there's no corpus called `{}`", name),
+        }
+    }
+}
+
+pub type Mix = BTreeMap<String, f64>;
+
+pub fn validate_mix(mix: &Mix, corpora: &Corpora) -> Result<()> {
+    for (name, &weight) in mix {
+        if name != SERVER && !corpora.corpora.contains_key(name) {
+            bail!("there's no corpus called `{}`", name);
+        }
+        if !(weight.is_finite() && weight >= 0.0) {
+            bail!("weights can't be negative");
+        }
+    }
+    if !mix.values().any(|&w| w > 0.0) {
+        bail!("at least one weight has to be more than 0");
+    }
+    Ok(())
+}
+
+/// Per-guild blend weights, keyed by corpus name or `server`.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Mixes {
+    guilds: HashMap<Id, Mix>,
+}
+
+impl Mixes {
+    pub fn get(&self, guild: Id) -> Option<&Mix> {
+        self.guilds.get(&guild)
+    }
+
+    pub fn set(&mut self, guild: Id, mix: Mix, corpora: &Corpora) -> Result<()> {
+        validate_mix(&mix, corpora)?;
+        self.guilds.insert(guild, mix);
+        Ok(())
+    }
+
+    pub fn reset(&mut self, guild: Id) {
+        self.guilds.remove(&guild);
+    }
+}
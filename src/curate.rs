@@ -0,0 +1,47 @@
+use std::collections::HashSet;
+
+/// Drops anything that identifies a Discord user: raw mentions and the
+/// `` `name#1234` `` tokens that mentions are learned as.
+pub fn scrub(text: &str) -> String {
+    text.split_whitespace()
+        .filter(|w| {
+            let w = w.trim_matches('`');
+            !(w.starts_with("<@") || w.starts_with("<#") || is_user_tag(w))
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn is_postable(text: &str, min_words: usize, max_chars: usize) -> bool {
+    let words = text.split_whitespace().count();
+    words >= min_words
+        && text.chars().count() <= max_chars
+        && !text.contains("http://")
+        && !text.contains("https://")
+}
+
+/// Jaccard similarity of the word bigrams in `a` and `b`, from 0 (nothing shared) to 1 (the same).
+/// Texts too short for bigrams are compared word by word.
+pub fn similarity(a: &str, b: &str) -> f64 {
+    fn grams(text: &str) -> HashSet<Vec<String>> {
+        let words: Vec<_> = text.split_whitespace().map(str::to_lowercase).collect();
+        let n = if words.len() < 2 { 1 } else { 2 };
+        words.windows(n).map(<[String]>::to_vec).collect()
+    }
+    let (a, b) = (grams(a), grams(b));
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 1.0;
+    }
+    a.intersection(&b).count() as f64 / union as f64
+}
+
+fn is_user_tag(word: &str) -> bool {
+    match word.rfind('#') {
+        Some(i) => {
+            let discriminator = &word[i + 1..];
+            i > 0 && discriminator.len() == 4 && discriminator.bytes().all(|b| b.is_ascii_digit())
+        }
+        None => false,
+    }
+}
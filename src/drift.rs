@@ -0,0 +1,144 @@
+//! "Personality drift": snapshots of what the model likes to say, taken every month, and reports on
+//! how it's changed since the last one.
+
+use chrono::{Datelike, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::markov::Markov;
+
+/// Everyday sentences whose likelihood shows which way the model is drifting
+pub const DEFAULT_PROBES: [&str; 8] = [
+    "i think that is a good idea",
+    "what are you doing today",
+    "that is so funny",
+    "i don't know what to say",
+    "this is the best thing ever",
+    "can someone help me with this",
+    "good morning everyone",
+    "why would you do that",
+];
+/// What a step the model has never seen counts as
+const UNSEEN: f64 = 1e-6;
+/// Kept per snapshot, and what counts as dominant
+const KEPT_WORDS: usize = 200;
+const DOMINANT: usize = 15;
+/// A year of monthly snapshots
+const KEPT_SNAPSHOTS: usize = 12;
+const SHOWN_WORDS: usize = 8;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Snapshot {
+    pub taken: i64,
+    /// The most common words, most common first, with their share of all words
+    words: Vec<(String, f64)>,
+    /// Each probe's average log-probability per step
+    probes: Vec<(String, f64)>,
+}
+
+impl Snapshot {
+    pub fn take(model: &Markov, probes: &[Vec<String>], now: i64) -> Self {
+        let counts = model.word_counts();
+        let total = counts.values().sum::<usize>().max(1) as f64;
+        let mut words: Vec<_> = counts
+            .into_iter()
+            .map(|(w, c)| (w, c as f64 / total))
+            .collect();
+        words.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        words.truncate(KEPT_WORDS);
+        let probes = probes
+            .iter()
+            .map(|p| {
+                (
+                    p.join(" "),
+                    model.log_probability(p.iter().cloned(), UNSEEN),
+                )
+            })
+            .collect();
+        Snapshot {
+            taken: now,
+            words,
+            probes,
+        }
+    }
+
+    fn top(&self, n: usize) -> impl Iterator<Item = &str> {
+        self.words.iter().take(n).map(|(w, _)| w.as_str())
+    }
+
+    /// How the model went from `self` to `now`.
+    pub fn report(&self, now: &Snapshot) -> String {
+        let since = Utc.timestamp_millis(self.taken).format("%B %-d, %Y");
+        let mut text = format!("🧭 **How I've changed since {}**", since);
+
+        let before: HashSet<_> = self.top(KEPT_WORDS).collect();
+        let after: HashSet<_> = now.top(KEPT_WORDS).collect();
+        let new: Vec<_> = now
+            .top(DOMINANT)
+            .filter(|w| !before.contains(w))
+            .take(SHOWN_WORDS)
+            .collect();
+        let vanished: Vec<_> = self
+            .top(DOMINANT)
+            .filter(|w| !after.contains(w))
+            .take(SHOWN_WORDS)
+            .collect();
+        let list = |words: Vec<&str>| match words.is_empty() {
+            true => String::from("none"),
+            false => words.join(", "),
+        };
+        text += &format!("\nNew favorite words: {}", list(new));
+        text += &format!("\nWords I've stopped using: {}", list(vanished));
+
+        let mut deltas = Vec::new();
+        for (probe, p) in &now.probes {
+            if let Some((_, old)) = self.probes.iter().find(|(o, _)| o == probe) {
+                deltas.push((probe, p - old));
+            }
+        }
+        if !deltas.is_empty() {
+            let mean = deltas.iter().map(|(_, d)| d).sum::<f64>() / deltas.len() as f64;
+            text += &format!(
+                "\nThe probe sentences are {:.2}× as likely per word as they were",
+                mean.exp()
+            );
+            for (probe, delta) in deltas {
+                let arrow = match delta {
+                    d if d > 0.05 => "📈",
+                    d if d < -0.05 => "📉",
+                    _ => "➖",
+                };
+                text += &format!("\n{} \"{}\" ×{:.2}", arrow, probe, delta.exp());
+            }
+        }
+        text
+    }
+}
+
+/// Midnight on the first of next month, when the next snapshot is due.
+pub fn next_month() -> i64 {
+    let today = Utc::now().date();
+    let (year, month) = match today.month() {
+        12 => (today.year() + 1, 1),
+        m => (today.year(), m + 1),
+    };
+    Utc.ymd(year, month, 1).and_hms(0, 0, 0).timestamp_millis()
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Snapshots {
+    history: Vec<Snapshot>,
+}
+
+impl Snapshots {
+    pub fn latest(&self) -> Option<&Snapshot> {
+        self.history.last()
+    }
+
+    pub fn push(&mut self, snapshot: Snapshot) {
+        self.history.push(snapshot);
+        if self.history.len() > KEPT_SNAPSHOTS {
+            self.history.remove(0);
+        }
+    }
+}
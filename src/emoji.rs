@@ -0,0 +1,174 @@
+use crate::bot::types::Id;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry as HashEntry;
+use std::collections::{HashMap, HashSet};
+
+type Counts = HashMap<String, usize>;
+/// Counts by guild, word and emoji
+type Tally = HashMap<(Id, String, String), usize>;
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct EmojiAssociations {
+    guilds: HashMap<Id, HashMap<String, Counts>>,
+}
+
+impl EmojiAssociations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, guild: Id, content: &str, emoji: &str) {
+        let words = self.guilds.entry(guild).or_default();
+        for word in association_words(content) {
+            *words
+                .entry(word)
+                .or_default()
+                .entry(String::from(emoji))
+                .or_default() += 1;
+        }
+    }
+
+    pub fn remove(&mut self, guild: Id, content: &str, emoji: &str) {
+        let words = match self.guilds.get_mut(&guild) {
+            Some(w) => w,
+            None => return,
+        };
+        for word in association_words(content) {
+            if let Some(counts) = words.get_mut(&word) {
+                if let Some(count) = counts.get_mut(emoji) {
+                    *count -= 1;
+                    if *count == 0 {
+                        counts.remove(emoji);
+                    }
+                }
+                if counts.is_empty() {
+                    words.remove(&word);
+                }
+            }
+        }
+    }
+
+    /// Takes `count` off how often `emoji` went with `word`.
+    fn subtract(&mut self, guild: Id, word: &str, emoji: &str, count: usize) {
+        let words = match self.guilds.get_mut(&guild) {
+            Some(w) => w,
+            None => return,
+        };
+        if let Some(counts) = words.get_mut(word) {
+            if let Some(c) = counts.get_mut(emoji) {
+                *c = c.saturating_sub(count);
+                if *c == 0 {
+                    counts.remove(emoji);
+                }
+            }
+            if counts.is_empty() {
+                words.remove(word);
+            }
+        }
+    }
+
+    /// Most associated emoji for `word`, or across every word if `None`, highest count first.
+    pub fn top(&self, guild: Id, word: Option<&str>, count: usize) -> Vec<(&str, usize)> {
+        let words = match self.guilds.get(&guild) {
+            Some(w) => w,
+            None => return Vec::new(),
+        };
+        let mut totals: HashMap<&str, usize> = HashMap::new();
+        let counts: Box<dyn Iterator<Item = &Counts>> = match word {
+            Some(w) => Box::new(words.get(&w.to_lowercase()).into_iter()),
+            None => Box::new(words.values()),
+        };
+        for (emoji, c) in counts.flatten() {
+            *totals.entry(emoji).or_default() += c;
+        }
+        let mut totals: Vec<_> = totals.into_iter().collect();
+        totals.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        totals.truncate(count);
+        totals
+    }
+
+    pub fn pick(&self, guild: Id, content: &str, rng: &mut impl Rng) -> Option<&str> {
+        let words = self.guilds.get(&guild)?;
+        let mut scores: HashMap<&str, usize> = HashMap::new();
+        for counts in association_words(content).filter_map(|w| words.get(&w)) {
+            for (emoji, count) in counts {
+                *scores.entry(emoji).or_default() += count;
+            }
+        }
+        let scores: Vec<_> = scores.into_iter().collect();
+        let dist = WeightedIndex::new(scores.iter().map(|(_, s)| *s)).ok()?;
+        Some(scores[dist.sample(rng)].0)
+    }
+}
+
+/// Who each association came from, a reaction's user and the author of the message it was on, so
+/// all of it can be taken back out when either of them opts out.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct EmojiLedger {
+    /// For each reacting user and author
+    pairs: HashMap<(Id, Id), Tally>,
+}
+
+impl EmojiLedger {
+    /// Notes `reactor` adding (or taking back) `emoji` on `author`'s message saying `content`.
+    pub fn record(
+        &mut self,
+        (reactor, author): (Id, Id),
+        guild: Id,
+        content: &str,
+        emoji: &str,
+        added: bool,
+    ) {
+        let counts = self.pairs.entry((reactor, author)).or_default();
+        for word in association_words(content) {
+            let key = (guild, word, String::from(emoji));
+            if added {
+                *counts.entry(key).or_default() += 1;
+            } else if let HashEntry::Occupied(mut count) = counts.entry(key) {
+                *count.get_mut() -= 1;
+                if *count.get() == 0 {
+                    count.remove();
+                }
+            }
+        }
+        if counts.is_empty() {
+            self.pairs.remove(&(reactor, author));
+        }
+    }
+
+    /// Takes every association `user` had a hand in out of `emoji`, and forgets them.
+    pub fn purge(&mut self, user: Id, emoji: &mut EmojiAssociations) {
+        self.pairs.retain(|&(reactor, author), counts| {
+            if reactor != user && author != user {
+                return true;
+            }
+            for ((guild, word, e), count) in counts.drain() {
+                emoji.subtract(guild, &word, &e, count);
+            }
+            false
+        });
+    }
+}
+
+/// Turns a reaction string back into something that renders in a message.
+pub fn display(emoji: &str) -> String {
+    if emoji.contains(':') {
+        format!("<:{}>", emoji)
+    } else {
+        String::from(emoji)
+    }
+}
+
+fn association_words(content: &str) -> impl Iterator<Item = String> {
+    content
+        .split_whitespace()
+        .map(|w| {
+            w.trim_matches(|c: char| !c.is_alphanumeric())
+                .to_lowercase()
+        })
+        .filter(|w| !w.is_empty())
+        .collect::<HashSet<_>>()
+        .into_iter()
+}
@@ -0,0 +1,91 @@
+//! `eg!dataexport`: everything the bot keeps about one server, as a tar archive its owner can read
+//! or hand to their own instance of the bot.
+
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::bot::types::Id;
+use crate::corpus::Mix;
+use crate::quota::Limits;
+use crate::settings::GuildSettings;
+use crate::transform::Transform;
+
+/// `settings.json`: how the server set the bot up.
+#[derive(Serialize, Debug)]
+pub struct Config<'a> {
+    pub mix: Option<&'a Mix>,
+    pub transforms: &'a [Transform],
+    pub settings: GuildSettings,
+    pub learning_blocklist: Vec<&'a str>,
+    /// The style each channel is tagged with
+    pub styles: HashMap<Id, &'a str>,
+    pub limits: Limits,
+}
+
+/// `audit.json`: what `eg!audit` would flag in one of the exported models, redacted.
+#[derive(Serialize, Debug)]
+pub struct Audit {
+    pub model: String,
+    pub findings: Vec<(String, &'static str)>,
+}
+
+/// `stats.json`
+#[derive(Serialize, Debug)]
+pub struct Stats {
+    pub guild: Id,
+    /// Entries in each exported model, by path
+    pub entries: HashMap<String, usize>,
+    /// Each exported model's fingerprint, by path
+    pub fingerprints: HashMap<String, String>,
+    /// When the bot last learned something here, in milliseconds since the epoch
+    pub last_active: Option<i64>,
+    pub exported_at: i64,
+}
+
+/// A tar archive being written, in the ustar format every `tar` can read.
+#[derive(Default)]
+pub struct Archive {
+    data: Vec<u8>,
+}
+
+const BLOCK: usize = 512;
+
+impl Archive {
+    /// Adds a file, with a `path` of at most 100 bytes.
+    pub fn add(&mut self, path: &str, contents: &[u8], mtime: i64) {
+        let mut header = [0; BLOCK];
+        let field = |header: &mut [u8; BLOCK], at: usize, value: &[u8]| {
+            header[at..at + value.len()].copy_from_slice(value);
+        };
+        field(&mut header, 0, &path.as_bytes()[..path.len().min(100)]);
+        field(&mut header, 100, b"0000644\0");
+        field(&mut header, 108, b"0000000\0");
+        field(&mut header, 116, b"0000000\0");
+        field(
+            &mut header,
+            124,
+            format!("{:011o}\0", contents.len()).as_bytes(),
+        );
+        field(
+            &mut header,
+            136,
+            format!("{:011o}\0", mtime.max(0)).as_bytes(),
+        );
+        field(&mut header, 148, b"        ");
+        header[156] = b'0';
+        field(&mut header, 257, b"ustar\0");
+        field(&mut header, 263, b"00");
+        let checksum: u32 = header.iter().map(|&b| b as u32).sum();
+        field(&mut header, 148, format!("{:06o}\0 ", checksum).as_bytes());
+
+        self.data.extend_from_slice(&header);
+        self.data.extend_from_slice(contents);
+        let padding = (BLOCK - contents.len() % BLOCK) % BLOCK;
+        self.data.resize(self.data.len() + padding, 0);
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        self.data.resize(self.data.len() + 2 * BLOCK, 0);
+        self.data
+    }
+}
@@ -0,0 +1,207 @@
+//! The bot's own blocklist, across every server: words and regexes that keep messages from being
+//! learned and generated text from being sent as it is, and users and channels that are never
+//! learned from. Admins change it with `eg!filter`; each server's `eg!blocklist` is on top of it.
+
+use anyhow::{bail, Result};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt::{self, Display};
+
+use crate::bot::types::Id;
+
+/// How many more times to generate after the filter holds something back
+pub const REROLLS: usize = 3;
+
+/// What happens to generated text the filter blocks.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterAction {
+    /// Generate something else, or say nothing if nothing else gets through
+    #[default]
+    Regenerate,
+    /// Black out what's blocked and send the rest
+    Censor,
+}
+
+/// What the filter has in it, by kind.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Kind {
+    Word,
+    Regex,
+    User,
+    Channel,
+}
+
+impl Kind {
+    pub fn parse(kind: &str) -> Result<Self> {
+        Ok(match kind {
+            "word" => Kind::Word,
+            "regex" => Kind::Regex,
+            "user" => Kind::User,
+            "channel" => Kind::Channel,
+            k => bail!("expected `word`, `regex`, `user` or `channel`, got `{}`", k),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Filter {
+    /// Lowercase, matched as whole words
+    words: BTreeSet<String>,
+    /// Matched case-insensitively anywhere
+    patterns: BTreeSet<String>,
+    users: BTreeSet<Id>,
+    channels: BTreeSet<Id>,
+    /// `patterns` compiled, see `compile`
+    #[serde(skip)]
+    compiled: Vec<Regex>,
+}
+
+impl Filter {
+    /// Compiles the patterns, once it's loaded. Ones that no longer compile are left out.
+    pub fn compile(&mut self) {
+        self.compiled = self
+            .patterns
+            .iter()
+            .filter_map(|p| match regex(p) {
+                Ok(r) => Some(r),
+                Err(e) => {
+                    eprintln!("filter: {}", e);
+                    None
+                }
+            })
+            .collect();
+    }
+
+    /// Whether there's any text it blocks, rather than only users and channels.
+    pub fn blocks_text(&self) -> bool {
+        !self.words.is_empty() || !self.compiled.is_empty()
+    }
+
+    /// Whether `user`'s messages are never learned.
+    pub fn blocks_user(&self, user: Id) -> bool {
+        self.users.contains(&user)
+    }
+
+    pub fn blocks_channel(&self, channel: Id) -> bool {
+        self.channels.contains(&channel)
+    }
+
+    /// Whether `text` has anything blocked in it.
+    pub fn blocks(&self, text: &str) -> bool {
+        words(text)
+            .iter()
+            .any(|(_, w)| self.words.contains(&w.to_lowercase()))
+            || self.compiled.iter().any(|r| r.is_match(text))
+    }
+
+    /// `text` with everything blocked in it blacked out.
+    pub fn censor(&self, text: &str) -> String {
+        let mut blocked = vec![false; text.len()];
+        for (at, word) in words(text) {
+            if self.words.contains(&word.to_lowercase()) {
+                blocked[at..at + word.len()].fill(true);
+            }
+        }
+        for r in &self.compiled {
+            for m in r.find_iter(text) {
+                blocked[m.range()].fill(true);
+            }
+        }
+        text.char_indices()
+            .map(|(at, c)| if blocked[at] { '█' } else { c })
+            .collect()
+    }
+
+    /// Returns whether `value` wasn't already in the filter.
+    pub fn add(&mut self, kind: Kind, value: &str) -> Result<bool> {
+        let added = match kind {
+            Kind::Word => self.words.insert(value.to_lowercase()),
+            Kind::Regex => {
+                let compiled = regex(value)?;
+                let added = self.patterns.insert(String::from(value));
+                if added {
+                    self.compiled.push(compiled);
+                }
+                added
+            }
+            Kind::User => self.users.insert(parse_id(value)?),
+            Kind::Channel => self.channels.insert(parse_id(value)?),
+        };
+        Ok(added)
+    }
+
+    /// Returns whether `value` was in the filter.
+    pub fn remove(&mut self, kind: Kind, value: &str) -> Result<bool> {
+        let removed = match kind {
+            Kind::Word => self.words.remove(&value.to_lowercase()),
+            Kind::Regex => self.patterns.remove(value),
+            Kind::User => self.users.remove(&parse_id(value)?),
+            Kind::Channel => self.channels.remove(&parse_id(value)?),
+        };
+        if removed && kind == Kind::Regex {
+            self.compile();
+        }
+        Ok(removed)
+    }
+}
+
+impl Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn list<T>(items: &BTreeSet<T>, show: impl Fn(&T) -> String) -> String {
+            match items.is_empty() {
+                true => String::from("none"),
+                false => items.iter().map(show).collect::<Vec<_>>().join(", "),
+            }
+        }
+        writeln!(f, "Words: {}", list(&self.words, |w| format!("`{}`", w)))?;
+        writeln!(
+            f,
+            "Regexes: {}",
+            list(&self.patterns, |p| format!("`{}`", p))
+        )?;
+        writeln!(f, "Users: {}", list(&self.users, |u| format!("<@{}>", u)))?;
+        write!(
+            f,
+            "Channels: {}",
+            list(&self.channels, |c| format!("<#{}>", c))
+        )
+    }
+}
+
+fn regex(pattern: &str) -> Result<Regex> {
+    match RegexBuilder::new(pattern).case_insensitive(true).build() {
+        Ok(r) => Ok(r),
+        Err(e) => bail!("`{}` isn't a regex: {}", pattern, e),
+    }
+}
+
+/// A user or channel, by id or mention.
+fn parse_id(value: &str) -> Result<Id> {
+    let id = value
+        .trim_start_matches(['<', '@', '!', '#'])
+        .trim_end_matches('>');
+    match id.parse() {
+        Ok(id) => Ok(id),
+        Err(_) => bail!("`{}` isn't a user or channel", value),
+    }
+}
+
+/// Each run of letters, digits and apostrophes in `text`, with where it starts.
+fn words(text: &str) -> Vec<(usize, &str)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (at, c) in text.char_indices().chain([(text.len(), ' ')]) {
+        let in_word = c.is_alphanumeric() || c == '\'';
+        match (start, in_word) {
+            (None, true) => start = Some(at),
+            (Some(s), false) => {
+                words.push((s, &text[s..at]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    words
+}
@@ -0,0 +1,149 @@
+//! Golden-output checks for generation: canned corpora in `fixtures/corpora`, a fixed seed, and
+//! the expected output of every sampler in `fixtures/golden`. Run with `cargo test golden` after
+//! touching the model or samplers, or `BLESS=1 cargo test golden` to accept new output.
+
+use anyhow::{Context, Result};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::markov::{Markov, Sampling};
+
+const CORPORA_DIR: &str = "fixtures/corpora";
+const GOLDEN_DIR: &str = "fixtures/golden";
+const SEED: u64 = 0x7ac0;
+const SAMPLES: usize = 8;
+
+type Sampler = fn(&[(String, Markov)], &mut StdRng) -> String;
+
+fn samplers() -> Vec<(&'static str, Sampler)> {
+    vec![
+        ("plain", |corpora, rng| {
+            per_corpus(corpora, rng, |model, rng| {
+                model.generate_sequence(rng).collect()
+            })
+        }),
+        ("greedy", |corpora, rng| {
+            per_corpus(corpora, rng, |model, rng| {
+                let chain = model.generate_sequence(rng);
+                chain.sampling(Sampling::Greedy).collect()
+            })
+        }),
+        ("top-k", |corpora, rng| {
+            per_corpus(corpora, rng, |model, rng| {
+                let chain = model.generate_sequence(rng);
+                chain.sampling(Sampling::TopK(2)).collect()
+            })
+        }),
+        ("top-p", |corpora, rng| {
+            per_corpus(corpora, rng, |model, rng| {
+                let chain = model.generate_sequence(rng);
+                chain.sampling(Sampling::TopP(0.5)).collect()
+            })
+        }),
+        ("temperature", |corpora, rng| {
+            per_corpus(corpora, rng, |model, rng| {
+                model.generate_sequence(rng).temperature(1.5).collect()
+            })
+        }),
+        ("blended", |corpora, rng| {
+            let models: Vec<_> = corpora.iter().map(|(_, m)| (m, 1.0)).collect();
+            (0..SAMPLES)
+                .map(|_| {
+                    Markov::generate_blended(&models, &mut *rng)
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        }),
+    ]
+}
+
+/// `SAMPLES` lines of `generate` from each corpus, under its name.
+fn per_corpus(
+    corpora: &[(String, Markov)],
+    rng: &mut StdRng,
+    generate: impl Fn(&Markov, &mut StdRng) -> Vec<String>,
+) -> String {
+    corpora
+        .iter()
+        .flat_map(|(name, model)| {
+            let lines: Vec<_> = (0..SAMPLES)
+                .map(|_| generate(model, &mut *rng).join(" "))
+                .collect();
+            std::iter::once(format!("# {}", name)).chain(lines)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Every sampler's output against its golden file. With `BLESS=1` set, the output is written to
+/// them instead.
+#[test]
+fn generation_matches_golden_files() {
+    let bless = std::env::var_os("BLESS").is_some();
+    let corpora = load_corpora().unwrap();
+    let mut mismatches = Vec::new();
+    for (name, sampler) in samplers() {
+        let path = PathBuf::from(GOLDEN_DIR).join(format!("{}.txt", name));
+        let output = sampler(&corpora, &mut StdRng::seed_from_u64(SEED)) + "\n";
+        if bless {
+            fs::create_dir_all(GOLDEN_DIR).unwrap();
+            fs::write(&path, &output).unwrap();
+            continue;
+        }
+        let expected = fs::read_to_string(&path)
+            .with_context(|| format!("couldn't read {}", path.display()))
+            .unwrap();
+        if expected != output {
+            mismatches.push(diff(name, &expected, &output));
+        }
+    }
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+}
+
+/// Where `output` differs from what `name`'s golden file `expected`.
+fn diff(name: &str, expected: &str, output: &str) -> String {
+    let mut diff = format!("MISMATCH {}", name);
+    for (i, (e, o)) in expected.lines().zip(output.lines()).enumerate() {
+        if e != o {
+            diff += &format!(
+                "\n  line {}:\n    expected: {}\n    got:      {}",
+                i + 1,
+                e,
+                o
+            );
+        }
+    }
+    if expected.lines().count() != output.lines().count() {
+        diff += &format!(
+            "\n  expected {} lines, got {}",
+            expected.lines().count(),
+            output.lines().count()
+        );
+    }
+    diff
+}
+
+fn load_corpora() -> Result<Vec<(String, Markov)>> {
+    let mut paths: Vec<_> = fs::read_dir(CORPORA_DIR)
+        .with_context(|| format!("couldn't read {}", CORPORA_DIR))?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    paths.sort();
+    paths.iter().map(|p| load_corpus(p)).collect()
+}
+
+fn load_corpus(path: &Path) -> Result<(String, Markov)> {
+    let mut model = Markov::new();
+    for line in fs::read_to_string(path)?.lines() {
+        model.insert_sequence(line.split_whitespace().map(String::from));
+    }
+    let name = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    Ok((name, model))
+}
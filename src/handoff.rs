@@ -0,0 +1,63 @@
+//! Moving a guild to another instance of the bot, like off the public one to a self-hosted one.
+//! `eg!handoff` packs up everything the guild has here, encrypted with a code only its owner gets,
+//! and `eg!handoff import CODE` on the other instance takes it in.
+
+use anyhow::{anyhow, Result};
+use rand::Rng;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::birthday::Celebration;
+use crate::bot::types::Id;
+use crate::corpus::Mix;
+use crate::persist::{self, Key};
+use crate::settings::GuildSettings;
+use crate::store::Scope;
+use crate::transform::Transform;
+
+/// Letters that can't be mistaken for each other when typed over
+const CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+const CODE_LEN: usize = 16;
+
+/// Everything a guild has, generic over the bot's scheduled jobs.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Handoff<J> {
+    pub guild: Id,
+    /// Each of the guild's own models, serialized, with its fingerprint
+    pub models: Vec<(Scope, Vec<u8>, String)>,
+    pub mix: Option<Mix>,
+    pub transforms: Vec<Transform>,
+    pub settings: GuildSettings,
+    pub learning_blocklist: Vec<String>,
+    pub styles: Vec<(Id, String)>,
+    pub celebrations: Vec<Celebration>,
+    /// The guild's scheduled jobs and when they're due, taken out of this instance
+    pub jobs: Vec<(i64, J)>,
+}
+
+impl<J: Serialize> Handoff<J> {
+    pub fn seal(&self, code: &str) -> Result<Vec<u8>> {
+        persist::seal(self, &key(code))
+    }
+}
+
+impl<J: DeserializeOwned> Handoff<J> {
+    pub fn open(bytes: &[u8], code: &str) -> Result<Self> {
+        persist::unseal(bytes, &key(code))
+            .map_err(|_| anyhow!("that's not a handoff file, or not the code it came with"))
+    }
+}
+
+/// A new verification code.
+pub fn code(rng: &mut impl Rng) -> String {
+    (0..CODE_LEN)
+        .map(|_| CODE_ALPHABET[rng.gen_range(0, CODE_ALPHABET.len())] as char)
+        .collect()
+}
+
+fn key(code: &str) -> Key {
+    blake3::derive_key(
+        "taco_bot 2026-10 handoff key",
+        code.trim().to_lowercase().as_bytes(),
+    )
+}
@@ -0,0 +1,152 @@
+//! `eg!import`: learning chat logs exported from Discord, so a new bot has something to say
+//! straight away. Takes a channel exported as JSON by DiscordChatExporter, or a `messages.json`
+//! from Discord's own data export.
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use serde::Deserialize;
+
+use crate::bot::types::Id;
+
+/// One message from an export.
+#[derive(Debug)]
+pub struct Imported {
+    pub channel: Option<Id>,
+    pub author: Option<Id>,
+    pub bot: bool,
+    pub timestamp: Option<DateTime<FixedOffset>>,
+    pub content: String,
+}
+
+/// DiscordChatExporter's JSON.
+#[derive(Deserialize)]
+struct Exported {
+    channel: ExportedChannel,
+    messages: Vec<ExportedMessage>,
+}
+
+#[derive(Deserialize)]
+struct ExportedChannel {
+    id: Id,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportedMessage {
+    #[serde(rename = "type", default)]
+    kind: String,
+    timestamp: String,
+    content: String,
+    author: ExportedAuthor,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportedAuthor {
+    id: Id,
+    #[serde(default)]
+    is_bot: bool,
+}
+
+/// A message in the data export's `messages.json`, which only has the exporting user's own
+/// messages, and says what channel they're from in the directory's name instead.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PackageMessage {
+    timestamp: String,
+    #[serde(default)]
+    contents: String,
+}
+
+/// The messages in an export, in whichever format it's in.
+pub fn parse(bytes: &[u8]) -> Result<Vec<Imported>> {
+    if let Ok(exported) = serde_json::from_slice::<Exported>(bytes) {
+        let channel = exported.channel.id;
+        return Ok(exported
+            .messages
+            .into_iter()
+            // joins, pins and the like say nothing
+            .filter(|m| matches!(m.kind.as_str(), "" | "Default" | "Reply"))
+            .map(|m| Imported {
+                channel: Some(channel),
+                author: Some(m.author.id),
+                bot: m.author.is_bot,
+                timestamp: timestamp(&m.timestamp),
+                content: m.content,
+            })
+            .collect());
+    }
+    match serde_json::from_slice::<Vec<PackageMessage>>(bytes) {
+        Ok(messages) => Ok(messages
+            .into_iter()
+            .map(|m| Imported {
+                channel: None,
+                author: None,
+                bot: false,
+                timestamp: timestamp(&m.timestamp),
+                content: m.contents,
+            })
+            .collect()),
+        Err(_) => {
+            bail!("that's not a DiscordChatExporter JSON export or a data export's messages.json")
+        }
+    }
+}
+
+fn timestamp(text: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(text)
+        .or_else(|_| DateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f%:z"))
+        .ok()
+}
+
+/// Which messages to learn. Anything the export doesn't say, like the channel in a data export,
+/// never matches a filter on it.
+#[derive(Default, Debug)]
+pub struct Filter {
+    pub channel: Option<Id>,
+    pub user: Option<Id>,
+    pub after: Option<NaiveDate>,
+    pub before: Option<NaiveDate>,
+}
+
+impl Filter {
+    /// Reads `--channel #CHANNEL`, `--user @USER`, `--after YYYY-MM-DD` and
+    /// `--before YYYY-MM-DD`.
+    pub fn parse<'a>(args: impl IntoIterator<Item = &'a str>) -> Result<Self> {
+        let mut filter = Filter::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| anyhow!("expected a value after `{}`", arg))?;
+            let id = || {
+                value
+                    .trim_start_matches(['<', '#', '@', '!'])
+                    .trim_end_matches('>')
+                    .parse::<Id>()
+            };
+            let date = || NaiveDate::parse_from_str(value, "%Y-%m-%d");
+            match arg {
+                "--channel" => filter.channel = Some(id()?),
+                "--user" => filter.user = Some(id()?),
+                "--after" => filter.after = Some(date()?),
+                "--before" => filter.before = Some(date()?),
+                a => bail!("unknown option `{}`", a),
+            }
+        }
+        Ok(filter)
+    }
+
+    pub fn matches(&self, message: &Imported) -> bool {
+        let date = message.timestamp.map(|t| t.naive_local().date());
+        let is = |want: Option<Id>, got: Option<Id>| want.is_none() || want == got;
+        is(self.channel, message.channel)
+            && is(self.user, message.author)
+            && self
+                .after
+                .is_none_or(|after| date.is_some_and(|d| d > after))
+            && self
+                .before
+                .is_none_or(|before| date.is_some_and(|d| d < before))
+    }
+}
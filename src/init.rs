@@ -0,0 +1,124 @@
+//! `taco_bot init`: asks for everything `bot.json` needs and writes it, checking the token with
+//! Discord on the way.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+use crate::bot::client::Client;
+use crate::bot::types::{Intent, TokenBuf};
+use crate::secrets;
+
+const DEFAULT_INTENTS: &str = "guilds, guild_messages, guild_message_reactions, direct_messages";
+
+#[derive(Serialize)]
+struct NewConfig {
+    token: String,
+    prefix: String,
+    intents: Vec<String>,
+    admins: Vec<String>,
+    channel_blacklist: Vec<String>,
+    announcement_channels: Vec<String>,
+}
+
+pub fn run() -> Result<()> {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+
+    let dir = PathBuf::from(ask(&mut input, "Data directory", Some("."))?);
+    let path = dir.join("bot.json");
+    if path.exists()
+        && !ask(
+            &mut input,
+            "bot.json already exists, overwrite it? (y/n)",
+            Some("n"),
+        )?
+        .eq_ignore_ascii_case("y")
+    {
+        bail!("left {} alone", path.display());
+    }
+
+    let token = loop {
+        let token = ask(&mut input, "Bot token", None)?;
+        match check_token(&token) {
+            Ok(name) => {
+                println!("Logged in as {}", name);
+                break token;
+            }
+            Err(e) => println!("{:#}", e),
+        }
+    };
+    let prefix = ask(&mut input, "Command prefix", Some("eg!"))?;
+    let intents = loop {
+        let list = ask(&mut input, "Intents", Some(DEFAULT_INTENTS))?;
+        let intents: Vec<_> = list
+            .split(',')
+            .map(|i| i.trim().to_lowercase())
+            .filter(|i| !i.is_empty())
+            .collect();
+        match intents.iter().find(|i| {
+            serde_json::from_value::<Intent>(serde_json::Value::from(i.as_str())).is_err()
+        }) {
+            Some(bad) => println!("`{}` isn't an intent", bad),
+            None => break intents,
+        }
+    };
+    let admins = ask(&mut input, "Your user ID, to make you an admin", Some(""))?;
+
+    let config = NewConfig {
+        token,
+        prefix,
+        intents,
+        admins: admins.split_whitespace().map(String::from).collect(),
+        channel_blacklist: Vec::new(),
+        announcement_channels: Vec::new(),
+    };
+    fs::create_dir_all(&dir)?;
+    fs::write(&path, serde_json::to_string_pretty(&config)? + "\n")
+        .with_context(|| format!("couldn't write {}", path.display()))?;
+    println!(
+        "Wrote {}. Start the bot from {} to use it.",
+        path.display(),
+        dir.display()
+    );
+    Ok(())
+}
+
+fn ask(input: &mut impl BufRead, question: &str, default: Option<&str>) -> Result<String> {
+    loop {
+        match default {
+            Some(d) if !d.is_empty() => print!("{} [{}]: ", question, d),
+            _ => print!("{}: ", question),
+        }
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            bail!("ran out of input");
+        }
+        match (line.trim(), default) {
+            ("", Some(d)) => return Ok(String::from(d)),
+            ("", None) => continue,
+            (answer, _) => return Ok(String::from(answer)),
+        }
+    }
+}
+
+/// Returns the bot's name if Discord accepts the token.
+fn check_token(token: &str) -> Result<String> {
+    #[derive(Deserialize)]
+    struct CurrentUser {
+        username: String,
+    }
+    secrets::validate_token(token)?;
+    let client = Client::new(&TokenBuf::from(token));
+    async_io::block_on(async {
+        client
+            .make_get_request::<CurrentUser>("/users/@me")
+            .await?
+            .get_response_owned()
+            .map(|u| u.username)
+            .context("Discord didn't accept that token")
+    })
+}
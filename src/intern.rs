@@ -0,0 +1,97 @@
+//! Interned words. Every distinct word any model knows is kept once, for as long as the bot runs,
+//! and models refer to it by a `Sym`, a number, instead of each entry and every transition owning
+//! its own copy. Interning is process-wide rather than per model, so models can still be blended
+//! and merged without translating between them. Syms are saved as the words they stand for, so
+//! nothing saved changes.
+
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::{LazyLock, RwLock};
+
+/// Words are never freed, so they can be handed out for as long as the bot runs.
+#[derive(Default)]
+struct Interner {
+    ids: HashMap<&'static str, u32>,
+    words: Vec<&'static str>,
+}
+
+static INTERNER: LazyLock<RwLock<Interner>> = LazyLock::new(RwLock::default);
+
+/// A word, as a number that stands for it.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Sym(u32);
+
+impl Sym {
+    pub fn new(word: &str) -> Self {
+        if let Some(&id) = INTERNER.read().unwrap().ids.get(word) {
+            return Sym(id);
+        }
+        let mut interner = INTERNER.write().unwrap();
+        // someone else could have added it in between
+        if let Some(&id) = interner.ids.get(word) {
+            return Sym(id);
+        }
+        let word: &'static str = Box::leak(Box::from(word));
+        let id = u32::try_from(interner.words.len()).expect("fewer than 4 billion words");
+        interner.words.push(word);
+        interner.ids.insert(word, id);
+        Sym(id)
+    }
+
+    /// `word`'s sym if it's been interned, without interning it, for looking words up.
+    pub fn get(word: &str) -> Option<Self> {
+        INTERNER.read().unwrap().ids.get(word).copied().map(Sym)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        INTERNER.read().unwrap().words[self.0 as usize]
+    }
+}
+
+impl Deref for Sym {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for Sym {
+    fn from(word: &str) -> Self {
+        Sym::new(word)
+    }
+}
+
+impl From<String> for Sym {
+    fn from(word: String) -> Self {
+        Sym::new(&word)
+    }
+}
+
+impl fmt::Display for Sym {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for Sym {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Serialize for Sym {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Sym {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Sym::new(&String::deserialize(deserializer)?))
+    }
+}
@@ -0,0 +1,111 @@
+use anyhow::{anyhow, Result};
+use async_io::{Async, Timer};
+use futures::channel::mpsc::{unbounded, UnboundedSender};
+use futures::io::BufReader;
+use futures::prelude::*;
+use futures::select;
+use serde::Deserialize;
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+
+use crate::bridge::BridgeMessage;
+use crate::store::Scope;
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct IrcConfig {
+    /// `host:port`, e.g. `irc.chat.twitch.tv:6667`
+    pub server: String,
+    pub nick: String,
+    /// For Twitch this is `oauth:<token>`
+    #[serde(default)]
+    pub password: Option<String>,
+    pub channel: String,
+    /// Learn into and generate from the same model as Discord instead of a separate one.
+    #[serde(default)]
+    pub share_global: bool,
+}
+
+impl IrcConfig {
+    pub fn scope(&self) -> Scope {
+        if self.share_global {
+            Scope::Global
+        } else {
+            let host = self.server.split(':').next().unwrap_or(&self.server);
+            Scope::Bridge(format!("{}/{}", host, self.channel))
+        }
+    }
+}
+
+/// Runs the bridge on its own thread, reconnecting whenever the connection drops.
+pub fn spawn(cfg: IrcConfig, events: UnboundedSender<BridgeMessage>) {
+    const RECONNECT_DELAY: Duration = Duration::from_secs(30);
+    thread::spawn(move || loop {
+        if let Err(e) = async_io::block_on(run(&cfg, &events)) {
+            eprintln!("irc {}: {}", cfg.server, e);
+        }
+        if events.is_closed() {
+            break;
+        }
+        async_io::block_on(Timer::after(RECONNECT_DELAY));
+    });
+}
+
+async fn run(cfg: &IrcConfig, events: &UnboundedSender<BridgeMessage>) -> Result<()> {
+    let stream = Async::new(TcpStream::connect(cfg.server.as_str())?)?;
+    let mut lines = BufReader::new(&stream).lines();
+    let mut writer = &stream;
+
+    if let Some(password) = &cfg.password {
+        send(&mut writer, &format!("PASS {}", password)).await?;
+    }
+    send(&mut writer, &format!("NICK {}", cfg.nick)).await?;
+    send(&mut writer, &format!("USER {} 0 * :{}", cfg.nick, cfg.nick)).await?;
+    send(&mut writer, &format!("JOIN {}", cfg.channel)).await?;
+
+    let (reply, mut replies) = unbounded::<String>();
+    loop {
+        select! {
+            line = lines.next().fuse() => {
+                let line = line.ok_or_else(|| anyhow!("connection closed"))??;
+                if let Some(token) = line.strip_prefix("PING ") {
+                    send(&mut writer, &format!("PONG {}", token)).await?;
+                } else if let Some((author, text)) = parse_privmsg(&line, &cfg.channel) {
+                    events.unbounded_send(BridgeMessage {
+                        scope: cfg.scope(),
+                        author: String::from(author),
+                        text: String::from(text),
+                        learn: true,
+                        reply: reply.clone(),
+                    })?;
+                }
+            }
+            text = replies.next() => {
+                if let Some(text) = text {
+                    for line in text.lines().filter(|l| !l.trim().is_empty()) {
+                        send(&mut writer, &format!("PRIVMSG {} :{}", cfg.channel, line)).await?;
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn send(writer: &mut &Async<TcpStream>, line: &str) -> Result<()> {
+    writer.write_all(line.as_bytes()).await?;
+    writer.write_all(b"\r\n").await?;
+    Ok(())
+}
+
+/// `:nick!user@host PRIVMSG #channel :text` -> `(nick, text)`
+fn parse_privmsg<'a>(line: &'a str, channel: &str) -> Option<(&'a str, &'a str)> {
+    let rest = line.strip_prefix(':')?;
+    let (prefix, rest) = rest.split_at(rest.find(' ')?);
+    let rest = rest.trim_start().strip_prefix("PRIVMSG ")?;
+    let (target, text) = rest.split_at(rest.find(' ')?);
+    if !target.eq_ignore_ascii_case(channel) {
+        return None;
+    }
+    let nick = prefix.split('!').next()?;
+    Some((nick, text.trim_start().strip_prefix(':')?))
+}
@@ -0,0 +1,45 @@
+//! Picking out what a message is about, so a reply to it can start from there instead of
+//! anywhere. It's the words left after dropping stopwords, mentions, links and anything too short
+//! to mean much.
+
+use std::collections::HashSet;
+
+/// Words too common to say what a message is about
+const STOPWORDS: &[&str] = &[
+    "about", "after", "again", "all", "also", "and", "any", "are", "because", "been", "before",
+    "being", "but", "can", "cant", "could", "did", "didnt", "does", "doesnt", "dont", "for",
+    "from", "had", "has", "hey", "have", "hello", "her", "here", "hers", "him", "his", "how",
+    "its", "just", "like", "lol", "more", "most", "much", "not", "now", "off", "okay", "one",
+    "only", "our", "out", "over", "really", "she", "should", "some", "still", "than", "that",
+    "thats", "the", "their", "them", "then", "there", "these", "they", "this", "those", "too",
+    "very", "was", "wasnt", "way", "were", "what", "when", "where", "which", "who", "why", "will",
+    "with", "would", "yeah", "yes", "you", "your", "youre",
+];
+
+/// Words shorter than this are never keywords
+const MIN_LEN: usize = 3;
+
+/// The keywords in `words`, as they were written, each once, in the order they came.
+pub fn extract(words: &[String]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    words
+        .iter()
+        .filter(|w| is_keyword(w))
+        .filter(|w| seen.insert(w.to_lowercase()))
+        .cloned()
+        .collect()
+}
+
+fn is_keyword(word: &str) -> bool {
+    let letters: String = word
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect();
+    letters.chars().count() >= MIN_LEN
+        && letters.chars().any(char::is_alphabetic)
+        && !STOPWORDS.contains(&letters.as_str())
+        // mentions, channels, custom emoji and links
+        && !word.starts_with('<')
+        && !word.contains("://")
+}
@@ -0,0 +1,223 @@
+//! Per-language punctuation rules, for splitting what's learned into words and joining generated
+//! words back up the way that language writes them. Which rules apply is worked out from the text
+//! itself by `detect`.
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A narrow no-break space, which French puts before `!`, `?`, `;`, `:` and inside `« »`.
+const NARROW_SPACE: char = '\u{202f}';
+
+const SPANISH_WORDS: [&str; 14] = [
+    "el", "los", "las", "que", "y", "es", "por", "pero", "muy", "está", "con", "para", "yo", "qué",
+];
+const FRENCH_WORDS: [&str; 14] = [
+    "le", "les", "est", "et", "je", "pas", "du", "mais", "très", "vous", "nous", "avec", "c'est",
+    "qui",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Language {
+    English,
+    Spanish,
+    French,
+    /// Chinese and Japanese, which don't put spaces between words
+    Cjk,
+}
+
+impl Language {
+    const ALL: [Language; 4] = [
+        Language::English,
+        Language::Spanish,
+        Language::French,
+        Language::Cjk,
+    ];
+
+    fn code(self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::Spanish => "es",
+            Language::French => "fr",
+            Language::Cjk => "cjk",
+        }
+    }
+}
+
+/// How a language's punctuation is split off and put back.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Rules {
+    /// Split off the front of words, and stuck back onto the word after them
+    pub openers: String,
+    /// Split off the end of words, and stuck back onto the word before them
+    pub closers: String,
+    /// Openers and closers that keep a narrow space between them and their word
+    pub spaced: String,
+    /// Openers a sentence needs when it has the matching closer, like `¿` for `?`
+    pub pairs: Vec<(char, char)>,
+    /// Written without spaces between words, so it's learned a character at a time
+    pub unspaced: bool,
+}
+
+impl Rules {
+    fn builtin(language: Language) -> Self {
+        match language {
+            Language::English => Rules::default(),
+            Language::Spanish => Rules {
+                openers: String::from("¿¡"),
+                pairs: vec![('¿', '?'), ('¡', '!')],
+                ..Rules::default()
+            },
+            Language::French => Rules {
+                openers: String::from("«"),
+                closers: String::from("!?;:»"),
+                spaced: String::from("!?;:«»"),
+                ..Rules::default()
+            },
+            Language::Cjk => Rules {
+                openers: String::from("「『（【"),
+                closers: String::from("。！？、，」』）】：；"),
+                unspaced: true,
+                ..Rules::default()
+            },
+        }
+    }
+}
+
+/// The rules for every language, the built-in ones with whatever the config changes.
+pub struct Punctuation {
+    rules: HashMap<Language, Rules>,
+}
+
+impl Punctuation {
+    pub fn new(overrides: &HashMap<String, Rules>) -> Result<Self> {
+        let mut rules: HashMap<_, _> = Language::ALL
+            .iter()
+            .map(|&l| (l, Rules::builtin(l)))
+            .collect();
+        for (code, r) in overrides {
+            match Language::ALL.iter().find(|l| l.code() == code) {
+                Some(l) => rules.insert(*l, r.clone()),
+                None => bail!("no punctuation rules for language `{}`", code),
+            };
+        }
+        Ok(Punctuation { rules })
+    }
+
+    /// Splits `text` into the words a model learns.
+    pub fn tokenize(&self, text: &str) -> Vec<String> {
+        let rules = &self.rules[&detect(text)];
+        let mut words = Vec::new();
+        for word in text.split_whitespace() {
+            let start = word.trim_start_matches(|c| rules.openers.contains(c));
+            let core = start.trim_end_matches(|c| rules.closers.contains(c));
+            if core.is_empty() {
+                words.push(String::from(word));
+                continue;
+            }
+            words.extend(word[..word.len() - start.len()].chars().map(String::from));
+            if rules.unspaced {
+                split_cjk(core, &mut words);
+            } else {
+                words.push(String::from(core));
+            }
+            words.extend(start[core.len()..].chars().map(String::from));
+        }
+        words
+    }
+
+    /// Joins generated words back into text.
+    pub fn join(&self, words: impl IntoIterator<Item = String>) -> String {
+        let words: Vec<_> = words.into_iter().collect();
+        let rules = &self.rules[&detect(&words.join(" "))];
+        let mut text = String::new();
+        let mut prev: Option<&str> = None;
+        for word in &words {
+            if let Some(prev) = prev {
+                let is_opener = prev.chars().all(|c| rules.openers.contains(c));
+                let is_closer = word.chars().all(|c| rules.closers.contains(c));
+                if (is_opener && prev.chars().all(|c| rules.spaced.contains(c)))
+                    || (is_closer && word.chars().all(|c| rules.spaced.contains(c)))
+                {
+                    text.push(NARROW_SPACE);
+                } else if !(is_opener
+                    || is_closer
+                    || rules.unspaced && ends_cjk(prev) && starts_cjk(word))
+                {
+                    text.push(' ');
+                }
+            }
+            text.push_str(word);
+            prev = Some(word);
+        }
+        for &(opener, closer) in &rules.pairs {
+            match (text.contains(opener), text.contains(closer)) {
+                (false, true) => text.insert(0, opener),
+                (true, false) => text.push(closer),
+                _ => {}
+            }
+        }
+        text
+    }
+}
+
+/// Guesses the language of `text` from its script and most common words.
+pub fn detect(text: &str) -> Language {
+    let letters = text.chars().filter(|c| c.is_alphabetic()).count();
+    let cjk = text.chars().filter(|&c| is_cjk(c)).count();
+    if cjk > 0 && cjk * 3 >= letters {
+        return Language::Cjk;
+    }
+    if text.contains(['¿', '¡']) {
+        return Language::Spanish;
+    }
+    let (mut spanish, mut french) = (0, 0);
+    for word in text.split_whitespace() {
+        let word = word
+            .trim_matches(|c: char| !c.is_alphabetic() && c != '\'')
+            .to_lowercase();
+        spanish += SPANISH_WORDS.contains(&word.as_str()) as usize;
+        french += FRENCH_WORDS.contains(&word.as_str()) as usize;
+    }
+    match spanish.cmp(&french) {
+        std::cmp::Ordering::Greater if spanish >= 2 => Language::Spanish,
+        std::cmp::Ordering::Less if french >= 2 => Language::French,
+        _ => Language::English,
+    }
+}
+
+/// Han and kana, the scripts without spaces. Hangul has spaces, so it's left out.
+fn is_cjk(c: char) -> bool {
+    matches!(c, '\u{3040}'..='\u{30ff}' | '\u{3400}'..='\u{4dbf}' | '\u{4e00}'..='\u{9fff}')
+}
+
+fn is_full_width(c: char) -> bool {
+    matches!(c, '\u{3000}'..='\u{303f}' | '\u{ff00}'..='\u{ffef}')
+}
+
+fn starts_cjk(word: &str) -> bool {
+    word.starts_with(|c| is_cjk(c) || is_full_width(c))
+}
+
+fn ends_cjk(word: &str) -> bool {
+    word.ends_with(|c| is_cjk(c) || is_full_width(c))
+}
+
+/// Every Han or kana character on its own, and anything else in between as whole words.
+fn split_cjk(text: &str, words: &mut Vec<String>) {
+    let mut run = String::new();
+    for c in text.chars() {
+        if is_cjk(c) || is_full_width(c) {
+            if !run.is_empty() {
+                words.push(std::mem::take(&mut run));
+            }
+            words.push(c.to_string());
+        } else {
+            run.push(c);
+        }
+    }
+    if !run.is_empty() {
+        words.push(run);
+    }
+}
@@ -0,0 +1,48 @@
+//! How long each kind of interaction has taken to answer, so ones likely to take longer than
+//! Discord waits get deferred first instead of failing with "the application did not respond".
+
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+/// Discord waits 3 seconds, this leaves room for getting the answer there
+pub const BUDGET: Duration = Duration::from_millis(2500);
+/// How many of the latest answers each prediction is based on
+const KEPT: usize = 20;
+
+#[derive(Default, Debug)]
+pub struct Latencies {
+    by_key: HashMap<String, VecDeque<Duration>>,
+}
+
+impl Latencies {
+    pub fn record(&mut self, key: &str, took: Duration) {
+        let times = self.by_key.entry(String::from(key)).or_default();
+        if times.len() >= KEPT {
+            times.pop_front();
+        }
+        times.push_back(took);
+    }
+
+    /// How long the next one will likely take: longer than 9 out of 10 of the latest.
+    pub fn predict(&self, key: &str) -> Option<Duration> {
+        let mut times: Vec<_> = self.by_key.get(key)?.iter().copied().collect();
+        times.sort();
+        times.get(times.len() * 9 / 10).or(times.last()).copied()
+    }
+
+    pub fn should_defer(&self, key: &str) -> bool {
+        self.predict(key).is_some_and(|t| t > BUDGET)
+    }
+}
+
+/// What interactions are grouped by: the slash command, or the button without any number at the
+/// end, like `adventure:`.
+pub fn key(command: Option<&str>, custom_id: Option<&str>) -> Option<String> {
+    match (command, custom_id) {
+        (Some(command), _) => Some(format!("/{}", command)),
+        (None, Some(id)) => Some(String::from(
+            id.trim_end_matches(|c: char| c.is_ascii_digit()),
+        )),
+        _ => None,
+    }
+}
@@ -0,0 +1,163 @@
+//! Deciding which instance leads: connects to Discord and learns. The leader holds a lease, a file
+//! on storage every instance can reach, and keeps renewing it. When it stops, because its host died
+//! or it hung, the lease runs out and a standby takes it and carries on in its place.
+//!
+//! Expiry is by the clock, so the instances' clocks should agree to within a few seconds.
+//!
+//! A shared file has no compare-and-swap, so this narrows the race between two instances taking
+//! the lease at once but doesn't close it. One that gets in just after another has checked it
+//! holds the lease wins, and the other only finds out when it next renews. Two instances can both
+//! think they lead for up to a third of `seconds`, until the one that lost stops.
+
+use anyhow::{anyhow, Result};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use crate::schedule::now_millis;
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct LeaseConfig {
+    /// On storage shared by every instance, like an NFS mount
+    pub path: PathBuf,
+    /// How long the lease lasts without being renewed
+    #[serde(default = "default_seconds")]
+    pub seconds: u64,
+    /// This instance's name in the lease, so it can take it straight back after a restart.
+    /// Random if not set
+    #[serde(default)]
+    pub node: Option<String>,
+}
+
+fn default_seconds() -> u64 {
+    30
+}
+
+/// What's in the lease file.
+#[derive(Serialize, Deserialize, Debug)]
+struct Held {
+    holder: String,
+    /// In milliseconds since the epoch
+    expires: i64,
+}
+
+pub struct Lease {
+    path: PathBuf,
+    holder: String,
+    duration: Duration,
+}
+
+impl Lease {
+    pub fn new(cfg: &LeaseConfig) -> Self {
+        let holder = cfg
+            .node
+            .clone()
+            .unwrap_or_else(|| format!("{:016x}", rand::thread_rng().gen::<u64>()));
+        Lease {
+            path: cfg.path.clone(),
+            holder,
+            duration: Duration::from_secs(cfg.seconds.max(3)),
+        }
+    }
+
+    /// The lease, if it hasn't run out. Only a missing file counts as nobody holding it: one that
+    /// can't be read is an error, so a blip on the shared storage doesn't hand the lease over.
+    fn held(&self) -> Result<Option<Held>> {
+        let bytes = match fs::read(&self.path) {
+            Ok(b) => b,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(anyhow!("couldn't read {}: {}", self.path.display(), e)),
+        };
+        let held: Held = serde_json::from_slice(&bytes)
+            .map_err(|e| anyhow!("couldn't read {}: {}", self.path.display(), e))?;
+        Ok(Some(held).filter(|held| held.expires > now_millis()))
+    }
+
+    /// Who holds the lease, for logging.
+    fn holder(&self) -> String {
+        match self.held() {
+            Ok(Some(held)) => held.holder,
+            Ok(None) => String::from("nobody"),
+            Err(_) => String::from("somebody"),
+        }
+    }
+
+    /// Takes the lease if nobody else holds it, or renews it if this instance does. When two
+    /// instances try at once, whichever wrote last gets it: both wait a moment and look again,
+    /// which catches most races but not all of them, see the module's docs.
+    pub fn try_acquire(&self) -> Result<bool> {
+        let held = self.held()?;
+        if held.as_ref().is_some_and(|held| held.holder != self.holder) {
+            return Ok(false);
+        }
+        self.write()?;
+        if held.is_none() {
+            thread::sleep(self.duration / 10);
+        }
+        Ok(self.held()?.is_some_and(|held| held.holder == self.holder))
+    }
+
+    /// Waits for the lease, for as long as it takes.
+    pub fn acquire(&self) -> Result<()> {
+        let mut told = false;
+        while !self.try_acquire()? {
+            if !told {
+                let holder = self.holder();
+                eprintln!("lease: waiting for {} to let go of the lease", holder);
+                told = true;
+            }
+            thread::sleep(self.duration / 3);
+        }
+        Ok(())
+    }
+
+    /// Renews the lease in the background for as long as the process runs. If it's lost, because
+    /// another instance took it or it couldn't be renewed in time, the process stops straight
+    /// away, without saving, so two instances never learn at once.
+    pub fn keep(self) {
+        thread::spawn(move || {
+            let mut renewed = now_millis();
+            loop {
+                thread::sleep(self.duration / 3);
+                match self.try_acquire() {
+                    Ok(true) => renewed = now_millis(),
+                    Ok(false) => lost(&format!("{} took the lease", self.holder())),
+                    Err(e) => {
+                        eprintln!("lease: couldn't renew: {}", e);
+                        if now_millis() - renewed >= self.duration.as_millis() as i64 {
+                            lost("it ran out before it could be renewed");
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    /// Writes the lease out as held by this instance, all at once, so it's never seen half
+    /// written.
+    fn write(&self) -> Result<()> {
+        let held = Held {
+            holder: self.holder.clone(),
+            expires: now_millis() + self.duration.as_millis() as i64,
+        };
+        let name = self
+            .path
+            .file_name()
+            .ok_or_else(|| anyhow!("the lease path should be a file"))?;
+        let mut temp = name.to_owned();
+        temp.push(format!(".{}", self.holder));
+        let temp = self.path.with_file_name(temp);
+        fs::write(&temp, serde_json::to_vec(&held)?)?;
+        fs::rename(&temp, &self.path)?;
+        Ok(())
+    }
+}
+
+fn lost(why: &str) -> ! {
+    eprintln!("lease: lost the lease, {}; stopping", why);
+    std::process::exit(3);
+}
@@ -0,0 +1,446 @@
+//! `taco_bot loadtest`: replays a synthetic stream of messages through the whole ingest pipeline,
+//! the way the gateway would hand them over, and reports how well it kept up. For checking
+//! redesigns like batching or sharding against the same numbers.
+
+use anyhow::{anyhow, bail, Result};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::fmt::{self, Display};
+use std::time::{Duration, Instant};
+
+use crate::async_markov::AsyncMarkov;
+use crate::backend::Storage;
+use crate::markov::Markov;
+use crate::persist::{load_or_default, save_to};
+
+pub struct Options {
+    pub guilds: u64,
+    /// In each guild
+    pub channels: u64,
+    pub messages: usize,
+    /// How many words messages have, on average
+    pub words: usize,
+    /// Messages a second, or as fast as they're taken in if 0
+    pub rate: u64,
+    pub seed: u64,
+    /// Whether each guild learns into its own model, like with `guild_models`
+    pub per_guild: bool,
+    pub storage: Storage,
+}
+
+impl Options {
+    /// Reads `--guilds N`, `--channels N`, `--messages N`, `--words N`, `--rate N`, `--seed N`,
+    /// `--per-guild` and `--storage files|sled`.
+    pub fn parse(args: &[String]) -> Result<Self> {
+        let mut options = Options {
+            guilds: 20,
+            channels: 5,
+            messages: 100_000,
+            words: 12,
+            rate: 0,
+            seed: 0x7ac0,
+            per_guild: false,
+            storage: Storage::Files,
+        };
+        let mut args = args.iter().map(String::as_str);
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| anyhow!("expected a value after `{}`", arg))
+            };
+            match arg {
+                "--guilds" => options.guilds = value()?.parse()?,
+                "--channels" => options.channels = value()?.parse()?,
+                "--messages" => options.messages = value()?.parse()?,
+                "--words" => options.words = value()?.parse()?,
+                "--rate" => options.rate = value()?.parse()?,
+                "--seed" => options.seed = value()?.parse()?,
+                "--per-guild" => options.per_guild = true,
+                "--storage" => {
+                    options.storage = serde_json::from_value(value()?.into())
+                        .map_err(|_| anyhow!("storage is `files` or `sled`"))?
+                }
+                a => bail!("unknown option `{}`", a),
+            }
+        }
+        if options.guilds == 0 || options.channels == 0 || options.words == 0 {
+            bail!("there has to be at least one guild, channel and word");
+        }
+        Ok(options)
+    }
+
+    /// When the `i`th message arrives, from the start.
+    pub fn due(&self, i: usize) -> Option<Duration> {
+        match self.rate {
+            0 => None,
+            rate => Some(Duration::from_secs_f64(i as f64 / rate as f64)),
+        }
+    }
+}
+
+/// The synthetic messages, as gateway JSON. Words are picked from a fixed vocabulary with a few
+/// much more common than the rest, like in real chat.
+pub struct Stream<'a> {
+    options: &'a Options,
+    rng: StdRng,
+    vocabulary: Vec<String>,
+    sent: usize,
+}
+
+impl<'a> Stream<'a> {
+    const VOCABULARY: usize = 5000;
+
+    pub fn new(options: &'a Options) -> Self {
+        let mut rng = StdRng::seed_from_u64(options.seed);
+        let vocabulary = (0..Self::VOCABULARY)
+            .map(|_| {
+                let len = rng.gen_range(1, 9);
+                (0..len)
+                    .map(|_| rng.gen_range(b'a', b'z' + 1) as char)
+                    .collect()
+            })
+            .collect();
+        Stream {
+            options,
+            rng,
+            vocabulary,
+            sent: 0,
+        }
+    }
+
+    /// What one message says.
+    fn words(&mut self) -> Vec<String> {
+        let len = self.rng.gen_range(1, self.options.words * 2);
+        let (rng, vocabulary) = (&mut self.rng, &self.vocabulary);
+        (0..len)
+            .map(|_| {
+                // cubing skews it towards the front
+                let at = rng.gen::<f64>().powi(3) * vocabulary.len() as f64;
+                vocabulary[at as usize].clone()
+            })
+            .collect()
+    }
+}
+
+impl Iterator for Stream<'_> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        if self.sent >= self.options.messages {
+            return None;
+        }
+        self.sent += 1;
+        let guild = self.rng.gen_range(0, self.options.guilds) + 1;
+        let channel = guild * 1000 + self.rng.gen_range(0, self.options.channels);
+        let author = self.rng.gen_range(0, 200) + 1;
+        let content = self.words();
+        Some(
+            serde_json::json!({
+                "id": self.sent.to_string(),
+                "channel_id": channel.to_string(),
+                "guild_id": guild.to_string(),
+                "content": content.join(" "),
+                "timestamp": "2026-01-01T00:00:00+00:00",
+                "author": { "id": author.to_string(), "username": "user", "discriminator": "0" },
+                "mentions": [],
+            })
+            .to_string(),
+        )
+    }
+}
+
+/// How the pipeline kept up.
+#[derive(Default)]
+pub struct Report {
+    /// How long each message took to take in
+    took: Vec<Duration>,
+    /// How many messages had arrived and were waiting, as each was taken in
+    depths: Vec<usize>,
+    pub elapsed: Duration,
+    pub models: usize,
+    pub entries: usize,
+    pub save: Duration,
+}
+
+impl Report {
+    pub fn record(&mut self, took: Duration, depth: usize) {
+        self.took.push(took);
+        self.depths.push(depth);
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut took = self.took.clone();
+        took.sort();
+        let at = |p: usize| took.get((took.len() * p / 100).min(took.len().saturating_sub(1)));
+        let throughput = took.len() as f64 / self.elapsed.as_secs_f64();
+        writeln!(f, "messages     {}", took.len())?;
+        writeln!(f, "throughput   {:.0} messages/s", throughput)?;
+        if let (Some(p50), Some(p90), Some(p99), Some(max)) = (at(50), at(90), at(99), took.last())
+        {
+            writeln!(
+                f,
+                "latency      p50 {:?}, p90 {:?}, p99 {:?}, max {:?}",
+                p50, p90, p99, max
+            )?;
+        }
+        let max_depth = self.depths.iter().max().copied().unwrap_or(0);
+        let mean_depth = self.depths.iter().sum::<usize>() as f64 / self.depths.len().max(1) as f64;
+        writeln!(f, "queue depth  mean {:.1}, max {}", mean_depth, max_depth)?;
+        writeln!(f, "models       {} ({} entries)", self.models, self.entries)?;
+        write!(f, "final save   {:?}", self.save)
+    }
+}
+
+/// `taco_bot formatbench`: how big a model learned from the same synthetic messages is in each
+/// save format, and how long it takes to save and load.
+pub struct Formats {
+    entries: usize,
+    /// Each format's name, size in bytes, and best save and load times
+    formats: Vec<(&'static str, u64, Duration, Duration)>,
+}
+
+impl Formats {
+    const RUNS: usize = 3;
+
+    pub fn run(options: &Options) -> Result<Self> {
+        let mut markov = Markov::new();
+        let mut stream = Stream::new(options);
+        for _ in 0..options.messages {
+            markov.insert_sequence(stream.words());
+        }
+        // saved and loaded the way the bot does it, file and all
+        let dir = std::env::temp_dir();
+        let legacy = dir.join(format!("taco_bot-legacy-{}", std::process::id()));
+        let compact = dir.join(format!("taco_bot-compact-{}", std::process::id()));
+        let legacy_save = best(|| Ok(std::fs::write(&legacy, markov.legacy_bytes()?)?))?;
+        let legacy_load = best(|| Ok(load_or_default::<Markov>(&legacy)))?;
+        let compact_save = best(|| save_to(&compact, &markov))?;
+        let compact_load = best(|| Ok(load_or_default::<Markov>(&compact)))?;
+        let formats = vec![
+            (
+                "legacy",
+                std::fs::metadata(&legacy)?.len(),
+                legacy_save,
+                legacy_load,
+            ),
+            (
+                "compact",
+                std::fs::metadata(&compact)?.len(),
+                compact_save,
+                compact_load,
+            ),
+        ];
+        std::fs::remove_file(legacy)?;
+        std::fs::remove_file(compact)?;
+        Ok(Formats {
+            entries: markov.entry_count(),
+            formats,
+        })
+    }
+}
+
+/// The quickest `f` ran out of `Formats::RUNS` tries.
+fn best<T>(mut f: impl FnMut() -> Result<T>) -> Result<Duration> {
+    let mut best = Duration::MAX;
+    for _ in 0..Formats::RUNS {
+        let started = Instant::now();
+        let made = f()?;
+        best = best.min(started.elapsed());
+        drop(made);
+    }
+    Ok(best)
+}
+
+impl Display for Formats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "model        {} entries", self.entries)?;
+        for (name, size, save, load) in &self.formats {
+            writeln!(
+                f,
+                "{:<12} {:.1} MB, save {:?}, load {:?}",
+                name,
+                *size as f64 / 1_000_000.0,
+                save,
+                load
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// `taco_bot parbench`: how long learning the synthetic messages all at once, like an import, and
+/// cleaning the model take on one thread and on every core.
+pub struct Parallel {
+    entries: usize,
+    threads: usize,
+    /// Each operation's name, and its best time on one thread and on every core
+    timings: Vec<(&'static str, Duration, Duration)>,
+}
+
+impl Parallel {
+    pub fn run(options: &Options) -> Result<Self> {
+        let mut stream = Stream::new(options);
+        let seqs: Vec<_> = (0..options.messages).map(|_| stream.words()).collect();
+        let one = rayon::ThreadPoolBuilder::new().num_threads(1).build()?;
+        let learn = || {
+            let mut markov = Markov::new();
+            markov.insert_sequences(&seqs);
+            markov
+        };
+        let serial = one.install(learn);
+        let parallel = learn();
+        if serial.fingerprint() != parallel.fingerprint() {
+            bail!("learning in parallel made a different model");
+        }
+        let learn_times = (one.install(|| best(|| Ok(learn()))), best(|| Ok(learn())));
+        // cleaned copies, so every run starts from the same model
+        let clean = || Ok(parallel.clone().clean());
+        let clean_times = (one.install(|| best(clean)), best(clean));
+        Ok(Parallel {
+            entries: parallel.entry_count(),
+            threads: rayon::current_num_threads(),
+            timings: vec![
+                ("learn", learn_times.0?, learn_times.1?),
+                ("clean", clean_times.0?, clean_times.1?),
+            ],
+        })
+    }
+}
+
+impl Display for Parallel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "model        {} entries, {} threads",
+            self.entries, self.threads
+        )?;
+        for (name, one, all) in &self.timings {
+            writeln!(
+                f,
+                "{:<12} {:?} on one thread, {:?} on all, {:.1}x",
+                name,
+                one,
+                all,
+                one.as_secs_f64() / all.as_secs_f64()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Also for `taco_bot parbench`: several threads learning the synthetic messages and generating
+/// now and then, all from one model, either through an `AsyncMarkov` or taking turns locking it.
+pub struct Serving {
+    workers: usize,
+    generated: usize,
+    /// Each way's name, how long it took altogether, and the longest a generation took
+    timings: Vec<(&'static str, Duration, Duration)>,
+}
+
+impl Serving {
+    const WORKERS: usize = 4;
+    /// Each worker generates after learning this many messages
+    const GENERATE_EVERY: usize = 10;
+    const MAX_WORDS: usize = 30;
+
+    pub fn run(options: &Options) -> Result<Self> {
+        let mut stream = Stream::new(options);
+        let seqs: Vec<_> = (0..options.messages).map(|_| stream.words()).collect();
+        let mut learned = Markov::new();
+        learned.insert_sequences(&seqs);
+        let shares: Vec<_> = seqs.chunks(seqs.len() / Self::WORKERS + 1).collect();
+
+        let locked = std::sync::Mutex::new(Markov::new());
+        let lock = Self::time(&shares, |seq, seed| {
+            let mut model = locked.lock().expect("no worker panics");
+            match seq {
+                Some(seq) => model.insert_sequence(seq.iter().cloned()),
+                None => drop(Self::generate(&model, seed)),
+            }
+        });
+        if locked.into_inner()?.fingerprint() != learned.fingerprint() {
+            bail!("taking turns made a different model");
+        }
+
+        let actor = AsyncMarkov::spawn(Markov::new());
+        let actor_times = Self::time(&shares, |seq, seed| match seq {
+            Some(seq) => actor.learn(seq.clone()),
+            None => drop(futures::executor::block_on(
+                actor.generate(seed, Self::MAX_WORDS),
+            )),
+        });
+        if futures::executor::block_on(actor.stop())?.fingerprint() != learned.fingerprint() {
+            bail!("the actor made a different model");
+        }
+        Ok(Serving {
+            workers: Self::WORKERS,
+            generated: options.messages / Self::GENERATE_EVERY,
+            timings: vec![
+                ("mutex", lock.0, lock.1),
+                ("actor", actor_times.0, actor_times.1),
+            ],
+        })
+    }
+
+    fn generate(model: &Markov, seed: u64) -> Vec<String> {
+        model
+            .generate_with_seed(seed)
+            .take(Self::MAX_WORDS)
+            .collect()
+    }
+
+    /// How long a worker per share took to `work` through it, learning each message (`Some`)
+    /// and generating (`None`) every so often, and the longest a generation took.
+    fn time(
+        shares: &[&[Vec<String>]],
+        work: impl Fn(Option<&Vec<String>>, u64) + Sync,
+    ) -> (Duration, Duration) {
+        let started = Instant::now();
+        let slowest = std::thread::scope(|scope| {
+            let workers: Vec<_> = shares
+                .iter()
+                .map(|share| {
+                    let work = &work;
+                    scope.spawn(move || {
+                        let mut slowest = Duration::ZERO;
+                        for (i, seq) in share.iter().enumerate() {
+                            work(Some(seq), 0);
+                            if i % Self::GENERATE_EVERY == Self::GENERATE_EVERY - 1 {
+                                let asked = Instant::now();
+                                work(None, i as u64);
+                                slowest = slowest.max(asked.elapsed());
+                            }
+                        }
+                        slowest
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .map(|w| w.join().expect("no worker panics"))
+                .max()
+                .unwrap_or_default()
+        });
+        (started.elapsed(), slowest)
+    }
+}
+
+impl Display for Serving {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "serving      {} workers, {} generations",
+            self.workers, self.generated
+        )?;
+        for (name, took, slowest) in &self.timings {
+            writeln!(
+                f,
+                "{:<12} {:?} altogether, slowest generation {:?}",
+                name, took, slowest
+            )?;
+        }
+        Ok(())
+    }
+}
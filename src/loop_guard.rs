@@ -0,0 +1,49 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::bot::types::{Id, Message};
+
+/// Keeps track of bots answering each other, so two of them can't ping-pong forever.
+pub struct LoopGuard {
+    capacity: usize,
+    order: VecDeque<Id>,
+    /// How many bot messages in a row lead up to each recent message, itself included
+    depths: HashMap<Id, usize>,
+    /// The depth of the last message in each channel, for bots that don't use replies
+    channels: HashMap<Id, usize>,
+}
+
+impl LoopGuard {
+    pub fn new(capacity: usize) -> Self {
+        LoopGuard {
+            capacity,
+            order: VecDeque::with_capacity(capacity),
+            depths: HashMap::with_capacity(capacity),
+            channels: HashMap::new(),
+        }
+    }
+
+    /// Records a message, ours included, and returns how deep into a chain of bots it is. A
+    /// person's message is 0 and starts over.
+    pub fn observe(&mut self, message: &Message<'_>, from_bot: bool) -> usize {
+        let depth = if from_bot {
+            let parent = message
+                .message_reference
+                .as_ref()
+                .and_then(|r| r.message_id)
+                .and_then(|id| self.depths.get(&id))
+                .or_else(|| self.channels.get(&message.channel_id));
+            1 + parent.copied().unwrap_or(0)
+        } else {
+            0
+        };
+        if self.order.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.depths.remove(&oldest);
+            }
+        }
+        self.order.push_back(message.id);
+        self.depths.insert(message.id, depth);
+        self.channels.insert(message.channel_id, depth);
+        depth
+    }
+}
@@ -1,45 +1,495 @@
 #![recursion_limit = "256"]
 #![deny(warnings)]
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 
-use crate::bot::client::Client;
-use crate::bot::message::event::DispatchPayload;
-use crate::markov::Markov;
+use crate::adventure::{Adventures, Session};
+use crate::alias::Aliases;
+use crate::api::{ApiConfig, SharedKeys};
+use crate::automod::Automod;
+use crate::backend::Storage;
+use crate::birthday::{Birthdays, Celebration, Occasion};
+use crate::bot::client::{Client, Rejected};
+use crate::bot::message::event::{
+    AutoModerationAction, DispatchPayload, MessageDeleteBulk, MessageReaction, ScheduledEvent,
+};
+use crate::bridge::BridgeMessage;
+use crate::cache::{Learned, MessageCache};
+use crate::canon::Canon;
+use crate::caption::{CaptionConfig, GuildStickers};
+use crate::commands::Plan;
+use crate::continuation::{Continuation, Continuations};
+use crate::corpus::{Corpora, Mixes};
+use crate::drift::{Snapshot, Snapshots};
+use crate::emoji::{EmojiAssociations, EmojiLedger};
+use crate::export::Archive;
+use crate::filter::{Filter, FilterAction};
+use crate::handoff::Handoff;
+use crate::irc::IrcConfig;
+use crate::lang::Rules;
+use crate::latency::Latencies;
+use crate::lease::Lease;
+use crate::loop_guard::LoopGuard;
+use crate::markov::{
+    combined_fingerprint, Bias, ChainState, DecayConfig, Format, GenerationConfig, Markov, Sampling,
+};
+use crate::mastodon::{Mastodon, MastodonConfig};
+#[cfg(feature = "matrix")]
+use crate::matrix::MatrixConfig;
+use crate::moderation::{Moderation, ModerationConfig};
+use crate::news::NewsConfig;
+use crate::otel::OtlpConfig;
+use crate::persist::{load_or_default, load_sealed, parse_key, save_sealed_to, save_to, Key};
+use crate::plugin::{CommandContext, Plugins};
+use crate::poll::Poll;
+use crate::prefs::{UserSettings, MAX_TEMPERATURE, MIN_TEMPERATURE};
+use crate::privacy::PrivacyConfig;
+use crate::quota::{Limits, Quotas, Work};
+use crate::replication::{Change, ReplicationConfig};
+use crate::roulette::{Guess, Roulette};
+use crate::schedule::{now_millis, Scheduler};
+use crate::sentry::{Sentry, SentryConfig};
+use crate::settings::{ChannelSettings, Level, Place, Resolved, Settings};
+use crate::store::{MarkovStore, Scope};
+use crate::stream::{Ending, Streams};
+use crate::style::StyleTags;
+use crate::tokenizer::{Tokenizer, TokenizerConfig};
+use crate::trace::Trace;
+use crate::transform::{GuildDefaults, Transform};
+use crate::webhook::WebhookConfig;
 use bot::types::*;
 use bot::Bot;
+use chrono::Utc;
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::FutureExt;
+use rand::seq::SliceRandom;
 use rand::Rng;
-use serde::Deserialize;
-use std::fs::File;
-use std::io::{BufReader, Write};
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{Display, Write as _};
+use std::panic::AssertUnwindSafe;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 
+pub mod adventure;
+pub mod alias;
+pub mod api;
+pub mod async_markov;
+pub mod audit;
+pub mod automod;
+pub mod backend;
+pub mod bias;
+pub mod birthday;
 pub mod bot;
+pub mod bridge;
+pub mod cache;
+pub mod canon;
+pub mod caption;
+pub mod chunks;
+pub mod commands;
+pub mod config;
+pub mod continuation;
+pub mod corpus;
+pub mod curate;
+pub mod drift;
+pub mod emoji;
+pub mod export;
+pub mod filter;
+#[cfg(test)]
+mod golden;
+pub mod handoff;
+pub mod import;
+pub mod init;
+pub mod intern;
+pub mod irc;
+pub mod keywords;
+pub mod lang;
+pub mod latency;
+pub mod lease;
+pub mod loadtest;
+pub mod loop_guard;
 pub mod markov;
+pub mod mastodon;
+#[cfg(feature = "matrix")]
+pub mod matrix;
+pub mod moderation;
+pub mod news;
+pub mod otel;
+pub mod perf;
+pub mod persist;
+pub mod plugin;
+pub mod poll;
+pub mod prefs;
+pub mod privacy;
+#[cfg(feature = "profiling")]
+pub mod profile;
+#[cfg(test)]
+mod public;
+pub mod quota;
+pub mod replication;
+pub mod roulette;
+pub mod sampler;
+pub mod schedule;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod secrets;
+pub mod sentry;
+pub mod settings;
+pub mod store;
+pub mod stream;
 pub mod strings;
+pub mod style;
+pub mod tokenizer;
+pub mod trace;
+pub mod transform;
+pub mod tune;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod webhook;
+
+const MESSAGE_CACHE_SIZE: usize = 2000;
+/// What's said instead when moderation holds back everything that was generated
+const HELD_BACK: &str = "I can't think of anything nice to say";
+/// How `eg!generate --spicy` and `--tame` generate
+const SPICY_TEMPERATURE: f64 = 1.6;
+const TAME_SAMPLING: Sampling = Sampling::TopP(0.5);
+/// All a mirror does, since anything else would change data it doesn't own
+const MIRROR_COMMANDS: &[&str] = &[
+    "mimic", "generate", "speak", "story", "emojify", "clap", "follows", "precedes", "starts",
+    "headline", "event", "seed", "complete",
+];
+
+#[derive(Serialize, Deserialize, Debug)]
+enum Job {
+    Celebrations,
+    PollResults(Poll),
+    MastodonPost,
+    Engagement {
+        guild: Option<Id>,
+        channel: Id,
+        message: Id,
+    },
+    Digests,
+    AdventureRound {
+        channel: Id,
+        message: Id,
+    },
+    DriftReport,
+    MirrorReload,
+    /// Learning a channel's history a page at a time, from the newest message down
+    Train {
+        channel: Id,
+        /// Where progress is reported
+        report: Id,
+        /// The oldest message learned so far, which the next page is from before
+        before: Option<Id>,
+        learned: usize,
+        limit: Option<usize>,
+    },
+    Decay,
+    /// Unlearning one person's messages from a channel's history a page at a time, like `Train`
+    Forget {
+        channel: Id,
+        report: Id,
+        user: Id,
+        before: Option<Id>,
+        forgotten: usize,
+    },
+    /// A guild's weekly round of impersonation roulette
+    Roulette {
+        guild: Id,
+    },
+    RouletteReveal {
+        guild: Id,
+        message: Id,
+    },
+    /// Generating `eg!batch`'s sentences a page at a time
+    Batch(Batch),
+    /// Like `PollResults`, for a poll posted as one of Discord's own
+    NativePollResults(Poll),
+}
+
+/// `eg!batch`'s sentences so far, which are uploaded to `channel` once there are `count`.
+#[derive(Serialize, Deserialize, Debug)]
+struct Batch {
+    channel: Id,
+    scope: Scope,
+    guild: Option<Id>,
+    count: usize,
+    sentences: Vec<String>,
+    attempts: usize,
+}
+
+impl Job {
+    /// What kind of job it is, for its span, see `otel`.
+    fn name(&self) -> &'static str {
+        match self {
+            Job::Celebrations => "job celebrations",
+            Job::PollResults(_) => "job poll results",
+            Job::MastodonPost => "job mastodon post",
+            Job::Engagement { .. } => "job engagement",
+            Job::Digests => "job digests",
+            Job::AdventureRound { .. } => "job adventure round",
+            Job::DriftReport => "job drift report",
+            Job::MirrorReload => "job mirror reload",
+            Job::Train { .. } => "job train",
+            Job::Decay => "job decay",
+            Job::Forget { .. } => "job forget",
+            Job::Roulette { .. } => "job roulette",
+            Job::RouletteReveal { .. } => "job roulette reveal",
+            Job::Batch(_) => "job batch",
+            Job::NativePollResults(_) => "job native poll results",
+        }
+    }
+}
+
+struct Data {
+    models: MarkovStore,
+    birthdays: Birthdays,
+    jobs: Scheduler<Job>,
+    emoji: EmojiAssociations,
+    emoji_ledger: EmojiLedger,
+    opt_outs: HashSet<Id>,
+    /// Users on bridged platforms who opted out, by how the bridge names them
+    bridge_opt_outs: HashSet<String>,
+    cache: MessageCache,
+    loops: LoopGuard,
+    styles: StyleTags,
+    corpora: Corpora,
+    mixes: Mixes,
+    /// Replies to messages with a pending `Job::Engagement`
+    replies: HashMap<Id, usize>,
+    canon: Canon,
+    /// When each guild last had a message, for warming the busiest models first
+    activity: HashMap<Id, i64>,
+    quotas: Quotas,
+    transforms: GuildDefaults,
+    prefs: UserSettings,
+    /// Encrypts the models and anything else taken from what people said, when set
+    key: Option<Key>,
+    /// Never saved, for mirrors
+    read_only: bool,
+    /// Words found by `eg!audit`, by the message holding the purge button
+    audits: HashMap<Id, (Scope, HashSet<String>)>,
+    /// The seed `eg!generate` last generated from in each channel, for `eg!seed`
+    seeds: HashMap<Id, u64>,
+    streams: Streams,
+    adventures: Adventures,
+    roulette: Roulette,
+    aliases: Aliases,
+    snapshots: Snapshots,
+    channel_settings: ChannelSettings,
+    /// Which category each channel is in, as far as it's been needed
+    categories: HashMap<Id, Option<Id>>,
+    resolved: Resolved,
+    /// When the bot last chattered in each channel, for the cooldown
+    chattered: HashMap<Id, i64>,
+    /// How long interactions have taken to answer, to know which to defer
+    latencies: Latencies,
+    automod: Automod,
+    filter: Filter,
+    continuations: Continuations,
+    /// Threads started for stories and adventures, to archive once they're over
+    threads: HashSet<Id>,
+    /// Models something panicked while using, to check with `eg!fsck`
+    fsck: HashSet<Scope>,
+    /// When everything was last saved, for `save_minutes`
+    saved: i64,
+    /// Shared with the HTTP API
+    api_keys: SharedKeys,
+    plugins: Plugins,
+    #[cfg(feature = "wasm")]
+    wasm: wasm::WasmFilters,
+    #[cfg(feature = "scripting")]
+    scripts: script::ScriptHooks,
+}
+
+impl Data {
+    /// Fails if an encrypted file is there but can't be read, so it isn't saved over.
+    fn load(key: Option<Key>) -> Result<Self> {
+        Ok(Data {
+            models: MarkovStore::load(key)?,
+            birthdays: {
+                let mut birthdays: Birthdays = load_or_default("birthdays.dat");
+                birthdays.restore_sent(load_or_default("celebrations_sent.dat"));
+                birthdays
+            },
+            jobs: load_or_default("jobs.dat"),
+            emoji: load_sealed("emoji.dat", key.as_ref())?,
+            emoji_ledger: load_sealed("emoji_ledger.dat", key.as_ref())?,
+            opt_outs: load_or_default("opt_outs.dat"),
+            bridge_opt_outs: load_or_default("bridge_opt_outs.dat"),
+            cache: MessageCache::new(MESSAGE_CACHE_SIZE),
+            loops: LoopGuard::new(MESSAGE_CACHE_SIZE),
+            styles: load_or_default("styles.dat"),
+            corpora: load_or_default("corpora.dat"),
+            mixes: load_or_default("mixes.dat"),
+            replies: load_or_default("replies.dat"),
+            canon: load_sealed("canon.dat", key.as_ref())?,
+            activity: load_or_default("activity.dat"),
+            quotas: load_or_default("quotas.dat"),
+            transforms: load_or_default("transforms.dat"),
+            prefs: load_or_default("prefs.dat"),
+            key,
+            read_only: false,
+            audits: HashMap::new(),
+            seeds: HashMap::new(),
+            streams: Streams::default(),
+            adventures: load_sealed("adventures.dat", key.as_ref())?,
+            roulette: load_sealed("roulette.dat", key.as_ref())?,
+            aliases: load_or_default("aliases.dat"),
+            snapshots: load_sealed("drift.dat", key.as_ref())?,
+            channel_settings: load_or_default("channel_settings.dat"),
+            categories: HashMap::new(),
+            resolved: Resolved::default(),
+            chattered: HashMap::new(),
+            latencies: Latencies::default(),
+            automod: load_or_default("automod.dat"),
+            filter: {
+                let mut filter: Filter = load_or_default("filter.dat");
+                filter.compile();
+                filter
+            },
+            continuations: load_sealed("continuations.dat", key.as_ref())?,
+            threads: load_or_default("threads.dat"),
+            fsck: load_or_default("fsck.dat"),
+            saved: now_millis(),
+            api_keys: Arc::new(Mutex::new(load_or_default("api_keys.dat"))),
+            plugins: Plugins::builtin(),
+            #[cfg(feature = "wasm")]
+            wasm: wasm::WasmFilters::load(),
+            #[cfg(feature = "scripting")]
+            scripts: script::ScriptHooks::load("scripts.dat"),
+        })
+    }
+
+    fn save(&mut self) -> Result<u64> {
+        if self.read_only {
+            return Ok(0);
+        }
+        let size = self.models.save()?
+            + save_to("birthdays.dat", &self.birthdays)?
+            + save_to("celebrations_sent.dat", self.birthdays.sent_so_far())?
+            + save_to("jobs.dat", &self.jobs)?
+            + save_sealed_to("emoji.dat", &self.emoji, self.key.as_ref())?
+            + save_sealed_to("emoji_ledger.dat", &self.emoji_ledger, self.key.as_ref())?
+            + save_to("opt_outs.dat", &self.opt_outs)?
+            + save_to("bridge_opt_outs.dat", &self.bridge_opt_outs)?
+            + save_to("styles.dat", &self.styles)?
+            + save_to("corpora.dat", &self.corpora)?
+            + save_to("mixes.dat", &self.mixes)?
+            + save_to("replies.dat", &self.replies)?
+            + save_to("aliases.dat", &self.aliases)?
+            + save_sealed_to("canon.dat", &self.canon, self.key.as_ref())?
+            + save_sealed_to("adventures.dat", &self.adventures, self.key.as_ref())?
+            + save_sealed_to("roulette.dat", &self.roulette, self.key.as_ref())?
+            + save_sealed_to("drift.dat", &self.snapshots, self.key.as_ref())?
+            + save_sealed_to("continuations.dat", &self.continuations, self.key.as_ref())?
+            + save_to("activity.dat", &self.activity)?
+            + save_to("quotas.dat", &self.quotas)?
+            + save_to("transforms.dat", &self.transforms)?
+            + save_to("prefs.dat", &self.prefs)?
+            + save_to("channel_settings.dat", &self.channel_settings)?
+            + save_to("automod.dat", &self.automod)?
+            + save_to("filter.dat", &self.filter)?
+            + save_to("threads.dat", &self.threads)?
+            + save_to("fsck.dat", &self.fsck)?
+            + save_to("api_keys.dat", &*self.api_keys.lock().unwrap())?;
+        #[cfg(feature = "scripting")]
+        let size = size + save_to("scripts.dat", self.scripts.sources())?;
+        if let Err(e) = chunks::collect_if_dropped() {
+            eprintln!("couldn't collect unused chunks: {:#}", e);
+        }
+        self.saved = now_millis();
+        Ok(size)
+    }
+}
 
 struct Handler<'a> {
-    markov: &'a mut Markov,
+    data: &'a mut Data,
     rng: rand::rngs::ThreadRng,
     id: Option<Id>,
+    /// The bot's username, to notice it being named
+    name: Option<String>,
     cfg: BotConfig,
+    token: TokenBuf,
+    mastodon: Option<Mastodon>,
+    moderation: Option<Moderation>,
+    tokenizer: Tokenizer,
+    /// Set by `eg!token reload` to reconnect with the new token
+    restart: bool,
+    /// The event being handled
+    trace: Trace,
+    sentry: Option<Sentry>,
+    stickers: GuildStickers,
+    /// Where changes to models go for standbys, on a primary
+    replication: Option<replication::Log>,
 }
 
 impl Handler<'_> {
+    /// Logs `text` with the event it's about.
+    fn log(&self, text: impl Display) {
+        eprintln!("[{}] {}", self.trace, text);
+    }
+
+    /// Sends `error` to Sentry, when it's set up.
+    fn report(&self, error: &anyhow::Error, command: Option<&str>) {
+        if let Some(sentry) = &self.sentry {
+            let context = sentry::Context {
+                guild: self.trace.guild(),
+                command,
+                trace: Some(self.trace.to_string()),
+            };
+            sentry.report(error, context);
+        }
+    }
+
     async fn handle_message(&mut self, client: &Client, message: &Message<'_>) -> Result<()> {
-        let (cmd, args) = match message.content.as_str().strip_prefix("eg!").and_then(|s| {
-            let mut args = s.split_whitespace().filter(|a| !a.is_empty());
-            args.next().map(|cmd| (cmd, args))
-        }) {
-            Some(p) => p,
-            _ => return Ok(()),
+        let words: Vec<_> = match message
+            .content
+            .as_str()
+            .strip_prefix(self.cfg.prefix.as_str())
+        {
+            Some(s) => s.split_whitespace().collect(),
+            None => return Ok(()),
+        };
+        self.run_command(client, message, &words, false).await
+    }
+
+    /// Runs the command `words` start with, with the rest as its arguments. Unless it's already
+    /// been `expanded`, an alias is expanded and run instead.
+    async fn run_command(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        words: &[&str],
+        expanded: bool,
+    ) -> Result<()> {
+        let mut args = words.iter().copied();
+        let cmd = match args.next() {
+            Some(cmd) => cmd,
+            None => return Ok(()),
         };
+        if self.cfg.mirror && !MIRROR_COMMANDS.contains(&cmd) {
+            return client
+                .create_message(
+                    message.channel_id,
+                    "I'm a read-only mirror, I can only generate",
+                )
+                .await;
+        }
+        let scope = self.scope(message.guild_id, message.channel_id);
 
         macro_rules! match_command {
             (
                 ($cmd:expr, $args:expr) {
-                    $( $name:literal ($($param:pat),*) => $result:expr )*
+                    $( $name:literal ($($param:pat),*) $([$rest:ident])? => $result:expr )*
                 }
+                $( else [$fallback_args:ident] => $fallback:block )?
             ) => {{
+                /// Every built-in command, which aliases can't take the place of
+                const COMMANDS: &[&str] = &[$($name),*];
                 let mut args = $args;
                 match (cmd) {
                     $(
@@ -50,27 +500,101 @@ impl Handler<'_> {
                                     _ => ::anyhow::bail!("expected `{}` but none was provided", stringify!($param)),
                                 };
                             )*
+                            $( let $rest = &mut args; )?
                             $result
                         }
                     )*
-                    _ => ()
+                    _ => { $( let $fallback_args = &mut args; $fallback )? }
                 }
             }}
         }
 
         match_command! {
             (cmd, args) {
-                "mimic"() => self.mimic(client, message.channel_id).await?
-                "follows"(word) => {
-                    println!("{}", word);
-                    self.create_list_message(client, message.channel_id, self.markov.what_follows(word)).await?;
+                "mimic"() => self.mimic(client, message, &scope).await?
+                "complete"() [args] => self.complete_command(client, message, &scope, args).await?
+                "generate"() [args] => self.generate_command(client, message, &scope, Vec::new(), args).await?
+                "speak"(channel) [args] => {
+                    let mut args = std::iter::once("--channel").chain(Some(channel)).chain(args);
+                    self.generate_command(client, message, &scope, Vec::new(), &mut args).await?
+                }
+                "story"() [args] => self.story(client, message, &scope, args).await?
+                "seed"() => self.seed_command(client, message).await?
+                "adventure"(action) [args] => self.adventure_command(client, message, action, args).await?
+                "roulette"(action) => self.roulette_command(client, message, action).await?
+                "emojify"() [args] => {
+                    self.generate_command(client, message, &scope, vec![Transform::Emojify], args).await?
+                }
+                "clap"() [args] => {
+                    self.generate_command(client, message, &scope, vec![Transform::Clap], args).await?
+                }
+                "style"(action) [args] => self.style_command(client, message, action, args).await?
+                "corpus"(action) [args] => self.corpus_command(client, message, action, args).await?
+                "mix"() [args] => self.mix_command(client, message, &scope, args).await?
+                "transforms"() [args] => self.transforms_command(client, message, args).await?
+                "prefs"() [args] => self.prefs_command(client, message, args).await?
+                "settings"() [args] => self.settings_command(client, message, args).await?
+                "tune"() => self.tune_command(client, message).await?
+                "blocklist"() [args] => self.blocklist_command(client, message, args).await?
+                "filter"() [args] => self.filter_command(client, message, args).await?
+                "tokenize"(action) [args] => self.tokenize_command(client, message, action, args).await?
+                "follows"(word) [args] => {
+                    let words: Vec<_> = std::iter::once(word).chain(args).collect();
+                    let title = format!("What follows **{}**", words.join(" "));
+                    self.transitions(client, message, &scope, &title, |m| m.what_follows(&words))
+                        .await?
+                }
+                "precedes"(word) [args] => {
+                    let words: Vec<_> = std::iter::once(word).chain(args).collect();
+                    let title = format!("What comes before **{}**", words.join(" "));
+                    self.transitions(client, message, &scope, &title, |m| m.what_precedes(&words))
+                        .await?
                 }
                 "starts"() => {
-                    self.create_list_message(client, message.channel_id, self.markov.what_starts()).await?;
+                    let title = "What messages start with";
+                    self.transitions(client, message, &scope, title, Markov::what_starts).await?
+                }
+                "poll"() => self.poll(client, message.channel_id, message.guild_id, &scope).await?
+                "headline"() => self.headline(client, message.channel_id).await?
+                "event"() => self.event(client, message.channel_id, message.guild_id).await?
+                "drift"() => self.drift(client, message.channel_id).await?
+                "stats"() => {
+                    if let Some(reply) = self.spend(message.guild_id, Work::Scan) {
+                        return client.create_message(message.channel_id, &reply).await;
+                    }
+                    self.model_stats(client, message.channel_id, &scope).await?
                 }
+                "emojistats"() [args] => self.emoji_stats(client, message, args.next()).await?
+                "optout"() => self.set_opt_out(client, message, true).await?
+                "optin"() => self.set_opt_out(client, message, false).await?
                 "save"() => self.save(client, message.channel_id).await?
+                "quota"() [args] => self.quota_command(client, message, args).await?
+                "token"(action) => self.token_command(client, message, action).await?
+                "reload"() => self.reload_command(client, message).await?
+                "perf"() => self.perf(client, message).await?
+                "profile"(seconds) => self.profile(client, message, seconds.parse()?).await?
                 "clean"() => self.clean(client, message).await?
+                "fsck"() => self.fsck(client, message).await?
+                "fingerprint"() => self.fingerprint(client, message).await?
+                "audit"() => self.audit(client, message, &scope).await?
+                "dataexport"() => self.data_export(client, message).await?
+                "handoff"() [args] => self.handoff_command(client, message, args).await?
+                "canonize"() [args] => self.canonize(client, message, args).await?
+                "canon"() => self.list_canon(client, message).await?
+                "uncanonize"(index) => self.uncanonize(client, message, index.parse()?).await?
+                "wasm"(action) [args] => self.wasm_command(client, message, action, args).await?
+                "script"(action) [args] => self.script_command(client, message, action, args).await?
+                "birthday"(action) [args] => {
+                    self.celebration_command(client, message, Occasion::Birthday, action, args).await?
+                }
+                "anniversary"(action) [args] => {
+                    self.celebration_command(client, message, Occasion::Anniversary, action, args).await?
+                }
                 "learn"(channel, max) => {
+                    // it reads any channel the bot can see, not just this server's
+                    if self.cfg.public && !self.is_admin_message(message) {
+                        bail!("only the bot's admins can use `learn` on a public bot");
+                    }
                     let max = match max.to_lowercase().as_str() {
                         "full" => None,
                         s => Some(s.parse()?)
@@ -78,6 +602,36 @@ impl Handler<'_> {
                     let learn_channel_id = channel.trim_start_matches("<#").trim_end_matches(">").parse()?;
                     self.learn_channel(client, message.channel_id, learn_channel_id, max).await?;
                 }
+                "train"(channel) [args] => self.train_command(client, message, channel, args).await?
+                "forget"(what) [args] => self.forget_command(client, message, what, args).await?
+                "merge"() [args] => self.merge(client, message, args.next()).await?
+                "import"() [args] => self.import(client, message, args).await?
+                "apikey"(action) [args] => self.api_key_command(client, message, action, args).await?
+                "batch"(count) => self.batch_command(client, message, &scope, count).await?
+                "alias"() [args] => self.alias_command(client, message, args, COMMANDS).await?
+            }
+            else [args] => {
+                if !expanded {
+                    let words = words.iter().copied().map(String::from).collect();
+                    let aliased =
+                        self.data
+                            .aliases
+                            .expand(message.guild_id, &self.cfg.aliases, words, COMMANDS)?;
+                    if let Some(words) = aliased {
+                        let words: Vec<_> = words.iter().map(String::as_str).collect();
+                        return Box::pin(self.run_command(client, message, &words, true)).await;
+                    }
+                }
+                let ctx = CommandContext {
+                    author: message.author.id,
+                    channel: message.channel_id,
+                    guild: message.guild_id,
+                    args: args.collect(),
+                    model: self.data.models.get_mut(&scope),
+                };
+                if let Some(reply) = self.data.plugins.command(cmd, ctx)? {
+                    client.create_message(message.channel_id, &reply).await?;
+                }
             }
         }
 
@@ -99,8 +653,91 @@ impl Handler<'_> {
         Ok(())
     }
 
-    async fn save(&self, client: &Client, channel: Id) -> Result<()> {
-        let result = save_markov(self.markov);
+    async fn pick_emoji(&mut self, client: &Client, message: &Message<'_>) -> Result<()> {
+        if !self.cfg.emoji_reactions || !self.rng.gen_ratio(1, self.cfg.emoji_reaction_odds) {
+            return Ok(());
+        }
+        let guild = match message.guild_id {
+            Some(g) => g,
+            None => return Ok(()),
+        };
+        if let Some(emoji) = self
+            .data
+            .emoji
+            .pick(guild, message.content.as_str(), &mut self.rng)
+        {
+            client
+                .create_reaction(message.channel_id, message.id, emoji)
+                .await?;
+        }
+        Ok(())
+    }
+
+    fn ingest_reaction(&mut self, reaction: &MessageReaction<'_>, added: bool) {
+        if self.id == Some(reaction.user_id) || self.data.opt_outs.contains(&reaction.user_id) {
+            return;
+        }
+        let (guild, emoji) = match (reaction.guild_id, reaction.emoji.to_reaction_string()) {
+            (Some(g), Some(e)) => (g, e),
+            _ => return,
+        };
+        let cached = match self.data.cache.get(reaction.message_id) {
+            Some(c) if !self.data.opt_outs.contains(&c.author) => c,
+            _ => return,
+        };
+        if added {
+            self.data.emoji.insert(guild, &cached.content, &emoji);
+        } else {
+            self.data.emoji.remove(guild, &cached.content, &emoji);
+        }
+        let users = (reaction.user_id, cached.author);
+        self.data
+            .emoji_ledger
+            .record(users, guild, &cached.content, &emoji, added);
+    }
+
+    async fn emoji_stats(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        word: Option<&str>,
+    ) -> Result<()> {
+        const SHOWN: usize = 10;
+        let top = match message.guild_id {
+            Some(guild) => self.data.emoji.top(guild, word, SHOWN),
+            None => Vec::new(),
+        };
+        let text = if top.is_empty() {
+            String::from("Nothing!")
+        } else {
+            top.into_iter().fold(String::new(), |p, (emoji, count)| {
+                p + format!("{} ×{}  ", emoji::display(emoji), count).as_str()
+            })
+        };
+        client.create_message(message.channel_id, &text).await
+    }
+
+    async fn set_opt_out(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        opt_out: bool,
+    ) -> Result<()> {
+        let reply = if opt_out {
+            self.data.opt_outs.insert(message.author.id);
+            let emoji = &mut self.data.emoji;
+            self.data.emoji_ledger.purge(message.author.id, emoji);
+            "Okay, I won't learn from your messages or reactions anymore, and I've forgotten the \
+             emoji reactions I learned from you"
+        } else {
+            self.data.opt_outs.remove(&message.author.id);
+            "Welcome back, I'll learn from you again"
+        };
+        client.create_message(message.channel_id, reply).await
+    }
+
+    async fn save(&mut self, client: &Client, channel: Id) -> Result<()> {
+        let result = self.data.save();
         let msg = match &result {
             Ok(s) => format!("Successfully saved ({})", file_size_to_string(*s)),
             Err(_) => String::from("Error saving :("),
@@ -132,217 +769,5531 @@ impl Handler<'_> {
         Ok(())
     }
 
-    async fn mimic(&mut self, client: &Client, channel: Id) -> Result<()> {
-        client
-            .create_message(
-                channel,
-                self.markov
-                    .generate_sequence(&mut self.rng)
-                    .fold(String::new(), |p, c| p + &c + " ")
-                    .as_str(),
-            )
-            .await
+    fn scope(&self, guild: Option<Id>, _channel: Id) -> Scope {
+        Scope::for_guild(guild, self.per_guild())
     }
 
-    async fn clean(&mut self, client: &Client, message: &Message<'_>) -> Result<()> {
-        if self.is_admin_message(message) {
-            let removed = self.markov.clean();
-            client
-                .create_message(message.channel_id, &format!("Removed {} entries", removed))
-                .await
-        } else {
-            client
-                .create_message(
-                    message.channel_id,
-                    "Watch it, string bean. You aren't an admin",
-                )
-                .await
+    /// Whether every channel gets its own model too. Never when public, since those can't be
+    /// encrypted with their guild's key.
+    fn channel_models(&self) -> bool {
+        self.cfg.channel_models && !self.cfg.public
+    }
+
+    /// Whether every guild gets its own model, which public hosting always needs.
+    fn per_guild(&self) -> bool {
+        self.cfg.guild_models || self.cfg.public
+    }
+
+    /// Writes models that haven't been used in a while to disk and drops them from memory.
+    fn unload_idle_models(&mut self) -> Result<()> {
+        use std::time::Duration;
+        let mut unloaded = 0;
+        if self.per_guild() {
+            let idle = Duration::from_secs(self.cfg.guild_idle_minutes * 60);
+            unloaded += self
+                .data
+                .models
+                .unload_idle(idle, |s| matches!(s, Scope::Guild(_)))?;
+        }
+        if self.channel_models() {
+            let idle = Duration::from_secs(self.cfg.guild_idle_minutes * 60);
+            unloaded += self
+                .data
+                .models
+                .unload_idle(idle, |s| matches!(s, Scope::Channel(_)))?;
         }
+        if let Some(hours) = self.cfg.evict_idle_hours {
+            let idle = Duration::from_secs(hours * 60 * 60);
+            unloaded += self.data.models.unload_idle(idle, |_| true)?;
+        }
+        if unloaded > 0 {
+            self.log(format_args!("unloaded {} idle models", unloaded));
+        }
+        Ok(())
+    }
+
+    /// Starts loading the models of the most recently active guilds in the background.
+    fn warm_models(&mut self) {
+        const WARM_GUILDS: usize = 50;
+        if !self.per_guild() {
+            return;
+        }
+        let mut guilds: Vec<_> = self.data.activity.iter().collect();
+        guilds.sort_by_key(|(_, &last)| std::cmp::Reverse(last));
+        let scopes = guilds
+            .into_iter()
+            .take(WARM_GUILDS)
+            .map(|(&guild, _)| Scope::Guild(guild))
+            .collect();
+        self.data.models.warm(scopes);
+    }
+
+    fn generate(&mut self, scope: &Scope, guild: Option<Id>) -> String {
+        let settings = self.resolve(Place::guild(guild), None);
+        self.generate_at(scope, &settings)
+    }
+
+    fn generate_at(&mut self, scope: &Scope, settings: &Settings) -> String {
+        let mut state = ChainState::new(&mut self.rng);
+        self.generate_from(scope, settings, &mut state)
     }
 
-    async fn create_list_message(
+    /// Generates a sentence going on from `state`, leaving it where the sentence ended.
+    fn generate_from(
         &mut self,
-        client: &Client,
-        channel: Id,
-        iter: impl IntoIterator<Item = impl ToString>,
-    ) -> Result<()> {
-        let mut iter = iter.into_iter().peekable();
-        let string = if iter.peek().is_none() {
-            String::from("Nothing!")
+        scope: &Scope,
+        settings: &Settings,
+        state: &mut ChainState,
+    ) -> String {
+        let max_tokens = settings.max_words.unwrap_or(self.cfg.generation.max_tokens);
+        let config = GenerationConfig {
+            temperature: settings
+                .temperature
+                .unwrap_or(self.cfg.generation.temperature),
+            max_tokens,
+            min_tokens: settings
+                .min_words
+                .unwrap_or(self.cfg.generation.min_tokens)
+                .min(max_tokens),
+            ..self.cfg.generation
+        };
+        let mix = settings.mix.as_ref();
+        let blend = self.data.corpora.blend(scope, mix);
+        let hidden = self.data.prefs.hidden_tags();
+        let keep = |w: &String| !hidden.contains(w.trim_matches('`'));
+        let biases = self.biases(settings);
+        let sampling = settings.sampling.unwrap_or_default();
+        // other servers only get the shared model privatized when they have their own
+        let privacy = self
+            .cfg
+            .shared_privacy
+            .as_ref()
+            .filter(|_| self.cfg.guild_models);
+        let words: Vec<_> = if blend.len() == 1 {
+            let mut chain = self.data.models.get_all_shared(
+                std::slice::from_ref(scope),
+                privacy,
+                &mut self.rng,
+            )[0]
+            .resume(state)
+            .config(config)
+            .sampling(sampling)
+            .biases(biases);
+            let words = chain.sentence().into_iter().filter(keep).collect();
+            *state = chain.state();
+            words
         } else {
-            iter.fold(String::new(), |p, c| p + &c.to_string() + " ")
+            let scopes: Vec<_> = blend.iter().map(|(s, _)| s.clone()).collect();
+            let models: Vec<_> = self
+                .data
+                .models
+                .get_all_shared(&scopes, privacy, &mut self.rng)
+                .into_iter()
+                .zip(blend.iter().map(|(_, w)| *w))
+                .collect();
+            let mut chain = Markov::resume_blended(&models, state)
+                .config(config)
+                .sampling(sampling)
+                .biases(biases);
+            let words = chain.sentence().into_iter().filter(keep).collect();
+            *state = chain.state();
+            words
         };
+        let text = self.tokenizer.detokenize(scope.owner(), words);
+        let text = self.data.plugins.post_process(text, &mut self.rng);
+        #[cfg(feature = "wasm")]
+        let text = self.data.wasm.output(text);
+        fit_message(text)
+    }
 
-        client.create_message(channel, &string).await
+    /// Everything that should steer generating with `settings`.
+    fn biases(&self, settings: &Settings) -> Vec<Box<dyn Bias>> {
+        let mut biases: Vec<Box<dyn Bias>> = Vec::new();
+        if let Some(words) = settings.suppress.clone().filter(|w| !w.is_empty()) {
+            biases.push(Box::new(bias::Suppress {
+                words,
+                factor: self.cfg.suppress_factor,
+            }));
+        }
+        if self.cfg.repetition_penalty != 1.0 {
+            biases.push(Box::new(bias::RepetitionPenalty {
+                penalty: self.cfg.repetition_penalty,
+            }));
+        }
+        if !self.cfg.avoid_starts.is_empty() {
+            biases.push(Box::new(bias::AvoidStarts {
+                words: self
+                    .cfg
+                    .avoid_starts
+                    .iter()
+                    .map(|w| w.to_lowercase())
+                    .collect(),
+                factor: self.cfg.suppress_factor,
+            }));
+        }
+        biases
     }
 
-    async fn learn_channel(
-        &mut self,
-        client: &Client,
-        return_channel: Id,
-        channel: Id,
-        max: Option<usize>,
-    ) -> Result<()> {
-        let mut oldest_id = None;
-        let mut oldest_ts = None;
-        let mut sum = 0;
-        loop {
-            let mut response = client
-                .get_channel_messages(channel, oldest_id.take())
-                .await?;
-            let rate_limit_end = response.rate_limit_end();
-            let messages = response.get_response().await?;
+    fn learn(&mut self, scopes: &[Scope], text: String) -> Option<Learned> {
+        self.learn_weighted(scopes, text, 1)
+    }
 
-            sum += messages.len();
-            for message in messages {
-                self.remember(&message);
-                if oldest_ts.map_or(true, |ts| message.timestamp < ts) {
-                    oldest_id = Some(message.id);
-                    oldest_ts = Some(message.timestamp);
-                }
+    /// Learns `text` as if it had been seen `times` times, returning what it was learned as.
+    fn learn_weighted(&mut self, scopes: &[Scope], text: String, times: usize) -> Option<Learned> {
+        if self.cfg.mirror {
+            return None;
+        }
+        if self.data.filter.blocks(&text) {
+            return None;
+        }
+        let mut span = otel::Span::start("model learn");
+        let _timer = perf::time(perf::Op::Learn);
+        span.attribute("taco.models", scopes.len());
+        // a server's own tokenizer decides how everything learned with its models is split
+        let guild = scopes.iter().find_map(Scope::owner);
+        let words = self.learnable_words(guild, text)?;
+        let mut learned = Vec::with_capacity(scopes.len());
+        for scope in scopes {
+            if !self.has_room(scope) {
+                continue;
             }
+            let model = self.data.models.get_mut(scope);
+            for _ in 0..times {
+                model.insert_sequence(words.iter().cloned());
+            }
+            learned.push(scope.clone());
+        }
+        if !learned.is_empty() {
+            self.replicate(Change::Learn {
+                scopes: learned.clone(),
+                words: words.clone(),
+                times,
+            });
+        }
+        Some(Learned {
+            scopes: learned,
+            words,
+        })
+    }
 
-            if oldest_id.is_none() || max.map(|m| sum >= m).unwrap_or(false) {
-                client
-                    .create_message(return_channel, &format!("learned from {} messages", sum))
-                    .await?;
-                break Ok(());
+    /// `learn` for a lot of texts at once, like an import, learned on every core. A server's
+    /// model can go past its `model_entries` by what one call learns, so learn in batches.
+    /// Returns how many of them were learnable.
+    fn learn_all(&mut self, scopes: &[Scope], texts: Vec<String>) -> usize {
+        if self.cfg.mirror {
+            return 0;
+        }
+        let mut span = otel::Span::start("model learn");
+        let _timer = perf::time(perf::Op::Learn);
+        span.attribute("taco.models", scopes.len());
+        span.attribute("taco.messages", texts.len());
+        let guild = scopes.iter().find_map(Scope::owner);
+        let seqs: Vec<_> = texts
+            .into_iter()
+            .filter_map(|text| match self.data.filter.blocks(&text) {
+                true => None,
+                false => self.learnable_words(guild, text),
+            })
+            .collect();
+        let learned: Vec<_> = scopes
+            .iter()
+            .filter(|scope| self.has_room(scope))
+            .cloned()
+            .collect();
+        for scope in &learned {
+            self.data.models.get_mut(scope).insert_sequences(&seqs);
+        }
+        if !learned.is_empty() {
+            for words in &seqs {
+                self.replicate(Change::Learn {
+                    scopes: learned.clone(),
+                    words: words.clone(),
+                    times: 1,
+                });
             }
+        }
+        seqs.len()
+    }
 
-            if let Some(time) = rate_limit_end {
-                async_io::Timer::at(time).await;
+    /// Whether `scope`'s model can learn more, under its server's `model_entries`.
+    fn has_room(&mut self, scope: &Scope) -> bool {
+        match scope {
+            Scope::Guild(guild) => {
+                let max = self.limits(*guild).model_entries;
+                let model = self.data.models.get_mut(scope);
+                max.is_none_or(|max| model.entry_count() < max)
             }
+            _ => true,
         }
     }
 
-    fn remember(&mut self, message: &Message<'_>) {
-        let words: Vec<_> = message
-            .content
-            .as_str()
-            .split_whitespace()
-            .filter_map(|s| {
-                if !s.is_empty() {
-                    if let Some(id) = s.strip_prefix("<@!").and_then(|s| s.strip_suffix('>')) {
-                        for user in &message.mentions {
-                            if Ok(user.id) == id.parse() {
-                                return Some(format!("`{}#{}`", user.username, user.discriminator));
-                            }
-                        }
-                        Some(format!("`<@!{}>`", id))
-                    } else {
-                        Some(String::from(s))
-                    }
-                } else {
-                    None
-                }
-            })
-            .collect();
-        if words.len() >= 3 {
-            self.markov.insert_sequence(words);
+    /// What `text` is learned as, if it's learned at all.
+    fn learnable_words(&mut self, guild: Option<Id>, text: String) -> Option<Vec<String>> {
+        let text = self.data.plugins.ingest(text)?;
+        #[cfg(feature = "wasm")]
+        let text = self.data.wasm.ingest(text)?;
+        let words = self.tokenizer.tokenize(guild, &text);
+        if words.len() < 3 && !words.iter().any(|w| caption::is_tag(w)) {
+            return None;
         }
+        Some(words)
     }
 
-    fn is_admin_message(&self, message: &Message<'_>) -> bool {
-        self.cfg
-            .admins
-            .iter()
-            .any(|admin| *admin == message.author.id)
+    /// Takes back whatever `learn` learned.
+    fn unlearn(&mut self, learned: &Learned) {
+        for scope in &learned.scopes {
+            self.data
+                .models
+                .get_mut(scope)
+                .remove_sequence(learned.words.iter().cloned());
+        }
+        self.replicate(Change::Unlearn {
+            scopes: learned.scopes.clone(),
+            words: learned.words.clone(),
+        });
     }
-}
 
-fn save_markov(markov: &Markov) -> Result<u64> {
-    let mut file = File::create("markov.dat")?;
-    file.write_all(&bincode::serialize(markov)?)?;
-    Ok(file.metadata()?.len())
-}
+    /// Pins `text` in `scope`'s model with the canon weight.
+    fn ensure(&mut self, scope: Scope, text: &str) {
+        let words = self.tokenizer.tokenize(scope.owner(), text);
+        self.data
+            .models
+            .get_mut(&scope)
+            .ensure_sequence(words.iter().cloned(), self.cfg.canon_weight);
+        self.replicate(Change::Ensure {
+            scope,
+            words,
+            weight: self.cfg.canon_weight,
+        });
+    }
 
-fn file_size_to_string(size: u64) -> String {
-    let mut size_f = size as f64;
-    let suffixes = ["bytes", "kb", "mb", "gb", "tb"];
-    for s in suffixes.iter() {
-        if size_f / 1024.0 < 1.0 {
-            return format!("{:.2}{}", size_f, s);
+    /// Appends `change` to the replication log, when there is one.
+    fn replicate(&mut self, change: Change) {
+        if let Some(log) = &mut self.replication {
+            if let Err(e) = log.append(&change) {
+                self.log(format_args!(
+                    "couldn't append to the replication log: {}",
+                    e
+                ));
+            }
         }
-        size_f /= 1024.0;
     }
-    String::from("way too fricken big file!")
-}
 
-impl bot::AsyncDispatchHandler for Handler<'_> {
-    fn handle_message<'a>(
-        &'a mut self,
-        payload: DispatchPayload<'a>,
-        client: &'a Client,
-    ) -> bot::AsyncDispatchFuture<'a> {
-        Box::pin(async move {
-            match payload {
-                DispatchPayload::MessageCreate(message) => {
-                    self.add_emojis(client, &message).await?;
-                    if self.id != Some(message.author.id) {
-                        self.handle_wot(client, &message).await?;
-                        self.engineer_gaming(client, &message).await?;
-                        self.handle_message(client, &message).await?;
-                        if !self
-                            .cfg
-                            .channel_blacklist
-                            .iter()
-                            .any(|&bc| bc == message.channel_id)
-                        {
-                            self.remember(&message);
-                        }
-                    }
-                    Ok(())
-                }
-                DispatchPayload::Ready(ready) => {
-                    self.id = Some(ready.user.id);
-                    for &chan in &self.cfg.announcement_channels {
-                        client.create_message(chan, "Dispenser goin' up!").await?;
-                    }
-                    Ok(())
-                }
-                _ => Ok(()),
+    /// Keeps whatever AutoMod flagged from being learned, and maybe what it matched too.
+    fn automod_action(&mut self, action: &AutoModerationAction<'_>) {
+        if let Some(id) = action.message_id {
+            match self.data.cache.take_learned(id) {
+                Some(learned) => self.unlearn(&learned),
+                // it usually gets here before the message does
+                None => self.data.automod.flag(id),
             }
-        })
+        }
+        if self.cfg.automod_blocklist {
+            if let Some(keyword) = action.matched_keyword.as_ref().map(|k| k.as_str()) {
+                self.data.automod.add(action.guild_id, keyword);
+            }
+        }
     }
-}
 
-#[derive(Deserialize)]
-struct BotConfig {
-    token: TokenBuf,
-    intents: Intents,
-    admins: Vec<Id>,
-    channel_blacklist: Vec<Id>,
-    announcement_channels: Vec<Id>,
-}
+    /// Unlearns messages a moderator bulk-deleted, as far as they're still cached.
+    async fn unlearn_bulk(&mut self, client: &Client, deleted: &MessageDeleteBulk) -> Result<()> {
+        let mut unlearned = 0;
+        for &id in &deleted.ids {
+            if let Some(learned) = self.data.cache.take_learned(id) {
+                self.unlearn(&learned);
+                unlearned += 1;
+            }
+        }
+        match self.cfg.audit_channel {
+            Some(channel) if unlearned > 0 => {
+                let text = format!(
+                    "🧹 Unlearned {} of {} messages bulk-deleted in <#{}> (trace `{}`)",
+                    unlearned,
+                    deleted.ids.len(),
+                    deleted.channel_id,
+                    self.trace
+                );
+                client.create_message(channel, &text).await
+            }
+            _ => Ok(()),
+        }
+    }
 
-fn run(markov: &mut Markov) -> Result<()> {
-    let bot_cfg: BotConfig = serde_json::from_reader(BufReader::new(File::open("bot.json")?))?;
+    fn generate_curated(&mut self, scope: &Scope, max_chars: usize) -> Option<String> {
+        const ATTEMPTS: usize = 20;
+        const MIN_WORDS: usize = 4;
+        let mut previous: Option<String> = None;
+        for _ in 0..ATTEMPTS {
+            let text = curate::scrub(&self.generate(scope, None));
+            if self.same_as(previous.as_deref(), &text) {
+                continue;
+            }
+            if curate::is_postable(&text, MIN_WORDS, max_chars) && !self.data.filter.blocks(&text) {
+                return Some(text);
+            }
+            previous = Some(text);
+        }
+        None
+    }
 
-    let bot = Bot::new(bot_cfg.token.clone(), bot_cfg.intents);
-    bot.run(Handler {
-        markov,
-        rng: rand::thread_rng(),
-        id: None,
-        cfg: bot_cfg,
-    })
-}
+    /// Whether a reroll came out too close to the attempt before it to be worth another look.
+    fn same_as(&self, previous: Option<&str>, text: &str) -> bool {
+        previous.is_some_and(|p| curate::similarity(p, text) > self.cfg.reroll_max_similarity)
+    }
 
-fn main() {
-    let mut markov = File::open("markov.dat")
-        .map_err(bincode::Error::from)
-        .and_then(bincode::deserialize_from)
-        .map_err(|e| {
-            eprintln!("{}", e);
-            e
-        })
-        .unwrap_or_else(|_| Markov::new());
+    /// `generate`, rerolling whatever moderation holds back. `None` if nothing got through.
+    async fn generate_moderated(&mut self, scope: &Scope, guild: Option<Id>) -> Option<String> {
+        let settings = self.resolve(Place::guild(guild), None);
+        self.generate_moderated_at(scope, &settings).await
+    }
+
+    async fn generate_moderated_at(
+        &mut self,
+        scope: &Scope,
+        settings: &Settings,
+    ) -> Option<String> {
+        let mut state = ChainState::new(&mut self.rng);
+        self.generate_moderated_from(scope, settings, &mut state)
+            .await
+    }
+
+    /// `generate_moderated_at`, carrying on from where the model (or any in its blend) best matches
+    /// the end of `prompt`, or else from one of its words.
+    async fn generate_prompted(
+        &mut self,
+        scope: &Scope,
+        settings: &Settings,
+        prompt: &str,
+    ) -> Option<String> {
+        let prompt = self.tokenizer.tokenize(scope.owner(), prompt);
+        let context = self.prompt_context(scope, settings, &prompt);
+        self.generate_continuing(scope, settings, context).await
+    }
+
+    /// `partial` as it was given, then the rest of a sentence carrying on from where the model (or
+    /// any in its blend) has seen its last words. Failing that from one of its words, and from the
+    /// start of a sentence if it knows none of them.
+    async fn generate_completion(
+        &mut self,
+        scope: &Scope,
+        settings: &Settings,
+        partial: &str,
+    ) -> Option<String> {
+        let prompt = self.tokenizer.tokenize(scope.owner(), partial);
+        let mut state = match self.prompt_context(scope, settings, &prompt) {
+            Some(context) => ChainState::from_context(context, &mut self.rng),
+            None => ChainState::new(&mut self.rng),
+        };
+        let rest = self
+            .generate_moderated_from(scope, settings, &mut state)
+            .await?;
+        // punctuation goes straight after the last word, like it would've been learned
+        let attached = rest.starts_with(['.', ',', '!', '?', ';', ':', ')']);
+        let space = if attached || rest.is_empty() { "" } else { " " };
+        Some(format!("{}{}{}", partial.trim(), space, rest.trim()))
+    }
+
+    /// `generate_moderated_at` answering `text`: starting from one of its keywords the model (or
+    /// any in its blend) knows, picked at random, so the answer stays on topic. Anywhere if it
+    /// knows none of them.
+    async fn generate_reply(
+        &mut self,
+        scope: &Scope,
+        settings: &Settings,
+        text: &str,
+    ) -> Option<String> {
+        /// Keywords tried at most, since each is a look through the whole model
+        const MAX_KEYWORDS: usize = 5;
+        let mut keywords = keywords::extract(&self.tokenizer.tokenize(scope.owner(), text));
+        keywords.shuffle(&mut self.rng);
+        let mut context = None;
+        for keyword in keywords.into_iter().take(MAX_KEYWORDS) {
+            context = self.prompt_context(scope, settings, &[keyword]);
+            if context.is_some() {
+                break;
+            }
+        }
+        self.generate_continuing(scope, settings, context).await
+    }
+
+    /// Where a walk carrying on from `prompt` should start, out of every model in `scope`'s blend.
+    fn prompt_context(
+        &mut self,
+        scope: &Scope,
+        settings: &Settings,
+        prompt: &[String],
+    ) -> Option<Vec<markov::Word>> {
+        let blend = self.data.corpora.blend(scope, settings.mix.as_ref());
+        let scopes: Vec<_> = blend.into_iter().map(|(s, _)| s).collect();
+        let mut best: Option<(usize, Vec<markov::Word>)> = None;
+        for model in self.data.models.get_all(&scopes) {
+            if let Some((matched, context)) = model.prompt_context(prompt, &mut self.rng) {
+                if best.as_ref().is_none_or(|(m, _)| matched > *m) {
+                    best = Some((matched, context));
+                }
+            }
+        }
+        best.map(|(_, context)| context)
+    }
+
+    /// Generates from `context` on, with `context` at the start, or from anywhere without one.
+    async fn generate_continuing(
+        &mut self,
+        scope: &Scope,
+        settings: &Settings,
+        context: Option<Vec<markov::Word>>,
+    ) -> Option<String> {
+        let mut state = match context {
+            Some(context) => ChainState::from_context(context, &mut self.rng),
+            None => ChainState::new(&mut self.rng),
+        };
+        let start = self
+            .tokenizer
+            .detokenize(scope.owner(), state.context_words());
+        let text = self
+            .generate_moderated_from(scope, settings, &mut state)
+            .await?;
+        Some(format!("{} {}", start, text).trim().to_string())
+    }
+
+    /// `generate_from`, rerolling whatever moderation holds back from the same place in the walk.
+    async fn generate_moderated_from(
+        &mut self,
+        scope: &Scope,
+        settings: &Settings,
+        state: &mut ChainState,
+    ) -> Option<String> {
+        let mut span = otel::Span::start("model generate");
+        let _timer = perf::time(perf::Op::Generate);
+        span.attribute("taco.scope", scope.name());
+        let mut rerolls = self.moderation.as_ref().map_or(0, |m| m.rerolls());
+        if self.data.filter.blocks_text() && self.cfg.filter_action == FilterAction::Regenerate {
+            rerolls = rerolls.max(filter::REROLLS);
+        }
+        let attempts = rerolls + 1;
+        let mut previous: Option<String> = None;
+        for i in 0..attempts {
+            let mut attempt = state.clone();
+            if i > 0 {
+                attempt.reseed(&mut self.rng);
+            }
+            let mut text = self.generate_from(scope, settings, &mut attempt);
+            if self.same_as(previous.as_deref(), &text) {
+                continue;
+            }
+            if self.data.filter.blocks(&text) {
+                match self.cfg.filter_action {
+                    FilterAction::Regenerate => {
+                        self.log(format_args!("the filter held back attempt {}", i + 1));
+                        previous = Some(text);
+                        continue;
+                    }
+                    FilterAction::Censor => text = self.data.filter.censor(&text),
+                }
+            }
+            match &self.moderation {
+                Some(moderation) if !moderation.allows(&text).await => {
+                    self.log(format_args!("moderation held back attempt {}", i + 1));
+                    previous = Some(text);
+                }
+                _ => {
+                    *state = attempt;
+                    return Some(text);
+                }
+            }
+        }
+        None
+    }
+
+    /// Runs the transforms from `settings`, then `extra`.
+    fn transform(
+        &mut self,
+        guild: Option<Id>,
+        settings: &Settings,
+        extra: &[Transform],
+        text: String,
+    ) -> String {
+        let transforms: Vec<_> = settings
+            .transforms
+            .iter()
+            .flatten()
+            .chain(extra)
+            .copied()
+            .collect();
+        let mut ctx = transform::Context {
+            emoji: &self.data.emoji,
+            guild,
+            rng: &mut self.rng,
+        };
+        transform::apply_all(&transforms, text, &mut ctx)
+    }
+
+    /// How to generate for `message`: the channel's settings, then its author's preferences.
+    async fn settings_for(&mut self, client: &Client, message: &Message<'_>) -> Result<Settings> {
+        let place = Place {
+            guild: message.guild_id,
+            channel: Some(message.channel_id),
+            user: Some(message.author.id),
+        };
+        self.settings_at(client, place).await
+    }
+
+    /// The settings cascade for `place`, looking up which category it's in if that matters.
+    async fn settings_at(&mut self, client: &Client, place: Place) -> Result<Settings> {
+        let category = match (place.guild, place.channel) {
+            (Some(_), Some(channel)) if self.data.resolved.get(&place).is_none() => {
+                self.category(client, channel).await?
+            }
+            _ => None,
+        };
+        Ok(self.resolve(place, category))
+    }
+
+    /// The settings cascade for `place`, its channel being in `category`.
+    fn resolve(&mut self, place: Place, category: Option<Id>) -> Settings {
+        if let Some(settings) = self.data.resolved.get(&place) {
+            return settings.clone();
+        }
+        let guild = place
+            .guild
+            .map(|g| Settings::of_guild(self.data.mixes.get(g), self.data.transforms.get(Some(g))));
+        let user = place
+            .user
+            .map(|u| Settings::of_user(&self.data.prefs.get(u)));
+        let mut layers = vec![Some(&self.cfg.default_settings), guild.as_ref()];
+        if let Some(g) = place.guild {
+            let channel = self
+                .data
+                .channel_settings
+                .layers(g, category, place.channel);
+            layers.extend(channel.iter().copied());
+        }
+        layers.push(user.as_ref());
+        let settings = settings::cascade(layers);
+        self.data.resolved.insert(place, settings.clone());
+        settings
+    }
+
+    /// The category `channel` is in, only looked up if some category has settings.
+    async fn category(&mut self, client: &Client, channel: Id) -> Result<Option<Id>> {
+        if !self.data.channel_settings.has_categories() {
+            return Ok(None);
+        }
+        if let Some(&category) = self.data.categories.get(&channel) {
+            return Ok(category);
+        }
+        let category = client.get_channel_parent(channel).await?;
+        self.data.categories.insert(channel, category);
+        Ok(category)
+    }
+
+    /// Tells the configured webhooks a job's over, see `webhook`.
+    fn notify(&self, event: webhook::Event) {
+        webhook::notify(&self.cfg.webhooks, event);
+    }
+
+    /// Tells the webhooks a job that went through `channel`'s history is over, with how it went.
+    async fn notify_channel_job(
+        &self,
+        client: &Client,
+        job: &'static str,
+        channel: Id,
+        outcome: Result<&str, &anyhow::Error>,
+    ) {
+        if self.cfg.webhooks.is_empty() {
+            return;
+        }
+        let guild = client.get_channel_guild(channel).await.ok().flatten();
+        let event = match outcome {
+            Ok(detail) => webhook::Event::done(job, guild, Some(channel), detail),
+            Err(e) => webhook::Event::failed(job, guild, Some(channel), e),
+        };
+        self.notify(event);
+    }
+
+    /// Sometimes answers a message without being asked to, more likely when it mentions, names or
+    /// replies to the bot, but not again in the same channel until the cooldown's over. Those it
+    /// answers about what they said.
+    async fn chatter(&mut self, client: &Client, message: &Message<'_>) -> Result<()> {
+        let content = message.content.as_str();
+        if message.author.bot || message.guild_id.is_none() || content.starts_with(&self.cfg.prefix)
+        {
+            return Ok(());
+        }
+        // the user's layer doesn't set chatter, and resolving it for everyone would fill the cache
+        let place = Place {
+            guild: message.guild_id,
+            channel: Some(message.channel_id),
+            user: None,
+        };
+        let settings = self.settings_at(client, place).await?;
+        let replied_to = message.referenced_message.as_ref().map(|r| r.author.id);
+        let mentioned = self.id.is_some_and(|id| {
+            message.mentions.iter().any(|u| u.id == id) || replied_to == Some(id)
+        }) || self
+            .name
+            .as_ref()
+            .is_some_and(|name| content.to_lowercase().contains(name.as_str()));
+        let chance = settings.chatter_chance(mentioned);
+        if chance <= 0.0 {
+            return Ok(());
+        }
+        let now = now_millis();
+        let cooldown = settings.chatter_cooldown() as i64 * 1000;
+        if let Some(&at) = self.data.chattered.get(&message.channel_id) {
+            if now - at < cooldown {
+                return Ok(());
+            }
+        }
+        if self.rng.gen::<f64>() >= chance {
+            return Ok(());
+        }
+        self.data.chattered.insert(message.channel_id, now);
+        let scope = self.scope(message.guild_id, message.channel_id);
+        self.answer(client, message, &scope, mentioned).await
+    }
+
+    async fn mimic(&mut self, client: &Client, message: &Message<'_>, scope: &Scope) -> Result<()> {
+        self.answer(client, message, scope, false).await
+    }
+
+    /// `eg!complete WORDS...`: the words, finished off as a sentence.
+    async fn complete_command(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        scope: &Scope,
+        args: &mut impl Iterator<Item = &str>,
+    ) -> Result<()> {
+        let partial = args.collect::<Vec<_>>().join(" ");
+        if partial.is_empty() {
+            bail!("expected the start of a sentence to complete");
+        }
+        if let Some(reply) = self.spend(message.guild_id, Work::Prompt) {
+            return client.create_message(message.channel_id, &reply).await;
+        }
+        let settings = self.settings_for(client, message).await?;
+        let scope = match &settings.style {
+            Some(style) => Scope::style(message.guild_id, style, self.cfg.public),
+            None => scope.clone(),
+        };
+        let text = match self.generate_completion(&scope, &settings, &partial).await {
+            Some(text) => text,
+            None => String::from(HELD_BACK),
+        };
+        self.send_generated(client, message.channel_id, message.guild_id, &text)
+            .await
+    }
+
+    /// Generates from `scope` into `message`'s channel, like `eg!mimic`, starting from what
+    /// `message` said if `replying`.
+    async fn answer(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        scope: &Scope,
+        replying: bool,
+    ) -> Result<()> {
+        let settings = self.settings_for(client, message).await?;
+        let scope = match &settings.style {
+            Some(style) => Scope::style(message.guild_id, style, self.cfg.public),
+            None => scope.clone(),
+        };
+        let generated = match replying {
+            true => {
+                let text = message.content.as_str();
+                self.generate_reply(&scope, &settings, text).await
+            }
+            false => self.generate_moderated_at(&scope, &settings).await,
+        };
+        let text = match generated {
+            Some(t) => self.transform(message.guild_id, &settings, &[], t),
+            None => return client.create_message(message.channel_id, HELD_BACK).await,
+        };
+        #[cfg(feature = "scripting")]
+        let text = match message.guild_id {
+            Some(guild) => self.data.scripts.on_output(guild, text),
+            None => text,
+        };
+        self.send_generated(client, message.channel_id, message.guild_id, &text)
+            .await
+    }
+
+    /// Sends what was generated, with the guild's stickers it names as the stickers themselves.
+    async fn send_generated(
+        &mut self,
+        client: &Client,
+        channel: Id,
+        guild: Option<Id>,
+        text: &str,
+    ) -> Result<()> {
+        let (rest, names) = caption::take_stickers(text);
+        let guild = match guild {
+            Some(guild) if self.cfg.captions.stickers && !names.is_empty() => guild,
+            _ => return client.create_message(channel, text).await,
+        };
+        if self.stickers.fresh(guild).is_none() {
+            let stickers = client.get_guild_stickers(guild).await?;
+            self.stickers.insert(guild, stickers);
+        }
+        let known = self.stickers.fresh(guild);
+        let ids: Vec<_> = names
+            .iter()
+            .filter_map(|n| known?.get(&n.to_lowercase()).copied())
+            .collect();
+        if ids.is_empty() {
+            return client.create_message(channel, text).await;
+        }
+        client
+            .post_message_with_stickers(channel, rest.trim(), &ids)
+            .await?;
+        Ok(())
+    }
+
+    async fn generate_command(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        scope: &Scope,
+        mut transforms: Vec<Transform>,
+        args: &mut impl Iterator<Item = &str>,
+    ) -> Result<()> {
+        let mut settings = self.settings_for(client, message).await?;
+        let mut scope = match &settings.style {
+            Some(style) => Scope::style(message.guild_id, style, self.cfg.public),
+            None => scope.clone(),
+        };
+        let mut prompt = None;
+        let mut seed = None;
+        while let Some(arg) = args.next() {
+            match arg {
+                "about" => prompt = Some(args.by_ref().collect::<Vec<_>>().join(" ")),
+                "--seed" => match args.next().map(str::parse) {
+                    Some(Ok(s)) => seed = Some(s),
+                    _ => bail!("expected a number after `--seed`"),
+                },
+                "--style" => match args.next() {
+                    Some(style) => scope = Scope::style(message.guild_id, style, self.cfg.public),
+                    None => bail!("expected a style after `--style`"),
+                },
+                "--channel" => match args.next() {
+                    Some(channel) => scope = self.channel_scope(client, message, channel).await?,
+                    None => bail!("expected a channel after `--channel`"),
+                },
+                "--spicy" => {
+                    settings.temperature = Some(SPICY_TEMPERATURE);
+                    settings.sampling = Some(Sampling::Weighted);
+                }
+                "--tame" => settings.sampling = Some(TAME_SAMPLING),
+                a => match Transform::parse(a) {
+                    Some(t) => transforms.push(t),
+                    None => bail!("unknown option `{}`", a),
+                },
+            }
+        }
+        if prompt.is_some() {
+            if seed.is_some() {
+                bail!("`--seed` doesn't work with `about`");
+            }
+            if let Some(reply) = self.spend(message.guild_id, Work::Prompt) {
+                return client.create_message(message.channel_id, &reply).await;
+            }
+        }
+        let text = match &prompt {
+            Some(prompt) => self.generate_prompted(&scope, &settings, prompt).await,
+            None => {
+                let seed = seed.unwrap_or_else(|| self.rng.gen());
+                self.data.seeds.insert(message.channel_id, seed);
+                let mut state = ChainState::seeded(seed);
+                self.generate_moderated_from(&scope, &settings, &mut state)
+                    .await
+            }
+        };
+        let text = match text {
+            Some(t) if t.trim().is_empty() => {
+                String::from("I haven't learned anything in that style yet")
+            }
+            Some(t) => match self.transform(message.guild_id, &settings, &transforms, t) {
+                t if t.trim().is_empty() => {
+                    String::from("No one's reacted to any of those words yet")
+                }
+                t => t,
+            },
+            None => String::from(HELD_BACK),
+        };
+        self.send_generated(client, message.channel_id, message.guild_id, &text)
+            .await
+    }
+
+    /// `eg!seed`: what `eg!generate` last generated from here, to say the same again.
+    async fn seed_command(&mut self, client: &Client, message: &Message<'_>) -> Result<()> {
+        let text = match self.data.seeds.get(&message.channel_id) {
+            Some(seed) => format!(
+                "The last thing I generated here came from seed {}: `eg!generate --seed {}` \
+                 (with the same options) says it again, as long as nothing's been learned since",
+                seed, seed
+            ),
+            None => String::from("I haven't generated anything here since I started"),
+        };
+        client.create_message(message.channel_id, &text).await
+    }
+
+    /// The model of a channel mentioned like `#general`, if it's in the same guild.
+    async fn channel_scope(
+        &self,
+        client: &Client,
+        message: &Message<'_>,
+        channel: &str,
+    ) -> Result<Scope> {
+        if !self.channel_models() {
+            bail!("channels don't have their own models here");
+        }
+        let channel: Id = channel
+            .trim_start_matches("<#")
+            .trim_end_matches('>')
+            .parse()?;
+        if client.get_channel_guild(channel).await? != message.guild_id {
+            bail!("that channel isn't in this server");
+        }
+        Ok(Scope::Channel(channel))
+    }
+
+    /// A few sentences in a row, streamed in like they're being typed.
+    async fn story(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        scope: &Scope,
+        args: &mut impl Iterator<Item = &str>,
+    ) -> Result<()> {
+        let settings = self.settings_for(client, message).await?;
+        let mut style = settings.style.clone();
+        while let Some(arg) = args.next() {
+            match arg {
+                "--style" => match args.next() {
+                    Some(s) => style = Some(String::from(s)),
+                    None => bail!("expected a style after `--style`"),
+                },
+                a => bail!("unknown option `{}`", a),
+            }
+        }
+        let scope = match &style {
+            Some(style) => Scope::style(message.guild_id, style, self.cfg.public),
+            None => scope.clone(),
+        };
+        if let Some(reply) = self.spend(message.guild_id, Work::Story) {
+            return client.create_message(message.channel_id, &reply).await;
+        }
+        let mut state = ChainState::new(&mut self.rng);
+        let story = self.tell_story(&scope, &settings, &mut state).await;
+        if story.is_empty() {
+            return client
+                .create_message(message.channel_id, "I don't have a story to tell yet")
+                .await;
+        }
+        let continuation = Continuation {
+            guild: message.guild_id,
+            style,
+            settings,
+            state,
+        };
+        let name = format!("Story for {}", message.author.username);
+        let channel = self.session_channel(client, message, &name).await;
+        self.post_story(client, channel, message.author.id, story, continuation)
+            .await
+    }
+
+    /// A few sentences going on from `state`.
+    async fn tell_story(
+        &mut self,
+        scope: &Scope,
+        settings: &Settings,
+        state: &mut ChainState,
+    ) -> String {
+        const SENTENCES: usize = 6;
+        const MAX_CHARS: usize = 2000;
+        let mut story = String::new();
+        for _ in 0..SENTENCES {
+            let mut next = state.clone();
+            let sentence = match self
+                .generate_moderated_from(scope, settings, &mut next)
+                .await
+            {
+                Some(s) if !s.trim().is_empty() => s,
+                _ => continue,
+            };
+            if story.chars().count() + sentence.trim().chars().count() + 1 > MAX_CHARS {
+                break;
+            }
+            *state = next;
+            if !story.is_empty() {
+                story.push(' ');
+            }
+            story.push_str(sentence.trim());
+        }
+        story
+    }
+
+    /// Streams `story` in, ending with a button to go on from `continuation`.
+    async fn post_story(
+        &mut self,
+        client: &Client,
+        channel: Id,
+        requester: Id,
+        story: String,
+        continuation: Continuation,
+    ) -> Result<()> {
+        let ending = Ending {
+            buttons: continuation::button(),
+            archive: self.data.threads.contains(&channel),
+        };
+        let (content, buttons) = stream::first(&story, &ending);
+        let id = client
+            .post_message_with_components(channel, &content, &buttons)
+            .await?;
+        self.data.continuations.insert(id, continuation);
+        if content != story {
+            self.data
+                .streams
+                .start(self.token.clone(), channel, id, requester, story, ending);
+        } else if ending.archive {
+            client.archive_thread(channel).await?;
+        }
+        Ok(())
+    }
+
+    /// Where to hold a story or adventure asked for with `message`: a new thread named `name` if
+    /// its channel is busy, or else right there.
+    async fn session_channel(&mut self, client: &Client, message: &Message<'_>, name: &str) -> Id {
+        const BUSY_MINUTES: i64 = 5;
+        let channel = message.channel_id;
+        let busy = self.cfg.thread_busy_messages;
+        if message.guild_id.is_none() || busy == 0 {
+            return channel;
+        }
+        let since = now_millis() - BUSY_MINUTES * 60 * 1000;
+        if self.data.cache.recent(channel, since) < busy {
+            return channel;
+        }
+        match client.create_thread(channel, message.id, name).await {
+            Ok(thread) => {
+                self.data.threads.insert(thread);
+                thread
+            }
+            // like when it's already in a thread
+            Err(e) => {
+                self.log(format_args!("couldn't start a thread: {}", e));
+                channel
+            }
+        }
+    }
+
+    /// Tells more of the story in a message, where it left off.
+    async fn continue_story(
+        &mut self,
+        client: &Client,
+        interaction: &Interaction<'_>,
+    ) -> Result<()> {
+        let (message, user, channel) = match (
+            &interaction.message,
+            interaction.author(),
+            interaction.channel_id,
+        ) {
+            (Some(m), Some(u), Some(c)) => (m, u.id, c),
+            _ => return Ok(()),
+        };
+        // before it's taken, so it can still go on once there's compute again
+        if let Some(reply) = self.spend(interaction.guild_id, Work::Story) {
+            return client
+                .create_interaction_response(interaction, &InteractionResponse::ephemeral(&reply))
+                .await;
+        }
+        let Continuation {
+            guild,
+            style,
+            settings,
+            mut state,
+        } = match self.data.continuations.take(message.id) {
+            Some(c) => c,
+            None => {
+                return client
+                    .create_interaction_response(
+                        interaction,
+                        &InteractionResponse::ephemeral("This story can't be continued anymore"),
+                    )
+                    .await
+            }
+        };
+        // it only goes on once, from wherever its button is pressed first
+        client
+            .create_interaction_response(
+                interaction,
+                &InteractionResponse::update(message.content.as_str(), &[]),
+            )
+            .await?;
+        let scope = match &style {
+            Some(style) => Scope::style(guild, style, self.cfg.public),
+            None => self.scope(guild, channel),
+        };
+        let story = self.tell_story(&scope, &settings, &mut state).await;
+        if story.is_empty() {
+            return client
+                .create_message(channel, "That's the end of the story")
+                .await;
+        }
+        let continuation = Continuation {
+            guild,
+            style,
+            settings,
+            state,
+        };
+        self.post_story(client, channel, user, story, continuation)
+            .await
+    }
+
+    async fn adventure_command(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        action: &str,
+        args: &mut impl Iterator<Item = &str>,
+    ) -> Result<()> {
+        let channel = message.channel_id;
+        match action {
+            "start" if self.data.adventures.is_running(channel) => {
+                client
+                    .create_message(channel, "There's already an adventure going on here")
+                    .await
+            }
+            "start" => {
+                let style = match (args.next(), args.next()) {
+                    (Some("--style"), Some(style)) => Some(style.to_lowercase()),
+                    (Some("--style"), None) => bail!("expected a style after `--style`"),
+                    (Some(a), _) => bail!("unknown option `{}`", a),
+                    (None, _) => None,
+                };
+                if let Some(reply) = self.spend(message.guild_id, Work::Adventure) {
+                    return client.create_message(channel, &reply).await;
+                }
+                let opening = match self
+                    .adventure_paragraph(message.guild_id, style.as_deref(), channel)
+                    .await
+                {
+                    Some(p) => p,
+                    None => {
+                        return client
+                            .create_message(channel, "I don't have a story to tell yet")
+                            .await
+                    }
+                };
+                let session = Session::new(message.guild_id, style, message.author.id, opening);
+                let channel = self.session_channel(client, message, "Adventure").await;
+                self.data.adventures.start(channel, session);
+                self.next_adventure_round(client, channel).await
+            }
+            "end" => {
+                let is_admin = self.is_admin_message(message);
+                let allowed = self
+                    .data
+                    .adventures
+                    .get_mut(channel)
+                    .map(|s| s.started_by == message.author.id || is_admin);
+                match allowed {
+                    None => {
+                        client
+                            .create_message(channel, "There's no adventure going on here")
+                            .await
+                    }
+                    Some(false) => {
+                        client
+                            .create_message(
+                                channel,
+                                "Only whoever started the adventure can end it",
+                            )
+                            .await
+                    }
+                    Some(true) => self.finish_adventure(client, channel).await,
+                }
+            }
+            a => bail!("unknown action `{}`", a),
+        }
+    }
+
+    /// A couple of sentences for an adventure, from its style or else the channel's model.
+    async fn adventure_paragraph(
+        &mut self,
+        guild: Option<Id>,
+        style: Option<&str>,
+        channel: Id,
+    ) -> Option<String> {
+        const SENTENCES: usize = 2;
+        let scope = match style {
+            Some(style) => Scope::style(guild, style, self.cfg.public),
+            None => self.scope(guild, channel),
+        };
+        let mut sentences = Vec::new();
+        for _ in 0..SENTENCES {
+            if let Some(s) = self.generate_moderated(&scope, guild).await {
+                if !s.trim().is_empty() {
+                    sentences.push(String::from(s.trim()));
+                }
+            }
+        }
+        Some(sentences.join(" ")).filter(|p| !p.is_empty())
+    }
+
+    /// Offers the options for what happens next and schedules the end of the vote.
+    async fn next_adventure_round(&mut self, client: &Client, channel: Id) -> Result<()> {
+        let (guild, style) = match self.data.adventures.get_mut(channel) {
+            Some(s) => (s.guild, s.style.clone()),
+            None => return Ok(()),
+        };
+        let count = self.rng.gen_range(2, adventure::LABELS.len() + 1);
+        let mut options = Vec::with_capacity(count);
+        for _ in 0..count {
+            if let Some(p) = self
+                .adventure_paragraph(guild, style.as_deref(), channel)
+                .await
+            {
+                options.push(p);
+            }
+        }
+        if options.len() < 2 {
+            return self.finish_adventure(client, channel).await;
+        }
+        let (text, buttons) = match self.data.adventures.get_mut(channel) {
+            Some(session) => {
+                session.next_round(options);
+                (session.render(), session.buttons())
+            }
+            None => return Ok(()),
+        };
+        let message = client
+            .post_message_with_components(channel, &text, &buttons)
+            .await?;
+        if let Some(session) = self.data.adventures.get_mut(channel) {
+            session.posted(message);
+        }
+        self.data.jobs.schedule(
+            now_millis() + self.cfg.adventure_round_minutes as i64 * 60_000,
+            Job::AdventureRound { channel, message },
+        );
+        Ok(())
+    }
+
+    async fn adventure_round(&mut self, client: &Client, channel: Id, message: Id) -> Result<()> {
+        let session = match self.data.adventures.get_mut(channel) {
+            // the vote was on an adventure that's since ended
+            Some(s) if s.message == Some(message) => s,
+            _ => return Ok(()),
+        };
+        let choice = session.winner(&mut self.rng);
+        let result = session.advance(choice);
+        let finished = session.is_finished();
+        client.edit_message(channel, message, &result, &[]).await?;
+        if finished {
+            self.finish_adventure(client, channel).await
+        } else {
+            self.next_adventure_round(client, channel).await
+        }
+    }
+
+    /// Ends the adventure in `channel` and uploads the whole story.
+    async fn finish_adventure(&mut self, client: &Client, channel: Id) -> Result<()> {
+        let session = match self.data.adventures.end(channel) {
+            Some(s) => s,
+            None => return Ok(()),
+        };
+        if let Some(message) = session.message.filter(|_| !session.options.is_empty()) {
+            client
+                .edit_message(channel, message, &session.render(), &[])
+                .await?;
+        }
+        client
+            .upload_file(
+                channel,
+                &format!("📖 The end, after {} parts!", session.paragraphs.len()),
+                "adventure.txt",
+                session.export().as_bytes(),
+            )
+            .await?;
+        if self.data.threads.remove(&channel) {
+            client.archive_thread(channel).await?;
+        }
+        Ok(())
+    }
+
+    async fn roulette_command(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        action: &str,
+    ) -> Result<()> {
+        let guild = match message.guild_id {
+            Some(g) => g,
+            None => bail!("impersonation roulette only works in servers"),
+        };
+        let (user, channel) = (message.author.id, message.channel_id);
+        let admin_only = matches!(action, "on" | "off" | "now");
+        if admin_only && !self.is_admin_message(message) {
+            bail!("only admins can run impersonation roulette");
+        }
+        let reply = match action {
+            "on" => {
+                self.data.roulette.turn_on(guild, channel);
+                let scheduled = self
+                    .data
+                    .jobs
+                    .any(|j| matches!(j, Job::Roulette { guild: g } if *g == guild));
+                if !scheduled {
+                    self.data.jobs.schedule(
+                        now_millis() + roulette::WEEK_MILLIS,
+                        Job::Roulette { guild },
+                    );
+                }
+                "🎭 Impersonation roulette is on, here, once a week. `eg!roulette join` to play"
+            }
+            "off" => match self.data.roulette.turn_off(guild) {
+                true => "Impersonation roulette is off",
+                false => "Impersonation roulette wasn't on",
+            },
+            "now" => return self.roulette_round(client, guild).await,
+            "join" if self.data.opt_outs.contains(&user) => {
+                bail!("you opted out of being learned from, so I can't impersonate you")
+            }
+            "join" => match self
+                .data
+                .roulette
+                .join(guild, user, message.author.username)
+            {
+                true => "You're in! I might impersonate you in the next round",
+                false => "You're already playing",
+            },
+            "leave" => match self.data.roulette.leave(guild, user) {
+                true => "You're out, I won't impersonate you anymore",
+                false => "You weren't playing",
+            },
+            "scores" => {
+                return client
+                    .create_message(channel, &self.data.roulette.scores(guild))
+                    .await
+            }
+            a => bail!("unknown action `{}`", a),
+        };
+        client.create_message(channel, reply).await
+    }
+
+    /// Impersonates one of `guild`'s players the bot remembers enough of, for everyone to guess.
+    async fn roulette_round(&mut self, client: &Client, guild: Id) -> Result<()> {
+        const MIN_REMEMBERED: usize = 5;
+        let channel = match self.data.roulette.channel(guild) {
+            Some(c) => c,
+            None => bail!("impersonation roulette isn't on here"),
+        };
+        if self.data.roulette.is_running(guild) {
+            bail!("there's already a round going on");
+        }
+        let mut active: Vec<_> = self
+            .data
+            .roulette
+            .players(guild)
+            .into_iter()
+            .filter(|p| !self.data.opt_outs.contains(p))
+            .filter(|&p| self.remembered_model(Some(guild), p).1 >= MIN_REMEMBERED)
+            .collect();
+        active.shuffle(&mut self.rng);
+        let mut impersonation = None;
+        for target in active {
+            let (model, _) = self.remembered_model(Some(guild), target);
+            let words = model
+                .generate_sequence(&mut self.rng)
+                .config(self.cfg.generation)
+                .sentence();
+            let text = fit_message(self.tokenizer.detokenize(Some(guild), words));
+            if text.trim().is_empty() || self.data.filter.blocks(&text) {
+                continue;
+            }
+            if let Some(moderation) = &self.moderation {
+                if !moderation.allows(&text).await {
+                    continue;
+                }
+            }
+            impersonation = Some((target, text));
+            break;
+        }
+        let started = impersonation.and_then(|(target, text)| {
+            self.data.roulette.start(guild, target, text, &mut self.rng)
+        });
+        let (text, buttons) = match started {
+            Some(round) => round,
+            None => {
+                let text = "🎭 Nobody who's playing impersonation roulette has said enough lately \
+                            for a round. `eg!roulette join` to play";
+                return client.create_message(channel, text).await;
+            }
+        };
+        let message = client
+            .post_message_with_components(channel, &text, &buttons)
+            .await?;
+        self.data.roulette.posted(guild, message);
+        self.data.jobs.schedule(
+            now_millis() + self.cfg.roulette_minutes as i64 * 60_000,
+            Job::RouletteReveal { guild, message },
+        );
+        Ok(())
+    }
+
+    async fn roulette_guess(
+        &mut self,
+        client: &Client,
+        interaction: &Interaction<'_>,
+        choice: usize,
+    ) -> Result<()> {
+        let (guild, message, user) = match (
+            interaction.guild_id,
+            &interaction.message,
+            interaction.author(),
+        ) {
+            (Some(g), Some(m), Some(u)) => (g, m.id, u.id),
+            _ => return Ok(()),
+        };
+        let reply = match self.data.roulette.guess(guild, message, user, choice) {
+            Guess::Counted(name) => format!("You guessed **{}**", name),
+            Guess::Over => String::from("That round's already over"),
+            Guess::Themselves => String::from("No fair, it's you! Let the others guess"),
+        };
+        client
+            .create_interaction_response(interaction, &InteractionResponse::ephemeral(&reply))
+            .await
+    }
+
+    async fn adventure_vote(
+        &mut self,
+        client: &Client,
+        interaction: &Interaction<'_>,
+        option: usize,
+    ) -> Result<()> {
+        let (channel, message, user) = match (
+            interaction.channel_id,
+            &interaction.message,
+            interaction.author(),
+        ) {
+            (Some(c), Some(m), Some(u)) => (c, m.id, u.id),
+            _ => return Ok(()),
+        };
+        let reply = match self.data.adventures.vote(channel, message, user, option) {
+            Some(label) => format!("You voted for **{}**", label),
+            None => String::from("That vote's already over"),
+        };
+        client
+            .create_interaction_response(interaction, &InteractionResponse::ephemeral(&reply))
+            .await
+    }
+
+    async fn style_command(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        action: &str,
+        args: &mut impl Iterator<Item = &str>,
+    ) -> Result<()> {
+        fn parse_channel(s: Option<&str>) -> Result<Id> {
+            match s {
+                Some(s) => Ok(s.trim_start_matches("<#").trim_end_matches('>').parse()?),
+                None => bail!("expected a channel"),
+            }
+        }
+
+        let reply = match action {
+            "list" => {
+                let list = self.data.styles.list();
+                if list.is_empty() {
+                    String::from("No channels have a style yet")
+                } else {
+                    list.into_iter().fold(String::new(), |p, (channel, style)| {
+                        p + channel.mention_channel().as_str() + ": " + style + "\n"
+                    })
+                }
+            }
+            _ if !self.is_admin_message(message) => {
+                String::from("Only admins can change channel styles")
+            }
+            "tag" => {
+                let channel = parse_channel(args.next())?;
+                let style = match args.next() {
+                    Some(s) => s,
+                    None => bail!("expected a style"),
+                };
+                self.data.styles.tag(channel, style)?;
+                format!(
+                    "Now learning {} as `{}`",
+                    channel.mention_channel(),
+                    style.to_lowercase()
+                )
+            }
+            "untag" => {
+                let channel = parse_channel(args.next())?;
+                match self.data.styles.untag(channel) {
+                    Some(style) => {
+                        format!("{} isn't `{}` anymore", channel.mention_channel(), style)
+                    }
+                    None => format!("{} doesn't have a style", channel.mention_channel()),
+                }
+            }
+            a => bail!("unknown action `{}`", a),
+        };
+        client.create_message(message.channel_id, &reply).await
+    }
+
+    async fn corpus_command(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        action: &str,
+        args: &mut impl Iterator<Item = &str>,
+    ) -> Result<()> {
+        const MAX_IMPORT_SIZE: u64 = 8 * 1024 * 1024;
+
+        let reply = match (action, args.next()) {
+            ("list", _) => {
+                let list = self.data.corpora.list().fold(String::new(), |p, (name, c)| {
+                    let state = if c.enabled { "enabled" } else { "disabled" };
+                    p + format!("`{}`: {}, weight {}\n", name, state, c.weight).as_str()
+                });
+                if list.is_empty() {
+                    String::from("No corpora yet")
+                } else {
+                    list
+                }
+            }
+            _ if !self.is_admin_message(message) => String::from("Only admins can manage corpora"),
+            ("import", Some(name)) => {
+                let today = Utc::now().date().naive_utc();
+                if let Some(guild) = message.guild_id {
+                    let limits = self.limits(guild);
+                    self.data.quotas.check_import(guild, &limits, today)?;
+                }
+                let scope = self.data.corpora.create(name)?;
+                let bytes = download_attachment(message, MAX_IMPORT_SIZE, "text file").await?;
+                let text = String::from_utf8_lossy(&bytes);
+                let mut lines = 0;
+                for line in text.lines().filter(|l| !l.trim().is_empty()) {
+                    self.learn(std::slice::from_ref(&scope), String::from(line));
+                    lines += 1;
+                }
+                if let Some(guild) = message.guild_id {
+                    self.data.quotas.count_import(guild, today);
+                }
+                format!("Imported {} lines into `{}`", lines, name)
+            }
+            ("enable", Some(name)) => {
+                self.data.corpora.set_enabled(name, true)?;
+                format!("Mixing `{}` into generation", name)
+            }
+            ("disable", Some(name)) => {
+                self.data.corpora.set_enabled(name, false)?;
+                format!("Not using `{}` anymore", name)
+            }
+            ("weight", Some(name)) => {
+                let weight = match args.next() {
+                    Some(w) => w.parse()?,
+                    None => bail!("expected a weight"),
+                };
+                self.data.corpora.set_weight(name, weight)?;
+                format!("`{}` now has weight {}", name, weight)
+            }
+            ("delete", Some(name)) => {
+                let scope = self.data.corpora.remove(name)?;
+                self.data.models.remove(&scope)?;
+                self.replicate(Change::Remove { scope });
+                format!("Deleted `{}`", name)
+            }
+            _ => String::from(
+                "Usage: `eg!corpus list`, `eg!corpus import NAME` with a text file attached, or `eg!corpus enable|disable|delete NAME`, or `eg!corpus weight NAME WEIGHT`",
+            ),
+        };
+        client.create_message(message.channel_id, &reply).await
+    }
+
+    async fn prefs_command(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        args: &mut impl Iterator<Item = &str>,
+    ) -> Result<()> {
+        let user = message.author.id;
+        if let Some(key) = args.next() {
+            let value = match args.next() {
+                Some(v) => v,
+                None => bail!("expected a value for `{}`", key),
+            };
+            if key == "digest" && value == "on" && self.cfg.public {
+                bail!("digests come from the shared model, which a public bot doesn't have");
+            }
+            let tag = format!(
+                "{}#{}",
+                message.author.username, message.author.discriminator
+            );
+            self.data.prefs.set(user, &tag, key, value)?;
+            self.data.resolved.invalidate();
+        }
+        let text = format!("Your preferences: {}", self.data.prefs.get(user));
+        client.create_message(message.channel_id, &text).await
+    }
+
+    /// DMs everyone who asked for one a few generated messages.
+    async fn send_digests(&mut self, client: &Client) -> Result<()> {
+        const MESSAGES: usize = 3;
+        for user in self.data.prefs.digest_users() {
+            let settings = self.resolve(Place::user(user), None);
+            let scope = match &settings.style {
+                Some(style) => Scope::style(None, style, self.cfg.public),
+                None => Scope::Global,
+            };
+            let mut digest = String::from("Here's what I've been thinking about today:");
+            for _ in 0..MESSAGES {
+                if let Some(text) = self.generate_moderated_at(&scope, &settings).await {
+                    digest = digest + "\n> " + text.trim();
+                }
+            }
+            let channel = client.create_dm(user).await?;
+            client.create_message(channel, &digest).await?;
+        }
+        Ok(())
+    }
+
+    async fn settings_command(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        args: &mut impl Iterator<Item = &str>,
+    ) -> Result<()> {
+        let (guild, channel) = match message.guild_id {
+            Some(g) => (g, message.channel_id),
+            None => bail!("settings only work in servers"),
+        };
+        if let Some(level) = args.next() {
+            if !self.is_admin_message(message) {
+                return client
+                    .create_message(channel, "Only admins can change settings")
+                    .await;
+            }
+            let level = match level {
+                "server" => Level::Guild(guild),
+                "channel" => Level::Channel(channel),
+                "category" => match client.get_channel_parent(channel).await? {
+                    Some(category) => {
+                        self.data.categories.insert(channel, Some(category));
+                        Level::Category(category)
+                    }
+                    None => bail!("this channel isn't in a category"),
+                },
+                l => bail!("expected `server`, `category` or `channel`, got `{}`", l),
+            };
+            let key = match args.next() {
+                Some(k) => k,
+                None => bail!("expected a setting"),
+            };
+            let values: Vec<_> = args.collect();
+            self.data
+                .channel_settings
+                .set(level, key, &values, &self.data.corpora)?;
+            self.data.resolved.invalidate();
+        }
+        let place = Place {
+            guild: Some(guild),
+            channel: Some(channel),
+            user: None,
+        };
+        let settings = self.settings_at(client, place).await?;
+        let text = format!("Settings here: {}", settings);
+        client.create_message(channel, &text).await
+    }
+
+    async fn blocklist_command(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        args: &mut impl Iterator<Item = &str>,
+    ) -> Result<()> {
+        let guild = match message.guild_id {
+            Some(g) => g,
+            None => bail!("blocklists only work in servers"),
+        };
+        if let Some(action) = args.next() {
+            if !self.is_admin_message(message) {
+                return client
+                    .create_message(message.channel_id, "Only admins can change the blocklist")
+                    .await;
+            }
+            let keyword = args.collect::<Vec<_>>().join(" ");
+            if keyword.is_empty() {
+                bail!("expected a keyword");
+            }
+            let changed = match action {
+                "add" => self.data.automod.add(guild, &keyword),
+                "remove" => self.data.automod.remove(guild, &keyword),
+                a => bail!("expected `add` or `remove`, got `{}`", a),
+            };
+            if !changed {
+                let text = format!(
+                    "`{}` already {}",
+                    keyword,
+                    match action {
+                        "add" => "was blocked",
+                        _ => "wasn't blocked",
+                    }
+                );
+                return client.create_message(message.channel_id, &text).await;
+            }
+        }
+        let keywords: Vec<_> = self
+            .data
+            .automod
+            .keywords(guild)
+            .map(|k| format!("`{}`", k))
+            .collect();
+        let text = match keywords.is_empty() {
+            true => String::from("Nothing's blocked from learning"),
+            false => format!("Not learning messages with: {}", keywords.join(", ")),
+        };
+        client.create_message(message.channel_id, &text).await
+    }
+
+    async fn filter_command(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        args: &mut impl Iterator<Item = &str>,
+    ) -> Result<()> {
+        if !self.is_admin_message(message) {
+            bail!("only the bot's admins can see or change the filter");
+        }
+        let text = match args.next() {
+            None | Some("list") => self.data.filter.to_string(),
+            Some(action @ ("add" | "remove")) => {
+                let kind = match args.next() {
+                    Some(kind) => filter::Kind::parse(kind)?,
+                    None => bail!("expected `word`, `regex`, `user` or `channel`"),
+                };
+                let value = args.collect::<Vec<_>>().join(" ");
+                if value.is_empty() {
+                    bail!("expected what to {}", action);
+                }
+                let changed = match action {
+                    "add" => self.data.filter.add(kind, &value)?,
+                    _ => self.data.filter.remove(kind, &value)?,
+                };
+                match (action, changed) {
+                    ("add", true) => format!("Filtering `{}` now", value),
+                    ("add", false) => format!("`{}` was already filtered", value),
+                    (_, true) => format!("Not filtering `{}` anymore", value),
+                    (_, false) => format!("`{}` wasn't filtered", value),
+                }
+            }
+            Some(a) => bail!("expected `add`, `remove` or `list`, got `{}`", a),
+        };
+        client.create_message(message.channel_id, &text).await
+    }
+
+    /// `eg!alias set NAME COMMAND...`, `eg!alias remove NAME` or `eg!alias list`, for this
+    /// server's own names for commands.
+    async fn alias_command(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        args: &mut impl Iterator<Item = &str>,
+        commands: &[&str],
+    ) -> Result<()> {
+        let guild = match message.guild_id {
+            Some(g) => g,
+            None => bail!("aliases only work in servers"),
+        };
+        let prefix = self.cfg.prefix.as_str();
+        let action = args.next();
+        let changes = action.is_some_and(|a| a != "list");
+        if changes && !self.is_owner_message(client, message, guild).await? {
+            bail!("only the server's owner can change its aliases");
+        }
+        let text = match action {
+            None | Some("list") => self.data.aliases.list(guild, &self.cfg.aliases, prefix),
+            Some("set") => {
+                let name = match args.next() {
+                    Some(n) => n,
+                    None => bail!("expected a name for the alias"),
+                };
+                let expansion = args.collect::<Vec<_>>().join(" ");
+                self.data.aliases.set(
+                    guild,
+                    name,
+                    &expansion,
+                    prefix,
+                    &self.cfg.aliases,
+                    commands,
+                )?;
+                format!(
+                    "`{}{}` is an alias now",
+                    prefix,
+                    name.trim_start_matches(prefix)
+                )
+            }
+            Some("remove") => match args.next() {
+                Some(name) => match self.data.aliases.remove(guild, name) {
+                    true => format!("`{}` isn't an alias anymore", name),
+                    false => format!("`{}` wasn't an alias here", name),
+                },
+                None => bail!("expected which alias to remove"),
+            },
+            Some(a) => bail!("expected `set`, `remove` or `list`, got `{}`", a),
+        };
+        client.create_message(message.channel_id, &text).await
+    }
+
+    /// `eg!batch N`: generates `N` different sentences from `scope`'s model in the background,
+    /// then uploads them as a text file.
+    async fn batch_command(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        scope: &Scope,
+        count: &str,
+    ) -> Result<()> {
+        const MAX_BATCH: usize = 1000;
+        if !self.is_admin_message(message) {
+            bail!("only the bot's admins can generate batches");
+        }
+        let count: usize = match count.parse() {
+            Ok(n) if (1..=MAX_BATCH).contains(&n) => n,
+            _ => bail!("expected how many sentences, up to {}", MAX_BATCH),
+        };
+        let channel = message.channel_id;
+        if self
+            .data
+            .jobs
+            .any(|j| matches!(j, Job::Batch(b) if b.channel == channel))
+        {
+            bail!("already generating a batch here");
+        }
+        let batch = Batch {
+            channel,
+            scope: scope.clone(),
+            guild: message.guild_id,
+            count,
+            sentences: Vec::new(),
+            attempts: 0,
+        };
+        self.data.jobs.schedule(now_millis(), Job::Batch(batch));
+        let text = format!("Generating {} sentences, I'll upload them here", count);
+        client.create_message(channel, &text).await
+    }
+
+    /// Generates one page of a `Job::Batch`'s sentences, leaving out ones it already has, then
+    /// schedules the next, or uploads them once there are enough or it's tried too many times.
+    async fn batch_page(&mut self, client: &Client, mut batch: Batch) -> Result<()> {
+        const PAGE: usize = 25;
+        /// Attempts per sentence asked for, before settling for fewer
+        const TRIES: usize = 3;
+        let mut seen: HashSet<_> = batch.sentences.iter().map(|s| s.to_lowercase()).collect();
+        let done = |b: &Batch| b.sentences.len() >= b.count || b.attempts >= b.count * TRIES;
+        for _ in 0..PAGE {
+            if done(&batch) {
+                break;
+            }
+            batch.attempts += 1;
+            if let Some(text) = self.generate_moderated(&batch.scope, batch.guild).await {
+                if !text.trim().is_empty() && seen.insert(text.to_lowercase()) {
+                    batch.sentences.push(text);
+                }
+            }
+        }
+        if !done(&batch) {
+            self.data.jobs.schedule(now_millis(), Job::Batch(batch));
+            return Ok(());
+        }
+        if batch.sentences.is_empty() {
+            return client
+                .create_message(batch.channel, "Couldn't generate anything for the batch")
+                .await;
+        }
+        let text = match batch.sentences.len() < batch.count {
+            true => format!(
+                "📝 Only {} different sentences out of {} asked for",
+                batch.sentences.len(),
+                batch.count
+            ),
+            false => format!("📝 {} sentences", batch.count),
+        };
+        let file = batch.sentences.join("\n") + "\n";
+        client
+            .upload_file(batch.channel, &text, "batch.txt", file.as_bytes())
+            .await?;
+        Ok(())
+    }
+
+    /// `eg!tokenize debug TEXT`: what each tokenizer stage makes of `TEXT` here.
+    async fn tokenize_command(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        action: &str,
+        args: &mut impl Iterator<Item = &str>,
+    ) -> Result<()> {
+        /// Long enough for a message that learned oddly, short enough for every stage to fit
+        const MAX_CHARS: usize = 200;
+        if action != "debug" {
+            bail!("expected `debug`, got `{}`", action);
+        }
+        let text = args.collect::<Vec<_>>().join(" ");
+        if text.is_empty() {
+            bail!("expected some text to tokenize");
+        }
+        if text.chars().count() > MAX_CHARS {
+            bail!(
+                "that's too long to show, try {} characters or less",
+                MAX_CHARS
+            );
+        }
+        let guild = self.scope(message.guild_id, message.channel_id).owner();
+        let mut reply = String::from("```\n");
+        for (stage, pieces) in self.tokenizer.debug(guild, &text) {
+            reply.push_str(&format!("{:<12} {:?}\n", stage.name(), pieces));
+        }
+        reply.push_str("```");
+        client.create_message(message.channel_id, &reply).await
+    }
+
+    async fn transforms_command(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        args: &mut impl Iterator<Item = &str>,
+    ) -> Result<()> {
+        let guild = match message.guild_id {
+            Some(g) => g,
+            None => bail!("default transforms only work in servers"),
+        };
+        let args: Vec<_> = args.collect();
+        if !args.is_empty() {
+            if !self.is_admin_message(message) {
+                return client
+                    .create_message(message.channel_id, "Only admins can change the transforms")
+                    .await;
+            }
+            if args == ["reset"] {
+                self.data.transforms.reset(guild);
+                self.data.resolved.invalidate();
+            } else {
+                self.data.transforms.set(guild, &args)?;
+                self.data.resolved.invalidate();
+            }
+        }
+
+        let names = |ts: &[Transform]| ts.iter().map(|t| t.name()).collect::<Vec<_>>().join(", ");
+        let current = match self.data.transforms.get(Some(guild)) {
+            [] => String::from("none"),
+            ts => names(ts),
+        };
+        let text = format!(
+            "Transforming everything with: {}\nAvailable: {}",
+            current,
+            names(&transform::ALL)
+        );
+        client.create_message(message.channel_id, &text).await
+    }
+
+    async fn mix_command(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        scope: &Scope,
+        args: &mut impl Iterator<Item = &str>,
+    ) -> Result<()> {
+        let guild = match message.guild_id {
+            Some(g) => g,
+            None => bail!("mixes only work in servers"),
+        };
+        let args: Vec<_> = args.collect();
+        if !args.is_empty() {
+            if !self.is_admin_message(message) {
+                return client
+                    .create_message(message.channel_id, "Only admins can change the mix")
+                    .await;
+            }
+            if args == ["reset"] {
+                self.data.mixes.reset(guild);
+                self.data.resolved.invalidate();
+            } else {
+                let mut mix = BTreeMap::new();
+                for arg in args {
+                    let (name, weight) = match arg.find('=') {
+                        Some(i) => (&arg[..i], arg[i + 1..].parse::<f64>()?),
+                        None => bail!("expected `NAME=WEIGHT`, got `{}`", arg),
+                    };
+                    mix.insert(String::from(name), weight);
+                }
+                self.data.mixes.set(guild, mix, &self.data.corpora)?;
+                self.data.resolved.invalidate();
+            }
+        }
+
+        let mix = self.data.mixes.get(guild);
+        let blend = self.data.corpora.blend(scope, mix);
+        let total: f64 = blend.iter().map(|(_, w)| w).sum();
+        let text = blend.iter().fold(String::from("Mixing "), |p, (s, w)| {
+            let name = match s {
+                Scope::Corpus(name) => name.as_str(),
+                _ => corpus::SERVER,
+            };
+            p + format!("{} {:.0}%  ", name, w / total * 100.0).as_str()
+        });
+        client.create_message(message.channel_id, &text).await
+    }
+
+    fn limits(&self, guild: Id) -> Limits {
+        self.data.quotas.limits(guild, &self.cfg.quotas)
+    }
+
+    /// Takes `work` out of the guild's compute budget, or returns what to say if it's used up.
+    fn spend(&mut self, guild: Option<Id>, work: Work) -> Option<String> {
+        let guild = guild?;
+        let limits = self.limits(guild);
+        match self.data.quotas.spend(guild, &limits, work, now_millis()) {
+            Ok(()) => None,
+            Err(wait) => {
+                self.log(format_args!("{:?} is over the compute budget", work));
+                Some(format!(
+                    "This server has used up its compute for now, try again in {} seconds",
+                    wait.as_secs().max(1)
+                ))
+            }
+        }
+    }
+
+    async fn quota_command(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        args: &mut impl Iterator<Item = &str>,
+    ) -> Result<()> {
+        fn parse_guild(s: Option<&str>) -> Result<Id> {
+            match s {
+                Some(s) => Ok(s.parse()?),
+                None => bail!("expected a server ID"),
+            }
+        }
+
+        let reply = match args.next() {
+            None => match message.guild_id {
+                Some(guild) => {
+                    let today = Utc::now().date().naive_utc();
+                    let mut used = format!(
+                        "{} imports today, {} scheduled posts",
+                        self.data.quotas.imports_on(guild, today),
+                        self.data.quotas.polls(guild)
+                    );
+                    let limits = self.limits(guild);
+                    if let Some(left) = self.data.quotas.compute_left(guild, &limits, now_millis())
+                    {
+                        used = format!("{}, {} compute left", used, left);
+                    }
+                    if self.per_guild() {
+                        let model = self.data.models.get_mut(&Scope::Guild(guild));
+                        used = format!("{} model entries, {}", model.entry_count(), used);
+                    }
+                    format!("{}\nUsed: {}", self.limits(guild), used)
+                }
+                None => bail!("quotas only apply to servers"),
+            },
+            Some(_) if !self.is_admin_message(message) => {
+                String::from("Only the bot's admins can change quotas")
+            }
+            Some("set") => {
+                let guild = parse_guild(args.next())?;
+                let (key, value) = match (args.next(), args.next()) {
+                    (Some(k), Some(v)) => (k, v),
+                    _ => bail!("expected a quota and a number or `none`"),
+                };
+                let mut limits = self.limits(guild);
+                limits.set(key, value)?;
+                self.data.quotas.set_override(guild, limits);
+                format!("Quotas for {} are now {}", guild, limits)
+            }
+            Some("reset") => {
+                let guild = parse_guild(args.next())?;
+                if self.data.quotas.clear_override(guild) {
+                    format!("{} is back to the default quotas", guild)
+                } else {
+                    format!("{} already has the default quotas", guild)
+                }
+            }
+            Some(_) => String::from(
+                "Usage: `eg!quota`, `eg!quota set SERVER_ID QUOTA NUMBER|none` or `eg!quota reset SERVER_ID`",
+            ),
+        };
+        client.create_message(message.channel_id, &reply).await
+    }
+
+    async fn headline(&mut self, client: &Client, channel: Id) -> Result<()> {
+        let scope = Scope::Corpus(String::from(news::SCOPE_NAME));
+        let text = match self.generate_moderated(&scope, None).await {
+            Some(t) if t.is_empty() => String::from("No news is good news"),
+            Some(t) => format!("**{}**", t),
+            None => String::from(HELD_BACK),
+        };
+        client.create_message(channel, &text).await
+    }
+
+    /// A made-up event, in the style of the ones the bot has seen.
+    async fn event(&mut self, client: &Client, channel: Id, guild: Option<Id>) -> Result<()> {
+        const DESCRIPTION_SENTENCES: usize = 2;
+        let scope = Scope::events(guild, self.per_guild());
+        let name = match self.generate_moderated(&scope, guild).await {
+            Some(n) if n.trim().is_empty() => {
+                return client
+                    .create_message(channel, "I haven't seen any events yet")
+                    .await
+            }
+            Some(n) => n,
+            None => return client.create_message(channel, HELD_BACK).await,
+        };
+        let mut description = Vec::new();
+        for _ in 0..DESCRIPTION_SENTENCES {
+            if let Some(s) = self.generate_moderated(&scope, guild).await {
+                if !s.trim().is_empty() {
+                    description.push(String::from(s.trim()));
+                }
+            }
+        }
+        let text = format!("📅 **{}**\n{}", name.trim(), description.join(" "));
+        client.create_message(channel, text.trim_end()).await
+    }
+
+    async fn poll(
+        &mut self,
+        client: &Client,
+        channel: Id,
+        guild: Option<Id>,
+        scope: &Scope,
+    ) -> Result<()> {
+        const OPTION_WORDS: usize = 12;
+        if let Some(guild) = guild {
+            if let Some(max) = self.limits(guild).scheduled_posts {
+                if self.data.quotas.polls(guild) >= max {
+                    return client
+                        .create_message(channel, "Too many polls are running here already")
+                        .await;
+                }
+            }
+        }
+        let settings = self.resolve(Place::guild(guild), None);
+        let mut question = match self.generate_moderated_at(scope, &settings).await {
+            Some(q) => self.transform(guild, &settings, &[], q),
+            None => return client.create_message(channel, HELD_BACK).await,
+        };
+        if question.trim().is_empty() {
+            return client
+                .create_message(channel, "I don't know enough words to ask anything yet")
+                .await;
+        }
+        question = question
+            .trim_end()
+            .trim_end_matches(&['.', '!'][..])
+            .to_string();
+        if !question.ends_with('?') {
+            question.push('?');
+        }
+
+        // options are whole sentences, just short ones, filtered and moderated like any other
+        let option_settings = Settings {
+            max_words: Some(
+                settings
+                    .max_words
+                    .map_or(OPTION_WORDS, |m| m.min(OPTION_WORDS)),
+            ),
+            ..settings.clone()
+        };
+        let option_count = self.rng.gen_range(2, poll::OPTION_EMOJIS.len() + 1);
+        let mut options: Vec<String> = Vec::with_capacity(option_count);
+        for _ in 0..option_count * 2 {
+            if options.len() == option_count {
+                break;
+            }
+            let option = match self.generate_moderated_at(scope, &option_settings).await {
+                Some(o) => self.transform(guild, &option_settings, &[], o),
+                None => continue,
+            };
+            let option = String::from(option.trim());
+            if !option.is_empty() && !options.contains(&option) {
+                options.push(option);
+            }
+        }
+        if options.len() < 2 {
+            return client
+                .create_message(
+                    channel,
+                    "I couldn't come up with enough answers to ask that",
+                )
+                .await;
+        }
+
+        let hours = self
+            .cfg
+            .poll_minutes
+            .div_ceil(60)
+            .clamp(1, poll::NATIVE_MAX_HOURS);
+        let native = match poll::fits_native(&question, &options) {
+            true => match client.post_poll(channel, &question, &options, hours).await {
+                Ok(message) => Some(message),
+                Err(e) => {
+                    self.log(format_args!(
+                        "couldn't post a native poll, using reactions: {}",
+                        e
+                    ));
+                    None
+                }
+            },
+            false => None,
+        };
+        let message = match native {
+            Some(message) => message,
+            None => {
+                let message = client
+                    .post_message(channel, &poll::render(&question, &options))
+                    .await?;
+                for emoji in &poll::OPTION_EMOJIS[..options.len()] {
+                    client.create_reaction(channel, message, emoji).await?;
+                }
+                message
+            }
+        };
+        if let Some(guild) = guild {
+            self.data.quotas.add_poll(guild, message);
+        }
+
+        let poll = Poll {
+            channel,
+            message,
+            question,
+            options,
+        };
+        self.data.jobs.schedule(
+            now_millis() + self.cfg.poll_minutes as i64 * 60_000,
+            match native {
+                Some(_) => Job::NativePollResults(poll),
+                None => Job::PollResults(poll),
+            },
+        );
+        Ok(())
+    }
+
+    fn drift_snapshot(&mut self) -> Snapshot {
+        let probes: Vec<_> = self
+            .cfg
+            .drift_probes
+            .iter()
+            .map(|p| self.tokenizer.tokenize(None, p))
+            .collect();
+        Snapshot::take(
+            self.data.models.get_mut(&Scope::Global),
+            &probes,
+            now_millis(),
+        )
+    }
+
+    /// Compares the model with the last monthly snapshot, without taking a new one.
+    async fn drift(&mut self, client: &Client, channel: Id) -> Result<()> {
+        let now = self.drift_snapshot();
+        let text = match self.data.snapshots.latest() {
+            Some(then) => then.report(&now),
+            None => {
+                String::from("There's nothing to compare with until the first monthly snapshot")
+            }
+        };
+        client.create_message(channel, &text).await
+    }
+
+    async fn drift_report(&mut self, client: &Client) -> Result<()> {
+        let channel = match self.cfg.digest_channel {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+        let now = self.drift_snapshot();
+        let text = match self.data.snapshots.latest() {
+            Some(then) => then.report(&now),
+            None => String::from(
+                "🧭 Took my first monthly snapshot, see you next month for how I've changed",
+            ),
+        };
+        self.data.snapshots.push(now);
+        client.create_message(channel, &text).await
+    }
+
+    async fn celebration_command(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        occasion: Occasion,
+        action: &str,
+        mut args: impl Iterator<Item = &str>,
+    ) -> Result<()> {
+        let guild = match message.guild_id {
+            Some(g) => g,
+            None => {
+                return client
+                    .create_message(message.channel_id, "That only works in a server")
+                    .await
+            }
+        };
+        let reply = match action {
+            "set" => {
+                let date = args
+                    .next()
+                    .ok_or_else(|| anyhow!("expected `date` but none was provided"))?;
+                match birthday::parse_month_day(date) {
+                    Some((month, day)) => {
+                        self.data.birthdays.set(
+                            guild,
+                            Celebration {
+                                user: message.author.id,
+                                channel: message.channel_id,
+                                occasion,
+                                month,
+                                day,
+                            },
+                        );
+                        format!("Marked down for {:02}-{:02}", month, day)
+                    }
+                    None => String::from("Dates look like MM-DD"),
+                }
+            }
+            "clear" => {
+                if self
+                    .data
+                    .birthdays
+                    .clear(guild, message.author.id, occasion)
+                {
+                    String::from("Forgotten")
+                } else {
+                    String::from("Nothing to forget")
+                }
+            }
+            a => bail!("unknown action `{}`", a),
+        };
+        client.create_message(message.channel_id, &reply).await
+    }
+
+    /// Sends today's celebrations. The day is only done once every one of them was sent, so the
+    /// ones that weren't are tried again.
+    async fn celebrate(&mut self, client: &Client) -> Result<()> {
+        let today = Utc::now().date().naive_utc();
+        let mut result = Ok(());
+        for (day, guild, celebration) in self.data.birthdays.due(today) {
+            let scope = self.scope(Some(guild), celebration.channel);
+            let settings = self.resolve(Place::guild(Some(guild)), None);
+            let seed = celebration.seed(guild, day);
+            let mut state = ChainState::seeded(seed);
+            let wish = self
+                .generate_moderated_from(&scope, &settings, &mut state)
+                .await
+                .unwrap_or_default();
+            let text = format!(
+                "{} {}! {}",
+                celebration.occasion.greeting(),
+                celebration.user.mention_user(),
+                wish
+            );
+            match client.create_message(celebration.channel, &text).await {
+                Ok(()) => {
+                    self.data.seeds.insert(celebration.channel, seed);
+                    self.data.birthdays.sent(day, guild, &celebration);
+                }
+                // the channel's gone or the bot can't post there, which won't change by retrying
+                Err(e) if e.is::<Rejected>() => {
+                    self.log(format_args!(
+                        "giving up on a celebration in <#{}>: {}",
+                        celebration.channel, e
+                    ));
+                    self.data.birthdays.sent(day, guild, &celebration);
+                }
+                Err(e) => result = result.and(Err(e)),
+            }
+        }
+        if result.is_ok() {
+            self.data.birthdays.done(today);
+        }
+        result
+    }
+
+    async fn run_job(&mut self, client: &Client, job: Job) -> Result<()> {
+        match job {
+            Job::Celebrations => {
+                const RETRY_MILLIS: i64 = 15 * 60 * 1000;
+                let result = self.celebrate(client).await;
+                let next = match result {
+                    Ok(()) => Utc::now().date().succ().and_hms(0, 0, 0).timestamp_millis(),
+                    Err(_) => now_millis() + RETRY_MILLIS,
+                };
+                self.data.jobs.schedule(next, Job::Celebrations);
+                result
+            }
+            Job::MastodonPost => {
+                let result = self.post_to_mastodon(client).await;
+                if let Some(mastodon) = &self.mastodon {
+                    let interval = mastodon.config().interval_minutes as i64 * 60_000;
+                    self.data
+                        .jobs
+                        .schedule(now_millis() + interval, Job::MastodonPost);
+                }
+                result
+            }
+            Job::Digests => {
+                let result = self.send_digests(client).await;
+                let tomorrow = Utc::now().date().succ().and_hms(0, 0, 0);
+                self.data
+                    .jobs
+                    .schedule(tomorrow.timestamp_millis(), Job::Digests);
+                result
+            }
+            Job::DriftReport => {
+                let result = self.drift_report(client).await;
+                self.data
+                    .jobs
+                    .schedule(drift::next_month(), Job::DriftReport);
+                result
+            }
+            Job::AdventureRound { channel, message } => {
+                self.adventure_round(client, channel, message).await
+            }
+            Job::Roulette { guild } => {
+                if self.data.roulette.channel(guild).is_none() {
+                    return Ok(());
+                }
+                self.data.jobs.schedule(
+                    now_millis() + roulette::WEEK_MILLIS,
+                    Job::Roulette { guild },
+                );
+                self.roulette_round(client, guild).await
+            }
+            Job::RouletteReveal { guild, message } => {
+                if let Some((channel, text)) = self.data.roulette.reveal(guild, message) {
+                    client.edit_message(channel, message, &text, &[]).await?;
+                }
+                Ok(())
+            }
+            Job::Decay => {
+                self.decay();
+                Ok(())
+            }
+            Job::MirrorReload => {
+                self.data.models.reload();
+                let interval = self.cfg.mirror_reload_minutes as i64 * 60_000;
+                self.data
+                    .jobs
+                    .schedule(now_millis() + interval, Job::MirrorReload);
+                Ok(())
+            }
+            Job::Engagement {
+                guild,
+                channel,
+                message,
+            } => self.weigh_engagement(client, guild, channel, message).await,
+            Job::Train {
+                channel,
+                report,
+                before,
+                learned,
+                limit,
+            } => {
+                let result = self
+                    .train_page(client, channel, report, before, learned, limit)
+                    .await;
+                if let Err(e) = &result {
+                    let text = format!("Stopped training on <#{}>: {}", channel, e);
+                    self.notify_channel_job(client, "train", channel, Err(e))
+                        .await;
+                    client.create_message(report, &text).await?;
+                }
+                result
+            }
+            Job::Forget {
+                channel,
+                report,
+                user,
+                before,
+                forgotten,
+            } => {
+                let result = self
+                    .forget_page(client, channel, report, user, before, forgotten)
+                    .await;
+                if let Err(e) = &result {
+                    let text = format!("Stopped forgetting <@{}> in <#{}>: {}", user, channel, e);
+                    self.notify_channel_job(client, "forget", channel, Err(e))
+                        .await;
+                    client.create_message(report, &text).await?;
+                }
+                result
+            }
+            Job::Batch(batch) => self.batch_page(client, batch).await,
+            Job::PollResults(poll) => {
+                self.data.quotas.finish_poll(poll.message);
+                let mut response = client.get_message(poll.channel, poll.message).await?;
+                let message = response.get_response().await?;
+                let votes = poll.tally(&message.reactions);
+                client
+                    .create_message(poll.channel, &poll.results_message(votes))
+                    .await
+            }
+            Job::NativePollResults(poll) => {
+                self.data.quotas.finish_poll(poll.message);
+                client.end_poll(poll.channel, poll.message).await?;
+                let mut response = client.get_message(poll.channel, poll.message).await?;
+                let message = response.get_response().await?;
+                let votes = poll.tally_native(message.poll.as_ref());
+                client
+                    .create_message(poll.channel, &poll.results_message(votes))
+                    .await
+            }
+        }
+    }
+
+    async fn post_to_mastodon(&mut self, client: &Client) -> Result<()> {
+        let review_channel = match &self.mastodon {
+            Some(m) => m.config().review_channel,
+            None => return Ok(()),
+        };
+        let text = match self.generate_curated(&Scope::Global, mastodon::MAX_STATUS_CHARS) {
+            Some(t) => t,
+            None => bail!("couldn't generate anything worth posting to mastodon"),
+        };
+        if let Some(moderation) = &self.moderation {
+            if !moderation.allows(&text).await {
+                bail!("moderation held back a mastodon post");
+            }
+        }
+        match (review_channel, &self.mastodon) {
+            (Some(channel), _) => {
+                let buttons = [ActionRow::new(vec![
+                    Button::new(ButtonStyle::Success, "Post", "mastodon:approve"),
+                    Button::new(ButtonStyle::Danger, "Discard", "mastodon:reject"),
+                ])];
+                client
+                    .post_message_with_components(channel, &text, &buttons)
+                    .await?;
+                Ok(())
+            }
+            (None, Some(mastodon)) => mastodon.post_status(&text).await,
+            (None, None) => Ok(()),
+        }
+    }
+
+    /// `/mimic`, `/generate`, `/speak` and `/train`, the same as their prefixed versions, and
+    /// `/imitate` and `/stats`.
+    async fn slash_command(
+        &mut self,
+        client: &Client,
+        interaction: &Interaction<'_>,
+    ) -> Result<()> {
+        let place = Place {
+            guild: interaction.guild_id,
+            channel: interaction.channel_id,
+            user: interaction.author().map(|u| u.id),
+        };
+        let settings = self.settings_at(client, place).await?;
+        let id = |name: &str| -> Result<Id> {
+            match interaction.option(name) {
+                Some(id) => Ok(id.parse()?),
+                None => bail!("expected a {}", name),
+            }
+        };
+        let text = match interaction.command_name() {
+            Some("mimic") | Some("generate") => {
+                let style = interaction
+                    .option("style")
+                    .map(String::from)
+                    .or_else(|| settings.style.clone());
+                let scope = match &style {
+                    Some(style) => Scope::style(place.guild, style, self.cfg.public),
+                    None => Scope::for_guild(place.guild, self.per_guild()),
+                };
+                self.slash_generate(&scope, place.guild, &settings).await
+            }
+            Some("speak") => {
+                let channel = id("channel")?;
+                if !self.channel_models() {
+                    bail!("channels don't have their own models here");
+                }
+                if client.get_channel_guild(channel).await? != place.guild {
+                    bail!("that channel isn't in this server");
+                }
+                self.slash_generate(&Scope::Channel(channel), place.guild, &settings)
+                    .await
+            }
+            Some("imitate") => self.imitate(place.guild, id("user")?).await?,
+            Some("train") => {
+                let (author, report) = match (place.user, place.channel) {
+                    (Some(author), Some(report)) => (author, report),
+                    _ => return Ok(()),
+                };
+                let limit = interaction
+                    .integer_option("limit")
+                    .map(|limit| limit.max(0) as usize);
+                self.start_training(client, place.guild, author, id("channel")?, report, limit)
+                    .await?
+            }
+            Some("stats") => self.stats(place.guild),
+            _ => return Ok(()),
+        };
+        client
+            .create_interaction_response(interaction, &InteractionResponse::message(&text))
+            .await
+    }
+
+    /// A moderated, transformed sentence from `scope` for a slash command.
+    async fn slash_generate(
+        &mut self,
+        scope: &Scope,
+        guild: Option<Id>,
+        settings: &Settings,
+    ) -> String {
+        match self.generate_moderated_at(scope, settings).await {
+            Some(t) if t.trim().is_empty() => String::from("I haven't learned anything here yet"),
+            Some(t) => self.transform(guild, settings, &[], t),
+            None => String::from(HELD_BACK),
+        }
+    }
+
+    /// Something `user` might say, from what the bot still remembers learning from them in
+    /// `guild`.
+    async fn imitate(&mut self, guild: Option<Id>, user: Id) -> Result<String> {
+        if self.data.opt_outs.contains(&user) {
+            bail!("they opted out of being learned from");
+        }
+        let (model, remembered) = self.remembered_model(guild, user);
+        if remembered == 0 {
+            bail!("I don't remember anything they said here");
+        }
+        let words = model
+            .generate_sequence(&mut self.rng)
+            .config(self.cfg.generation)
+            .sentence();
+        let mut text = fit_message(self.tokenizer.detokenize(guild, words));
+        if self.data.filter.blocks(&text) {
+            match self.cfg.filter_action {
+                FilterAction::Regenerate => return Ok(String::from(HELD_BACK)),
+                FilterAction::Censor => text = self.data.filter.censor(&text),
+            }
+        }
+        match &self.moderation {
+            Some(moderation) if !moderation.allows(&text).await => Ok(String::from(HELD_BACK)),
+            _ => Ok(text),
+        }
+    }
+
+    /// A model of what the bot still remembers learning from `user` in `guild`, and how many of
+    /// their messages that is.
+    fn remembered_model(&self, guild: Option<Id>, user: Id) -> (Markov, usize) {
+        let mut model = Markov::new();
+        let mut remembered = 0;
+        for id in self.data.cache.by_author(user, guild) {
+            let learned = self.data.cache.get(id).and_then(|m| m.learned.as_ref());
+            if let Some(learned) = learned {
+                model.insert_sequence(learned.words.iter().cloned());
+                remembered += 1;
+            }
+        }
+        (model, remembered)
+    }
+
+    /// How big the model of `guild` is, and its fingerprint.
+    fn stats(&mut self, guild: Option<Id>) -> String {
+        let scope = Scope::for_guild(guild, self.per_guild());
+        let model = self.data.models.get_mut(&scope);
+        format!(
+            "📊 `{}` has {} entries, looks back {} words, and has the fingerprint `{}`",
+            scope.name(),
+            model.entry_count(),
+            model.order(),
+            model.fingerprint()
+        )
+    }
+
+    /// How big `scope`'s model is, what's in it most and how spread out it is, for knowing when to
+    /// `eg!clean` it or raise its decay.
+    async fn model_stats(&mut self, client: &Client, channel: Id, scope: &Scope) -> Result<()> {
+        const SHOWN: usize = 5;
+        let stats = self.data.models.get_mut(scope).stats(SHOWN);
+        let list = |lines: Vec<String>| match lines.is_empty() {
+            true => String::from("nothing yet"),
+            false => lines.join("\n"),
+        };
+        let field = |name: &str, value: String, inline: bool| EmbedField {
+            name: String::from(name),
+            value,
+            inline,
+        };
+        let starts = stats
+            .top_starts
+            .iter()
+            .map(|(word, n)| format!("`{}` ×{}", word, n))
+            .collect();
+        let transitions = stats
+            .top_transitions
+            .iter()
+            .map(|(index, word, n)| {
+                let index: Vec<_> = index.iter().map(ToString::to_string).collect();
+                format!("`{}` → `{}` ×{}", index.join(" "), word, n)
+            })
+            .collect();
+        let embed = Embed {
+            title: format!("📊 {}", scope.name()),
+            fields: vec![
+                field("Entries", stats.entries.to_string(), true),
+                field("Words learned", stats.total_weight.to_string(), true),
+                field("Distinct words", stats.distinct_words.to_string(), true),
+                field("Entropy", format!("{:.2} bits", stats.entropy), true),
+                field(
+                    "Memory",
+                    format!("~{:.1} MB", stats.memory as f64 / 1e6),
+                    true,
+                ),
+                field("Most common starts", list(starts), false),
+                field("Most common transitions", list(transitions), false),
+            ],
+            ..Embed::default()
+        };
+        client.post_embed(channel, &embed).await
+    }
+
+    /// Makes the slash commands registered with Discord match `commands::defined`, globally and
+    /// in every dev guild, changing only what's different.
+    async fn sync_commands(&self, client: &Client, application: Id) -> Result<()> {
+        if !self.cfg.sync_commands {
+            return Ok(());
+        }
+        let defined = commands::defined();
+        let guilds = self.cfg.dev_guilds.iter().copied().map(Some);
+        for guild in std::iter::once(None).chain(guilds) {
+            let registered = client.get_application_commands(application, guild).await?;
+            let plan = Plan::new(registered, &defined);
+            let place = guild.map_or_else(|| String::from("global"), |g| format!("guild {}", g));
+            if self.cfg.sync_commands_dry_run {
+                println!("{} slash commands, dry run: {}", place, plan);
+                continue;
+            }
+            println!("{} slash commands: {}", place, plan);
+            for command in &plan.create {
+                client
+                    .create_application_command(application, guild, command)
+                    .await?;
+            }
+            for (id, command) in &plan.update {
+                client
+                    .edit_application_command(application, guild, *id, command)
+                    .await?;
+            }
+            for (id, _) in &plan.delete {
+                client
+                    .delete_application_command(application, guild, *id)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Answers `interaction`, deferring it first if it's likely to take too long.
+    async fn handle_interaction(
+        &mut self,
+        client: &Client,
+        interaction: &Interaction<'_>,
+    ) -> Result<()> {
+        let key = match latency::key(interaction.command_name(), interaction.custom_id()) {
+            Some(k) => k,
+            None => return Ok(()),
+        };
+        if self.data.latencies.should_defer(&key) {
+            client.defer_interaction(interaction).await?;
+        }
+        let start = std::time::Instant::now();
+        let result = self.answer_interaction(client, interaction).await;
+        self.data.latencies.record(&key, start.elapsed());
+        // only whoever used the command sees what went wrong
+        if let (Err(e), Interaction::APPLICATION_COMMAND) = (&result, interaction.kind) {
+            let text = format!("⚠️ {}", e);
+            client
+                .create_interaction_response(interaction, &InteractionResponse::ephemeral(&text))
+                .await?;
+        }
+        result
+    }
+
+    async fn answer_interaction(
+        &mut self,
+        client: &Client,
+        interaction: &Interaction<'_>,
+    ) -> Result<()> {
+        if interaction.kind == Interaction::APPLICATION_COMMAND {
+            return self.slash_command(client, interaction).await;
+        }
+        if interaction.kind != Interaction::COMPONENT {
+            return Ok(());
+        }
+        let custom_id = match interaction.custom_id() {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+        let is_admin = interaction
+            .author()
+            .is_some_and(|u| self.cfg.admins.contains(&u.id));
+        if custom_id == stream::STOP_ID {
+            return self.stop_stream(client, interaction, is_admin).await;
+        }
+        if custom_id == continuation::CONTINUE_ID {
+            return self.continue_story(client, interaction).await;
+        }
+        if let Some(option) = custom_id.strip_prefix(adventure::BUTTON_PREFIX) {
+            return self
+                .adventure_vote(client, interaction, option.parse()?)
+                .await;
+        }
+        if let Some(choice) = custom_id.strip_prefix(roulette::BUTTON_PREFIX) {
+            return self
+                .roulette_guess(client, interaction, choice.parse()?)
+                .await;
+        }
+        if !is_admin {
+            return client
+                .create_interaction_response(
+                    interaction,
+                    &InteractionResponse::ephemeral("Only admins get to decide that"),
+                )
+                .await;
+        }
+        if let Some(key) = custom_id.strip_prefix(tune::PREFIX) {
+            return self.tune(client, interaction, key).await;
+        }
+        match custom_id {
+            "mastodon:approve" => self.review_mastodon_post(client, interaction, true).await,
+            "mastodon:reject" => self.review_mastodon_post(client, interaction, false).await,
+            "audit:purge" => self.purge_audit(client, interaction, true).await,
+            "audit:dismiss" => self.purge_audit(client, interaction, false).await,
+            _ => Ok(()),
+        }
+    }
+
+    async fn stop_stream(
+        &mut self,
+        client: &Client,
+        interaction: &Interaction<'_>,
+        is_admin: bool,
+    ) -> Result<()> {
+        let (message, user) = match (&interaction.message, interaction.author()) {
+            (Some(m), Some(u)) => (m, u.id),
+            _ => return Ok(()),
+        };
+        if !self.data.streams.stop(message.id, user, is_admin) {
+            return client
+                .create_interaction_response(
+                    interaction,
+                    &InteractionResponse::ephemeral("Only whoever asked for this can stop it"),
+                )
+                .await;
+        }
+        // it stops where it is, minus the cursor
+        let text = message.content.as_str().trim_end_matches(" ▌");
+        client
+            .create_interaction_response(interaction, &InteractionResponse::update(text, &[]))
+            .await
+    }
+
+    async fn review_mastodon_post(
+        &mut self,
+        client: &Client,
+        interaction: &Interaction<'_>,
+        approved: bool,
+    ) -> Result<()> {
+        let (mastodon, text) = match (&self.mastodon, &interaction.message) {
+            (Some(m), Some(message)) => (m, message.content.as_str()),
+            _ => return Ok(()),
+        };
+        let update = if approved {
+            match mastodon.post_status(text).await {
+                Ok(()) => format!("✅ Posted: {}", text),
+                Err(e) => format!("⚠️ Couldn't post ({}): {}", e, text),
+            }
+        } else {
+            format!("🗑️ Discarded: ~~{}~~", text)
+        };
+        client
+            .create_interaction_response(interaction, &InteractionResponse::update(&update, &[]))
+            .await
+    }
+
+    async fn audit(&mut self, client: &Client, message: &Message<'_>, scope: &Scope) -> Result<()> {
+        const SHOWN: usize = 25;
+        if !self.is_admin_message(message) {
+            return client
+                .create_message(message.channel_id, "Only admins can audit the model")
+                .await;
+        }
+        if let Some(reply) = self.spend(message.guild_id, Work::Scan) {
+            return client.create_message(message.channel_id, &reply).await;
+        }
+        let findings = audit::audit(self.data.models.get_mut(scope), &self.cfg.blocklist)?;
+        if findings.is_empty() {
+            return client
+                .create_message(message.channel_id, "✨ Nothing to clean up")
+                .await;
+        }
+        let mut report = format!("🔍 Found {} words to look at:\n", findings.len());
+        for (word, kind) in findings.iter().take(SHOWN) {
+            report += format!("• `{}` ({})\n", audit::redact(word), kind).as_str();
+        }
+        if findings.len() > SHOWN {
+            report += format!("…and {} more\n", findings.len() - SHOWN).as_str();
+        }
+        let buttons = [ActionRow::new(vec![
+            Button::new(ButtonStyle::Danger, "Purge all", "audit:purge"),
+            Button::new(ButtonStyle::Secondary, "Dismiss", "audit:dismiss"),
+        ])];
+        let id = client
+            .post_message_with_components(message.channel_id, &report, &buttons)
+            .await?;
+        let words = findings.into_keys().collect();
+        self.data.audits.insert(id, (scope.clone(), words));
+        Ok(())
+    }
+
+    /// The models that are `guild`'s alone, given its channels.
+    fn models_of(&self, guild: Id, channels: &[Id]) -> Vec<Scope> {
+        let mut scopes = Vec::new();
+        if self.per_guild() {
+            scopes.push(Scope::Guild(guild));
+        }
+        if self.channel_models() {
+            let channels = channels.iter().map(|&c| Scope::Channel(c));
+            scopes.extend(channels.filter(|s| self.data.models.exists(s)));
+        }
+        for (channel, style) in self.data.styles.list() {
+            let scope = Scope::style(Some(guild), style, self.cfg.public);
+            if channels.contains(&channel)
+                && scope.owner() == Some(guild)
+                && !scopes.contains(&scope)
+            {
+                scopes.push(scope);
+            }
+        }
+        scopes
+    }
+
+    /// Sends the server's owner everything the bot keeps about the server. Only models that are the
+    /// server's alone are in it, never the shared ones.
+    async fn data_export(&mut self, client: &Client, message: &Message<'_>) -> Result<()> {
+        let guild = match message.guild_id {
+            Some(g) => g,
+            None => bail!("exports only work in servers"),
+        };
+        if !self.is_owner_message(client, message, guild).await? {
+            return client
+                .create_message(
+                    message.channel_id,
+                    "Only the server's owner can export its data",
+                )
+                .await;
+        }
+        let channel = Some(message.channel_id);
+        let archive = match client.get_guild_channels(guild).await {
+            Ok(channels) => self.export_archive(guild, &channels),
+            Err(e) => Err(e),
+        };
+        let sent = match archive {
+            Ok((filename, archive)) => {
+                let dm = client.create_dm(message.author.id).await?;
+                client
+                    .upload_file(
+                        dm,
+                        "📦 Everything I keep about your server",
+                        &filename,
+                        &archive,
+                    )
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        let reply = match sent {
+            Ok(()) => {
+                let reply = "📦 Sent you the export in DMs";
+                self.notify(webhook::Event::done(
+                    "dataexport",
+                    Some(guild),
+                    channel,
+                    reply,
+                ));
+                String::from(reply)
+            }
+            Err(e) => {
+                self.notify(webhook::Event::failed(
+                    "dataexport",
+                    Some(guild),
+                    channel,
+                    &e,
+                ));
+                format!("⚠️ Couldn't send the export: {}", e)
+            }
+        };
+        client.create_message(message.channel_id, &reply).await
+    }
+
+    /// Everything kept about `guild` as a tar archive, and what to call it, for `eg!dataexport`.
+    fn export_archive(&mut self, guild: Id, channels: &[Id]) -> Result<(String, Vec<u8>)> {
+        let scopes = self.models_of(guild, channels);
+
+        let now = now_millis();
+        let mut archive = Archive::default();
+        let mut entries = HashMap::new();
+        let mut fingerprints = HashMap::new();
+        let mut audits = Vec::new();
+        for scope in &scopes {
+            let path = scope.path().to_string_lossy().into_owned();
+            let model = self.data.models.get_mut(scope);
+            archive.add(&path, &bincode::serialize(model)?, now / 1000);
+            entries.insert(path.clone(), model.entry_count());
+            fingerprints.insert(path.clone(), model.fingerprint());
+            let findings = audit::audit(model, &self.cfg.blocklist)?
+                .into_iter()
+                .map(|(word, kind)| (audit::redact(&word), kind))
+                .collect();
+            audits.push(export::Audit {
+                model: path,
+                findings,
+            });
+        }
+        let config = export::Config {
+            mix: self.data.mixes.get(guild),
+            transforms: self.data.transforms.get(Some(guild)),
+            settings: self.data.channel_settings.of_guild(guild, channels),
+            learning_blocklist: self.data.automod.keywords(guild).collect(),
+            styles: self
+                .data
+                .styles
+                .list()
+                .into_iter()
+                .filter(|(channel, _)| channels.contains(channel))
+                .collect(),
+            limits: self.limits(guild),
+        };
+        let filename = format!(
+            "export-{}-{}.tar",
+            guild,
+            combined_fingerprint(fingerprints.values().map(String::as_str))
+        );
+        let stats = export::Stats {
+            guild,
+            entries,
+            fingerprints,
+            last_active: self.data.activity.get(&guild).copied(),
+            exported_at: now,
+        };
+        archive.add(
+            "settings.json",
+            &serde_json::to_vec_pretty(&config)?,
+            now / 1000,
+        );
+        archive.add(
+            "audit.json",
+            &serde_json::to_vec_pretty(&audits)?,
+            now / 1000,
+        );
+        archive.add(
+            "stats.json",
+            &serde_json::to_vec_pretty(&stats)?,
+            now / 1000,
+        );
+        Ok((filename, archive.finish()))
+    }
+
+    async fn handoff_command(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        args: &mut impl Iterator<Item = &str>,
+    ) -> Result<()> {
+        const MAX_HANDOFF_SIZE: u64 = 25 * 1024 * 1024;
+        let guild = match message.guild_id {
+            Some(g) => g,
+            None => bail!("handoffs only work in servers"),
+        };
+        if !self.is_owner_message(client, message, guild).await? {
+            return client
+                .create_message(
+                    message.channel_id,
+                    "Only the server's owner can hand it off",
+                )
+                .await;
+        }
+        match args.next() {
+            None => self.hand_off(client, message, guild).await,
+            Some("import") => {
+                let code = match args.next() {
+                    Some(c) => c,
+                    None => bail!("expected the code that came with the handoff file"),
+                };
+                let bytes = download_attachment(message, MAX_HANDOFF_SIZE, "handoff file").await?;
+                let handoff = Handoff::open(&bytes, code)?;
+                let reply = self.take_handoff(guild, handoff)?;
+                client.create_message(message.channel_id, &reply).await
+            }
+            Some(a) => bail!("unknown action `{}`, try `import`", a),
+        }
+    }
+
+    /// Packs up the guild for its owner, moving its scheduled jobs along with it.
+    async fn hand_off(&mut self, client: &Client, message: &Message<'_>, guild: Id) -> Result<()> {
+        let channels = client.get_guild_channels(guild).await?;
+        let (handoff, fingerprint) = self.pack_handoff(guild, &channels)?;
+        let code = handoff::code(&mut self.rng);
+        let sealed = handoff.seal(&code)?;
+
+        let dm = client.create_dm(message.author.id).await?;
+        let text = format!(
+            "📦 Your server, ready to move. In the server, attach this to \
+             `eg!handoff import {}` for the other bot. Keep the code to yourself, it's the key.",
+            code
+        );
+        let filename = format!("handoff-{}-{}.dat", guild, fingerprint);
+        let reply = match client.upload_file(dm, &text, &filename, &sealed).await {
+            Ok(()) => String::from("📦 Sent you the handoff in DMs"),
+            Err(e) => {
+                for (at, job) in handoff.jobs {
+                    self.data.jobs.schedule(at, job);
+                }
+                format!("⚠️ Couldn't send the handoff: {}", e)
+            }
+        };
+        client.create_message(message.channel_id, &reply).await
+    }
+
+    /// Everything `hand_off` sends the owner of `guild`, with its models' combined fingerprint.
+    /// Takes the guild's scheduled jobs out, for the caller to put back if it can't be sent.
+    fn pack_handoff(&mut self, guild: Id, channels: &[Id]) -> Result<(Handoff<Job>, String)> {
+        let mut models = Vec::new();
+        for scope in self.models_of(guild, channels) {
+            let model = self.data.models.get_mut(&scope);
+            models.push((scope, bincode::serialize(model)?, model.fingerprint()));
+        }
+        let fingerprint = combined_fingerprint(models.iter().map(|(_, _, f)| f.as_str()));
+        let mut handoff = Handoff {
+            guild,
+            models,
+            mix: self.data.mixes.get(guild).cloned(),
+            transforms: self.data.transforms.get(Some(guild)).to_vec(),
+            settings: self.data.channel_settings.of_guild(guild, channels),
+            learning_blocklist: self
+                .data
+                .automod
+                .keywords(guild)
+                .map(String::from)
+                .collect(),
+            styles: self
+                .data
+                .styles
+                .list()
+                .into_iter()
+                .filter(|(channel, _)| channels.contains(channel))
+                .map(|(channel, style)| (channel, String::from(style)))
+                .collect(),
+            celebrations: self.data.birthdays.of_guild(guild).to_vec(),
+            jobs: Vec::new(),
+        };
+        // the jobs only move once the owner has the file, so they aren't lost if it can't be sent
+        handoff.jobs = self.data.jobs.take_where(|job| match job {
+            Job::PollResults(poll) | Job::NativePollResults(poll) => {
+                channels.contains(&poll.channel)
+            }
+            Job::Engagement { guild: g, .. } => *g == Some(guild),
+            _ => false,
+        });
+        Ok((handoff, fingerprint))
+    }
+
+    /// Takes in a guild another instance handed off, as long as it won't overwrite anything.
+    fn take_handoff(&mut self, guild: Id, handoff: Handoff<Job>) -> Result<String> {
+        if handoff.guild != guild {
+            bail!("that handoff is for another server");
+        }
+        let limits = self.limits(guild);
+        let mut models = Vec::with_capacity(handoff.models.len());
+        for (scope, bytes, fingerprint) in &handoff.models {
+            let scope = match scope {
+                Scope::GuildStyle(_, name) => Scope::style(Some(guild), name, self.cfg.public),
+                Scope::Events(_) => Scope::events(Some(guild), self.per_guild()),
+                Scope::Channel(channel) if self.channel_models() => Scope::Channel(*channel),
+                Scope::Channel(_) => continue,
+                _ => Scope::for_guild(Some(guild), self.per_guild()),
+            };
+            let model: Markov = bincode::deserialize(bytes)?;
+            if model.fingerprint() != *fingerprint {
+                bail!(
+                    "`{}` doesn't match its fingerprint, it was corrupted on the way",
+                    scope.path().display()
+                );
+            }
+            if self.data.models.get_mut(&scope).entry_count() > 0 {
+                bail!("there's already a model at `{}`", scope.path().display());
+            }
+            if limits
+                .model_entries
+                .is_some_and(|max| model.entry_count() > max)
+            {
+                bail!(
+                    "`{}` is bigger than this bot allows",
+                    scope.path().display()
+                );
+            }
+            models.push((scope, model));
+        }
+        for (scope, model) in models {
+            if self.replication.is_some() {
+                let model = bincode::serialize(&model)?;
+                self.replicate(Change::Replace {
+                    scope: scope.clone(),
+                    model,
+                });
+            }
+            self.data.models.replace(scope, model);
+        }
+
+        let mut skipped = Vec::new();
+        if let Some(mix) = handoff.mix {
+            if let Err(e) = self.data.mixes.set(guild, mix, &self.data.corpora) {
+                skipped.push(format!("the mix ({})", e));
+            }
+        }
+        let transforms: Vec<_> = handoff.transforms.iter().map(|t| t.name()).collect();
+        if !transforms.is_empty() {
+            self.data.transforms.set(guild, &transforms)?;
+        }
+        self.data.channel_settings.restore(guild, handoff.settings);
+        self.data.resolved.invalidate();
+        for keyword in &handoff.learning_blocklist {
+            self.data.automod.add(guild, keyword);
+        }
+        for (channel, style) in &handoff.styles {
+            self.data.styles.tag(*channel, style)?;
+        }
+        for celebration in handoff.celebrations {
+            self.data.birthdays.set(guild, celebration);
+        }
+        for (at, job) in handoff.jobs {
+            if let Job::PollResults(poll) | Job::NativePollResults(poll) = &job {
+                self.data.quotas.add_poll(guild, poll.message);
+            }
+            self.data.jobs.schedule(at, job);
+        }
+
+        let mut reply = format!(
+            "✅ Took in {} models and the server's settings, styles and celebrations",
+            handoff.models.len()
+        );
+        if !skipped.is_empty() {
+            reply += format!(", except {}", skipped.join(" and ")).as_str();
+        }
+        Ok(reply)
+    }
+
+    async fn purge_audit(
+        &mut self,
+        client: &Client,
+        interaction: &Interaction<'_>,
+        purge: bool,
+    ) -> Result<()> {
+        let pending = interaction
+            .message
+            .as_ref()
+            .and_then(|m| self.data.audits.remove(&m.id));
+        let update = match pending {
+            Some((scope, words)) if purge => {
+                let removed = self.data.models.get_mut(&scope).remove_words(&words);
+                let purged = words.len();
+                self.replicate(Change::RemoveWords { scope, words });
+                format!("🧹 Purged {} words ({} entries)", purged, removed)
+            }
+            Some(_) => String::from("Audit dismissed"),
+            // pending audits don't survive restarts
+            None => String::from("This audit expired, run `eg!audit` again"),
+        };
+        client
+            .create_interaction_response(interaction, &InteractionResponse::update(&update, &[]))
+            .await
+    }
+
+    /// Posts `eg!tune`'s panel for the server's defaults.
+    async fn tune_command(&mut self, client: &Client, message: &Message<'_>) -> Result<()> {
+        let guild = match message.guild_id {
+            Some(g) => g,
+            None => bail!("tuning only works in servers"),
+        };
+        if !self.is_admin_message(message) {
+            return client
+                .create_message(message.channel_id, "Only admins can tune the bot")
+                .await;
+        }
+        let (text, rows) = self.tune_panel(client, guild, message.channel_id).await?;
+        client
+            .post_message_with_components(message.channel_id, &text, &rows)
+            .await?;
+        Ok(())
+    }
+
+    /// Sets what was picked on `eg!tune`'s panel, or previews it again, and updates the panel.
+    async fn tune(
+        &mut self,
+        client: &Client,
+        interaction: &Interaction<'_>,
+        key: &str,
+    ) -> Result<()> {
+        let (guild, channel) = match (interaction.guild_id, interaction.channel_id) {
+            (Some(g), Some(c)) => (g, c),
+            _ => return Ok(()),
+        };
+        if interaction.custom_id() == Some(tune::DONE_ID) {
+            let text = tune::summary(&self.server_settings(guild));
+            return client
+                .create_interaction_response(interaction, &InteractionResponse::update(&text, &[]))
+                .await;
+        }
+        if let (true, Some(value)) = (tune::is_knob(key), interaction.selected()) {
+            self.data.channel_settings.set(
+                Level::Guild(guild),
+                key,
+                &[value],
+                &self.data.corpora,
+            )?;
+            self.data.resolved.invalidate();
+        }
+        let (text, rows) = self.tune_panel(client, guild, channel).await?;
+        client
+            .create_interaction_response(interaction, &InteractionResponse::update(&text, &rows))
+            .await
+    }
+
+    /// `eg!tune`'s panel, previewing what the bot would say in `channel`.
+    async fn tune_panel(
+        &mut self,
+        client: &Client,
+        guild: Id,
+        channel: Id,
+    ) -> Result<(String, Vec<ActionRow>)> {
+        let place = Place {
+            guild: Some(guild),
+            channel: Some(channel),
+            user: None,
+        };
+        let settings = self.settings_at(client, place).await?;
+        let scope = self.scope(Some(guild), channel);
+        let preview = self.generate_moderated_at(&scope, &settings).await;
+        Ok(tune::panel(
+            &self.server_settings(guild),
+            preview.as_deref(),
+        ))
+    }
+
+    /// What `eg!settings server` has set for `guild`.
+    fn server_settings(&self, guild: Id) -> Settings {
+        let [server, ..] = self.data.channel_settings.layers(guild, None, None);
+        server.cloned().unwrap_or_default()
+    }
+
+    async fn canonize(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        args: &mut impl Iterator<Item = &str>,
+    ) -> Result<()> {
+        if !self.is_admin_message(message) {
+            return client
+                .create_message(message.channel_id, "Only admins can decide what's canon")
+                .await;
+        }
+        let text = args
+            .collect::<Vec<_>>()
+            .join(" ")
+            .trim_matches(|c| c == '"' || c == '“' || c == '”')
+            .to_string();
+        if text.split_whitespace().count() < 2 {
+            bail!("canon needs at least two words");
+        }
+        let reply = if self
+            .data
+            .canon
+            .pin(message.guild_id, message.channel_id, text.clone())
+        {
+            let scope = self.scope(message.guild_id, message.channel_id);
+            self.ensure(scope, &text);
+            format!("📜 \"{}\" is now canon", text)
+        } else {
+            String::from("That's already canon")
+        };
+        client.create_message(message.channel_id, &reply).await
+    }
+
+    async fn list_canon(&mut self, client: &Client, message: &Message<'_>) -> Result<()> {
+        let text = self
+            .data
+            .canon
+            .guild(message.guild_id)
+            .enumerate()
+            .fold(String::new(), |p, (i, pinned)| {
+                p + format!("{}. {}\n", i + 1, pinned.text).as_str()
+            });
+        let text = if text.is_empty() {
+            String::from("Nothing is canon yet")
+        } else {
+            text
+        };
+        client.create_message(message.channel_id, &text).await
+    }
+
+    async fn uncanonize(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        index: usize,
+    ) -> Result<()> {
+        let reply = if !self.is_admin_message(message) {
+            String::from("Only admins can decide what's canon")
+        } else {
+            match self.data.canon.unpin(message.guild_id, index) {
+                Some(pinned) => format!("\"{}\" isn't canon anymore", pinned.text),
+                None => format!("There's no canon number {}", index),
+            }
+        };
+        client.create_message(message.channel_id, &reply).await
+    }
+
+    /// Puts canon back after anything that lowers or removes weights.
+    fn reapply_canon(&mut self) {
+        let pinned: Vec<_> = self
+            .data
+            .canon
+            .all()
+            .iter()
+            .map(|p| (self.scope(p.guild, p.channel), p.text.clone()))
+            .collect();
+        for (scope, text) in pinned {
+            self.ensure(scope, &text);
+        }
+    }
+
+    async fn clean(&mut self, client: &Client, message: &Message<'_>) -> Result<()> {
+        if self.is_admin_message(message) {
+            let scope = self.scope(message.guild_id, message.channel_id);
+            let removed = self.data.models.get_mut(&scope).clean();
+            self.reapply_canon();
+            client
+                .create_message(message.channel_id, &format!("Removed {} entries", removed))
+                .await
+        } else {
+            client
+                .create_message(
+                    message.channel_id,
+                    "Watch it, string bean. You aren't an admin",
+                )
+                .await
+        }
+    }
+
+    /// Repairs the models something panicked while using, and this channel's.
+    async fn fsck(&mut self, client: &Client, message: &Message<'_>) -> Result<()> {
+        if !self.is_admin_message(message) {
+            bail!("only the bot's admins can fsck models");
+        }
+        let mut scopes: Vec<_> = self.data.fsck.drain().collect();
+        let scope = self.scope(message.guild_id, message.channel_id);
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+        let mut lines = Vec::new();
+        for scope in scopes {
+            let repaired = self.data.models.get_mut(&scope).fsck();
+            lines.push(format!("`{}`: repaired {} entries", scope.name(), repaired));
+        }
+        client
+            .create_message(message.channel_id, &lines.join("\n"))
+            .await
+    }
+
+    /// Every model of the server with its fingerprint, or the one used in DMs, for checking two
+    /// instances of the bot hold the same ones.
+    async fn fingerprint(&mut self, client: &Client, message: &Message<'_>) -> Result<()> {
+        if !self.is_admin_message(message) {
+            bail!("only the bot's admins can fingerprint models");
+        }
+        let scopes = match message.guild_id {
+            Some(guild) => {
+                let channels = client.get_guild_channels(guild).await?;
+                let mut scopes = self.models_of(guild, &channels);
+                let scope = self.scope(message.guild_id, message.channel_id);
+                if !scopes.contains(&scope) {
+                    scopes.push(scope);
+                }
+                scopes
+            }
+            None => vec![self.scope(None, message.channel_id)],
+        };
+        let lines: Vec<_> = scopes
+            .iter()
+            .map(|scope| {
+                let model = self.data.models.get_mut(scope);
+                format!(
+                    "`{}`: `{}` ({} entries)",
+                    scope.name(),
+                    model.fingerprint(),
+                    model.entry_count()
+                )
+            })
+            .collect();
+        client
+            .create_message(message.channel_id, &lines.join("\n"))
+            .await
+    }
+
+    /// Makes an error of a handler's panic, after flagging the model it was likely using for
+    /// `eg!fsck`. The panic hook already printed it, and reported it if Sentry is set up.
+    fn panicked(&mut self, scope: Option<Scope>, panic: Box<dyn Any + Send>) -> anyhow::Error {
+        let what = match panic.downcast::<String>() {
+            Ok(s) => *s,
+            Err(panic) => panic
+                .downcast_ref::<&str>()
+                .copied()
+                .unwrap_or("panic")
+                .into(),
+        };
+        if let Some(scope) = scope {
+            self.log(format_args!("flagged `{}` for eg!fsck", scope.name()));
+            self.data.fsck.insert(scope);
+        }
+        anyhow!("panicked: {}", what)
+    }
+
+    /// Fades out every loaded model, then schedules the next time.
+    fn decay(&mut self) {
+        let cfg = match self.cfg.decay {
+            Some(cfg) if cfg.factor > 0.0 && cfg.factor <= 1.0 => cfg,
+            Some(cfg) => {
+                return self.log(format_args!(
+                    "not decaying, the factor has to be more than 0 and at most 1, not {}",
+                    cfg.factor
+                ))
+            }
+            None => return,
+        };
+        let started = std::time::Instant::now();
+        let mut forgotten = 0;
+        // decay is random, so standbys are sent the models it leaves rather than told to decay
+        let replicating = self.replication.is_some();
+        let mut decayed = Vec::new();
+        let rng = &mut self.rng;
+        let result = self.data.models.change_all(|scope, markov| {
+            forgotten += markov.decay(cfg.factor, cfg.min_weight, rng);
+            if replicating {
+                decayed.push((scope.clone(), bincode::serialize(markov)?));
+            }
+            Ok(())
+        });
+        for (scope, model) in decayed {
+            self.replicate(Change::Replace { scope, model });
+        }
+        // canon isn't meant to fade
+        self.reapply_canon();
+        match result {
+            Ok(()) => self.log(format_args!(
+                "decayed models by {}, forgetting {} transitions, in {:?}",
+                cfg.factor,
+                forgotten,
+                started.elapsed()
+            )),
+            Err(e) => self.log(format_args!("couldn't decay every model: {:#}", e)),
+        }
+        self.schedule_decay();
+    }
+
+    fn schedule_decay(&mut self) {
+        if let Some(cfg) = self.cfg.decay {
+            let interval = cfg.every_days as i64 * 24 * 60 * 60_000;
+            self.data.jobs.schedule(now_millis() + interval, Job::Decay);
+        }
+    }
+
+    async fn token_command(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        action: &str,
+    ) -> Result<()> {
+        if !self.is_admin_message(message) {
+            return client
+                .create_message(message.channel_id, "Only admins can touch the token")
+                .await;
+        }
+        let reply = match action {
+            "reload" => {
+                let configured = bot_config()?.token;
+                let token = secrets::token(configured.as_ref())?;
+                if token == self.token {
+                    "That's the token I'm already using"
+                } else {
+                    self.restart = true;
+                    "Got a new token, reconnecting…"
+                }
+            }
+            a => bail!("unknown action `{}`", a),
+        };
+        client.create_message(message.channel_id, reply).await
+    }
+
+    async fn reload_command(&mut self, client: &Client, message: &Message<'_>) -> Result<()> {
+        if !self.is_admin_message(message) {
+            bail!("only admins can reload the config");
+        }
+        let reply = self.reload_config()?;
+        client.create_message(message.channel_id, reply).await
+    }
+
+    async fn perf(&mut self, client: &Client, message: &Message<'_>) -> Result<()> {
+        if !self.is_admin_message(message) {
+            bail!("only admins can see how the bot's performing");
+        }
+        let fields = perf::Op::ALL
+            .iter()
+            .map(|&op| EmbedField {
+                name: String::from(op.name()),
+                value: perf::summary(op)
+                    .map_or_else(|| String::from("nothing yet"), |s| s.to_string()),
+                inline: false,
+            })
+            .collect();
+        let embed = Embed {
+            title: String::from("Latency over the last hour"),
+            fields,
+            ..Embed::default()
+        };
+        client.post_embed(message.channel_id, &embed).await
+    }
+
+    /// Reloads the config if SIGHUP asked for it, keeping the old one if the new one's broken.
+    fn reload_if_signalled(&mut self) {
+        if !config::reload_requested() {
+            return;
+        }
+        match self.reload_config() {
+            Ok(done) => self.log(format_args!("SIGHUP: {}", done)),
+            Err(e) => self.log(format_args!("SIGHUP: kept the old config: {:#}", e)),
+        }
+    }
+
+    /// Swaps in the config as it is now, and everything built from it, if it's valid. Changing
+    /// the token, intents, storage or `mirror` needs a reconnect, which builds it all anyway.
+    fn reload_config(&mut self) -> Result<&'static str> {
+        let cfg = bot_config()?;
+        if cfg.dedup_snapshots && self.data.models.master_key().is_some() {
+            bail!(DEDUP_ENCRYPTED);
+        }
+        let token = secrets::token(cfg.token.as_ref())?;
+        if token != self.token
+            || cfg.intents != self.cfg.intents
+            || cfg.storage != self.cfg.storage
+            || cfg.mirror != self.cfg.mirror
+        {
+            self.restart = true;
+            return Ok("Reloaded the config, reconnecting to apply it…");
+        }
+        let mastodon = cfg.mastodon.clone().map(Mastodon::new).transpose()?;
+        let moderation = cfg.moderation.clone().map(Moderation::new).transpose()?;
+        let tokenizer = Tokenizer::new(cfg.tokenizer.clone(), &cfg.punctuation)?;
+        let sentry = cfg.sentry.as_ref().map(Sentry::new).transpose()?;
+        self.data.plugins.init(&cfg.plugins)?;
+        self.data
+            .models
+            .set_orders(cfg.chain_order, cfg.chain_orders.clone())?;
+        persist::keep_snapshots(cfg.save_snapshots);
+        persist::dedup_snapshots(cfg.dedup_snapshots);
+        self.mastodon = mastodon;
+        self.moderation = moderation;
+        self.tokenizer = tokenizer;
+        self.sentry = sentry;
+        self.cfg = cfg;
+        self.data.resolved.invalidate();
+        Ok("Reloaded the config. Bridges, `api`, `replication` and `otlp` only change on a restart")
+    }
+
+    #[cfg(feature = "profiling")]
+    async fn profile(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        seconds: u64,
+    ) -> Result<()> {
+        if !self.is_admin_message(message) {
+            return client
+                .create_message(message.channel_id, "Only admins can profile the bot")
+                .await;
+        }
+        if seconds == 0 || seconds > profile::MAX_SECONDS {
+            bail!("profiles can be 1 to {} seconds long", profile::MAX_SECONDS);
+        }
+        profile::spawn(
+            self.token.clone(),
+            message.channel_id,
+            std::time::Duration::from_secs(seconds),
+        );
+        client
+            .create_message(
+                message.channel_id,
+                &format!("Profiling for {} seconds…", seconds),
+            )
+            .await
+    }
+
+    #[cfg(not(feature = "profiling"))]
+    async fn profile(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        _seconds: u64,
+    ) -> Result<()> {
+        client
+            .create_message(message.channel_id, "This build doesn't support profiling")
+            .await
+    }
+
+    #[cfg(feature = "wasm")]
+    async fn wasm_command(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        action: &str,
+        args: &mut impl Iterator<Item = &str>,
+    ) -> Result<()> {
+        if !self.is_admin_message(message) {
+            return client
+                .create_message(message.channel_id, "Only admins can manage wasm filters")
+                .await;
+        }
+        let reply = match (action, args.next()) {
+            ("list", _) => list_text(self.data.wasm.names()),
+            ("remove", Some(name)) => {
+                if self.data.wasm.remove(name)? {
+                    format!("Removed `{}`", name)
+                } else {
+                    format!("There's no filter called `{}`", name)
+                }
+            }
+            ("install", Some(name)) => {
+                let bytes = download_attachment(message, wasm::MAX_MODULE_SIZE, "`.wasm` file").await?;
+                match self.data.wasm.install(name, &bytes) {
+                    Ok(()) => format!("Installed `{}`", name),
+                    Err(e) => format!("Couldn't install `{}`: {}", name, e),
+                }
+            }
+            _ => String::from("Usage: `eg!wasm list`, `eg!wasm install NAME` with the file attached, or `eg!wasm remove NAME`"),
+        };
+        client.create_message(message.channel_id, &reply).await
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    async fn wasm_command(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        _action: &str,
+        _args: &mut impl Iterator<Item = &str>,
+    ) -> Result<()> {
+        client
+            .create_message(
+                message.channel_id,
+                "This build doesn't support wasm filters",
+            )
+            .await
+    }
+
+    #[cfg(feature = "scripting")]
+    async fn script_command(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        action: &str,
+        args: &mut impl Iterator<Item = &str>,
+    ) -> Result<()> {
+        let guild = match message.guild_id {
+            Some(g) => g,
+            None => bail!("scripts only work in servers"),
+        };
+        if !self.is_admin_message(message) {
+            return client
+                .create_message(message.channel_id, "Only admins can manage scripts")
+                .await;
+        }
+        let reply = match (action, args.next()) {
+            ("list", _) => list_text(self.data.scripts.names(guild)),
+            ("remove", Some(name)) => {
+                if self.data.scripts.remove(guild, name) {
+                    format!("Removed `{}`", name)
+                } else {
+                    format!("There's no script called `{}`", name)
+                }
+            }
+            ("add", Some(name)) => {
+                // everything after the name, keeping newlines, minus any code block fences
+                let content = message.content.as_str();
+                let source = content[content.find(name).unwrap_or(0) + name.len()..]
+                    .trim()
+                    .trim_start_matches("```rust")
+                    .trim_start_matches("```rhai")
+                    .trim_matches('`');
+                match self.data.scripts.install(guild, name, source) {
+                    Ok(()) => format!("Installed `{}`", name),
+                    Err(e) => format!("Couldn't install `{}`: {}", name, e),
+                }
+            }
+            _ => String::from(
+                "Usage: `eg!script list`, `eg!script add NAME CODE` or `eg!script remove NAME`",
+            ),
+        };
+        client.create_message(message.channel_id, &reply).await
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    async fn script_command(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        _action: &str,
+        _args: &mut impl Iterator<Item = &str>,
+    ) -> Result<()> {
+        client
+            .create_message(message.channel_id, "This build doesn't support scripts")
+            .await
+    }
+
+    #[cfg(feature = "scripting")]
+    async fn run_scripts(&mut self, client: &Client, message: &Message<'_>) -> Result<()> {
+        let guild = match message.guild_id {
+            Some(g) => g,
+            None => return Ok(()),
+        };
+        let replies =
+            self.data
+                .scripts
+                .on_message(guild, message.author.username, message.content.as_str());
+        for reply in replies {
+            client.create_message(message.channel_id, &reply).await?;
+        }
+        Ok(())
+    }
+
+    /// Answers with what `query` finds in `scope`'s model, for `eg!follows` and the like.
+    async fn transitions(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        scope: &Scope,
+        title: &str,
+        query: impl FnOnce(&Markov) -> Vec<(markov::Word, usize)>,
+    ) -> Result<()> {
+        if let Some(reply) = self.spend(message.guild_id, Work::Scan) {
+            return client.create_message(message.channel_id, &reply).await;
+        }
+        let found = query(self.data.models.get_mut(scope));
+        client
+            .create_message(message.channel_id, &transitions_text(title, &found))
+            .await
+    }
+
+    async fn learn_channel(
+        &mut self,
+        client: &Client,
+        return_channel: Id,
+        channel: Id,
+        max: Option<usize>,
+    ) -> Result<()> {
+        let mut oldest_id = None;
+        let mut oldest_ts = None;
+        let mut sum = 0;
+        loop {
+            let mut response = client
+                .get_channel_messages(channel, oldest_id.take())
+                .await?;
+            let rate_limit_end = response.rate_limit_end();
+            let messages = response.get_response().await?;
+
+            sum += messages.len();
+            for message in messages {
+                self.remember(&message);
+                if oldest_ts.map_or(true, |ts| message.timestamp < ts) {
+                    oldest_id = Some(message.id);
+                    oldest_ts = Some(message.timestamp);
+                }
+            }
+
+            if oldest_id.is_none() || max.map(|m| sum >= m).unwrap_or(false) {
+                client
+                    .create_message(return_channel, &format!("learned from {} messages", sum))
+                    .await?;
+                break Ok(());
+            }
+
+            if let Some(time) = rate_limit_end {
+                async_io::Timer::at(time).await;
+            }
+        }
+    }
+
+    /// `eg!train #channel [limit]`: learns a channel's history in the background, a page per tick,
+    /// picking up where it left off after a restart.
+    async fn train_command(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        channel: &str,
+        args: &mut impl Iterator<Item = &str>,
+    ) -> Result<()> {
+        let channel: Id = channel
+            .trim_start_matches("<#")
+            .trim_end_matches('>')
+            .parse()?;
+        let limit = args.next().map(str::parse).transpose()?;
+        let (guild, author, report) = (message.guild_id, message.author.id, message.channel_id);
+        let reply = self
+            .start_training(client, guild, author, channel, report, limit)
+            .await?;
+        client.create_message(message.channel_id, &reply).await
+    }
+
+    /// Schedules `Job::Train` for `channel`, asked for by `author` in `guild`, reporting in
+    /// `report`. Returns what to answer.
+    async fn start_training(
+        &mut self,
+        client: &Client,
+        guild: Option<Id>,
+        author: Id,
+        channel: Id,
+        report: Id,
+        limit: Option<usize>,
+    ) -> Result<String> {
+        // the bot's admins can train on any channel it can see, owners on their own server's
+        let allowed = match guild {
+            Some(guild) if client.get_channel_guild(channel).await? == Some(guild) => {
+                self.is_owner(client, author, guild).await?
+            }
+            _ => self.is_admin(author),
+        };
+        if !allowed {
+            bail!("only the server's owner can train on its channels' history");
+        }
+        if self
+            .data
+            .jobs
+            .any(|j| matches!(j, Job::Train { channel: c, .. } if *c == channel))
+        {
+            bail!("already training on <#{}>", channel);
+        }
+        self.data.jobs.schedule(
+            now_millis(),
+            Job::Train {
+                channel,
+                report,
+                before: None,
+                learned: 0,
+                limit,
+            },
+        );
+        Ok(format!(
+            "Training on <#{}>, I'll say how it's going",
+            channel
+        ))
+    }
+
+    /// Learns one page of a channel's history for `Job::Train`, then schedules the next.
+    async fn train_page(
+        &mut self,
+        client: &Client,
+        channel: Id,
+        report: Id,
+        before: Option<Id>,
+        learned: usize,
+        limit: Option<usize>,
+    ) -> Result<()> {
+        const PROGRESS_EVERY: usize = 1000;
+
+        let mut response = client.get_channel_messages(channel, before).await?;
+        let rate_limit_end = response.rate_limit_end();
+        let mut messages = response.get_response().await?;
+        messages.sort_by_key(|m| std::cmp::Reverse(m.timestamp));
+        if let Some(limit) = limit {
+            messages.truncate(limit.saturating_sub(learned));
+        }
+        let oldest = messages.last().map(|m| m.id);
+        for message in &messages {
+            self.remember(message);
+        }
+        let so_far = learned + messages.len();
+
+        let oldest = match oldest {
+            Some(oldest) if limit.is_none_or(|l| so_far < l) => oldest,
+            _ => {
+                let text = format!(
+                    "Done training on <#{}>: learned {} messages",
+                    channel, so_far
+                );
+                self.notify_channel_job(client, "train", channel, Ok(&text))
+                    .await;
+                return client.create_message(report, &text).await;
+            }
+        };
+        if so_far / PROGRESS_EVERY > learned / PROGRESS_EVERY {
+            let text = format!("Training on <#{}>: {} messages so far", channel, so_far);
+            client.create_message(report, &text).await?;
+        }
+        let wait = rate_limit_end.map_or(0, |end| {
+            end.saturating_duration_since(std::time::Instant::now())
+                .as_millis() as i64
+        });
+        self.data.jobs.schedule(
+            now_millis() + wait,
+            Job::Train {
+                channel,
+                report,
+                before: Some(oldest),
+                learned: so_far,
+                limit,
+            },
+        );
+        Ok(())
+    }
+
+    /// Merges a channel's model, or one that's attached, into the server's.
+    async fn merge(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        channel: Option<&str>,
+    ) -> Result<()> {
+        const MAX_MODEL_SIZE: u64 = 25 * 1024 * 1024;
+        let allowed = match message.guild_id {
+            Some(guild) => self.is_owner_message(client, message, guild).await?,
+            None => self.is_admin_message(message),
+        };
+        if !allowed {
+            bail!("only the server's owner can merge models");
+        }
+        let into = Scope::for_guild(message.guild_id, self.per_guild());
+        let model = match channel {
+            Some(channel) => {
+                let from = self.channel_scope(client, message, channel).await?;
+                self.data.models.get_mut(&from).clone()
+            }
+            None => {
+                let bytes = download_attachment(message, MAX_MODEL_SIZE, "model file").await?;
+                bincode::deserialize(&bytes)
+                    .map_err(|_| anyhow!("that's not a model file, like the ones in exports"))?
+            }
+        };
+        if let Some(guild) = into.owner() {
+            let max = self.limits(guild).model_entries;
+            let total = self.data.models.get_mut(&into).entry_count() + model.entry_count();
+            if max.is_some_and(|max| total > max) {
+                bail!("the server's model would be bigger than this bot allows");
+            }
+        }
+        let merged = model.entry_count();
+        let serialized = match self.replication {
+            Some(_) => Some(bincode::serialize(&model)?),
+            None => None,
+        };
+        let added = self.data.models.get_mut(&into).merge(model)?;
+        if let Some(model) = serialized {
+            self.replicate(Change::Merge {
+                scope: into.clone(),
+                model,
+            });
+        }
+        let reply = format!(
+            "🔀 Merged {} entries into `{}`, {} of them new",
+            merged,
+            into.name(),
+            added
+        );
+        client.create_message(message.channel_id, &reply).await
+    }
+
+    /// Issues, revokes and lists the server's keys for the HTTP API, see `api`.
+    async fn api_key_command(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        action: &str,
+        args: &mut impl Iterator<Item = &str>,
+    ) -> Result<()> {
+        let guild = match message.guild_id {
+            Some(guild) => guild,
+            None => bail!("API keys belong to a server, ask in one"),
+        };
+        if !self.is_owner_message(client, message, guild).await? {
+            bail!("only the server's owner can manage its API keys");
+        }
+        let reply = match (action, args.next()) {
+            ("issue", Some(_)) if !self.per_guild() => {
+                bail!("keys need `guild_models`, or they'd generate from every server's messages")
+            }
+            ("issue", Some(name)) => {
+                let key = self.data.api_keys.lock().unwrap().issue(
+                    &mut self.rng,
+                    guild,
+                    name,
+                    message.author.id,
+                )?;
+                self.log(format_args!(
+                    "api key `{}` issued for guild {} by {}",
+                    name, guild, message.author.id
+                ));
+                let dm = client.create_dm(message.author.id).await?;
+                let text = format!(
+                    "🔑 The API key `{}`: `{}`. Send it as `Authorization: Bearer KEY`. It won't \
+                     be shown again, keep it to yourself",
+                    name, key
+                );
+                client.create_message(dm, &text).await?;
+                String::from("🔑 Sent you the key in DMs")
+            }
+            ("revoke", Some(name)) => {
+                self.data.api_keys.lock().unwrap().revoke(guild, name)?;
+                self.log(format_args!(
+                    "api key `{}` revoked for guild {} by {}",
+                    name, guild, message.author.id
+                ));
+                format!("🔑 Revoked `{}`", name)
+            }
+            ("list", _) => {
+                let keys = self.data.api_keys.lock().unwrap();
+                let lines: Vec<_> = keys
+                    .of_guild(guild)
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect();
+                match lines.is_empty() {
+                    true => String::from("This server has no API keys"),
+                    false => lines.join("\n"),
+                }
+            }
+            _ => bail!("try `issue NAME`, `revoke NAME` or `list`"),
+        };
+        client.create_message(message.channel_id, &reply).await
+    }
+
+    /// Learns the chat logs in an attached export, see `import`.
+    async fn import(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        args: &mut impl Iterator<Item = &str>,
+    ) -> Result<()> {
+        let allowed = match message.guild_id {
+            Some(guild) => self.is_owner_message(client, message, guild).await?,
+            None => self.is_admin_message(message),
+        };
+        if !allowed {
+            bail!("only the server's owner can import chat logs");
+        }
+        let filter = import::Filter::parse(args)?;
+        let today = Utc::now().date().naive_utc();
+        if let Some(guild) = message.guild_id {
+            let limits = self.limits(guild);
+            self.data.quotas.check_import(guild, &limits, today)?;
+        }
+        let (guild, channel) = (message.guild_id, Some(message.channel_id));
+        let reply = match self.learn_import(message, &filter).await {
+            Ok(reply) => {
+                // only imports that went through count against the quota
+                if let Some(guild) = guild {
+                    self.data.quotas.count_import(guild, today);
+                }
+                self.notify(webhook::Event::done("import", guild, channel, &reply));
+                reply
+            }
+            Err(e) => {
+                self.notify(webhook::Event::failed("import", guild, channel, &e));
+                return Err(e);
+            }
+        };
+        client.create_message(message.channel_id, &reply).await
+    }
+
+    /// Learns what `filter` lets through of the export attached to `message`. Returns what to
+    /// answer.
+    async fn learn_import(
+        &mut self,
+        message: &Message<'_>,
+        filter: &import::Filter,
+    ) -> Result<String> {
+        const MAX_EXPORT_SIZE: u64 = 25 * 1024 * 1024;
+        let bytes = download_attachment(message, MAX_EXPORT_SIZE, "export").await?;
+        let imported = import::parse(&bytes)?;
+        // into the server's model, since the channels they came from may not even be here
+        let mut scopes = vec![self.scope(message.guild_id, message.channel_id)];
+        if scopes[0] != Scope::Global && !self.cfg.public {
+            scopes.push(Scope::Global);
+        }
+        const BATCH: usize = 1000;
+        let (mut matched, mut learned) = (0, 0);
+        let mut batch = Vec::with_capacity(BATCH);
+        for m in imported.iter().filter(|m| filter.matches(m)) {
+            matched += 1;
+            if m.bot
+                || m.author.is_some_and(|a| {
+                    self.data.opt_outs.contains(&a) || self.data.filter.blocks_user(a)
+                })
+            {
+                continue;
+            }
+            if let Some(guild) = message.guild_id {
+                if self.data.automod.blocks(guild, &m.content) {
+                    continue;
+                }
+            }
+            batch.push(m.content.clone());
+            if batch.len() == BATCH {
+                learned += self.learn_all(&scopes, std::mem::take(&mut batch));
+            }
+        }
+        learned += self.learn_all(&scopes, batch);
+        Ok(format!(
+            "📥 Learned {} of the {} messages that matched, out of {} in the export",
+            learned,
+            matched,
+            imported.len()
+        ))
+    }
+
+    /// Purges a word, or everything somebody said, from the models.
+    async fn forget_command(
+        &mut self,
+        client: &Client,
+        message: &Message<'_>,
+        what: &str,
+        args: &mut impl Iterator<Item = &str>,
+    ) -> Result<()> {
+        let allowed = match message.guild_id {
+            Some(guild) => self.is_owner_message(client, message, guild).await?,
+            None => self.is_admin_message(message),
+        };
+        if !allowed {
+            bail!("only the server's owner can make the bot forget things");
+        }
+        let reply = match what {
+            "word" => {
+                let word = match args.next() {
+                    Some(w) => w,
+                    None => bail!("expected a word to forget"),
+                };
+                // the shared models hold every server's messages, so only the bot's admins purge them
+                let shared = !self.cfg.public && self.is_admin_message(message);
+                let removed = self.forget_word(message.guild_id, shared, word);
+                format!(
+                    "🧹 Forgot `{}` ({} entries). Add it to `{}blocklist` so it isn't learned again",
+                    audit::redact(word),
+                    removed,
+                    self.cfg.prefix
+                )
+            }
+            "user" => {
+                let user: Id = match args.next() {
+                    Some(u) => u
+                        .trim_start_matches("<@")
+                        .trim_start_matches('!')
+                        .trim_end_matches('>')
+                        .parse()?,
+                    None => bail!("expected somebody to forget"),
+                };
+                let forgotten = self.forget_recent(user, message.guild_id);
+                let mut channels = Vec::new();
+                for channel in args {
+                    let channel: Id = channel
+                        .trim_start_matches("<#")
+                        .trim_end_matches('>')
+                        .parse()?;
+                    if client.get_channel_guild(channel).await? != message.guild_id
+                        && !self.is_admin_message(message)
+                    {
+                        bail!("<#{}> isn't in this server", channel);
+                    }
+                    channels.push(channel);
+                }
+                for &channel in &channels {
+                    self.data.jobs.schedule(
+                        now_millis(),
+                        Job::Forget {
+                            channel,
+                            report: message.channel_id,
+                            user,
+                            before: None,
+                            forgotten: 0,
+                        },
+                    );
+                }
+                let mut reply = format!("🧹 Forgot {} recent messages from <@{}>", forgotten, user);
+                if !channels.is_empty() {
+                    reply += ", and I'm going through the history of the channels for the rest";
+                }
+                reply
+            }
+            w => bail!(
+                "unknown `{}`, try `word WORD` or `user @USER [#CHANNEL...]`",
+                w
+            ),
+        };
+        client.create_message(message.channel_id, &reply).await
+    }
+
+    /// Removes every entry containing `word` from `guild`'s models, and from the shared ones too if
+    /// `shared`. Returns how many were removed.
+    fn forget_word(&mut self, guild: Option<Id>, shared: bool, word: &str) -> usize {
+        // so the server's own model is purged even if it isn't loaded
+        let scope = Scope::for_guild(guild, self.per_guild());
+        self.data.models.get_mut(&scope);
+        let mut removed = 0;
+        let mut purged = Vec::new();
+        for (scope, markov) in self.data.models.loaded_mut() {
+            let ours = match scope.owner() {
+                Some(owner) => Some(owner) == guild,
+                None => shared,
+            };
+            if ours {
+                removed += markov.remove_containing(word);
+                purged.push(scope.clone());
+            }
+        }
+        for scope in purged {
+            self.replicate(Change::RemoveContaining {
+                scope,
+                word: String::from(word),
+            });
+        }
+        removed
+    }
+
+    /// Unlearns the messages `user` sent in `guild` that are still cached. Returns how many.
+    fn forget_recent(&mut self, user: Id, guild: Option<Id>) -> usize {
+        let mut forgotten = 0;
+        for id in self.data.cache.by_author(user, guild) {
+            if let Some(learned) = self.data.cache.take_learned(id) {
+                self.unlearn(&learned);
+                forgotten += 1;
+            }
+        }
+        forgotten
+    }
+
+    /// Unlearns `user`'s messages from one page of a channel's history for `Job::Forget`, then
+    /// schedules the next.
+    async fn forget_page(
+        &mut self,
+        client: &Client,
+        channel: Id,
+        report: Id,
+        user: Id,
+        before: Option<Id>,
+        forgotten: usize,
+    ) -> Result<()> {
+        let mut response = client.get_channel_messages(channel, before).await?;
+        let rate_limit_end = response.rate_limit_end();
+        let messages = response.get_response().await?;
+        let oldest = messages.iter().min_by_key(|m| m.timestamp).map(|m| m.id);
+        let learned = !self.cfg.channel_blacklist.contains(&channel);
+        let mut so_far = forgotten;
+        for message in messages.iter().filter(|m| m.author.id == user && learned) {
+            so_far += self.forget_message(message) as usize;
+        }
+        let oldest = match oldest {
+            Some(oldest) => oldest,
+            None => {
+                let text = format!(
+                    "Done forgetting <@{}> in <#{}>: {} messages",
+                    user, channel, so_far
+                );
+                self.notify_channel_job(client, "forget", channel, Ok(&text))
+                    .await;
+                return client.create_message(report, &text).await;
+            }
+        };
+        let wait = rate_limit_end.map_or(0, |end| {
+            end.saturating_duration_since(std::time::Instant::now())
+                .as_millis() as i64
+        });
+        self.data.jobs.schedule(
+            now_millis() + wait,
+            Job::Forget {
+                channel,
+                report,
+                user,
+                before: Some(oldest),
+                forgotten: so_far,
+            },
+        );
+        Ok(())
+    }
+
+    /// Keeps track of somebody else's message, before anything answers it.
+    fn observe(&mut self, message: &Message<'_>) {
+        self.data.cache.insert(message);
+        if let Some(guild) = message.guild_id {
+            self.data.activity.insert(guild, now_millis());
+        }
+    }
+
+    /// Learns from somebody else's message, unless its channel is blacklisted.
+    fn ingest(&mut self, message: &Message<'_>) {
+        if !self
+            .cfg
+            .channel_blacklist
+            .iter()
+            .any(|&bc| bc == message.channel_id)
+        {
+            self.remember(message);
+            self.track_engagement(message);
+        }
+    }
+
+    fn remember(&mut self, message: &Message<'_>) {
+        if self.data.opt_outs.contains(&message.author.id)
+            || self.data.automod.is_flagged(message.id)
+            || self.data.filter.blocks_user(message.author.id)
+            || self.data.filter.blocks_channel(message.channel_id)
+        {
+            return;
+        }
+        if let Some(guild) = message.guild_id {
+            if self.data.automod.blocks(guild, message.content.as_str()) {
+                return;
+            }
+        }
+        let scopes = self.remember_scopes(message);
+        if let Some(learned) = self.learn(&scopes, self.message_text(message)) {
+            self.data.cache.set_learned(message.id, learned);
+        }
+    }
+
+    /// What's learned from `message`: its text, then its stickers and alt text if they're learned.
+    fn message_text(&self, message: &Message<'_>) -> String {
+        let mut text = if self.tokenizer.keeps_mentions(message.guild_id) {
+            learnable_text(message)
+        } else {
+            String::from(message.content.as_str())
+        };
+        for tag in caption::tags(message, &self.cfg.captions) {
+            if !text.is_empty() {
+                text.push(' ');
+            }
+            text.push_str(&tag);
+        }
+        text
+    }
+
+    fn remember_scopes(&self, message: &Message<'_>) -> Vec<Scope> {
+        // announcements published from elsewhere sound nothing like the server itself
+        if message.is_crosspost() && self.cfg.learn_events {
+            vec![Scope::events(message.guild_id, self.per_guild())]
+        } else {
+            self.learn_scopes(message.guild_id, message.channel_id)
+        }
+    }
+
+    /// Takes back what `remember` learned from `message`: what it was learned as if it's still
+    /// cached, or else what it would be learned as now. Returns whether there was anything.
+    fn forget_message(&mut self, message: &Message<'_>) -> bool {
+        if self.data.cache.get(message.id).is_some() {
+            return match self.data.cache.take_learned(message.id) {
+                Some(learned) => {
+                    self.unlearn(&learned);
+                    true
+                }
+                None => false,
+            };
+        }
+        let scopes = self.remember_scopes(message);
+        let guild = scopes.iter().find_map(Scope::owner);
+        let words = match self.learnable_words(guild, self.message_text(message)) {
+            Some(w) => w,
+            None => return false,
+        };
+        let learned = Learned { scopes, words };
+        self.unlearn(&learned);
+        true
+    }
+
+    /// Learns a new scheduled event's name and description, like an announcement.
+    fn learn_event(&mut self, event: &ScheduledEvent<'_>) {
+        if !self.cfg.learn_events
+            || event
+                .creator_id
+                .is_some_and(|c| self.data.opt_outs.contains(&c))
+        {
+            return;
+        }
+        let scopes = [Scope::events(Some(event.guild_id), self.per_guild())];
+        for text in std::iter::once(&event.name).chain(&event.description) {
+            if !self.data.automod.blocks(event.guild_id, text.as_str()) {
+                self.learn(&scopes, String::from(text.as_str()));
+            }
+        }
+    }
+
+    fn learn_scopes(&self, guild: Option<Id>, channel: Id) -> Vec<Scope> {
+        let mut scopes = vec![self.scope(guild, channel)];
+        // the global model still feeds Mastodon and bridges that share it, unless guilds have
+        // to be kept apart
+        if scopes[0] != Scope::Global && !self.cfg.public {
+            scopes.push(Scope::Global);
+        }
+        if let Some(style) = self.data.styles.get(channel) {
+            scopes.push(Scope::style(guild, style, self.cfg.public));
+        }
+        if self.channel_models() {
+            scopes.push(Scope::Channel(channel));
+        }
+        scopes
+    }
+
+    /// Counts replies to messages that are waiting for their engagement to be sampled.
+    fn track_engagement(&mut self, message: &Message<'_>) {
+        if !self.cfg.engagement_weighting {
+            return;
+        }
+        if let Some(replied) = message
+            .message_reference
+            .as_ref()
+            .and_then(|r| r.message_id)
+        {
+            if self
+                .data
+                .jobs
+                .any(|j| matches!(j, Job::Engagement { message, .. } if *message == replied))
+            {
+                *self.data.replies.entry(replied).or_default() += 1;
+            }
+        }
+        if !self.data.opt_outs.contains(&message.author.id) {
+            let delay = self.cfg.engagement_delay_minutes as i64 * 60_000;
+            self.data.jobs.schedule(
+                now_millis() + delay,
+                Job::Engagement {
+                    guild: message.guild_id,
+                    channel: message.channel_id,
+                    message: message.id,
+                },
+            );
+        }
+    }
+
+    /// Learns a message again for every reaction and reply it got, up to the configured maximum.
+    async fn weigh_engagement(
+        &mut self,
+        client: &Client,
+        guild: Option<Id>,
+        channel: Id,
+        message: Id,
+    ) -> Result<()> {
+        let replies = self.data.replies.remove(&message).unwrap_or(0);
+        let mut response = client.get_message(channel, message).await?;
+        let message = response.get_response().await?;
+        if self.data.opt_outs.contains(&message.author.id) {
+            return Ok(());
+        }
+        let reactions: usize = message
+            .reactions
+            .iter()
+            .map(|r| r.count - r.me as usize)
+            .sum();
+        let extra = (reactions + replies).min(self.cfg.engagement_max_weight);
+        if extra > 0 {
+            let scopes = self.learn_scopes(guild, channel);
+            self.learn_weighted(&scopes, learnable_text(&message), extra);
+        }
+        Ok(())
+    }
+
+    async fn handle_bridge_message(&mut self, message: BridgeMessage) -> Result<()> {
+        let mut args = match message.text.strip_prefix(self.cfg.prefix.as_str()) {
+            Some(s) => s.split_whitespace(),
+            None => {
+                if message.learn && !self.data.bridge_opt_outs.contains(&message.author) {
+                    self.learn(std::slice::from_ref(&message.scope), message.text);
+                }
+                return Ok(());
+            }
+        };
+        let reply = match (args.next(), args.next()) {
+            (Some("optout"), _) => {
+                self.data.bridge_opt_outs.insert(message.author);
+                String::from("Okay, I won't learn from your messages anymore")
+            }
+            (Some("optin"), _) => {
+                self.data.bridge_opt_outs.remove(&message.author);
+                String::from("Welcome back, I'll learn from you again")
+            }
+            (Some("mimic"), _) => match self.generate_moderated(&message.scope, None).await {
+                Some(text) => text,
+                None => String::from(HELD_BACK),
+            },
+            (Some("follows"), Some(word)) => {
+                let words: Vec<_> = std::iter::once(word).chain(args).collect();
+                let model = self.data.models.get_mut(&message.scope);
+                let title = format!("What follows {}", words.join(" "));
+                transitions_text(&title, &model.what_follows(&words))
+            }
+            (Some("precedes"), Some(word)) => {
+                let words: Vec<_> = std::iter::once(word).chain(args).collect();
+                let model = self.data.models.get_mut(&message.scope);
+                let title = format!("What comes before {}", words.join(" "));
+                transitions_text(&title, &model.what_precedes(&words))
+            }
+            (Some("starts"), _) => {
+                let model = self.data.models.get_mut(&message.scope);
+                transitions_text("What messages start with", &model.what_starts())
+            }
+            _ => return Ok(()),
+        };
+        message.reply.unbounded_send(reply)?;
+        Ok(())
+    }
+
+    fn is_admin_message(&self, message: &Message<'_>) -> bool {
+        self.is_admin(message.author.id)
+    }
+
+    fn is_admin(&self, user: Id) -> bool {
+        self.cfg.admins.iter().any(|admin| *admin == user)
+    }
+
+    /// Whether the message is from `guild`'s owner, or one of the bot's admins.
+    async fn is_owner_message(
+        &self,
+        client: &Client,
+        message: &Message<'_>,
+        guild: Id,
+    ) -> Result<bool> {
+        self.is_owner(client, message.author.id, guild).await
+    }
+
+    async fn is_owner(&self, client: &Client, user: Id, guild: Id) -> Result<bool> {
+        Ok(self.is_admin(user) || client.get_guild_owner(guild).await? == user)
+    }
+}
+
+/// A message's content the way it gets learned, with mentions swapped for names so generated
+/// messages don't ping anybody.
+fn learnable_text(message: &Message<'_>) -> String {
+    message
+        .content
+        .as_str()
+        .split_whitespace()
+        .filter_map(|s| {
+            if !s.is_empty() {
+                if let Some(id) = s.strip_prefix("<@!").and_then(|s| s.strip_suffix('>')) {
+                    for user in &message.mentions {
+                        if Ok(user.id) == id.parse() {
+                            return Some(format!("`{}#{}`", user.username, user.discriminator));
+                        }
+                    }
+                    Some(format!("`<@!{}>`", id))
+                } else {
+                    Some(String::from(s))
+                }
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(any(feature = "wasm", feature = "scripting"))]
+fn list_text(iter: impl IntoIterator<Item = impl ToString>) -> String {
+    let mut iter = iter.into_iter().peekable();
+    if iter.peek().is_none() {
+        String::from("Nothing!")
+    } else {
+        iter.fold(String::new(), |p, c| p + c.to_string().as_str() + " ")
+    }
+}
+
+/// The likeliest of `found` with their share of all of it, under `title`.
+fn transitions_text(title: &str, found: &[(markov::Word, usize)]) -> String {
+    const SHOWN: usize = 10;
+    if found.is_empty() {
+        return String::from("Nothing!");
+    }
+    let total: usize = found.iter().map(|(_, weight)| weight).sum();
+    let mut text = format!("{}:", title);
+    for (word, weight) in found.iter().take(SHOWN) {
+        let share = *weight as f64 / total as f64 * 100.0;
+        let _ = write!(text, "\n`{}` {:.1}% ({})", word, share, weight);
+    }
+    if found.len() > SHOWN {
+        let _ = write!(text, "\n…and {} more", found.len() - SHOWN);
+    }
+    text
+}
+
+/// `text` cut down to fit in one Discord message, at a word if there is one.
+fn fit_message(text: String) -> String {
+    const MAX_CHARS: usize = 2000;
+    match text.char_indices().nth(MAX_CHARS) {
+        None => text,
+        Some((end, _)) => {
+            let cut = &text[..end];
+            cut.rfind(char::is_whitespace)
+                .map_or(cut, |at| &cut[..at])
+                .to_owned()
+        }
+    }
+}
+
+async fn download_attachment(message: &Message<'_>, max_size: u64, what: &str) -> Result<Vec<u8>> {
+    use futures::AsyncReadExt;
+
+    let attachment = match message.attachments.first() {
+        Some(a) if a.size <= max_size => a,
+        Some(_) => bail!(
+            "the {} can be at most {}",
+            what,
+            file_size_to_string(max_size)
+        ),
+        None => bail!("attach the {} to the message", what),
+    };
+    let mut response = isahc::get_async(attachment.url.as_str()).await?;
+    let mut bytes = Vec::new();
+    response.body_mut().read_to_end(&mut bytes).await?;
+    Ok(bytes)
+}
+
+fn file_size_to_string(size: u64) -> String {
+    let mut size_f = size as f64;
+    let suffixes = ["bytes", "kb", "mb", "gb", "tb"];
+    for s in suffixes.iter() {
+        if size_f / 1024.0 < 1.0 {
+            return format!("{:.2}{}", size_f, s);
+        }
+        size_f /= 1024.0;
+    }
+    String::from("way too fricken big file!")
+}
+
+impl Handler<'_> {
+    async fn dispatch(&mut self, payload: DispatchPayload<'_>, client: &Client) -> Result<()> {
+        match payload {
+            DispatchPayload::MessageCreate(message) => {
+                let ours = self.id == Some(message.author.id);
+                let depth = self
+                    .data
+                    .loops
+                    .observe(&message, ours || message.author.bot);
+                // bots answering each other this long are probably stuck doing it
+                let looping = depth > self.cfg.max_bot_chain;
+                if !looping {
+                    self.add_emojis(client, &message).await?;
+                }
+                if !ours {
+                    self.observe(&message);
+                    if !looping {
+                        self.pick_emoji(client, &message).await?;
+                        self.handle_wot(client, &message).await?;
+                        self.engineer_gaming(client, &message).await?;
+                        #[cfg(feature = "scripting")]
+                        self.run_scripts(client, &message).await?;
+                        self.handle_message(client, &message).await?;
+                        self.chatter(client, &message).await?;
+                    }
+                    self.ingest(&message);
+                }
+                Ok(())
+            }
+            DispatchPayload::Ready(ready) => {
+                self.id = Some(ready.user.id);
+                self.name = Some(ready.user.username.to_lowercase());
+                self.warm_models();
+                if let Err(e) = self.sync_commands(client, ready.application.id).await {
+                    self.log(format_args!("couldn't sync slash commands: {:#}", e));
+                }
+                if self.cfg.mirror {
+                    // whatever was scheduled is the primary's to do
+                    self.data.jobs = Scheduler::new();
+                    let interval = self.cfg.mirror_reload_minutes as i64 * 60_000;
+                    self.data
+                        .jobs
+                        .schedule(now_millis() + interval, Job::MirrorReload);
+                    return Ok(());
+                }
+                if !self.data.jobs.any(|j| matches!(j, Job::Celebrations)) {
+                    self.data.jobs.schedule(now_millis(), Job::Celebrations);
+                }
+                if self.cfg.digest_channel.is_some()
+                    && !self.data.jobs.any(|j| matches!(j, Job::DriftReport))
+                {
+                    self.data
+                        .jobs
+                        .schedule(drift::next_month(), Job::DriftReport);
+                }
+                if self.cfg.decay.is_some() && !self.data.jobs.any(|j| matches!(j, Job::Decay)) {
+                    self.schedule_decay();
+                }
+                if !self.data.jobs.any(|j| matches!(j, Job::Digests)) {
+                    let tomorrow = Utc::now().date().succ().and_hms(0, 0, 0);
+                    self.data
+                        .jobs
+                        .schedule(tomorrow.timestamp_millis(), Job::Digests);
+                }
+                if self.mastodon.is_some()
+                    && !self.data.jobs.any(|j| matches!(j, Job::MastodonPost))
+                {
+                    self.data.jobs.schedule(now_millis(), Job::MastodonPost);
+                }
+                for &chan in &self.cfg.announcement_channels {
+                    client.create_message(chan, "Dispenser goin' up!").await?;
+                }
+                Ok(())
+            }
+            DispatchPayload::MessageReactionAdd(reaction) => {
+                self.ingest_reaction(&reaction, true);
+                Ok(())
+            }
+            DispatchPayload::MessageReactionRemove(reaction) => {
+                self.ingest_reaction(&reaction, false);
+                Ok(())
+            }
+            DispatchPayload::InteractionCreate(interaction) => {
+                self.handle_interaction(client, &interaction).await
+            }
+            DispatchPayload::AutoModerationActionExecution(action) => {
+                self.automod_action(&action);
+                Ok(())
+            }
+            DispatchPayload::GuildScheduledEventCreate(event) => {
+                self.learn_event(&event);
+                Ok(())
+            }
+            DispatchPayload::MessageDeleteBulk(deleted) => {
+                self.unlearn_bulk(client, &deleted).await
+            }
+            DispatchPayload::ChannelUpdate(channel) => {
+                if let Some(category) = self.data.categories.get_mut(&channel.id) {
+                    *category = channel.parent_id;
+                }
+                self.data.resolved.invalidate_channel(channel.id);
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl bot::AsyncDispatchHandler for Handler<'_> {
+    type External = BridgeMessage;
+
+    fn handle_message<'a>(
+        &'a mut self,
+        payload: DispatchPayload<'a>,
+        client: &'a Client,
+    ) -> bot::AsyncDispatchFuture<'a> {
+        Box::pin(async move {
+            let (guild, scope, command) = match &payload {
+                DispatchPayload::MessageCreate(message) => {
+                    let command = message
+                        .content
+                        .as_str()
+                        .strip_prefix(self.cfg.prefix.as_str())
+                        .and_then(|c| c.split_whitespace().next());
+                    let scope = self.scope(message.guild_id, message.channel_id);
+                    (message.guild_id, Some(scope), command.map(String::from))
+                }
+                DispatchPayload::InteractionCreate(interaction) => {
+                    let command = interaction.command_name().or(interaction.custom_id());
+                    let scope = interaction
+                        .channel_id
+                        .map(|c| self.scope(interaction.guild_id, c));
+                    (interaction.guild_id, scope, command.map(String::from))
+                }
+                _ => (None, None, None),
+            };
+            self.trace = Trace::new(&mut self.rng, guild);
+            let trace = self.trace;
+            self.reload_if_signalled();
+            let mut event = otel::Span::root(&trace, payload.name());
+            if let Some(guild) = guild {
+                event.attribute("discord.guild", guild);
+            }
+            let mut handler = otel::Span::start("handle");
+            if let Some(command) = &command {
+                handler.attribute("taco.command", command);
+            }
+            let result = match AssertUnwindSafe(self.dispatch(payload, client))
+                .catch_unwind()
+                .await
+            {
+                Ok(result) => {
+                    if let Err(e) = &result {
+                        self.report(e, command.as_deref());
+                    }
+                    result
+                }
+                Err(panic) => Err(self.panicked(scope, panic)),
+            };
+            if let Err(e) = &result {
+                handler.fail(e);
+                event.fail(e);
+            }
+            drop(handler);
+            result.map_err(|e| anyhow!("[{}] {:#}", trace, e))
+        })
+    }
+
+    fn handle_tick<'a>(&'a mut self, client: &'a Client) -> bot::AsyncDispatchFuture<'a> {
+        Box::pin(async move {
+            self.trace = Trace::new(&mut self.rng, None);
+            self.reload_if_signalled();
+            if let Err(e) = self.unload_idle_models() {
+                self.log(format_args!("{:#}", e));
+                self.report(&e, None);
+            }
+            let save_every = self.cfg.save_minutes.map(|m| m as i64 * 60_000);
+            if save_every.is_some_and(|every| now_millis() - self.data.saved >= every) {
+                if let Err(e) = self.data.save() {
+                    self.log(format_args!("couldn't save: {:#}", e));
+                    self.report(&e, None);
+                }
+            }
+            for job in self.data.jobs.take_due(now_millis()) {
+                self.trace = Trace::new(&mut self.rng, None);
+                let mut span = otel::Span::root(&self.trace, job.name());
+                match AssertUnwindSafe(self.run_job(client, job))
+                    .catch_unwind()
+                    .await
+                {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => {
+                        span.fail(&e);
+                        self.log(format_args!("{:#}", e));
+                        self.report(&e, None);
+                    }
+                    Err(panic) => {
+                        let e = self.panicked(None, panic);
+                        self.log(e);
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn handle_external<'a>(
+        &'a mut self,
+        event: BridgeMessage,
+        _client: &'a Client,
+    ) -> bot::AsyncDispatchFuture<'a> {
+        Box::pin(async move {
+            self.trace = Trace::new(&mut self.rng, None);
+            let trace = self.trace;
+            self.reload_if_signalled();
+            let scope = event.scope.clone();
+            let mut span = otel::Span::root(&trace, "bridge message");
+            span.attribute("taco.scope", scope.name());
+            let result = match AssertUnwindSafe(self.handle_bridge_message(event))
+                .catch_unwind()
+                .await
+            {
+                Ok(result) => {
+                    if let Err(e) = &result {
+                        self.report(e, None);
+                    }
+                    result
+                }
+                Err(panic) => Err(self.panicked(Some(scope), panic)),
+            };
+            if let Err(e) = &result {
+                span.fail(e);
+            }
+            result.map_err(|e| anyhow!("[{}] {:#}", trace, e))
+        })
+    }
+
+    fn restart_requested(&self) -> bool {
+        self.restart
+    }
+}
+
+#[derive(Deserialize)]
+struct BotConfig {
+    /// Only used when the token isn't kept as a secret, see `secrets`
+    #[serde(default)]
+    token: Option<TokenBuf>,
+    #[serde(default = "default_prefix")]
+    prefix: String,
+    intents: Intents,
+    admins: Vec<Id>,
+    channel_blacklist: Vec<Id>,
+    announcement_channels: Vec<Id>,
+    #[serde(default = "default_poll_minutes")]
+    poll_minutes: u64,
+    #[serde(default)]
+    emoji_reactions: bool,
+    #[serde(default = "default_emoji_reaction_odds")]
+    emoji_reaction_odds: u32,
+    #[serde(default)]
+    engagement_weighting: bool,
+    #[serde(default = "default_engagement_delay_minutes")]
+    engagement_delay_minutes: u64,
+    #[serde(default = "default_engagement_max_weight")]
+    engagement_max_weight: usize,
+    #[serde(default)]
+    blocklist: Vec<String>,
+    /// What happens to generated text `eg!filter` blocks
+    #[serde(default)]
+    filter_action: FilterAction,
+    /// Whether keywords AutoMod matches get added to the server's learning blocklist
+    #[serde(default)]
+    automod_blocklist: bool,
+    #[serde(default = "default_canon_weight")]
+    canon_weight: usize,
+    /// Where monthly reports, like how the model has drifted, get posted
+    digest_channel: Option<Id>,
+    /// Where moderation the bot does on its own, like unlearning bulk deletes, gets logged
+    audit_channel: Option<Id>,
+    #[serde(default = "default_drift_probes")]
+    drift_probes: Vec<String>,
+    #[serde(default = "default_adventure_round_minutes")]
+    adventure_round_minutes: u64,
+    /// How long everyone has to guess in a round of impersonation roulette
+    #[serde(default = "default_roulette_minutes")]
+    roulette_minutes: u64,
+    /// Aliases every server has, unless it has its own by the same name
+    #[serde(default)]
+    aliases: BTreeMap<String, String>,
+    #[serde(default = "default_thread_busy_messages")]
+    thread_busy_messages: usize,
+    #[serde(default = "default_suppress_factor")]
+    suppress_factor: f64,
+    #[serde(default = "default_repetition_penalty")]
+    repetition_penalty: f64,
+    #[serde(default)]
+    generation: GenerationConfig,
+    /// Leaves rare transitions out of the shared model and blurs the rest, for what other servers
+    /// get of it, see `privacy`
+    #[serde(default)]
+    shared_privacy: Option<PrivacyConfig>,
+    #[serde(default)]
+    decay: Option<DecayConfig>,
+    /// Whether scheduled events and crossposted announcements are learned, into their own model
+    #[serde(default)]
+    learn_events: bool,
+    #[serde(default)]
+    captions: CaptionConfig,
+    #[serde(default)]
+    avoid_starts: Vec<String>,
+    #[serde(default = "default_reroll_max_similarity")]
+    reroll_max_similarity: f64,
+    #[serde(default = "default_max_bot_chain")]
+    max_bot_chain: usize,
+    #[serde(default)]
+    guild_models: bool,
+    /// Serves generation from another instance's data without learning or saving anything
+    #[serde(default)]
+    mirror: bool,
+    #[serde(default = "default_mirror_reload_minutes")]
+    mirror_reload_minutes: u64,
+    #[serde(default)]
+    channel_models: bool,
+    #[serde(default)]
+    sync_commands: bool,
+    #[serde(default)]
+    sync_commands_dry_run: bool,
+    /// Guilds slash commands are also registered in directly, where changes show up right away
+    #[serde(default)]
+    dev_guilds: Vec<Id>,
+    #[serde(default = "default_chain_order")]
+    chain_order: usize,
+    #[serde(default)]
+    chain_orders: HashMap<String, usize>,
+    #[serde(default)]
+    storage: Storage,
+    #[serde(default)]
+    sentry: Option<SentryConfig>,
+    /// Where spans are exported to, see `otel`
+    #[serde(default)]
+    otlp: Option<OtlpConfig>,
+    #[serde(default)]
+    replication: Option<ReplicationConfig>,
+    #[serde(default)]
+    api: Option<ApiConfig>,
+    #[serde(default = "default_guild_idle_minutes")]
+    guild_idle_minutes: u64,
+    #[serde(default)]
+    evict_idle_hours: Option<u64>,
+    #[serde(default)]
+    quotas: Limits,
+    #[serde(default)]
+    public: bool,
+    #[serde(default)]
+    encryption_key: Option<String>,
+    #[serde(default)]
+    irc: Vec<IrcConfig>,
+    #[cfg(feature = "matrix")]
+    #[serde(default)]
+    matrix: Vec<MatrixConfig>,
+    #[serde(default)]
+    mastodon: Option<MastodonConfig>,
+    #[serde(default)]
+    news: Option<NewsConfig>,
+    #[serde(default)]
+    moderation: Option<ModerationConfig>,
+    /// Told when long jobs like training end
+    #[serde(default)]
+    webhooks: Vec<WebhookConfig>,
+    #[serde(default)]
+    plugins: HashMap<String, serde_json::Value>,
+    #[serde(default)]
+    punctuation: HashMap<String, Rules>,
+    #[serde(default)]
+    tokenizer: TokenizerConfig,
+    /// Generation settings everywhere, before any server, channel or user changes them
+    #[serde(default)]
+    default_settings: Settings,
+    /// How often everything's saved while the bot runs, besides on `eg!save` and reconnecting
+    #[serde(default)]
+    save_minutes: Option<u64>,
+    /// Earlier versions of each file kept when it's saved over
+    #[serde(default)]
+    save_snapshots: usize,
+    /// Snapshots share chunks of what didn't change instead of being whole copies
+    #[serde(default)]
+    dedup_snapshots: bool,
+}
+
+impl BotConfig {
+    /// Catches what would only go wrong later, like a chain order of 0 or a chance over 1.
+    fn validate(&self) -> Result<()> {
+        if self.prefix.is_empty() || self.prefix.contains(char::is_whitespace) {
+            bail!("`prefix` can't be empty or have spaces in it");
+        }
+        if self.chain_order == 0 || self.chain_orders.values().any(|&o| o == 0) {
+            bail!("chain orders have to be at least 1");
+        }
+        if self.emoji_reaction_odds == 0 {
+            bail!("`emoji_reaction_odds` has to be at least 1");
+        }
+        for (name, fraction) in [
+            ("suppress_factor", self.suppress_factor),
+            ("reroll_max_similarity", self.reroll_max_similarity),
+        ] {
+            if !(0.0..=1.0).contains(&fraction) {
+                bail!("`{}` has to be between 0 and 1", name);
+            }
+        }
+        if self.repetition_penalty < 1.0 {
+            bail!("`repetition_penalty` has to be at least 1");
+        }
+        if self.mirror_reload_minutes == 0 || self.save_minutes == Some(0) {
+            bail!("intervals have to be at least a minute");
+        }
+        if let Some(t) = self.default_settings.temperature {
+            if !(MIN_TEMPERATURE..=MAX_TEMPERATURE).contains(&t) {
+                bail!(
+                    "the default temperature has to be between {} and {}",
+                    MIN_TEMPERATURE,
+                    MAX_TEMPERATURE
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+fn default_prefix() -> String {
+    String::from("eg!")
+}
+
+fn default_poll_minutes() -> u64 {
+    60
+}
+
+fn default_emoji_reaction_odds() -> u32 {
+    25
+}
+
+fn default_engagement_delay_minutes() -> u64 {
+    60
+}
+
+fn default_engagement_max_weight() -> usize {
+    5
+}
+
+fn default_suppress_factor() -> f64 {
+    0.05
+}
+
+fn default_repetition_penalty() -> f64 {
+    1.0
+}
+
+fn default_reroll_max_similarity() -> f64 {
+    0.5
+}
+
+fn default_drift_probes() -> Vec<String> {
+    drift::DEFAULT_PROBES
+        .iter()
+        .map(|&p| String::from(p))
+        .collect()
+}
+
+fn default_adventure_round_minutes() -> u64 {
+    3
+}
+
+fn default_roulette_minutes() -> u64 {
+    60
+}
+
+fn default_thread_busy_messages() -> usize {
+    15
+}
+
+fn default_canon_weight() -> usize {
+    10
+}
+
+fn default_max_bot_chain() -> usize {
+    4
+}
+
+fn default_guild_idle_minutes() -> u64 {
+    60
+}
+
+fn default_mirror_reload_minutes() -> u64 {
+    30
+}
+
+fn default_chain_order() -> usize {
+    markov::DEFAULT_ORDER
+}
+
+/// The config, from `bot.toml` or `bot.json`, see `config`.
+fn bot_config() -> Result<BotConfig> {
+    let cfg: BotConfig = config::load()?;
+    cfg.validate()
+        .map_err(|e| anyhow!("{}: {}", config::path(), e))?;
+    Ok(cfg)
+}
+
+/// Encrypted files are different bytes every time they're saved, so they'd never share a chunk
+const DEDUP_ENCRYPTED: &str = "`dedup_snapshots` doesn't work with an `encryption_key`";
+
+/// Read before anything else is loaded, since it's needed to load it.
+fn encryption_key() -> Result<Option<Key>> {
+    // a missing or broken bot.json gets reported properly by `run`
+    let cfg = bot_config().ok();
+    let configured = cfg.as_ref().and_then(|c| c.encryption_key.as_deref());
+    let key = secrets::load("encryption_key", configured)?
+        .map(|k| parse_key(&k))
+        .transpose()?;
+    if key.is_none() && cfg.as_ref().is_some_and(|c| c.public) {
+        bail!("public mode needs an `encryption_key`");
+    }
+    if key.is_some() && cfg.is_some_and(|c| c.dedup_snapshots) {
+        bail!(DEDUP_ENCRYPTED);
+    }
+    Ok(key)
+}
+
+fn replication_secret(cfg: &BotConfig) -> Result<String> {
+    let configured = cfg.replication.as_ref().and_then(|r| r.secret.as_deref());
+    secrets::load("replication_secret", configured)?
+        .ok_or_else(|| anyhow!("replication needs a `secret`"))
+}
+
+/// `taco_bot standby`, see `replication`. With a lease, it goes on to run the bot once it's taken
+/// over.
+fn standby() -> Result<bool> {
+    let cfg = bot_config()?;
+    let replication = cfg
+        .replication
+        .as_ref()
+        .ok_or_else(|| anyhow!("a standby needs `replication` in bot.json"))?;
+    let secret = replication_secret(&cfg)?;
+    let key = encryption_key()?;
+    let mut models = MarkovStore::load(key)?;
+    models.set_storage(cfg.storage)?;
+    models.set_orders(cfg.chain_order, cfg.chain_orders.clone())?;
+    let lease = replication.lease.as_ref().map(Lease::new);
+    replication::run_standby(replication, &secret, key, &mut models, lease.as_ref())?;
+    drop(models);
+    lead(lease);
+    Ok(true)
+}
+
+/// `taco_bot downgrade FORMAT [DIR]`: every model written to `DIR` (`downgraded` by default) in
+/// an older format, for going back to a bot from before it without losing what was learned since.
+fn downgrade(args: &[String]) -> Result<bool> {
+    let format: Format = match args.first() {
+        Some(format) => format.parse()?,
+        None => bail!("usage: taco_bot downgrade bare|ordered|compact [DIR]"),
+    };
+    let dir = PathBuf::from(args.get(1).map_or("downgraded", String::as_str));
+    let cfg = bot_config()?;
+    let mut models = MarkovStore::load(encryption_key()?)?;
+    models.set_read_only(true);
+    models.set_storage(cfg.storage)?;
+    let count = models.export_in(format, &dir)?;
+    println!("wrote {} models to {}", count, dir.display());
+    Ok(true)
+}
+
+/// Takes the lease when there is one, waiting for it if another instance holds it, and keeps it.
+fn take_lease(lease: Option<Lease>) -> Result<()> {
+    let lease = match lease {
+        Some(lease) => lease,
+        None => match bot_config()?.replication.and_then(|r| r.lease) {
+            Some(cfg) => {
+                let lease = Lease::new(&cfg);
+                lease.acquire()?;
+                lease
+            }
+            None => return Ok(()),
+        },
+    };
+    lease.keep();
+    Ok(())
+}
+
+fn run(
+    data: &mut Data,
+    bridge_events: &mut Option<UnboundedSender<BridgeMessage>>,
+    bridge_messages: &mut UnboundedReceiver<BridgeMessage>,
+) -> Result<()> {
+    let bot_cfg = bot_config()?;
+    let token = secrets::token(bot_cfg.token.as_ref())?;
+
+    // bridges outlive gateway reconnects, so they're only started the first time around
+    if let Some(events) = bridge_events.take() {
+        for irc in &bot_cfg.irc {
+            irc::spawn(irc.clone(), events.clone());
+        }
+        #[cfg(feature = "matrix")]
+        for matrix in &bot_cfg.matrix {
+            matrix::spawn(matrix.clone(), events.clone());
+        }
+        if let Some(news) = &bot_cfg.news {
+            news::spawn(news.clone(), events.clone());
+        }
+        // and so is serving the replication log
+        if let Some(listen) = bot_cfg
+            .replication
+            .as_ref()
+            .and_then(|r| r.listen.as_deref())
+        {
+            replication::serve(listen, replication_secret(&bot_cfg)?)?;
+        }
+        if let Some(api) = &bot_cfg.api {
+            let per_guild = bot_cfg.guild_models || bot_cfg.public;
+            let (keys, prefix) = (data.api_keys.clone(), bot_cfg.prefix.clone());
+            api::spawn(api.clone(), keys, per_guild, prefix, events.clone())?;
+        }
+    }
+
+    data.plugins.init(&bot_cfg.plugins)?;
+    data.read_only = bot_cfg.mirror;
+    data.models.set_read_only(bot_cfg.mirror);
+    data.models.set_storage(bot_cfg.storage)?;
+    data.models
+        .set_orders(bot_cfg.chain_order, bot_cfg.chain_orders.clone())?;
+    persist::keep_snapshots(bot_cfg.save_snapshots);
+    persist::dedup_snapshots(bot_cfg.dedup_snapshots);
+    let mastodon = bot_cfg.mastodon.clone().map(Mastodon::new).transpose()?;
+    let moderation = bot_cfg
+        .moderation
+        .clone()
+        .map(Moderation::new)
+        .transpose()?;
+    let tokenizer = Tokenizer::new(bot_cfg.tokenizer.clone(), &bot_cfg.punctuation)?;
+    let replication = match bot_cfg.replication.as_ref().and_then(|r| r.listen.as_ref()) {
+        Some(_) => Some(replication::Log::open(data.models.master_key())?),
+        None => None,
+    };
+    let sentry = bot_cfg.sentry.as_ref().map(Sentry::new).transpose()?;
+    if let Some(sentry) = &sentry {
+        sentry.install_panic_hook();
+    }
+    if let Some(otlp) = &bot_cfg.otlp {
+        otel::init(otlp)?;
+    }
+    let bot = Bot::new(token.clone(), bot_cfg.intents);
+    bot.run(
+        Handler {
+            data,
+            rng: rand::thread_rng(),
+            id: None,
+            name: None,
+            cfg: bot_cfg,
+            token,
+            mastodon,
+            moderation,
+            tokenizer,
+            restart: false,
+            trace: Trace::default(),
+            sentry,
+            stickers: GuildStickers::default(),
+            replication,
+        },
+        bridge_messages,
+    )
+}
+
+/// `taco_bot loadtest`, see `loadtest`. It runs in a scratch directory, so no real data is touched.
+fn load_test(args: &[String]) -> Result<bool> {
+    use std::time::{Duration, Instant};
+
+    let options = loadtest::Options::parse(args)?;
+    let dir = std::env::temp_dir().join(format!("taco_bot-loadtest-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    std::env::set_current_dir(&dir)?;
+    let cfg: BotConfig = serde_json::from_value(serde_json::json!({
+        "intents": [],
+        "admins": [],
+        "channel_blacklist": [],
+        "announcement_channels": [],
+        "guild_models": options.per_guild,
+    }))?;
+    let mut data = Data::load(None)?;
+    data.models.set_storage(options.storage)?;
+    let tokenizer = Tokenizer::new(cfg.tokenizer.clone(), &cfg.punctuation)?;
+    let mut handler = Handler {
+        data: &mut data,
+        rng: rand::thread_rng(),
+        id: None,
+        name: None,
+        cfg,
+        token: TokenBuf::from(""),
+        mastodon: None,
+        moderation: None,
+        tokenizer,
+        restart: false,
+        trace: Trace::default(),
+        sentry: None,
+        stickers: GuildStickers::default(),
+        replication: None,
+    };
+
+    let mut report = loadtest::Report::default();
+    let start = Instant::now();
+    for (i, json) in loadtest::Stream::new(&options).enumerate() {
+        let depth = match options.due(i) {
+            Some(due) => {
+                if let Some(early) = due.checked_sub(start.elapsed()) {
+                    std::thread::sleep(early);
+                }
+                // everything that's arrived by now and hasn't been taken in yet
+                let arrived = (start.elapsed().as_secs_f64() * options.rate as f64) as usize;
+                arrived.min(options.messages).saturating_sub(i)
+            }
+            None => 0,
+        };
+        let began = Instant::now();
+        let message: Message<'_> = serde_json::from_str(&json)?;
+        handler.data.loops.observe(&message, false);
+        handler.observe(&message);
+        handler.ingest(&message);
+        report.record(began.elapsed(), depth);
+    }
+    report.elapsed = start.elapsed().max(Duration::from_nanos(1));
+    for markov in handler.data.models.loaded() {
+        report.models += 1;
+        report.entries += markov.entry_count();
+    }
+    let began = Instant::now();
+    handler.data.save()?;
+    report.save = began.elapsed();
+
+    println!("{}", report);
+    std::env::set_current_dir(std::env::temp_dir())?;
+    std::fs::remove_dir_all(&dir)?;
+    Ok(true)
+}
+
+fn main() {
+    let args: Vec<_> = std::env::args().skip(1).collect();
+    let tool = match args.first().map(String::as_str) {
+        Some("init") => Some(init::run().map(|()| true)),
+        Some("loadtest") => Some(load_test(&args[1..])),
+        Some("standby") => Some(standby()),
+        Some("downgrade") => Some(downgrade(&args[1..])),
+        Some("backup") => Some(chunks::backup(&args[1..])),
+        Some("restore") => Some(chunks::restore(&args[1..])),
+        Some("formatbench") => Some(loadtest::Options::parse(&args[1..]).and_then(|options| {
+            println!("{}", loadtest::Formats::run(&options)?);
+            Ok(true)
+        })),
+        Some("parbench") => Some(loadtest::Options::parse(&args[1..]).and_then(|options| {
+            println!("{}", loadtest::Parallel::run(&options)?);
+            println!("{}", loadtest::Serving::run(&options)?);
+            Ok(true)
+        })),
+        _ => None,
+    };
+    if let Some(result) = tool {
+        match result {
+            Ok(true) => return,
+            Ok(false) => std::process::exit(1),
+            Err(e) => {
+                eprintln!("{:#}", e);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    lead(None);
+}
+
+/// Runs the bot until it's stopped, holding `lease`, or the configured one once it's been taken.
+fn lead(lease: Option<Lease>) {
+    if let Err(e) = take_lease(lease) {
+        eprintln!("lease: {:#}", e);
+        std::process::exit(2);
+    }
+    let key = match encryption_key() {
+        Ok(key) => key,
+        Err(e) => {
+            eprintln!("encryption key: {:#}", e);
+            std::process::exit(2);
+        }
+    };
+    let mut data = match Data::load(key) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("{:#}", e);
+            std::process::exit(2);
+        }
+    };
+    config::reload_on_sighup();
+    let (bridge_events, mut bridge_messages) = mpsc::unbounded();
+    let mut bridge_events = Some(bridge_events);
 
-    while let Err(e) = run(&mut markov) {
-        save_markov(&markov).unwrap();
+    while let Err(e) = run(&mut data, &mut bridge_events, &mut bridge_messages) {
+        data.save().unwrap();
         for cause in e.chain() {
-            println!("{}", cause);
+            println!("{}", secrets::redact(&cause.to_string()));
         }
     }
 
-    save_markov(&markov).unwrap();
+    data.plugins.shutdown();
+    data.save().unwrap();
 }
@@ -1,70 +1,185 @@
-use rand::distributions::{WeightedError, WeightedIndex};
-use rand::{distributions::Distribution, Rng};
-use serde::{Deserialize, Serialize};
+use bincode::Options;
+use rand::distributions::WeightedIndex;
+use rand::rngs::StdRng;
+use rand::{distributions::Distribution, Rng, SeedableRng};
+use rayon::prelude::*;
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
+use serde::{ser, Deserialize, Serialize, Serializer};
 use std::collections::hash_map::{Entry as HashEntry, HashMap};
 use std::collections::HashSet;
-use std::convert::TryFrom;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+use std::sync::Mutex;
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Hash, Clone)]
+use crate::intern::Sym;
+use crate::sampler::Cumulative;
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Hash, Clone, Copy)]
 pub enum Word {
     Start,
     End,
-    Word(String),
+    Word(Sym),
+}
+
+impl fmt::Display for Word {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Word::Start => f.write_str("(start)"),
+            Word::End => f.write_str("(end)"),
+            Word::Word(w) => f.write_str(w),
+        }
+    }
+}
+
+impl From<String> for Word {
+    fn from(word: String) -> Self {
+        Word::Word(Sym::from(word))
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
-#[serde(try_from = "HashMap<Word, usize>")]
+#[serde(from = "HashMap<Word, usize>")]
 #[serde(into = "HashMap<Word, usize>")]
-struct Entry {
+pub struct Entry {
     weight_pairs: Vec<(Word, usize)>,
-    dist: WeightedIndex<usize>,
+    /// The weights in `weight_pairs`, kept in step with them
+    sums: Cumulative,
 }
 
 impl Entry {
     fn new(word: Word) -> Self {
-        Entry {
-            weight_pairs: vec![(word, 1)],
-            dist: WeightedIndex::new(std::iter::once(1))
-                .expect("should create value weighted index"),
-        }
+        Entry::from_pairs(vec![(word, 1)])
+    }
+
+    fn from_pairs(weight_pairs: Vec<(Word, usize)>) -> Self {
+        let sums = Cumulative::new(weight_pairs.iter().map(|(_, w)| *w));
+        Entry { weight_pairs, sums }
     }
 
+    /// A word that comes next, or the end if nothing does anymore, like after unlearning
+    /// everything that did.
     fn get_random(&self, rng: &mut impl Rng) -> Word {
-        self.weight_pairs[self.dist.sample(rng)].0.clone()
+        match self.sums.sample(rng) {
+            Some(i) => self.weight_pairs[i].0,
+            None => Word::End,
+        }
+    }
+
+    /// `get_random` with every weight raised to `1 / temperature` first, then multiplied by
+    /// every bias, then narrowed down by `sampling`.
+    fn get_random_at(
+        &self,
+        rng: &mut impl Rng,
+        temperature: f64,
+        sampling: Sampling,
+        biases: &[Box<dyn Bias>],
+        so_far: &[String],
+    ) -> Word {
+        if temperature == 1.0 && sampling == Sampling::Weighted && biases.is_empty() {
+            return self.get_random(rng);
+        }
+        let mut weights: Vec<_> = self
+            .weight_pairs
+            .iter()
+            .map(|(word, w)| {
+                let weight = (*w as f64).powf(1.0 / temperature);
+                biases
+                    .iter()
+                    .fold(weight, |weight, b| weight * b.weight(so_far, word))
+            })
+            .collect();
+        sampling.narrow(&mut weights);
+        match WeightedIndex::new(weights) {
+            Ok(dist) => self.weight_pairs[dist.sample(rng)].0,
+            Err(_) => self.get_random(rng),
+        }
     }
 
     fn insert(&mut self, new_word: Word) {
-        for (i, pair) in self.weight_pairs.iter_mut().enumerate() {
-            let (word, weight) = pair;
+        for (i, (word, weight)) in self.weight_pairs.iter_mut().enumerate() {
             if *word == new_word {
                 *weight += 1;
-                self.dist
-                    .update_weights(&[(i, weight)])
-                    .expect("update should keep valid weights");
+                self.sums.set(i, *weight);
                 return;
             }
         }
         self.weight_pairs.push((new_word, 1));
-        self.dist = self
-            .gen_new_weights()
-            .expect("dist with added word should be valid");
+        self.sums.push(1);
     }
 
-    fn gen_new_weights(&self) -> Result<WeightedIndex<usize>, WeightedError> {
-        WeightedIndex::new(self.weight_pairs.iter().map(|(_, w)| *w))
+    /// Raises `word`'s weight to at least `weight`.
+    fn ensure(&mut self, new_word: Word, weight: usize) {
+        match self.weight_pairs.iter().position(|(w, _)| *w == new_word) {
+            Some(i) if self.weight_pairs[i].1 >= weight => {}
+            Some(i) => {
+                self.weight_pairs[i].1 = weight;
+                self.sums.set(i, weight);
+            }
+            None => {
+                self.weight_pairs.push((new_word, weight));
+                self.sums.push(weight);
+            }
+        }
     }
-}
 
-impl TryFrom<HashMap<Word, usize>> for Entry {
-    type Error = WeightedError;
+    /// Takes one off `word`'s weight, returning whether it had any. Words left weighing nothing
+    /// are dropped, and so can every word be.
+    fn remove(&mut self, word: &Word) -> bool {
+        let i = match self.weight_pairs.iter().position(|(w, _)| w == word) {
+            Some(i) => i,
+            None => return false,
+        };
+        self.weight_pairs[i].1 -= 1;
+        if self.weight_pairs[i].1 == 0 {
+            self.weight_pairs.swap_remove(i);
+            self.rebuild();
+        } else {
+            self.sums.set(i, self.weight_pairs[i].1);
+        }
+        true
+    }
+
+    /// Adds `other`'s weights to these, rebuilding the distribution once.
+    fn merge(&mut self, other: Entry) {
+        for (word, weight) in other.weight_pairs {
+            match self.weight_pairs.iter_mut().find(|(w, _)| *w == word) {
+                Some(pair) => pair.1 += weight,
+                None => self.weight_pairs.push((word, weight)),
+            }
+        }
+        self.rebuild();
+    }
 
-    fn try_from(map: HashMap<Word, usize>) -> Result<Self, Self::Error> {
-        let mut weight_pairs = Vec::with_capacity(map.len());
-        let dist = WeightedIndex::new(map.into_iter().map(|(w, s)| {
-            weight_pairs.push((w, s));
-            s
-        }))?;
-        Ok(Entry { weight_pairs, dist })
+    /// Multiplies every weight by `factor`, rounding up or down at random so they stay the same on
+    /// average, and drops the ones that fall under `min_weight`. The distribution is only rebuilt
+    /// once, at the end. Returns how many were dropped.
+    fn decay(&mut self, factor: f64, min_weight: usize, rng: &mut impl Rng) -> usize {
+        let min_weight = min_weight.max(1);
+        let len = self.weight_pairs.len();
+        self.weight_pairs.retain_mut(|(_, weight)| {
+            let scaled = *weight as f64 * factor;
+            *weight = scaled.floor() as usize + rng.gen_bool(scaled.fract()) as usize;
+            *weight >= min_weight
+        });
+        self.rebuild();
+        len - self.weight_pairs.len()
+    }
+
+    /// Whether nothing comes next anymore.
+    fn is_empty(&self) -> bool {
+        self.sums.total() == 0
+    }
+
+    /// Brings `sums` back in step after `weight_pairs` changed more than a weight at a time.
+    fn rebuild(&mut self) {
+        self.sums = Cumulative::new(self.weight_pairs.iter().map(|(_, w)| *w));
+    }
+}
+
+impl From<HashMap<Word, usize>> for Entry {
+    fn from(map: HashMap<Word, usize>) -> Self {
+        Entry::from_pairs(map.into_iter().collect())
     }
 }
 
@@ -74,24 +189,390 @@ impl From<Entry> for HashMap<Word, usize> {
     }
 }
 
-pub const WORD_COUNT: usize = 2;
-pub type WordArray = [Word; WORD_COUNT];
-pub const START_WORDS: WordArray = [Word::Start, Word::Start];
+/// How many words models look back at, unless they're made with another order
+pub const DEFAULT_ORDER: usize = 2;
+/// Starts models saved with their order. Before that every model was a bare map of order 2
+/// entries, which starts with its length, and no map is this long.
+const ORDERED_MARKER: u64 = u64::MAX;
+/// Starts models saved in the compact format, followed by `COMPACT_VERSION`. Neither of the
+/// formats before can start with it either.
+const COMPACT_MAGIC: u64 = u64::from_be_bytes(*b"taco-mkv");
+const COMPACT_VERSION: u32 = 1;
+
+/// The formats models have been saved in, oldest first. Every one of them still loads, but only
+/// the newest is saved, except by `taco_bot downgrade` for going back to an older bot.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+    /// A bare map of order 2 entries
+    Bare,
+    /// `ORDERED_MARKER`, the order, then the entries
+    Ordered,
+    /// `COMPACT_MAGIC` and `COMPACT_VERSION`, see `Compact`
+    Compact,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "bare" => Format::Bare,
+            "ordered" => Format::Ordered,
+            "compact" => Format::Compact,
+            _ => anyhow::bail!("`{}` isn't a format, try bare, ordered or compact", s),
+        })
+    }
+}
+
+/// A model serialized in a given format, see `Markov::in_format`.
+pub struct InFormat<'a>(&'a Markov, Format);
+
+impl Serialize for InFormat<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let InFormat(markov, format) = self;
+        match format {
+            Format::Bare => {
+                let entries: Vec<_> = markov
+                    .entries
+                    .iter()
+                    .map(|(index, entry)| ((&index[0], &index[1]), entry))
+                    .collect();
+                entries.serialize(serializer)
+            }
+            Format::Ordered => {
+                (ORDERED_MARKER, markov.order, &markov.entries).serialize(serializer)
+            }
+            Format::Compact => markov.serialize(serializer),
+        }
+    }
+}
+
+/// The compact format: every word once, then entries as numbers into them, all variable-length
+/// encoded. 0 is a start and 1 an end, the rest are `words` from 2 on.
+#[derive(Serialize, Deserialize)]
+struct Compact {
+    words: Vec<String>,
+    entries: Vec<CompactEntry>,
+}
+
+/// An entry's words, and each word that follows them with its weight
+type CompactEntry = (Vec<u32>, Vec<(u32, usize)>);
+
+/// Bytes serialized as bytes all at once, rather than as a sequence of numbers.
+struct Bytes(Vec<u8>);
+
+impl Serialize for Bytes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Bytes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct BytesVisitor;
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(transparent)]
+        impl<'de> Visitor<'de> for BytesVisitor {
+            type Value = Bytes;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("bytes")
+            }
+
+            fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Bytes, E> {
+                Ok(Bytes(bytes.to_vec()))
+            }
+
+            fn visit_byte_buf<E: de::Error>(self, bytes: Vec<u8>) -> Result<Bytes, E> {
+                Ok(Bytes(bytes))
+            }
+        }
+
+        deserializer.deserialize_byte_buf(BytesVisitor)
+    }
+}
+
+impl Compact {
+    fn new<'a>(entries: &'a Entries) -> Self {
+        let mut ids: HashMap<Sym, u32> = HashMap::new();
+        let mut words = Vec::new();
+        let mut id = |word: &'a Word| -> u32 {
+            match word {
+                Word::Start => 0,
+                Word::End => 1,
+                Word::Word(w) => *ids.entry(*w).or_insert_with(|| {
+                    words.push(String::from(w.as_str()));
+                    words.len() as u32 + 1
+                }),
+            }
+        };
+        // always in the same order, so what didn't change saves to the same bytes, which
+        // deduplicated snapshots rely on
+        let mut sorted: Vec<_> = entries.iter().collect();
+        sorted.sort_by_cached_key(|(index, _)| {
+            // by the words rather than syms, which are numbered differently every run
+            let mut hasher = std::hash::DefaultHasher::new();
+            for word in index.iter() {
+                match word {
+                    Word::Word(w) => w.as_str().hash(&mut hasher),
+                    other => std::mem::discriminant(other).hash(&mut hasher),
+                }
+            }
+            hasher.finish()
+        });
+        let entries = sorted
+            .into_iter()
+            .map(|(index, entry)| {
+                let index = index.iter().map(&mut id).collect();
+                let pairs = entry
+                    .weight_pairs
+                    .iter()
+                    .map(|(w, n)| (id(w), *n))
+                    .collect();
+                (index, pairs)
+            })
+            .collect();
+        Compact { words, entries }
+    }
+
+    fn into_entries(self, order: usize) -> Result<Entries, String> {
+        let mut words = vec![Word::Start, Word::End];
+        words.extend(self.words.into_iter().map(Word::from));
+        let word = |id: u32| {
+            words
+                .get(id as usize)
+                .cloned()
+                .ok_or_else(|| format!("there's no word {}", id))
+        };
+        let mut entries = HashMap::with_capacity(self.entries.len());
+        for (index, pairs) in self.entries {
+            if index.len() != order {
+                return Err(String::from("the model's entries don't match its order"));
+            }
+            let index = index.into_iter().map(word).collect::<Result<_, _>>()?;
+            let weight_pairs: Vec<_> = pairs
+                .into_iter()
+                .map(|(id, n)| Ok((word(id)?, n)))
+                .collect::<Result<_, String>>()?;
+            entries.insert(index, Entry::from_pairs(weight_pairs));
+        }
+        Ok(entries)
+    }
+}
+
+/// What follows each run of words a model has seen
+type Entries = HashMap<Vec<Word>, Entry>;
+
+/// Fewer sequences than this are learned on one thread, since splitting them up costs more
+const PARALLEL_MIN: usize = 256;
+
+/// Which of `shards` the entry at `index` belongs to, the same every time.
+fn shard_of(index: &[Word], shards: usize) -> usize {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    index.hash(&mut hasher);
+    (hasher.finish() % shards as u64) as usize
+}
+
+/// Makes some words more or less likely while generating, without changing the model, so any
+/// number of them can be stacked on a chain.
+pub trait Bias {
+    /// What to multiply `candidate`'s weight by, when `so_far` has been generated.
+    fn weight(&self, so_far: &[String], candidate: &Word) -> f64;
+}
+
+/// Which words follow the last `order` words, every time.
+#[derive(Clone, Debug)]
 pub struct Markov {
-    entries: HashMap<WordArray, Entry>,
+    order: usize,
+    entries: Entries,
+    /// Entries added to, changed or removed since the model was last saved
+    changed: HashSet<Vec<Word>>,
+    /// Set when too much changed to keep track of, so the whole model has to be saved
+    all_changed: bool,
+}
+
+/// What changed in a model since it was last saved.
+pub enum Changes<'a> {
+    All,
+    Entries(&'a HashSet<Vec<Word>>),
+}
+
+impl Default for Markov {
+    fn default() -> Self {
+        Markov::with_order(DEFAULT_ORDER)
+    }
+}
+
+impl Serialize for Markov {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let compact = bincode::DefaultOptions::new()
+            .serialize(&Compact::new(&self.entries))
+            .map_err(ser::Error::custom)?;
+        (COMPACT_MAGIC, COMPACT_VERSION, self.order, Bytes(compact)).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Markov {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct MarkovVisitor;
+
+        impl<'de> Visitor<'de> for MarkovVisitor {
+            type Value = Markov;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a markov model")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Markov, A::Error> {
+                let truncated = || de::Error::custom("the model is truncated");
+                let first: u64 = seq.next_element()?.ok_or_else(truncated)?;
+                if first == COMPACT_MAGIC {
+                    let version: u32 = seq.next_element()?.ok_or_else(truncated)?;
+                    if version != COMPACT_VERSION {
+                        return Err(de::Error::custom(format!(
+                            "the model is in version {} of the format, which this bot can't read",
+                            version
+                        )));
+                    }
+                    let order: usize = seq.next_element()?.ok_or_else(truncated)?;
+                    let compact: Bytes = seq.next_element()?.ok_or_else(truncated)?;
+                    let compact: Compact = bincode::DefaultOptions::new()
+                        .deserialize(&compact.0)
+                        .map_err(de::Error::custom)?;
+                    if order == 0 {
+                        return Err(de::Error::custom("the model has no order"));
+                    }
+                    let entries = compact.into_entries(order).map_err(de::Error::custom)?;
+                    return Ok(Markov::from_parts(order, entries));
+                }
+                // the formats from before, which are saved again compact the next time
+                if first == ORDERED_MARKER {
+                    let order: usize = seq.next_element()?.ok_or_else(truncated)?;
+                    let entries: Entries = seq.next_element()?.ok_or_else(truncated)?;
+                    if order == 0 || entries.keys().any(|k| k.len() != order) {
+                        return Err(de::Error::custom(
+                            "the model's entries don't match its order",
+                        ));
+                    }
+                    let mut markov = Markov::from_parts(order, entries);
+                    markov.mark_all_changed();
+                    return Ok(markov);
+                }
+                // the bare map from before, which is always order 2
+                let mut entries = HashMap::new();
+                for _ in 0..first {
+                    let (key, entry): ([Word; 2], Entry) =
+                        seq.next_element()?.ok_or_else(truncated)?;
+                    entries.insert(key.to_vec(), entry);
+                }
+                let mut markov = Markov::from_parts(2, entries);
+                markov.mark_all_changed();
+                Ok(markov)
+            }
+        }
+
+        // how long it is depends on which of the two it is, so it's read until it's done
+        deserializer.deserialize_tuple(usize::MAX, MarkovVisitor)
+    }
+}
+
+/// `words` advanced by `word`, keeping its length.
+fn advance(words: &mut Vec<Word>, word: Word) {
+    if !words.is_empty() {
+        words.remove(0);
+        words.push(word);
+    }
+}
+
+/// The last `order` of `words`, with starts in front if there aren't enough.
+fn context(order: usize, words: &[Word]) -> Vec<Word> {
+    let known = &words[words.len().saturating_sub(order)..];
+    let mut context = vec![Word::Start; order - known.len()];
+    context.extend_from_slice(known);
+    context
 }
 
 impl Markov {
     pub fn new() -> Self {
+        Markov::default()
+    }
+
+    /// A model that looks back at `order` words, which has to be at least 1.
+    pub fn with_order(order: usize) -> Self {
+        assert!(order > 0, "markov models need an order of at least 1");
+        Markov::from_parts(order, HashMap::new())
+    }
+
+    fn from_parts(order: usize, entries: Entries) -> Self {
         Markov {
-            entries: HashMap::new(),
+            order,
+            entries,
+            changed: HashSet::new(),
+            all_changed: false,
         }
     }
 
-    pub fn insert(&mut self, index: WordArray, word: Word) {
+    /// A model made of `entries` as they were saved, each `order` words long.
+    pub fn from_entries(
+        order: usize,
+        entries: impl IntoIterator<Item = (Vec<Word>, Entry)>,
+    ) -> anyhow::Result<Self> {
+        let entries: Entries = entries.into_iter().collect();
+        if order == 0 || entries.keys().any(|k| k.len() != order) {
+            anyhow::bail!("the model's entries don't match its order");
+        }
+        Ok(Markov::from_parts(order, entries))
+    }
+
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    pub fn entry(&self, index: &[Word]) -> Option<&Entry> {
+        self.entries.get(index)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&Vec<Word>, &Entry)> {
+        self.entries.iter()
+    }
+
+    pub fn changes(&self) -> Changes<'_> {
+        if self.all_changed {
+            Changes::All
+        } else {
+            Changes::Entries(&self.changed)
+        }
+    }
+
+    /// Forgets what changed, once it's saved.
+    pub fn mark_saved(&mut self) {
+        self.changed.clear();
+        self.all_changed = false;
+    }
+
+    /// Makes the whole model be saved next time, like when it's new to where it's saved.
+    pub fn mark_all_changed(&mut self) {
+        self.changed.clear();
+        self.all_changed = true;
+    }
+
+    fn mark_changed(&mut self, index: &[Word]) {
+        if !self.all_changed && !self.changed.contains(index) {
+            self.changed.insert(index.to_vec());
+        }
+    }
+
+    fn start(&self) -> Vec<Word> {
+        vec![Word::Start; self.order]
+    }
+
+    /// Counts `word` after `index`, which has to be `order` words long.
+    pub fn insert(&mut self, index: Vec<Word>, word: Word) {
+        assert_eq!(
+            index.len(),
+            self.order,
+            "index doesn't match the model's order"
+        );
+        self.mark_changed(&index);
         match self.entries.entry(index) {
             HashEntry::Occupied(mut e) => {
                 e.get_mut().insert(word);
@@ -103,112 +584,1370 @@ impl Markov {
     }
 
     pub fn insert_sequence(&mut self, seq: impl IntoIterator<Item = String>) {
-        let mut prevs = (Word::Start, Word::Start);
+        let mut prevs = self.start();
         for cur in seq {
-            let cur = Word::Word(cur);
-            self.insert([prevs.0, prevs.1.clone()], cur.clone());
-            prevs.0 = std::mem::replace(&mut prevs.1, cur);
+            let cur = Word::from(cur);
+            self.insert(prevs.clone(), cur);
+            advance(&mut prevs, cur);
+        }
+        self.insert(prevs, Word::End);
+    }
+
+    /// `insert_sequence` for every one of `seqs`, on every core. Transitions are split up by which
+    /// shard their entry's in, so each thread learns its own entries without waiting on the others,
+    /// and the model ends up just as if they'd been learned one at a time.
+    pub fn insert_sequences(&mut self, seqs: &[Vec<String>]) {
+        let shards = rayon::current_num_threads();
+        if seqs.len() < PARALLEL_MIN || shards == 1 {
+            for seq in seqs {
+                self.insert_sequence(seq.iter().cloned());
+            }
+            return;
+        }
+        let start = self.start();
+        let chunks: Vec<_> = seqs
+            .par_iter()
+            .fold(
+                || vec![Vec::new(); shards],
+                |mut by_shard, seq| {
+                    let mut prevs = start.clone();
+                    let words = seq
+                        .iter()
+                        .map(|w| Word::Word(Sym::new(w)))
+                        .chain(std::iter::once(Word::End));
+                    for cur in words {
+                        by_shard[shard_of(&prevs, shards)].push((prevs.clone(), cur));
+                        advance(&mut prevs, cur);
+                    }
+                    by_shard
+                },
+            )
+            .collect();
+        let mut by_shard = vec![Vec::new(); shards];
+        for chunk in chunks {
+            for (shard, transitions) in chunk.into_iter().enumerate() {
+                by_shard[shard].push(transitions);
+            }
+        }
+        let learned: Vec<Entries> = by_shard
+            .into_par_iter()
+            .map(|chunks| {
+                let mut entries = Entries::default();
+                for (index, word) in chunks.into_iter().flatten() {
+                    match entries.entry(index) {
+                        HashEntry::Occupied(mut e) => e.get_mut().insert(word),
+                        HashEntry::Vacant(e) => {
+                            e.insert(Entry::new(word));
+                        }
+                    }
+                }
+                entries
+            })
+            .collect();
+        for (index, entry) in learned.into_iter().flatten() {
+            self.mark_changed(&index);
+            match self.entries.entry(index) {
+                HashEntry::Occupied(mut e) => e.get_mut().merge(entry),
+                HashEntry::Vacant(e) => {
+                    e.insert(entry);
+                }
+            }
+        }
+    }
+
+    /// Undoes `insert_sequence`, returning how many of its transitions were still there to remove.
+    pub fn remove_sequence(&mut self, seq: impl IntoIterator<Item = String>) -> usize {
+        let mut removed = 0;
+        let mut prevs = self.start();
+        let words = seq
+            .into_iter()
+            .map(Word::from)
+            .chain(std::iter::once(Word::End));
+        for cur in words {
+            let mut changed = false;
+            if let HashEntry::Occupied(mut e) = self.entries.entry(prevs.clone()) {
+                changed = e.get_mut().remove(&cur);
+                if e.get().is_empty() {
+                    e.remove();
+                }
+            }
+            if changed {
+                removed += 1;
+                self.mark_changed(&prevs);
+            }
+            advance(&mut prevs, cur);
+        }
+        removed
+    }
+
+    /// Makes sure every transition in `seq` has at least `weight`, without adding to
+    /// transitions that already do, so it can be applied any number of times.
+    pub fn ensure_sequence(&mut self, seq: impl IntoIterator<Item = String>, weight: usize) {
+        let mut prevs = self.start();
+        let words = seq
+            .into_iter()
+            .map(Word::from)
+            .chain(std::iter::once(Word::End));
+        for cur in words {
+            self.mark_changed(&prevs);
+            match self.entries.entry(prevs.clone()) {
+                HashEntry::Occupied(mut e) => e.get_mut().ensure(cur, weight),
+                HashEntry::Vacant(e) => {
+                    let mut entry = Entry::new(cur);
+                    entry.ensure(cur, weight);
+                    e.insert(entry);
+                }
+            }
+            advance(&mut prevs, cur);
         }
-        self.insert([prevs.0, prevs.1], Word::End);
     }
 
     pub fn generate_sequence<R: Rng>(&self, rng: R) -> Chain<'_, R> {
         Chain {
             entries: &self.entries,
-            cur_words: START_WORDS,
+            cur_words: self.start(),
+            rng,
+            temperature: 1.0,
+            sampling: Sampling::Weighted,
+            biases: Vec::new(),
+            so_far: Vec::new(),
+            config: None,
+            taken: 0,
+            sentences: 0,
+        }
+    }
+
+    /// `generate_sequence` from `seed`, which generates the same from the same model every time.
+    pub fn generate_with_seed(&self, seed: u64) -> Chain<'_, StdRng> {
+        self.generate_sequence(StdRng::seed_from_u64(seed))
+    }
+
+    /// Picks up a walk where `state` left off.
+    pub fn resume(&self, state: &ChainState) -> Chain<'_, StdRng> {
+        Chain {
+            cur_words: context(self.order, &state.cur_words),
+            so_far: state.so_far.clone(),
+            ..self.generate_with_seed(state.seed)
+        }
+    }
+
+    /// Generates from several models at once. At every step one of the models that knows the
+    /// current words is picked, in proportion to its weight, to choose the next word. Models can
+    /// have different orders, each looking back as far as it does.
+    pub fn generate_blended<'a, R: Rng>(
+        models: &[(&'a Markov, f64)],
+        rng: R,
+    ) -> BlendedChain<'a, R> {
+        let models: Vec<_> = models
+            .iter()
+            .filter(|(_, w)| *w > 0.0)
+            .map(|(m, w)| (&m.entries, m.order, *w))
+            .collect();
+        let order = models
+            .iter()
+            .map(|(_, o, _)| *o)
+            .max()
+            .unwrap_or(DEFAULT_ORDER);
+        BlendedChain {
+            models,
+            cur_words: vec![Word::Start; order],
             rng,
+            temperature: 1.0,
+            sampling: Sampling::Weighted,
+            biases: Vec::new(),
+            so_far: Vec::new(),
+            config: None,
+            taken: 0,
+            sentences: 0,
+        }
+    }
+
+    /// `resume` for `generate_blended`.
+    pub fn resume_blended<'a>(
+        models: &[(&'a Markov, f64)],
+        state: &ChainState,
+    ) -> BlendedChain<'a, StdRng> {
+        let chain = Markov::generate_blended(models, StdRng::seed_from_u64(state.seed));
+        BlendedChain {
+            cur_words: context(chain.cur_words.len(), &state.cur_words),
+            so_far: state.so_far.clone(),
+            ..chain
         }
     }
 
     pub fn clean(&mut self) -> usize {
+        self.mark_all_changed();
         let old_len = self.entries.len();
 
         let mut to_remove = Vec::new();
 
-        if let Some(start) = self.entries.get_mut(&START_WORDS) {
+        let start_words = self.start();
+        if let Some(start) = self.entries.get_mut(&start_words) {
             start.weight_pairs.retain(|(word, weight)| {
                 if *weight <= 1 {
-                    to_remove.push(word.clone());
+                    to_remove.push(*word);
                     false
                 } else {
                     true
                 }
             });
-            start.dist = start.gen_new_weights().unwrap();
+            start.rebuild();
         }
         for k in to_remove {
-            self.entries.remove(&[Word::Start, k]);
-        }
-
-        let visited = {
-            let mut visited = HashSet::new();
-            let mut to_visit = vec![START_WORDS];
-            while let Some(key) = to_visit.pop() {
-                let entry = match self.entries.get(&key) {
-                    Some(e) => e,
-                    None => continue,
-                };
-                if visited.insert(entry as *const _) {
-                    for (word, _) in &entry.weight_pairs {
-                        to_visit.push([key[1].clone(), word.clone()])
+            let mut key = start_words.clone();
+            advance(&mut key, k);
+            self.entries.remove(&key);
+        }
+
+        self.remove_unreachable();
+        old_len - self.entries.len()
+    }
+
+    /// Drops every entry a walk from the start can't get to anymore.
+    fn remove_unreachable(&mut self) {
+        let unreachable: Vec<_> = {
+            let reached = self.reachable(&self.start());
+            self.entries
+                .par_iter()
+                .map(|(key, _)| key)
+                .filter(|key| !reached[shard_of(key, reached.len())].contains(key))
+                .cloned()
+                .collect()
+        };
+        for key in unreachable {
+            self.entries.remove(&key);
+        }
+    }
+
+    /// A copy of the model with every transition's weight changed by `weigh`, given the weight it
+    /// has, leaving out the ones it gives 0 and whatever can't be reached without them.
+    pub fn reweighed(&self, mut weigh: impl FnMut(usize) -> usize) -> Markov {
+        let entries = self
+            .entries
+            .iter()
+            .filter_map(|(index, entry)| {
+                let pairs: Vec<_> = entry
+                    .weight_pairs
+                    .iter()
+                    .map(|&(word, weight)| (word, weigh(weight)))
+                    .filter(|&(_, weight)| weight > 0)
+                    .collect();
+                Some((index.clone(), Entry::from_pairs(pairs))).filter(|(_, e)| !e.is_empty())
+            })
+            .collect();
+        let mut markov = Markov::from_parts(self.order, entries);
+        markov.remove_unreachable();
+        markov
+    }
+
+    /// Every entry a walk from `from` can get to, split up by `shard_of`. It's walked a step at a
+    /// time, with every entry a step away looked at in parallel.
+    fn reachable(&self, from: &[Word]) -> Vec<HashSet<&Vec<Word>>> {
+        let shards: Vec<Mutex<HashSet<&Vec<Word>>>> = (0..rayon::current_num_threads())
+            .map(|_| Mutex::default())
+            .collect();
+        let visit = |key: &Vec<Word>| -> Option<&Vec<Word>> {
+            let (key, _) = self.entries.get_key_value(key)?;
+            let mut shard = shards[shard_of(key, shards.len())].lock().unwrap();
+            shard.insert(key).then_some(key)
+        };
+        let mut frontier: Vec<_> = visit(&from.to_vec()).into_iter().collect();
+        while !frontier.is_empty() {
+            frontier = frontier
+                .par_iter()
+                .flat_map_iter(|&key| {
+                    self.entries[key]
+                        .weight_pairs
+                        .iter()
+                        .filter_map(move |(word, _)| {
+                            let mut next = key.clone();
+                            advance(&mut next, *word);
+                            visit(&next)
+                        })
+                })
+                .collect();
+        }
+        shards
+            .into_iter()
+            .map(|shard| shard.into_inner().unwrap())
+            .collect()
+    }
+
+    /// Where a walk should start to carry on from `prompt`: the entry whose words before it match
+    /// the most of the end of `prompt`, ignoring case, picked at random among ties. Failing that, an
+    /// entry right after any word of `prompt`. Returns how many words matched, and the entry's words.
+    pub fn prompt_context(
+        &self,
+        prompt: &[String],
+        rng: &mut impl Rng,
+    ) -> Option<(usize, Vec<Word>)> {
+        /// The candidate that matched the most, picking each tie with the same chance without
+        /// collecting them all.
+        fn best<'a>(
+            candidates: impl Iterator<Item = (usize, &'a Vec<Word>)>,
+            rng: &mut impl Rng,
+        ) -> Option<(usize, &'a Vec<Word>)> {
+            let mut best: Option<(usize, &Vec<Word>)> = None;
+            let mut ties = 0;
+            for (matched, key) in candidates {
+                match best {
+                    Some((m, _)) if m > matched => {}
+                    Some((m, _)) if m == matched => {
+                        ties += 1;
+                        if rng.gen_range(0, ties) == 0 {
+                            best = Some((matched, key));
+                        }
+                    }
+                    _ => {
+                        ties = 1;
+                        best = Some((matched, key));
                     }
                 }
             }
-            visited
-        };
+            best
+        }
 
-        self.entries
-            .retain(|_, v| visited.contains(&(v as *const _)));
-        old_len - self.entries.len()
+        let prompt: Vec<_> = prompt.iter().map(|w| w.to_lowercase()).collect();
+        let same = |word: &Word, p: &str| matches!(word, Word::Word(w) if w.to_lowercase() == p);
+        let at_end = self.entries.keys().filter_map(|key| {
+            let matched = key
+                .iter()
+                .rev()
+                .zip(prompt.iter().rev())
+                .take_while(|(word, p)| same(word, p))
+                .count();
+            Some((matched, key)).filter(|_| matched > 0)
+        });
+        let anywhere = self
+            .entries
+            .keys()
+            .filter(|key| {
+                key.last()
+                    .is_some_and(|w| prompt.iter().any(|p| same(w, p)))
+            })
+            .map(|key| (1, key));
+        best(at_end, rng)
+            .or_else(|| best(anywhere, rng))
+            .map(|(matched, key)| (matched, key.clone()))
     }
 
-    pub fn what_follows(&self, word: &str) -> HashSet<String> {
-        let word = Word::Word(word.into());
-        self.entries
+    /// Repairs whatever would make generating panic: entries of the wrong length, ones with nothing
+    /// after them, words counted twice or not at all. Returns how many entries were fixed or dropped.
+    pub fn fsck(&mut self) -> usize {
+        let order = self.order;
+        let mut repaired = 0;
+        self.entries.retain(|key, entry| {
+            if key.len() != order {
+                repaired += 1;
+                return false;
+            }
+            let len = entry.weight_pairs.len();
+            let mut pairs: Vec<(Word, usize)> = Vec::with_capacity(len);
+            for (word, weight) in entry.weight_pairs.drain(..) {
+                match pairs.iter_mut().find(|(w, _)| *w == word) {
+                    Some(pair) => pair.1 += weight,
+                    None if weight > 0 => pairs.push((word, weight)),
+                    None => {}
+                }
+            }
+            let fixed = pairs.len() != len;
+            entry.weight_pairs = pairs;
+            // rebuilt either way, since it can't be compared with the weights
+            entry.rebuild();
+            if entry.is_empty() {
+                repaired += 1;
+                return false;
+            }
+            repaired += fixed as usize;
+            true
+        });
+        if repaired > 0 {
+            self.mark_all_changed();
+        }
+        repaired
+    }
+
+    /// Fades out everything the model has seen by `factor` (see `DecayConfig`), so what it saw
+    /// long ago counts for less than what it sees now. Returns how many transitions were forgotten.
+    pub fn decay(&mut self, factor: f64, min_weight: usize, rng: &mut impl Rng) -> usize {
+        let mut forgotten = 0;
+        self.entries.retain(|_, entry| {
+            forgotten += entry.decay(factor, min_weight, rng);
+            !entry.is_empty()
+        });
+        self.mark_all_changed();
+        forgotten
+    }
+
+    /// How many runs of `order` words the model knows what follows, as a rough measure of its size.
+    pub fn entry_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The model in the format models were saved in before the compact one, for comparing them.
+    pub fn legacy_bytes(&self) -> bincode::Result<Vec<u8>> {
+        bincode::serialize(&InFormat(self, Format::Ordered))
+    }
+
+    /// The model, to be saved in `format` instead of the newest one. Only order 2 models can be
+    /// bare.
+    pub fn in_format(&self, format: Format) -> anyhow::Result<InFormat<'_>> {
+        if format == Format::Bare && self.order != 2 {
+            anyhow::bail!(
+                "only order 2 models can be saved bare, this one's order {}",
+                self.order
+            );
+        }
+        Ok(InFormat(self, format))
+    }
+
+    /// A short hash of everything the model knows, the same for the same transitions and weights
+    /// however they're stored or were learned, so two copies can be checked against each other.
+    pub fn fingerprint(&self) -> String {
+        let mut transitions: Vec<_> = self
+            .entries
             .iter()
-            .filter_map(|([_, snd], e)| if *snd == word { Some(e) } else { None })
-            .flat_map(|e| {
-                e.weight_pairs.iter().filter_map(|(word, _)| match word {
-                    Word::Word(w) => Some(w.clone()),
-                    _ => None,
+            .flat_map(|(index, entry)| {
+                entry.weight_pairs.iter().map(move |pair| {
+                    let bytes = bincode::serialize(&(index, pair)).expect("words serialize");
+                    *blake3::hash(&bytes).as_bytes()
                 })
             })
-            .collect()
+            .collect();
+        transitions.sort_unstable();
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&(self.order as u64).to_le_bytes());
+        for t in &transitions {
+            hasher.update(t);
+        }
+        short_hex(hasher.finalize())
     }
 
-    pub fn what_starts(&self) -> HashSet<String> {
-        self.entries
-            .get(&START_WORDS)
+    /// How often every word has been seen, case-insensitively.
+    pub fn word_counts(&self) -> HashMap<String, usize> {
+        let mut counts = HashMap::new();
+        for (word, weight) in self.entries.values().flat_map(|e| e.weight_pairs.iter()) {
+            if let Word::Word(w) = word {
+                *counts.entry(w.to_lowercase()).or_default() += weight;
+            }
+        }
+        counts
+    }
+
+    /// What's in the model and how spread out it is, with the `top` most common starts and
+    /// transitions.
+    pub fn stats(&self, top: usize) -> Stats {
+        let mut words = HashSet::new();
+        let mut transitions = Vec::new();
+        let (mut total_weight, mut entropy) = (0, 0.0);
+        let mut memory = self.entries.capacity()
+            * (std::mem::size_of::<Vec<Word>>() + std::mem::size_of::<Entry>() + 1);
+        for (index, entry) in &self.entries {
+            let total = entry.sums.total();
+            total_weight += total;
+            for (word, weight) in &entry.weight_pairs {
+                if let Word::Word(w) = word {
+                    words.insert(w.as_str());
+                }
+                let p = *weight as f64 / total as f64;
+                entropy -= *weight as f64 * p.log2();
+                transitions.push((*weight, index, word));
+            }
+            memory += index.capacity() * std::mem::size_of::<Word>()
+                + entry.weight_pairs.capacity() * std::mem::size_of::<(Word, usize)>()
+                + entry.sums.len() * std::mem::size_of::<usize>();
+        }
+        // interned words are kept once however many times they're used, see `intern`
+        memory += words.iter().map(|w| w.len()).sum::<usize>();
+        let mut top_starts: Vec<_> = self
+            .entries
+            .get(&self.start())
+            .map(|e| e.weight_pairs.clone())
+            .unwrap_or_default();
+        top_starts.sort_by_key(|&(_, n)| std::cmp::Reverse(n));
+        top_starts.truncate(top);
+        if transitions.len() > top && top > 0 {
+            transitions.select_nth_unstable_by(top - 1, |a, b| b.0.cmp(&a.0));
+        }
+        transitions.truncate(top);
+        transitions.sort_by_key(|&(n, _, _)| std::cmp::Reverse(n));
+        Stats {
+            entries: self.entries.len(),
+            total_weight,
+            distinct_words: words.len(),
+            top_starts,
+            top_transitions: transitions
+                .into_iter()
+                .map(|(weight, index, word)| (index.clone(), *word, weight))
+                .collect(),
+            entropy: match total_weight {
+                0 => 0.0,
+                total => entropy / total as f64,
+            },
+            memory,
+        }
+    }
+
+    /// The average log-probability of each step the model would take to say `seq`. Steps it has
+    /// never seen count as `floor`.
+    pub fn log_probability(&self, seq: impl IntoIterator<Item = String>, floor: f64) -> f64 {
+        let mut prevs = self.start();
+        let (mut total, mut steps) = (0.0, 0);
+        let words = seq
             .into_iter()
-            .flat_map(|e| {
-                e.weight_pairs.iter().filter_map(|(word, _)| match word {
-                    Word::Word(s) => Some(s.clone()),
-                    _ => None,
+            .map(Word::from)
+            .chain(std::iter::once(Word::End));
+        for cur in words {
+            let p = self
+                .entries
+                .get(&prevs)
+                .and_then(|e| {
+                    let sum: usize = e.weight_pairs.iter().map(|(_, w)| w).sum();
+                    let (_, weight) = e.weight_pairs.iter().find(|(w, _)| *w == cur)?;
+                    Some(*weight as f64 / sum as f64)
                 })
+                .unwrap_or(floor);
+            total += p.ln();
+            steps += 1;
+            advance(&mut prevs, cur);
+        }
+        total / steps as f64
+    }
+
+    /// Every distinct word the model knows.
+    pub fn words(&self) -> HashSet<&str> {
+        self.entries
+            .values()
+            .flat_map(|e| e.weight_pairs.iter())
+            .filter_map(|(word, _)| match word {
+                Word::Word(w) => Some(w.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Forgets every transition to or from any of `words`, returning how many entries went away.
+    pub fn remove_words(&mut self, words: &HashSet<String>) -> usize {
+        self.remove_matching(|w| words.contains(w))
+    }
+
+    /// Adds everything `other` learned to this model, entry by entry, like a model trained
+    /// elsewhere or a channel's into its server's. Entries only `other` has are taken as they are,
+    /// without rebuilding them. Returns how many were new.
+    pub fn merge(&mut self, other: Markov) -> anyhow::Result<usize> {
+        if other.order != self.order {
+            anyhow::bail!(
+                "can't merge a model that looks back {} words into one that looks back {}",
+                other.order,
+                self.order
+            );
+        }
+        let mut added = 0;
+        for (index, entry) in other.entries {
+            self.mark_changed(&index);
+            match self.entries.entry(index) {
+                HashEntry::Occupied(mut e) => e.get_mut().merge(entry),
+                HashEntry::Vacant(e) => {
+                    e.insert(entry);
+                    added += 1;
+                }
+            }
+        }
+        Ok(added)
+    }
+
+    /// Forgets every transition to or from `word`, in any case, like when it's a slur. Returns how
+    /// many entries went away.
+    pub fn remove_containing(&mut self, word: &str) -> usize {
+        let word = word.to_lowercase();
+        self.remove_matching(|w| w.to_lowercase() == word)
+    }
+
+    fn remove_matching(&mut self, matches: impl Fn(&str) -> bool) -> usize {
+        let is_removed = |w: &Word| matches!(w, Word::Word(s) if matches(s));
+        self.mark_all_changed();
+        let old_len = self.entries.len();
+        self.entries.retain(|key, entry| {
+            if key.iter().any(is_removed) {
+                return false;
+            }
+            let len = entry.weight_pairs.len();
+            entry.weight_pairs.retain(|(w, _)| !is_removed(w));
+            if entry.weight_pairs.len() != len {
+                entry.rebuild();
+            }
+            !entry.is_empty()
+        });
+        old_len - self.entries.len()
+    }
+
+    /// Every word that's followed `words`, the last of some entry's context, with how many times,
+    /// most first. Only the last `order` of `words` count.
+    pub fn what_follows(&self, words: &[&str]) -> Vec<(Word, usize)> {
+        let words = match lookup(&words[words.len().saturating_sub(self.order)..]) {
+            Some(words) => words,
+            None => return Vec::new(),
+        };
+        let mut follows = HashMap::new();
+        for (key, entry) in &self.entries {
+            if key.ends_with(&words) {
+                for &(word, weight) in &entry.weight_pairs {
+                    *follows.entry(word).or_default() += weight;
+                }
+            }
+        }
+        by_weight(follows)
+    }
+
+    /// Every word that's come right before `words`, with how many times, most first. Up to
+    /// `order` words can be asked about.
+    pub fn what_precedes(&self, words: &[&str]) -> Vec<(Word, usize)> {
+        let words = match lookup(words) {
+            Some(words) if !words.is_empty() && words.len() <= self.order => words,
+            _ => return Vec::new(),
+        };
+        let (last, before) = words.split_last().unwrap();
+        let mut precedes = HashMap::new();
+        for (key, entry) in &self.entries {
+            if !key.ends_with(before) {
+                continue;
+            }
+            let preceding = key[key.len() - before.len() - 1];
+            for &(word, weight) in &entry.weight_pairs {
+                if word == *last {
+                    *precedes.entry(preceding).or_default() += weight;
+                }
+            }
+        }
+        by_weight(precedes)
+    }
+
+    /// Every word sentences have started with, with how many times, most first.
+    pub fn what_starts(&self) -> Vec<(Word, usize)> {
+        let starts = self.entries.get(&self.start()).into_iter();
+        by_weight(
+            starts
+                .flat_map(|e| e.weight_pairs.iter().copied())
+                .collect(),
+        )
+    }
+}
+
+/// `words` as the model knows them, or `None` if it's never seen one.
+fn lookup(words: &[&str]) -> Option<Vec<Word>> {
+    words.iter().map(|w| Sym::get(w).map(Word::Word)).collect()
+}
+
+/// The heaviest first, then alphabetically.
+fn by_weight(weights: HashMap<Word, usize>) -> Vec<(Word, usize)> {
+    let mut weights: Vec<_> = weights.into_iter().collect();
+    weights.sort_by_cached_key(|&(word, weight)| (std::cmp::Reverse(weight), word.to_string()));
+    weights
+}
+
+/// What's in a model, see `Markov::stats`.
+#[derive(Debug)]
+pub struct Stats {
+    pub entries: usize,
+    /// Every transition's weight added up, so about how many words it's learned
+    pub total_weight: usize,
+    pub distinct_words: usize,
+    /// What sentences start with most and how often, most first
+    pub top_starts: Vec<(Word, usize)>,
+    /// The words looked back at, what came after them and how often, most first
+    pub top_transitions: Vec<(Vec<Word>, Word, usize)>,
+    /// In bits, how unsure the model is of the next word, on average over everything it's seen
+    pub entropy: f64,
+    /// Roughly how many bytes the model takes in memory, with each of its words counted once
+    pub memory: usize,
+}
+
+/// Where a walk through a model is, so it can go on later, even after a restart, instead of
+/// starting over.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChainState {
+    /// The last words of the walk, as many as it looks back at
+    cur_words: Vec<Word>,
+    /// What's been generated, for the biases
+    so_far: Vec<String>,
+    /// Seeds the RNG the walk goes on with
+    seed: u64,
+}
+
+impl ChainState {
+    /// A walk that hasn't started.
+    pub fn new(rng: &mut impl Rng) -> Self {
+        ChainState::seeded(rng.gen())
+    }
+
+    /// A walk that hasn't started, which goes the same way every time from `seed`.
+    pub fn seeded(seed: u64) -> Self {
+        ChainState {
+            cur_words: Vec::new(),
+            so_far: Vec::new(),
+            seed,
+        }
+    }
+
+    /// A walk going on from `context`, like one `Markov::prompt_context` found.
+    pub fn from_context(context: Vec<Word>, rng: &mut impl Rng) -> Self {
+        ChainState {
+            cur_words: context,
+            so_far: Vec::new(),
+            seed: rng.gen(),
+        }
+    }
+
+    /// The words the walk is going on from, without starts, for saying before what it generates.
+    pub fn context_words(&self) -> Vec<String> {
+        self.cur_words
+            .iter()
+            .filter_map(|w| match w {
+                Word::Word(w) => Some(w.to_string()),
+                _ => None,
             })
             .collect()
     }
+
+    /// Makes the walk go a different way from here, for rerolls.
+    pub fn reseed(&mut self, rng: &mut impl Rng) {
+        self.seed = rng.gen();
+    }
+
+    /// Where a chain is now. One that reached the end of a sentence goes on with the next.
+    fn of(cur_words: &[Word], so_far: &[String], rng: &mut impl Rng) -> Self {
+        let cur_words = match cur_words.last() {
+            Some(Word::End) => Vec::new(),
+            _ => cur_words.to_vec(),
+        };
+        ChainState {
+            cur_words,
+            so_far: so_far.to_vec(),
+            seed: rng.gen(),
+        }
+    }
+}
+
+/// Bounds on what a chain generates, so a chain that loops can't run on forever and one that ends
+/// right away gets another go.
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct GenerationConfig {
+    /// Words a sentence is cut off at
+    #[serde(default = "default_max_tokens")]
+    pub max_tokens: usize,
+    /// Words a sentence needs, or it's generated again
+    #[serde(default = "default_min_tokens")]
+    pub min_tokens: usize,
+    /// How many more times a sentence that's too short is generated, before the longest is taken
+    #[serde(default = "default_max_retries")]
+    pub max_retries: usize,
+    #[serde(default = "default_temperature")]
+    pub temperature: f64,
+    /// How many sentences are generated, one after another, before the chain ends
+    #[serde(default = "default_sentences")]
+    pub sentences: usize,
+}
+
+impl Default for GenerationConfig {
+    fn default() -> Self {
+        GenerationConfig {
+            max_tokens: default_max_tokens(),
+            min_tokens: default_min_tokens(),
+            max_retries: default_max_retries(),
+            temperature: default_temperature(),
+            sentences: default_sentences(),
+        }
+    }
+}
+
+/// Comfortably under Discord's 2000 characters, for most words.
+fn default_max_tokens() -> usize {
+    200
+}
+
+fn default_min_tokens() -> usize {
+    1
+}
+
+fn default_max_retries() -> usize {
+    4
+}
+
+fn default_temperature() -> f64 {
+    1.0
+}
+
+fn default_sentences() -> usize {
+    1
+}
+
+/// How models forget what they saw long ago.
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct DecayConfig {
+    /// What every weight is multiplied by each time, like 0.9
+    pub factor: f64,
+    /// Transitions with less weight than this afterwards are forgotten
+    #[serde(default = "default_min_weight")]
+    pub min_weight: usize,
+    #[serde(default = "default_decay_days")]
+    pub every_days: u64,
+}
+
+fn default_min_weight() -> usize {
+    1
+}
+
+fn default_decay_days() -> u64 {
+    7
+}
+
+/// One fingerprint for several models together, whatever order they come in.
+pub fn combined_fingerprint<'a>(fingerprints: impl IntoIterator<Item = &'a str>) -> String {
+    let mut fingerprints: Vec<_> = fingerprints.into_iter().collect();
+    fingerprints.sort_unstable();
+    short_hex(blake3::hash(fingerprints.join(",").as_bytes()))
+}
+
+fn short_hex(hash: blake3::Hash) -> String {
+    String::from(&hash.to_hex()[..16])
+}
+
+/// How the next word is picked out of the ones that could follow.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Sampling {
+    /// In proportion to their weights
+    #[default]
+    Weighted,
+    /// Always the likeliest
+    Greedy,
+    /// Out of the likeliest this many
+    TopK(usize),
+    /// Out of the likeliest that make up this fraction of the weight
+    TopP(f64),
+}
+
+impl Sampling {
+    /// Reads `weighted`, `greedy`, `top-k N` or `top-p P`.
+    pub fn parse(values: &[&str]) -> Option<Self> {
+        match values {
+            ["weighted"] => Some(Sampling::Weighted),
+            ["greedy"] => Some(Sampling::Greedy),
+            ["top-k", k] => k.parse().ok().filter(|&k| k > 0).map(Sampling::TopK),
+            ["top-p", p] => p
+                .parse()
+                .ok()
+                .filter(|p| (0.0..=1.0).contains(p) && *p > 0.0)
+                .map(Sampling::TopP),
+            _ => None,
+        }
+    }
+
+    /// Zeroes the weights that aren't in the running. Ties with the last one in are kept.
+    fn narrow(self, weights: &mut [f64]) {
+        if weights.is_empty() {
+            return;
+        }
+        let mut sorted = weights.to_vec();
+        sorted.sort_by(|a, b| b.total_cmp(a));
+        let cutoff = match self {
+            Sampling::Weighted => return,
+            Sampling::Greedy => sorted[0],
+            Sampling::TopK(k) => sorted[k.min(sorted.len()) - 1],
+            Sampling::TopP(p) => {
+                let wanted = p * sorted.iter().sum::<f64>();
+                let mut sum = 0.0;
+                *sorted
+                    .iter()
+                    .find(|&&w| {
+                        sum += w;
+                        sum >= wanted
+                    })
+                    .unwrap_or(&sorted[sorted.len() - 1])
+            }
+        };
+        for w in weights {
+            if *w < cutoff {
+                *w = 0.0;
+            }
+        }
+    }
+}
+
+impl fmt::Display for Sampling {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Sampling::Weighted => write!(f, "weighted"),
+            Sampling::Greedy => write!(f, "greedy"),
+            Sampling::TopK(k) => write!(f, "top-k {}", k),
+            Sampling::TopP(p) => write!(f, "top-p {}", p),
+        }
+    }
 }
 
+/// Where a walk was, to start a retry from.
+type Position = (Vec<Word>, Vec<String>);
+
 pub struct Chain<'a, R> {
-    entries: &'a HashMap<WordArray, Entry>,
-    cur_words: WordArray,
+    entries: &'a Entries,
+    cur_words: Vec<Word>,
     rng: R,
+    temperature: f64,
+    sampling: Sampling,
+    biases: Vec<Box<dyn Bias>>,
+    /// What's been generated, kept for the biases
+    so_far: Vec<String>,
+    config: Option<GenerationConfig>,
+    /// Words generated since the chain last started a sentence, or paragraph when `config` wants
+    /// several sentences
+    taken: usize,
+    /// Sentences ended so far, when `config` wants more than one
+    sentences: usize,
+}
+
+impl<R> Chain<'_, R> {
+    /// Below 1 sticks to the likeliest words, above 1 gives unlikely ones more of a chance.
+    pub fn temperature(mut self, temperature: f64) -> Self {
+        self.temperature = temperature;
+        self
+    }
+
+    pub fn sampling(mut self, sampling: Sampling) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    /// Bounds sentences by `config`, and generates at its temperature.
+    pub fn config(mut self, config: GenerationConfig) -> Self {
+        self.temperature = config.temperature;
+        self.config = Some(config);
+        self
+    }
+
+    /// Applies `biases` on top of any already added, at every step.
+    pub fn biases(mut self, biases: impl IntoIterator<Item = Box<dyn Bias>>) -> Self {
+        self.biases.extend(biases);
+        self
+    }
+}
+
+impl<R: Rng + SeedableRng> Chain<'_, R> {
+    /// Where the chain is now. It goes on from the seed the state keeps, so it walks the same way
+    /// `Markov::resume` would from the state.
+    pub fn state(&mut self) -> ChainState {
+        let state = ChainState::of(&self.cur_words, &self.so_far, &mut self.rng);
+        self.rng = R::seed_from_u64(state.seed);
+        state
+    }
+}
+
+impl<R: Rng> Chain<'_, R> {
+    /// One sentence, or as many as `config` wants, within its bounds if there are any.
+    pub fn sentence(&mut self) -> Vec<String> {
+        let config = self.config.unwrap_or_default();
+        bounded(
+            self,
+            &config,
+            |chain| (chain.cur_words.clone(), chain.so_far.clone()),
+            |chain, (cur_words, so_far)| {
+                chain.cur_words = cur_words;
+                chain.so_far = so_far;
+                chain.taken = 0;
+                chain.sentences = 0;
+            },
+        )
+    }
 }
 
 impl<R: Rng> Iterator for Chain<'_, R> {
     type Item = String;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if cut_off(self.config.as_ref(), &mut self.taken, &mut self.cur_words) {
+            return None;
+        }
         let cur_entry = self.entries.get(&self.cur_words)?;
-        let word = cur_entry.get_random(&mut self.rng);
+        let word = cur_entry.get_random_at(
+            &mut self.rng,
+            self.temperature,
+            self.sampling,
+            &self.biases,
+            &self.so_far,
+        );
         eprintln!("got {:?} looking after {:?}", word, self.cur_words);
-        self.cur_words[0] = std::mem::replace(&mut self.cur_words[1], Word::End);
-        self.cur_words[1] = word.clone();
+        advance(&mut self.cur_words, word);
         match word {
-            Word::Word(w) => Some(w),
+            Word::Word(w) => {
+                let w = w.to_string();
+                if !self.biases.is_empty() {
+                    self.so_far.push(w.clone());
+                }
+                Some(w)
+            }
+            Word::End
+                if next_sentence(
+                    self.config.as_ref(),
+                    &mut self.sentences,
+                    &mut self.cur_words,
+                ) =>
+            {
+                self.next()
+            }
+            Word::End => None,
+            Word::Start => unreachable!(),
+        }
+    }
+}
+
+pub struct BlendedChain<'a, R> {
+    /// Each model's entries, order and weight
+    models: Vec<(&'a Entries, usize, f64)>,
+    /// As many words as the highest order looks back at
+    cur_words: Vec<Word>,
+    rng: R,
+    temperature: f64,
+    sampling: Sampling,
+    biases: Vec<Box<dyn Bias>>,
+    so_far: Vec<String>,
+    config: Option<GenerationConfig>,
+    taken: usize,
+    sentences: usize,
+}
+
+impl<R> BlendedChain<'_, R> {
+    /// Same as `Chain::temperature`.
+    pub fn temperature(mut self, temperature: f64) -> Self {
+        self.temperature = temperature;
+        self
+    }
+
+    /// Same as `Chain::sampling`.
+    pub fn sampling(mut self, sampling: Sampling) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    /// Same as `Chain::config`.
+    pub fn config(mut self, config: GenerationConfig) -> Self {
+        self.temperature = config.temperature;
+        self.config = Some(config);
+        self
+    }
+
+    /// Same as `Chain::biases`.
+    pub fn biases(mut self, biases: impl IntoIterator<Item = Box<dyn Bias>>) -> Self {
+        self.biases.extend(biases);
+        self
+    }
+}
+
+impl<R: Rng + SeedableRng> BlendedChain<'_, R> {
+    /// Same as `Chain::state`.
+    pub fn state(&mut self) -> ChainState {
+        let state = ChainState::of(&self.cur_words, &self.so_far, &mut self.rng);
+        self.rng = R::seed_from_u64(state.seed);
+        state
+    }
+}
+
+impl<R: Rng> BlendedChain<'_, R> {
+    /// Same as `Chain::sentence`.
+    pub fn sentence(&mut self) -> Vec<String> {
+        let config = self.config.unwrap_or_default();
+        bounded(
+            self,
+            &config,
+            |chain| (chain.cur_words.clone(), chain.so_far.clone()),
+            |chain, (cur_words, so_far)| {
+                chain.cur_words = cur_words;
+                chain.so_far = so_far;
+                chain.taken = 0;
+                chain.sentences = 0;
+            },
+        )
+    }
+}
+
+impl<R: Rng> Iterator for BlendedChain<'_, R> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if cut_off(self.config.as_ref(), &mut self.taken, &mut self.cur_words) {
+            return None;
+        }
+        let len = self.cur_words.len();
+        let candidates: Vec<_> = self
+            .models
+            .iter()
+            .filter_map(|(entries, order, w)| {
+                entries.get(&self.cur_words[len - order..]).map(|e| (e, *w))
+            })
+            .collect();
+        let dist = WeightedIndex::new(candidates.iter().map(|(_, w)| *w)).ok()?;
+        let word = candidates[dist.sample(&mut self.rng)].0.get_random_at(
+            &mut self.rng,
+            self.temperature,
+            self.sampling,
+            &self.biases,
+            &self.so_far,
+        );
+        advance(&mut self.cur_words, word);
+        match word {
+            Word::Word(w) => {
+                let w = w.to_string();
+                if !self.biases.is_empty() {
+                    self.so_far.push(w.clone());
+                }
+                Some(w)
+            }
+            Word::End
+                if next_sentence(
+                    self.config.as_ref(),
+                    &mut self.sentences,
+                    &mut self.cur_words,
+                ) =>
+            {
+                self.next()
+            }
             Word::End => None,
             Word::Start => unreachable!(),
         }
     }
 }
+
+/// Whether a chain has generated all `config` lets it, counting another word if not. One that has
+/// is left at the end of its sentence, so it starts a new one when it goes on.
+fn cut_off(
+    config: Option<&GenerationConfig>,
+    taken: &mut usize,
+    cur_words: &mut Vec<Word>,
+) -> bool {
+    match config {
+        Some(config) if *taken >= config.max_tokens => {
+            advance(cur_words, Word::End);
+            true
+        }
+        _ => {
+            *taken += 1;
+            false
+        }
+    }
+}
+
+/// Whether a chain that just ended a sentence goes on with another one, as `config` wants it to
+/// until it has enough. If it does, it's taken back to the start.
+fn next_sentence(
+    config: Option<&GenerationConfig>,
+    sentences: &mut usize,
+    cur_words: &mut [Word],
+) -> bool {
+    *sentences += 1;
+    match config {
+        Some(config) if *sentences < config.sentences => {
+            cur_words.fill(Word::Start);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Generates a sentence with `chain`, and again from the same place while it's shorter than
+/// `config` wants, keeping the longest. `chain` is left where that one ended.
+fn bounded<C: Iterator<Item = String>>(
+    chain: &mut C,
+    config: &GenerationConfig,
+    save: impl Fn(&C) -> Position,
+    restore: impl Fn(&mut C, Position),
+) -> Vec<String> {
+    let start = save(chain);
+    let mut best: Option<(Vec<String>, Position)> = None;
+    for attempt in 0..=config.max_retries {
+        if attempt > 0 {
+            restore(chain, start.clone());
+        }
+        let words: Vec<_> = chain.by_ref().collect();
+        let enough = words.len() >= config.min_tokens;
+        if best.as_ref().is_none_or(|(b, _)| words.len() > b.len()) {
+            best = Some((words, save(chain)));
+        }
+        if enough {
+            break;
+        }
+    }
+    let (words, end) = best.expect("there's always a first attempt");
+    restore(chain, end);
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(text: &str) -> Vec<String> {
+        text.split(' ').map(String::from).collect()
+    }
+
+    fn word(w: &str) -> Word {
+        Word::from(String::from(w))
+    }
+
+    fn weights(markov: &Markov, index: &[Word]) -> Vec<(Word, usize)> {
+        let mut pairs = markov.entry(index).unwrap().weight_pairs.clone();
+        pairs.sort_by_key(|(w, _)| w.to_string());
+        pairs
+    }
+
+    #[test]
+    fn insert_counts_transitions() {
+        let mut markov = Markov::with_order(1);
+        markov.insert(vec![word("a")], word("b"));
+        markov.insert(vec![word("a")], word("b"));
+        markov.insert(vec![word("a")], Word::End);
+        assert_eq!(markov.entry_count(), 1);
+        assert_eq!(
+            weights(&markov, &[word("a")]),
+            [(Word::End, 1), (word("b"), 2)]
+        );
+    }
+
+    #[test]
+    fn insert_sequence_runs_from_start_to_end() {
+        let mut markov = Markov::with_order(2);
+        markov.insert_sequence(words("a b"));
+        assert_eq!(markov.entry_count(), 3);
+        assert_eq!(
+            weights(&markov, &[Word::Start, Word::Start]),
+            [(word("a"), 1)]
+        );
+        assert_eq!(weights(&markov, &[word("a"), word("b")]), [(Word::End, 1)]);
+    }
+
+    #[test]
+    fn generates_the_only_sentence_it_knows() {
+        let mut markov = Markov::new();
+        let sentence = words("the only thing it ever heard");
+        markov.insert_sequence(sentence.clone());
+        for seed in 0..20 {
+            let generated: Vec<_> = markov.generate_with_seed(seed).collect();
+            assert_eq!(generated, sentence);
+        }
+    }
+
+    #[test]
+    fn generates_nothing_from_an_empty_model() {
+        assert_eq!(Markov::new().generate_with_seed(1).next(), None);
+    }
+
+    #[test]
+    fn remove_sequence_undoes_insert_sequence() {
+        let mut markov = Markov::new();
+        markov.insert_sequence(words("it stays here"));
+        markov.insert_sequence(words("it goes away"));
+        let removed = markov.remove_sequence(words("it goes away"));
+        assert_eq!(removed, 4);
+        let mut fresh = Markov::new();
+        fresh.insert_sequence(words("it stays here"));
+        assert_eq!(markov.fingerprint(), fresh.fingerprint());
+        assert_eq!(markov.remove_sequence(words("it stays here")), 4);
+        assert_eq!(markov.entry_count(), 0);
+    }
+
+    #[test]
+    fn remove_sequence_ignores_what_was_never_learned() {
+        let mut markov = Markov::new();
+        markov.insert_sequence(words("the one"));
+        assert_eq!(markov.remove_sequence(words("the two")), 1);
+        assert_eq!(markov.remove_sequence(words("the two")), 0);
+    }
+
+    #[test]
+    fn merge_adds_weights_together() {
+        let mut a = Markov::new();
+        a.insert_sequence(words("same start"));
+        let mut b = Markov::new();
+        b.insert_sequence(words("same start"));
+        b.insert_sequence(words("other end"));
+        let added = a.merge(b).unwrap();
+        let mut both = Markov::new();
+        both.insert_sequence(words("same start"));
+        both.insert_sequence(words("same start"));
+        both.insert_sequence(words("other end"));
+        assert_eq!(added, 2);
+        assert_eq!(a.fingerprint(), both.fingerprint());
+    }
+
+    fn corpus() -> Markov {
+        let mut markov = Markov::new();
+        for sentence in [
+            "the cat sat on the mat",
+            "the cat ate the fish",
+            "the dog sat on the cat",
+            "a dog ate the mat",
+            "the fish sat on a dog",
+        ] {
+            markov.insert_sequence(words(sentence));
+        }
+        markov
+    }
+
+    #[test]
+    fn generates_the_same_from_the_same_seed() {
+        let markov = corpus();
+        for (seed, expected) in [
+            (1, "the cat ate the mat"),
+            (2, "the cat"),
+            (3, "the cat sat on a dog"),
+            (42, "the cat sat on the mat"),
+        ] {
+            let generated: Vec<_> = markov.generate_with_seed(seed).collect();
+            assert_eq!(generated.join(" "), expected, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn generate_with_seed_is_a_seeded_std_rng() {
+        let markov = corpus();
+        for seed in 0..20 {
+            let seeded: Vec<_> = markov.generate_with_seed(seed).collect();
+            let rng = StdRng::seed_from_u64(seed);
+            assert_eq!(seeded, markov.generate_sequence(rng).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn resumes_where_a_seeded_walk_left_off() {
+        let markov = corpus();
+        for seed in 0..50 {
+            let mut chain = markov.generate_with_seed(seed);
+            let _: Vec<_> = chain.by_ref().take(2).collect();
+            let state = chain.state();
+            let rest: Vec<_> = chain.collect();
+            assert_eq!(
+                markov.resume(&state).collect::<Vec<_>>(),
+                rest,
+                "seed {}",
+                seed
+            );
+        }
+    }
+
+    #[test]
+    fn resumes_where_a_blended_walk_left_off() {
+        let (a, b) = (corpus(), corpus());
+        let models = [(&a, 1.0), (&b, 2.0)];
+        for seed in 0..50 {
+            let mut chain = Markov::generate_blended(&models, StdRng::seed_from_u64(seed));
+            let _: Vec<_> = chain.by_ref().take(2).collect();
+            let state = chain.state();
+            let rest: Vec<_> = chain.collect();
+            let resumed: Vec<_> = Markov::resume_blended(&models, &state).collect();
+            assert_eq!(resumed, rest, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn merge_refuses_another_order() {
+        let mut a = Markov::with_order(1);
+        assert!(a.merge(Markov::with_order(3)).is_err());
+    }
+}
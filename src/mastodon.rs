@@ -0,0 +1,67 @@
+use anyhow::{bail, Result};
+use http::Request;
+use isahc::{HttpClient, ResponseExt};
+use serde::{Deserialize, Serialize};
+
+use crate::bot::types::Id;
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct MastodonConfig {
+    /// e.g. `https://botsin.space`
+    pub instance: String,
+    pub access_token: String,
+    #[serde(default = "default_interval_minutes")]
+    pub interval_minutes: u64,
+    /// When set, posts wait in this channel until an admin approves them.
+    #[serde(default)]
+    pub review_channel: Option<Id>,
+}
+
+fn default_interval_minutes() -> u64 {
+    6 * 60
+}
+
+pub const MAX_STATUS_CHARS: usize = 500;
+
+pub struct Mastodon {
+    http: HttpClient,
+    cfg: MastodonConfig,
+}
+
+impl Mastodon {
+    pub fn new(cfg: MastodonConfig) -> Result<Self> {
+        Ok(Mastodon {
+            http: HttpClient::new()?,
+            cfg,
+        })
+    }
+
+    pub fn config(&self) -> &MastodonConfig {
+        &self.cfg
+    }
+
+    pub async fn post_status(&self, status: &str) -> Result<()> {
+        #[derive(Serialize)]
+        struct NewStatus<'a> {
+            status: &'a str,
+        }
+        let request = Request::post(format!(
+            "{}/api/v1/statuses",
+            self.cfg.instance.trim_end_matches('/')
+        ))
+        .header("Authorization", format!("Bearer {}", self.cfg.access_token))
+        .header("Content-Type", "application/json")
+        .body(serde_json::to_vec(&NewStatus { status })?)?;
+
+        let mut response = self.http.send_async(request).await?;
+        if !response.status().is_success() {
+            bail!(
+                "{} from {}: {}",
+                response.status(),
+                self.cfg.instance,
+                response.text()?
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,236 @@
+use anyhow::{anyhow, bail, Result};
+use async_io::Timer;
+use futures::channel::mpsc::{unbounded, UnboundedSender};
+use futures::prelude::*;
+use futures::{select, stream};
+use http::Request;
+use isahc::{HttpClient, ResponseExt};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::thread;
+use std::time::Duration;
+use url::Url;
+
+use crate::bridge::BridgeMessage;
+use crate::store::Scope;
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct MatrixConfig {
+    /// e.g. `https://matrix.org`
+    pub homeserver: String,
+    pub access_token: String,
+    /// The bot's own user, so it doesn't learn from itself, e.g. `@taco:matrix.org`
+    pub user_id: String,
+    pub rooms: Vec<String>,
+    #[serde(default)]
+    pub share_global: bool,
+    /// Rooms and users never learned from, like `channel_blacklist` and `eg!filter user`
+    #[serde(default)]
+    pub blacklist: Vec<String>,
+}
+
+impl MatrixConfig {
+    fn scope(&self, room: &str) -> Scope {
+        if self.share_global {
+            Scope::Global
+        } else {
+            let host = self
+                .homeserver
+                .trim_start_matches("https://")
+                .trim_start_matches("http://")
+                .trim_end_matches('/');
+            Scope::Bridge(format!("{}/{}", host, room))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Sync {
+    next_batch: String,
+    #[serde(default)]
+    rooms: SyncRooms,
+}
+
+#[derive(Deserialize, Default)]
+struct SyncRooms {
+    #[serde(default)]
+    join: HashMap<String, JoinedRoom>,
+}
+
+#[derive(Deserialize)]
+struct JoinedRoom {
+    timeline: Timeline,
+}
+
+#[derive(Deserialize)]
+struct Timeline {
+    events: Vec<RoomEvent>,
+}
+
+#[derive(Deserialize)]
+struct RoomEvent {
+    #[serde(rename = "type")]
+    kind: String,
+    sender: String,
+    #[serde(default)]
+    content: MessageContent,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct MessageContent {
+    #[serde(default)]
+    msgtype: String,
+    #[serde(default)]
+    body: String,
+}
+
+/// Runs the bridge on its own thread, reconnecting whenever the homeserver stops answering.
+pub fn spawn(cfg: MatrixConfig, events: UnboundedSender<BridgeMessage>) {
+    const RECONNECT_DELAY: Duration = Duration::from_secs(30);
+    thread::spawn(move || loop {
+        if let Err(e) = async_io::block_on(run(&cfg, &events)) {
+            eprintln!("matrix {}: {}", cfg.homeserver, e);
+        }
+        if events.is_closed() {
+            break;
+        }
+        async_io::block_on(Timer::after(RECONNECT_DELAY));
+    });
+}
+
+struct Session<'a> {
+    http: HttpClient,
+    cfg: &'a MatrixConfig,
+    txn: Cell<u64>,
+}
+
+impl Session<'_> {
+    async fn request<T: DeserializeOwned>(&self, request: Request<Vec<u8>>) -> Result<T> {
+        let mut response = self.http.send_async(request).await?;
+        if !response.status().is_success() {
+            bail!(
+                "{} from homeserver: {}",
+                response.status(),
+                response.text()?
+            );
+        }
+        Ok(response.json()?)
+    }
+
+    /// The client API's `path`, with every segment and `query` percent-encoded, since room IDs
+    /// and sync tokens can have anything in them.
+    fn url(&self, path: &[&str], query: &[(&str, &str)]) -> Result<String> {
+        let base = format!(
+            "{}/_matrix/client/v3",
+            self.cfg.homeserver.trim_end_matches('/')
+        );
+        let mut url = Url::parse(&base)?;
+        url.path_segments_mut()
+            .map_err(|()| anyhow!("{} isn't a homeserver URL", self.cfg.homeserver))?
+            .extend(path);
+        if !query.is_empty() {
+            url.query_pairs_mut().extend_pairs(query);
+        }
+        Ok(url.to_string())
+    }
+
+    async fn sync(&self, since: Option<&str>) -> Result<Sync> {
+        let url = match since {
+            Some(since) => self.url(&["sync"], &[("timeout", "30000"), ("since", since)])?,
+            // the first sync only needs a position to start from, not the backlog
+            None => self.url(
+                &["sync"],
+                &[("filter", r#"{"room":{"timeline":{"limit":0}}}"#)],
+            )?,
+        };
+        self.request(
+            Request::get(url)
+                .header("Authorization", format!("Bearer {}", self.cfg.access_token))
+                .body(Vec::new())?,
+        )
+        .await
+    }
+
+    async fn send(&self, room: &str, body: String) -> Result<()> {
+        #[derive(Deserialize)]
+        struct Sent {}
+        self.txn.set(self.txn.get() + 1);
+        let txn = format!("taco{}-{}", crate::schedule::now_millis(), self.txn.get());
+        let url = self.url(&["rooms", room, "send", "m.room.message", &txn], &[])?;
+        let content = MessageContent {
+            msgtype: String::from("m.text"),
+            body,
+        };
+        self.request::<Sent>(
+            Request::put(url)
+                .header("Authorization", format!("Bearer {}", self.cfg.access_token))
+                .header("Content-Type", "application/json")
+                .body(serde_json::to_vec(&content)?)?,
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+async fn run(cfg: &MatrixConfig, events: &UnboundedSender<BridgeMessage>) -> Result<()> {
+    let session = Session {
+        http: HttpClient::new()?,
+        cfg,
+        txn: Cell::new(0),
+    };
+
+    let mut replies = Vec::new();
+    let mut senders = HashMap::new();
+    for room in &cfg.rooms {
+        let (tx, rx) = unbounded::<String>();
+        senders.insert(room.clone(), tx);
+        let room = room.clone();
+        replies.push(rx.map(move |text| (room.clone(), text)));
+    }
+    let mut replies = stream::select_all(replies);
+
+    let mut since = session.sync(None).await?.next_batch;
+    loop {
+        let sync = {
+            let mut sync = Box::pin(session.sync(Some(&since)).fuse());
+            loop {
+                select! {
+                    s = sync => break s?,
+                    reply = replies.select_next_some() => {
+                        let (room, text) = reply;
+                        session.send(&room, text).await?;
+                    }
+                }
+            }
+        };
+        since = sync.next_batch;
+
+        for (room, joined) in sync.rooms.join {
+            let reply = match senders.get(&room) {
+                Some(r) => r,
+                None => continue,
+            };
+            for event in joined.timeline.events {
+                if event.kind != "m.room.message"
+                    || event.content.msgtype != "m.text"
+                    || event.sender == cfg.user_id
+                {
+                    continue;
+                }
+                let learn = !cfg
+                    .blacklist
+                    .iter()
+                    .any(|b| *b == room || *b == event.sender);
+                events.unbounded_send(BridgeMessage {
+                    scope: cfg.scope(&room),
+                    author: event.sender,
+                    text: event.content.body,
+                    learn,
+                    reply: reply.clone(),
+                })?;
+            }
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! Scores generated text with an outside moderation service before it's sent, so the bot can
+//! reroll or stay quiet instead of saying something awful.
+
+use anyhow::{anyhow, bail, Result};
+use async_io::Timer;
+use futures::{Future, FutureExt};
+use http::Request;
+use isahc::config::Configurable;
+use isahc::{HttpClient, ResponseExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::time::Duration;
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct ModerationConfig {
+    /// Gets `{"text": "..."}` POSTed to it and answers with a JSON object of scores from 0 to 1,
+    /// like a detoxify server does.
+    pub url: String,
+    /// Which score in the response to look at
+    #[serde(default = "default_field")]
+    pub field: String,
+    /// Text scoring above this is held back
+    #[serde(default = "default_threshold")]
+    pub threshold: f64,
+    /// How long a check may take before it counts as failed
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Send text anyway when the check fails, instead of holding it back
+    #[serde(default)]
+    pub fail_open: bool,
+    /// How many more times to generate after something is held back
+    #[serde(default = "default_rerolls")]
+    pub rerolls: usize,
+}
+
+fn default_field() -> String {
+    String::from("toxicity")
+}
+
+fn default_threshold() -> f64 {
+    0.7
+}
+
+fn default_timeout_ms() -> u64 {
+    500
+}
+
+fn default_rerolls() -> usize {
+    3
+}
+
+pub type ScoreFuture<'a> = Pin<Box<dyn Future<Output = Result<f64>> + 'a>>;
+
+/// Something that can tell how bad a piece of text is.
+pub trait Moderator {
+    /// From 0 (fine) to 1 (as bad as it gets).
+    fn score<'a>(&'a self, text: &'a str) -> ScoreFuture<'a>;
+}
+
+/// Asks a moderation service over HTTP.
+pub struct HttpModerator {
+    http: HttpClient,
+    url: String,
+    field: String,
+}
+
+impl HttpModerator {
+    pub fn new(cfg: &ModerationConfig) -> Result<Self> {
+        Ok(HttpModerator {
+            http: HttpClient::builder()
+                .timeout(Duration::from_millis(cfg.timeout_ms))
+                .build()?,
+            url: cfg.url.clone(),
+            field: cfg.field.clone(),
+        })
+    }
+}
+
+impl Moderator for HttpModerator {
+    fn score<'a>(&'a self, text: &'a str) -> ScoreFuture<'a> {
+        #[derive(Serialize)]
+        struct Check<'a> {
+            text: &'a str,
+        }
+        Box::pin(async move {
+            let request = Request::post(self.url.as_str())
+                .header("Content-Type", "application/json")
+                .body(serde_json::to_vec(&Check { text })?)?;
+            let mut response = self.http.send_async(request).await?;
+            if !response.status().is_success() {
+                bail!("{} from {}", response.status(), self.url);
+            }
+            let scores: HashMap<String, f64> = response.json()?;
+            match scores.get(&self.field) {
+                Some(&score) => Ok(score),
+                None => bail!("{} didn't give a `{}` score", self.url, self.field),
+            }
+        })
+    }
+}
+
+pub struct Moderation {
+    moderator: Box<dyn Moderator>,
+    cfg: ModerationConfig,
+}
+
+impl Moderation {
+    pub fn new(cfg: ModerationConfig) -> Result<Self> {
+        Ok(Moderation {
+            moderator: Box::new(HttpModerator::new(&cfg)?),
+            cfg,
+        })
+    }
+
+    pub fn rerolls(&self) -> usize {
+        self.cfg.rerolls
+    }
+
+    /// Whether `text` is fine to send.
+    pub async fn allows(&self, text: &str) -> bool {
+        let timeout = Duration::from_millis(self.cfg.timeout_ms);
+        let score = futures::select! {
+            score = self.moderator.score(text).fuse() => score,
+            _ = Timer::after(timeout).fuse() => {
+                Err(anyhow!("took longer than {}ms", self.cfg.timeout_ms))
+            }
+        };
+        match score {
+            Ok(score) => score <= self.cfg.threshold,
+            Err(e) => {
+                eprintln!("moderation: {}", e);
+                self.cfg.fail_open
+            }
+        }
+    }
+}
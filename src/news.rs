@@ -0,0 +1,234 @@
+use anyhow::{bail, Result};
+use async_io::Timer;
+use futures::channel::mpsc::{unbounded, UnboundedSender};
+use isahc::config::Configurable;
+use isahc::{HttpClient, ResponseExt};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::thread;
+use std::time::Duration;
+use url::Url;
+
+use crate::bridge::BridgeMessage;
+use crate::persist::{load_or_default, save_to};
+use crate::store::Scope;
+
+pub const SCOPE_NAME: &str = "news";
+
+const USER_AGENT: &str = "taco_bot (+https://github.com/theInkSquid/discord-bot)";
+const POLITENESS_DELAY: Duration = Duration::from_secs(5);
+const SEEN_PATH: &str = "news_seen.dat";
+const SEEN_CAPACITY: usize = 10_000;
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct NewsConfig {
+    /// RSS/Atom feeds or plain web pages
+    pub sources: Vec<String>,
+    #[serde(default = "default_interval_minutes")]
+    pub interval_minutes: u64,
+}
+
+fn default_interval_minutes() -> u64 {
+    60
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Seen {
+    order: VecDeque<String>,
+    set: HashSet<String>,
+}
+
+impl Seen {
+    /// Returns `true` the first time `text` is seen.
+    fn insert(&mut self, text: &str) -> bool {
+        if !self.set.insert(String::from(text)) {
+            return false;
+        }
+        self.order.push_back(String::from(text));
+        if self.order.len() > SEEN_CAPACITY {
+            if let Some(old) = self.order.pop_front() {
+                self.set.remove(&old);
+            }
+        }
+        true
+    }
+}
+
+pub fn spawn(cfg: NewsConfig, events: UnboundedSender<BridgeMessage>) {
+    thread::spawn(move || {
+        let http = match HttpClient::builder()
+            .default_header("User-Agent", USER_AGENT)
+            .timeout(Duration::from_secs(30))
+            .build()
+        {
+            Ok(h) => h,
+            Err(e) => return eprintln!("news: {}", e),
+        };
+        let mut seen: Seen = load_or_default(SEEN_PATH);
+        let mut robots = HashMap::new();
+        while !events.is_closed() {
+            async_io::block_on(async {
+                for source in &cfg.sources {
+                    if let Err(e) = fetch(&http, source, &mut robots, &mut seen, &events).await {
+                        eprintln!("news {}: {}", source, e);
+                    }
+                    Timer::after(POLITENESS_DELAY).await;
+                }
+                if let Err(e) = save_to(SEEN_PATH, &seen) {
+                    eprintln!("news: {}", e);
+                }
+                robots.clear();
+                Timer::after(Duration::from_secs(cfg.interval_minutes * 60)).await;
+            });
+        }
+    });
+}
+
+async fn fetch(
+    http: &HttpClient,
+    source: &str,
+    robots: &mut HashMap<String, Vec<String>>,
+    seen: &mut Seen,
+    events: &UnboundedSender<BridgeMessage>,
+) -> Result<()> {
+    let url = Url::parse(source)?;
+    let origin = url.origin().ascii_serialization();
+    if !robots.contains_key(&origin) {
+        let rules = match http.get_async(format!("{}/robots.txt", origin)).await {
+            Ok(mut r) if r.status().is_success() => disallowed_paths(&r.text()?),
+            _ => Vec::new(),
+        };
+        robots.insert(origin.clone(), rules);
+        Timer::after(POLITENESS_DELAY).await;
+    }
+    if robots[&origin]
+        .iter()
+        .any(|p| url.path().starts_with(p.as_str()))
+    {
+        bail!("disallowed by robots.txt");
+    }
+
+    let mut response = http.get_async(source).await?;
+    if !response.status().is_success() {
+        bail!("{}", response.status());
+    }
+    let body = response.text()?;
+
+    // nobody replies to a news feed
+    let (reply, _) = unbounded();
+    for text in extract_text(&body) {
+        if seen.insert(&text) {
+            events.unbounded_send(BridgeMessage {
+                scope: Scope::Corpus(String::from(SCOPE_NAME)),
+                author: String::from(source),
+                text,
+                learn: true,
+                reply: reply.clone(),
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// `Disallow` rules that apply to every user agent.
+fn disallowed_paths(robots_txt: &str) -> Vec<String> {
+    let mut applies = false;
+    let mut paths = Vec::new();
+    for line in robots_txt.lines() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let (key, value) = match line.find(':') {
+            Some(i) => (line[..i].trim().to_ascii_lowercase(), line[i + 1..].trim()),
+            None => continue,
+        };
+        match key.as_str() {
+            "user-agent" => applies = value == "*" || USER_AGENT.starts_with(value),
+            "disallow" if applies && !value.is_empty() => paths.push(String::from(value)),
+            _ => (),
+        }
+    }
+    paths
+}
+
+/// Titles and summaries from RSS/Atom items, or paragraphs from anything else.
+fn extract_text(body: &str) -> Vec<String> {
+    let items: Vec<_> = ["item", "entry"]
+        .iter()
+        .flat_map(|tag| elements(body, tag))
+        .collect();
+    let texts: Vec<_> = if items.is_empty() {
+        elements(body, "p").collect()
+    } else {
+        items
+            .into_iter()
+            .flat_map(|item| {
+                ["title", "description", "summary"]
+                    .iter()
+                    .flat_map(move |tag| elements(item, tag))
+            })
+            .collect()
+    };
+    texts
+        .into_iter()
+        .map(plain_text)
+        // bridge messages starting with the prefix are treated as commands
+        .filter(|t| t.split_whitespace().count() >= 3 && !t.starts_with("eg!"))
+        .collect()
+}
+
+/// Contents of every `<tag ...>...</tag>` in `doc`, not handling nesting of the same tag.
+fn elements<'a>(doc: &'a str, tag: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+    let open = format!("<{}", tag);
+    let close = format!("</{}>", tag);
+    let mut rest = doc;
+    std::iter::from_fn(move || loop {
+        let start = rest.find(&open)?;
+        let after = &rest[start + open.len()..];
+        // make sure `<p` didn't match `<pre`
+        if !after.starts_with(|c: char| c == '>' || c.is_whitespace() || c == '/') {
+            rest = after;
+            continue;
+        }
+        let content_start = after.find('>')? + 1;
+        let content = &after[content_start..];
+        let end = content.find(&close)?;
+        rest = &content[end + close.len()..];
+        return Some(&content[..end]);
+    })
+}
+
+fn plain_text(s: &str) -> String {
+    let s = s.trim();
+    let s = s
+        .strip_prefix("<![CDATA[")
+        .and_then(|s| s.strip_suffix("]]>"))
+        .unwrap_or(s);
+    // feeds often escape their HTML, so unescape before stripping tags
+    let s = decode_entities(s);
+    let mut out = String::with_capacity(s.len());
+    let mut in_tag = false;
+    for c in s.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                out.push(' ');
+            }
+            c if !in_tag => out.push(c),
+            _ => (),
+        }
+    }
+    decode_entities(&out)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn decode_entities(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
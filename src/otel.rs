@@ -0,0 +1,265 @@
+//! Exporting spans to an OpenTelemetry collector over OTLP/HTTP, as JSON, so the bot's latency
+//! shows up in the same tracing stack as everything else it runs next to.
+//!
+//! Each event the bot handles is a trace, with the same id as its correlation id in the logs (see
+//! `trace`): a span for the event, one for handling it, and ones for model operations and Discord
+//! API calls made along the way. Events are handled one at a time, so whatever span is open is the
+//! parent of the next one started. Without `otlp` configured, spans cost next to nothing.
+
+use anyhow::{bail, Result};
+use isahc::config::Configurable;
+use isahc::HttpClient;
+use rand::Rng;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::trace::Trace;
+
+/// Spans sent in one request at most
+const BATCH: usize = 512;
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct OtlpConfig {
+    /// The collector's traces endpoint, like `http://localhost:4318/v1/traces`
+    pub endpoint: String,
+    /// Sent with every request, for collectors that want a key
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+    /// How long spans wait to be sent with others
+    #[serde(default = "default_flush_seconds")]
+    pub flush_seconds: u64,
+}
+
+fn default_service_name() -> String {
+    String::from("taco_bot")
+}
+
+fn default_flush_seconds() -> u64 {
+    5
+}
+
+/// What a span is doing, as OTLP puts it.
+#[derive(Clone, Copy, Debug)]
+pub enum Kind {
+    Internal = 1,
+    Client = 3,
+    Consumer = 5,
+}
+
+/// The trace being handled and its open spans, innermost last.
+struct Context {
+    exporter: Option<Sender<Finished>>,
+    trace: Option<u128>,
+    open: Vec<u64>,
+}
+
+static CONTEXT: Mutex<Context> = Mutex::new(Context {
+    exporter: None,
+    trace: None,
+    open: Vec::new(),
+});
+
+/// Starts sending spans to the collector in `cfg`. Only the first call does anything.
+pub fn init(cfg: &OtlpConfig) -> Result<()> {
+    let mut context = CONTEXT.lock().unwrap();
+    if context.exporter.is_some() {
+        return Ok(());
+    }
+    let http = HttpClient::builder().timeout(TIMEOUT).build()?;
+    let (sender, spans) = mpsc::channel();
+    let cfg = cfg.clone();
+    thread::spawn(move || export(cfg, http, spans));
+    context.exporter = Some(sender);
+    Ok(())
+}
+
+/// A span, ended when it's dropped. Does nothing unless spans are being exported.
+pub struct Span {
+    open: Option<Finished>,
+}
+
+struct Finished {
+    trace: u128,
+    id: u64,
+    parent: Option<u64>,
+    name: String,
+    kind: Kind,
+    start: u128,
+    started: Instant,
+    duration: Duration,
+    attributes: Vec<(&'static str, String)>,
+    error: Option<String>,
+}
+
+impl Span {
+    /// The first span of `trace`, which everything until it ends is part of.
+    pub fn root(trace: &Trace, name: &str) -> Self {
+        Span::open(Some(trace.id()), name, Kind::Consumer)
+    }
+
+    /// A span inside whichever one is open.
+    pub fn start(name: &str) -> Self {
+        Span::open(None, name, Kind::Internal)
+    }
+
+    /// A call to another service inside whichever span is open.
+    pub fn client(name: &str) -> Self {
+        Span::open(None, name, Kind::Client)
+    }
+
+    fn open(root: Option<u128>, name: &str, kind: Kind) -> Self {
+        let mut context = CONTEXT.lock().unwrap();
+        if context.exporter.is_none() {
+            return Span { open: None };
+        }
+        if let Some(trace) = root {
+            context.trace = Some(trace);
+            context.open.clear();
+        }
+        let trace = match context.trace {
+            Some(trace) => trace,
+            None => return Span { open: None },
+        };
+        let id = rand::thread_rng().gen_range(1, u64::MAX);
+        let parent = context.open.last().copied();
+        context.open.push(id);
+        Span {
+            open: Some(Finished {
+                trace,
+                id,
+                parent,
+                name: String::from(name),
+                kind,
+                start: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_nanos(),
+                started: Instant::now(),
+                duration: Duration::default(),
+                attributes: Vec::new(),
+                error: None,
+            }),
+        }
+    }
+
+    pub fn attribute(&mut self, key: &'static str, value: impl ToString) {
+        if let Some(open) = &mut self.open {
+            open.attributes.push((key, value.to_string()));
+        }
+    }
+
+    /// Marks the span as having failed with `error`.
+    pub fn fail(&mut self, error: &anyhow::Error) {
+        if let Some(open) = &mut self.open {
+            open.error = Some(format!("{:#}", error));
+        }
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let mut span = match self.open.take() {
+            Some(span) => span,
+            None => return,
+        };
+        span.duration = span.started.elapsed();
+        let mut context = CONTEXT.lock().unwrap();
+        if let Some(at) = context.open.iter().rposition(|&id| id == span.id) {
+            context.open.truncate(at);
+        }
+        if span.parent.is_none() {
+            context.trace = None;
+        }
+        if let Some(exporter) = &context.exporter {
+            exporter.send(span).ok();
+        }
+    }
+}
+
+/// Sends spans as they come in, a batch at a time, until the bot stops.
+fn export(cfg: OtlpConfig, http: HttpClient, spans: Receiver<Finished>) {
+    let flush_every = Duration::from_secs(cfg.flush_seconds.max(1));
+    let mut batch = Vec::new();
+    let mut flushed = Instant::now();
+    loop {
+        let wait = flush_every.saturating_sub(flushed.elapsed());
+        let stopped = match spans.recv_timeout(wait) {
+            Ok(span) => {
+                batch.push(span);
+                false
+            }
+            Err(RecvTimeoutError::Timeout) => false,
+            Err(RecvTimeoutError::Disconnected) => true,
+        };
+        if batch.len() >= BATCH || flushed.elapsed() >= flush_every || stopped {
+            if !batch.is_empty() {
+                if let Err(e) = send(&cfg, &http, &batch) {
+                    eprintln!("otlp: dropped {} spans: {}", batch.len(), e);
+                }
+                batch.clear();
+            }
+            flushed = Instant::now();
+        }
+        if stopped {
+            return;
+        }
+    }
+}
+
+fn send(cfg: &OtlpConfig, http: &HttpClient, batch: &[Finished]) -> Result<()> {
+    let string = |value: &str| json!({ "stringValue": value });
+    let spans: Vec<_> = batch
+        .iter()
+        .map(|s| {
+            let attributes: Vec<_> = s
+                .attributes
+                .iter()
+                .map(|(key, value)| json!({ "key": key, "value": string(value) }))
+                .collect();
+            let status = match &s.error {
+                Some(message) => json!({ "code": 2, "message": message }),
+                None => json!({ "code": 0 }),
+            };
+            json!({
+                "traceId": format!("{:032x}", s.trace),
+                "spanId": format!("{:016x}", s.id),
+                "parentSpanId": s.parent.map(|p| format!("{:016x}", p)).unwrap_or_default(),
+                "name": s.name,
+                "kind": s.kind as u8,
+                "startTimeUnixNano": s.start.to_string(),
+                "endTimeUnixNano": (s.start + s.duration.as_nanos()).to_string(),
+                "attributes": attributes,
+                "status": status,
+            })
+        })
+        .collect();
+    let body = json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [{ "key": "service.name", "value": string(&cfg.service_name) }],
+            },
+            "scopeSpans": [{
+                "scope": { "name": "taco_bot", "version": env!("CARGO_PKG_VERSION") },
+                "spans": spans,
+            }],
+        }],
+    });
+    let mut request =
+        http::Request::post(cfg.endpoint.as_str()).header("Content-Type", "application/json");
+    for (name, value) in &cfg.headers {
+        request = request.header(name.as_str(), value.as_str());
+    }
+    let response = http.send(request.body(serde_json::to_vec(&body)?)?)?;
+    if !response.status().is_success() {
+        bail!("{} from the collector", response.status());
+    }
+    Ok(())
+}
@@ -0,0 +1,111 @@
+//! How long learning, generating and Discord API requests have taken over the last hour, for
+//! `eg!perf`. Kept in memory only, so it starts over with the bot.
+
+use std::collections::VecDeque;
+use std::fmt::{self, Display};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How far back timings are kept
+pub const WINDOW: Duration = Duration::from_secs(60 * 60);
+/// Timings kept for each operation at most, however busy the hour was
+const MAX_KEPT: usize = 100_000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Op {
+    Learn,
+    Generate,
+    Discord,
+}
+
+impl Op {
+    pub const ALL: [Op; 3] = [Op::Learn, Op::Generate, Op::Discord];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Op::Learn => "Learning",
+            Op::Generate => "Generating",
+            Op::Discord => "Discord API",
+        }
+    }
+}
+
+static TIMINGS: Mutex<[VecDeque<(Instant, Duration)>; 3]> =
+    Mutex::new([VecDeque::new(), VecDeque::new(), VecDeque::new()]);
+
+/// Times `op` until it's dropped.
+pub struct Timer {
+    op: Op,
+    started: Instant,
+}
+
+pub fn time(op: Op) -> Timer {
+    Timer {
+        op,
+        started: Instant::now(),
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        let now = Instant::now();
+        let mut timings = TIMINGS.lock().unwrap();
+        let timings = &mut timings[self.op as usize];
+        prune(timings, now);
+        if timings.len() >= MAX_KEPT {
+            timings.pop_front();
+        }
+        timings.push_back((now, now - self.started));
+    }
+}
+
+fn prune(timings: &mut VecDeque<(Instant, Duration)>, now: Instant) {
+    while timings
+        .front()
+        .is_some_and(|(at, _)| now.duration_since(*at) > WINDOW)
+    {
+        timings.pop_front();
+    }
+}
+
+/// Percentiles of how long `op` took over the last hour.
+#[derive(Debug)]
+pub struct Summary {
+    pub count: usize,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+pub fn summary(op: Op) -> Option<Summary> {
+    let mut timings = TIMINGS.lock().unwrap();
+    let timings = &mut timings[op as usize];
+    prune(timings, Instant::now());
+    let mut took: Vec<_> = timings.iter().map(|(_, took)| *took).collect();
+    took.sort();
+    let max = *took.last()?;
+    let at = |p: usize| took[(took.len() * p / 100).min(took.len() - 1)];
+    Some(Summary {
+        count: took.len(),
+        p50: at(50),
+        p95: at(95),
+        p99: at(99),
+        max,
+    })
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |d: Duration| format!("{:.1}ms", d.as_secs_f64() * 1000.0);
+        write!(
+            f,
+            "p50 {} · p95 {} · p99 {}\nmax {} over {}",
+            ms(self.p50),
+            ms(self.p95),
+            ms(self.p99),
+            ms(self.max),
+            self.count
+        )
+    }
+}
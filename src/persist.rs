@@ -0,0 +1,257 @@
+use anyhow::{anyhow, Result};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand::Rng;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::bot::types::Id;
+use crate::chunks;
+
+pub type Key = [u8; 32];
+
+/// Marks encrypted files, so ones written before encryption was turned on still load.
+const ENCRYPTED_MAGIC: &[u8] = b"taco-enc1";
+const NONCE_LEN: usize = 24;
+
+/// How many earlier versions of each file to keep when saving over it, see `keep_snapshots`
+static SNAPSHOTS: AtomicUsize = AtomicUsize::new(0);
+/// Whether snapshots are kept as manifests of shared chunks, see `dedup_snapshots`
+static DEDUP: AtomicBool = AtomicBool::new(false);
+
+/// Makes saving keep the last `count` versions of each file, as `name.1` (the newest) to
+/// `name.{count}`.
+pub fn keep_snapshots(count: usize) {
+    SNAPSHOTS.store(count, Ordering::Relaxed);
+}
+
+/// Makes snapshots saved from now on manifests of chunks in `chunks/`, which unchanged parts of
+/// files share, instead of whole copies.
+pub fn dedup_snapshots(on: bool) {
+    DEDUP.store(on, Ordering::Relaxed);
+}
+
+pub fn load_or_default<T: DeserializeOwned + Default>(path: impl AsRef<Path>) -> T {
+    let path = path.as_ref();
+    File::open(path)
+        .map_err(bincode::Error::from)
+        .and_then(bincode::deserialize_from)
+        .map_err(|e| {
+            eprintln!("{}: {}", path.display(), e);
+            e
+        })
+        .unwrap_or_default()
+}
+
+pub fn save_to(path: impl AsRef<Path>, value: &impl Serialize) -> Result<u64> {
+    write_atomically(path.as_ref(), &bincode::serialize(value)?)
+}
+
+/// Writes `bytes` to a temporary file next to `path` and renames it over `path`, so a crash
+/// leaves either the old file or the new one, never half of one.
+fn write_atomically(path: &Path, bytes: &[u8]) -> Result<u64> {
+    let temp = with_suffix(path, "tmp");
+    let mut file = File::create(&temp)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    rotate(path)?;
+    fs::rename(&temp, path)?;
+    Ok(bytes.len() as u64)
+}
+
+/// Moves `path`'s snapshots along one, dropping the oldest, and links the current file in as the
+/// newest, so `path` itself is there until it's renamed over.
+fn rotate(path: &Path) -> Result<()> {
+    let count = SNAPSHOTS.load(Ordering::Relaxed);
+    if count == 0 || !path.exists() {
+        return Ok(());
+    }
+    let dedup = DEDUP.load(Ordering::Relaxed);
+    if dedup && with_suffix(path, &count.to_string()).exists() {
+        chunks::dropped();
+    }
+    for n in (1..count).rev() {
+        let older = with_suffix(path, &n.to_string());
+        if older.exists() {
+            fs::rename(&older, with_suffix(path, &(n + 1).to_string()))?;
+        }
+    }
+    let newest = with_suffix(path, "1");
+    if newest.exists() {
+        fs::remove_file(&newest)?;
+    }
+    if dedup {
+        let (manifest, _) = chunks::store(&fs::read(path)?)?;
+        fs::write(&newest, manifest)?;
+        return Ok(());
+    }
+    // filesystems without hard links get a copy
+    if fs::hard_link(path, &newest).is_err() {
+        fs::copy(path, &newest)?;
+    }
+    Ok(())
+}
+
+/// `path` with `.{suffix}` after its extension.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", suffix));
+    PathBuf::from(name)
+}
+
+/// Like `load_or_default`, for files written by `save_encrypted_to` with the same key, see
+/// `load_sealed`.
+pub fn load_encrypted<T: DeserializeOwned + Default>(
+    path: impl AsRef<Path>,
+    key: &Key,
+) -> Result<T> {
+    load_sealed(path, Some(key))
+}
+
+pub fn save_encrypted_to(path: impl AsRef<Path>, value: &impl Serialize, key: &Key) -> Result<u64> {
+    let sealed = seal(value, key)
+        .map_err(|e| anyhow!("couldn't encrypt {}: {}", path.as_ref().display(), e))?;
+    write_atomically(path.as_ref(), &sealed)
+}
+
+/// `value` encrypted the way `save_encrypted_to` writes it, for sending elsewhere.
+pub fn seal(value: &impl Serialize, key: &Key) -> Result<Vec<u8>> {
+    let mut nonce = [0; NONCE_LEN];
+    rand::thread_rng().fill(&mut nonce);
+    let sealed = XChaCha20Poly1305::new(key.into())
+        .encrypt(
+            XNonce::from_slice(&nonce),
+            bincode::serialize(value)?.as_slice(),
+        )
+        .map_err(|_| anyhow!("encryption failed"))?;
+    let mut bytes = ENCRYPTED_MAGIC.to_vec();
+    bytes.extend_from_slice(&nonce);
+    bytes.extend_from_slice(&sealed);
+    Ok(bytes)
+}
+
+/// The value `seal` encrypted with the same key.
+pub fn unseal<T: DeserializeOwned>(bytes: &[u8], key: &Key) -> Result<T> {
+    match bytes.strip_prefix(ENCRYPTED_MAGIC) {
+        Some(sealed) => Ok(bincode::deserialize(&decrypt(key, sealed)?)?),
+        None => Err(anyhow!("not an encrypted file")),
+    }
+}
+
+fn decrypt(key: &Key, sealed: &[u8]) -> Result<Vec<u8>> {
+    if sealed.len() < NONCE_LEN {
+        return Err(anyhow!("encrypted file is truncated"));
+    }
+    let (nonce, sealed) = sealed.split_at(NONCE_LEN);
+    XChaCha20Poly1305::new(key.into())
+        .decrypt(XNonce::from_slice(nonce), sealed)
+        .map_err(|_| anyhow!("couldn't decrypt, wrong key?"))
+}
+
+/// What `save_sealed_to` saved at `path`, or the default if nothing is. A file that's there but
+/// won't decrypt or decode is an error, like an encrypted one with no `key`, so it isn't saved
+/// over with the default.
+pub fn load_sealed<T: DeserializeOwned + Default>(
+    path: impl AsRef<Path>,
+    key: Option<&Key>,
+) -> Result<T> {
+    let path = path.as_ref();
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(T::default()),
+        Err(e) => return Err(anyhow!("couldn't read {}: {}", path.display(), e)),
+    };
+    let plain = match (bytes.strip_prefix(ENCRYPTED_MAGIC), key) {
+        (Some(sealed), Some(key)) => {
+            decrypt(key, sealed).map_err(|e| anyhow!("{}: {}", path.display(), e))?
+        }
+        (Some(_), None) => {
+            return Err(anyhow!(
+                "{} is encrypted, and there's no key to read it with",
+                path.display()
+            ))
+        }
+        (None, _) => bytes,
+    };
+    bincode::deserialize(&plain).map_err(|e| anyhow!("couldn't read {}: {}", path.display(), e))
+}
+
+/// `save_to`, or `save_encrypted_to` when there's a key.
+pub fn save_sealed_to(
+    path: impl AsRef<Path>,
+    value: &impl Serialize,
+    key: Option<&Key>,
+) -> Result<u64> {
+    match key {
+        Some(key) => save_encrypted_to(path, value, key),
+        None => save_to(path, value),
+    }
+}
+
+/// The key for one guild's data, so no two guilds share one.
+pub fn guild_key(master: &Key, guild: Id) -> Key {
+    let mut material = master.to_vec();
+    material.extend_from_slice(guild.to_string().as_bytes());
+    blake3::derive_key("taco_bot 2026-10 guild data key", &material)
+}
+
+/// Parses a key written as 64 hex digits.
+pub fn parse_key(hex: &str) -> Result<Key> {
+    let hex = hex.trim();
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err(anyhow!("keys are 64 hex digits"));
+    }
+    let mut key = [0; 32];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)?;
+    }
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("taco_bot-persist-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn a_missing_file_loads_as_the_default() {
+        let path = scratch("missing");
+        assert_eq!(
+            load_sealed::<Vec<u32>>(&path, None).unwrap(),
+            Vec::<u32>::new()
+        );
+        assert_eq!(
+            load_sealed::<Vec<u32>>(&path, Some(&[1; 32])).unwrap(),
+            Vec::<u32>::new()
+        );
+    }
+
+    #[test]
+    fn an_encrypted_file_needs_its_key() {
+        let path = scratch("encrypted");
+        save_sealed_to(&path, &vec![1u32, 2, 3], Some(&[1; 32])).unwrap();
+        let opened = load_sealed::<Vec<u32>>(&path, Some(&[1; 32]));
+        let wrong = load_sealed::<Vec<u32>>(&path, Some(&[2; 32]));
+        let none = load_sealed::<Vec<u32>>(&path, None);
+        let _ = fs::remove_file(&path);
+        assert_eq!(opened.unwrap(), [1, 2, 3]);
+        assert!(wrong.is_err());
+        assert!(none.is_err());
+    }
+
+    #[test]
+    fn a_corrupt_file_is_an_error() {
+        let path = scratch("corrupt");
+        fs::write(&path, [0xff; 3]).unwrap();
+        let loaded = load_sealed::<Vec<u32>>(&path, None);
+        let _ = fs::remove_file(&path);
+        assert!(loaded.is_err());
+    }
+}
@@ -0,0 +1,108 @@
+//! Plugins add commands, filter what gets learned and rewrite what gets said without touching the
+//! rest of the bot. Each one gets its own section of the `plugins` object in `bot.json`, keyed by
+//! its name.
+
+use anyhow::{Context, Result};
+use rand::RngCore;
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::bot::types::Id;
+use crate::markov::Markov;
+
+pub struct CommandContext<'a> {
+    pub author: Id,
+    pub channel: Id,
+    pub guild: Option<Id>,
+    pub args: Vec<&'a str>,
+    /// The model of wherever the command was sent
+    pub model: &'a mut Markov,
+}
+
+pub trait Plugin {
+    fn name(&self) -> &'static str;
+
+    /// Called every time the config is (re)loaded, with `Value::Null` if there's no section.
+    fn init(&mut self, _config: &Value) -> Result<()> {
+        Ok(())
+    }
+
+    /// Commands this plugin answers to. Built-in commands take precedence.
+    fn commands(&self) -> &[&'static str] {
+        &[]
+    }
+
+    /// Returns the reply to send, if any.
+    fn command(&mut self, _name: &str, _ctx: CommandContext<'_>) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Runs on text before it's learned. Returning `None` drops it.
+    fn ingest(&mut self, text: String) -> Option<String> {
+        Some(text)
+    }
+
+    /// Runs on generated text before it's sent.
+    fn post_process(&mut self, text: String, _rng: &mut dyn RngCore) -> String {
+        text
+    }
+
+    fn shutdown(&mut self) {}
+}
+
+#[derive(Default)]
+pub struct Plugins {
+    plugins: Vec<Box<dyn Plugin>>,
+}
+
+impl Plugins {
+    /// Plugins compiled into this build. Plugins living in other crates are registered here
+    /// behind a feature of the same name, e.g.
+    /// `#[cfg(feature = "cowsay")] plugins.register(Box::new(cowsay::Cowsay::default()));`
+    pub fn builtin() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, plugin: Box<dyn Plugin>) {
+        self.plugins.push(plugin);
+    }
+
+    pub fn init(&mut self, config: &HashMap<String, Value>) -> Result<()> {
+        for plugin in &mut self.plugins {
+            let cfg = config.get(plugin.name()).unwrap_or(&Value::Null);
+            plugin
+                .init(cfg)
+                .with_context(|| format!("failed to start plugin `{}`", plugin.name()))?;
+        }
+        Ok(())
+    }
+
+    pub fn command(&mut self, name: &str, ctx: CommandContext<'_>) -> Result<Option<String>> {
+        match self
+            .plugins
+            .iter_mut()
+            .find(|p| p.commands().contains(&name))
+        {
+            Some(plugin) => plugin.command(name, ctx),
+            None => Ok(None),
+        }
+    }
+
+    pub fn ingest(&mut self, text: String) -> Option<String> {
+        self.plugins
+            .iter_mut()
+            .try_fold(text, |text, plugin| plugin.ingest(text))
+    }
+
+    pub fn post_process(&mut self, text: String, rng: &mut dyn RngCore) -> String {
+        self.plugins
+            .iter_mut()
+            .fold(text, |text, plugin| plugin.post_process(text, rng))
+    }
+
+    pub fn shutdown(&mut self) {
+        for plugin in &mut self.plugins {
+            plugin.shutdown();
+        }
+    }
+}
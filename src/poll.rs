@@ -0,0 +1,80 @@
+use crate::bot::types::{Id, MessagePoll, Reaction};
+use serde::{Deserialize, Serialize};
+
+pub const OPTION_EMOJIS: [&str; 4] = ["🇦", "🇧", "🇨", "🇩"];
+/// The longest question and answers Discord takes in a native poll, in characters
+const NATIVE_QUESTION_LEN: usize = 300;
+const NATIVE_ANSWER_LEN: usize = 55;
+/// The longest a native poll can be open for
+pub const NATIVE_MAX_HOURS: u64 = 32 * 24;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Poll {
+    pub channel: Id,
+    pub message: Id,
+    pub question: String,
+    pub options: Vec<String>,
+}
+
+pub fn render(question: &str, options: &[String]) -> String {
+    options
+        .iter()
+        .zip(OPTION_EMOJIS.iter())
+        .fold(format!("📊 **{}**", question), |p, (option, emoji)| {
+            p + "\n" + *emoji + " " + option.as_str()
+        })
+}
+
+/// Whether `question` and `options` fit in a native poll, or have to be reacted to instead.
+pub fn fits_native(question: &str, options: &[String]) -> bool {
+    question.chars().count() <= NATIVE_QUESTION_LEN
+        && options
+            .iter()
+            .all(|o| o.chars().count() <= NATIVE_ANSWER_LEN)
+}
+
+impl Poll {
+    /// The votes for each option, from the reactions to the poll.
+    pub fn tally(&self, reactions: &[Reaction<'_>]) -> Vec<usize> {
+        OPTION_EMOJIS[..self.options.len()]
+            .iter()
+            .map(|&emoji| {
+                reactions
+                    .iter()
+                    .find(|r| r.emoji.name.as_ref().map(|n| n.as_str()) == Some(emoji))
+                    // the bot's own reaction doesn't count as a vote
+                    .map_or(0, |r| r.count - r.me as usize)
+            })
+            .collect()
+    }
+
+    /// The votes for each option of a native poll.
+    pub fn tally_native(&self, poll: Option<&MessagePoll>) -> Vec<usize> {
+        let counts = poll
+            .and_then(|p| p.results.as_ref())
+            .map_or(&[][..], |r| &r.answer_counts[..]);
+        (1..=self.options.len())
+            .map(|id| counts.iter().find(|c| c.id == id).map_or(0, |c| c.count))
+            .collect()
+    }
+
+    pub fn results_message(&self, votes: Vec<usize>) -> String {
+        let best = votes.iter().copied().max().unwrap_or(0);
+        let mut msg = format!("📊 Results for **{}**", self.question);
+        for ((option, emoji), count) in self.options.iter().zip(OPTION_EMOJIS.iter()).zip(votes) {
+            msg += &format!(
+                "\n{} {} — {} vote{}{}",
+                emoji,
+                option,
+                count,
+                if count == 1 { "" } else { "s" },
+                if count == best && best > 0 {
+                    " 🏆"
+                } else {
+                    ""
+                }
+            );
+        }
+        msg
+    }
+}
@@ -0,0 +1,117 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::bot::types::Id;
+use crate::store::is_valid_name;
+
+pub const MIN_TEMPERATURE: f64 = 0.1;
+pub const MAX_TEMPERATURE: f64 = 5.0;
+
+/// How someone likes the bot to generate for them, wherever they ask.
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct UserPrefs {
+    /// Below 1 sticks to the likeliest words, above 1 gets weirder
+    pub temperature: Option<f64>,
+    /// A style to generate in when they don't ask for one
+    pub persona: Option<String>,
+    /// The tag they're learned under, while they don't want it showing up in generated messages
+    pub hidden_tag: Option<String>,
+    /// Whether they get a daily DM of generated messages
+    pub digest: bool,
+}
+
+impl UserPrefs {
+    pub fn temperature(&self) -> f64 {
+        self.temperature.unwrap_or(1.0)
+    }
+}
+
+impl fmt::Display for UserPrefs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.temperature {
+            Some(t) => write!(f, "temperature: {}", t)?,
+            None => write!(f, "temperature: default")?,
+        }
+        write!(
+            f,
+            ", persona: {}",
+            self.persona.as_deref().unwrap_or("none")
+        )?;
+        let on_off = |b: bool| if b { "on" } else { "off" };
+        write!(f, ", mentions: {}", on_off(self.hidden_tag.is_none()))?;
+        write!(f, ", digest: {}", on_off(self.digest))
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct UserSettings {
+    users: HashMap<Id, UserPrefs>,
+}
+
+impl UserSettings {
+    pub fn get(&self, user: Id) -> UserPrefs {
+        self.users.get(&user).cloned().unwrap_or_default()
+    }
+
+    /// Changes one preference. `tag` is what the user's mentions are learned as.
+    pub fn set(&mut self, user: Id, tag: &str, key: &str, value: &str) -> Result<()> {
+        fn on_off(value: &str) -> Result<bool> {
+            match value {
+                "on" => Ok(true),
+                "off" => Ok(false),
+                v => bail!("expected `on` or `off`, got `{}`", v),
+            }
+        }
+
+        let prefs = self.users.entry(user).or_default();
+        match key {
+            "temperature" => {
+                prefs.temperature = match value {
+                    "default" => None,
+                    v => match v.parse::<f64>()? {
+                        t if (MIN_TEMPERATURE..=MAX_TEMPERATURE).contains(&t) => Some(t),
+                        _ => bail!(
+                            "temperature has to be between {} and {}",
+                            MIN_TEMPERATURE,
+                            MAX_TEMPERATURE
+                        ),
+                    },
+                }
+            }
+            "persona" => {
+                prefs.persona = match value.to_lowercase() {
+                    v if v == "none" => None,
+                    v if is_valid_name(&v) => Some(v),
+                    _ => bail!("style names can only have letters, numbers and `_`"),
+                }
+            }
+            "mentions" => {
+                prefs.hidden_tag = match on_off(value)? {
+                    true => None,
+                    false => Some(String::from(tag)),
+                }
+            }
+            "digest" => prefs.digest = on_off(value)?,
+            k => bail!("unknown preference `{}`", k),
+        }
+        Ok(())
+    }
+
+    /// Tags that shouldn't show up in anything generated.
+    pub fn hidden_tags(&self) -> HashSet<&str> {
+        self.users
+            .values()
+            .filter_map(|p| p.hidden_tag.as_deref())
+            .collect()
+    }
+
+    pub fn digest_users(&self) -> Vec<Id> {
+        self.users
+            .iter()
+            .filter(|(_, p)| p.digest)
+            .map(|(&id, _)| id)
+            .collect()
+    }
+}
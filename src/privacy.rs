@@ -0,0 +1,57 @@
+//! What other servers get of the shared model, when servers have their own (`guild_models`) but
+//! everything is still learned into the shared one for Mastodon, bridges and digests. With
+//! `shared_privacy` set, those generate from a copy where transitions seen only a few times are
+//! left out and every weight is blurred with Laplace noise, the usual mechanism for differential
+//! privacy, so a rare phrase someone said once in one server isn't repeated somewhere else.
+
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::markov::Markov;
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct PrivacyConfig {
+    /// Transitions seen fewer times than this are never shared, before or after the noise
+    #[serde(default = "default_min_count")]
+    pub min_count: usize,
+    /// The privacy budget for each count: noise with a scale of `1 / epsilon` is added to every
+    /// weight, so smaller is more private. No noise without it, only `min_count`
+    #[serde(default)]
+    pub epsilon: Option<f64>,
+    /// How often the shared copy is made again, with new noise
+    #[serde(default = "default_refresh_minutes")]
+    pub refresh_minutes: u64,
+}
+
+fn default_min_count() -> usize {
+    3
+}
+
+fn default_refresh_minutes() -> u64 {
+    60
+}
+
+impl PrivacyConfig {
+    /// A copy of `model` that's safe to share, as far as `self` says.
+    pub fn apply(&self, model: &Markov, rng: &mut impl Rng) -> Markov {
+        model.reweighed(|weight| {
+            if weight < self.min_count {
+                return 0;
+            }
+            let noisy = match self.epsilon {
+                Some(epsilon) => (weight as f64 + laplace(1.0 / epsilon, rng)).round(),
+                None => weight as f64,
+            };
+            match noisy >= self.min_count as f64 {
+                true => noisy as usize,
+                false => 0,
+            }
+        })
+    }
+}
+
+/// A sample from the Laplace distribution centered on 0 with `scale`.
+fn laplace(scale: f64, rng: &mut impl Rng) -> f64 {
+    let u: f64 = rng.gen_range(-0.5, 0.5);
+    -scale * u.signum() * (1.0 - 2.0 * u.abs()).ln()
+}
@@ -0,0 +1,45 @@
+//! CPU profiling for `eg!profile`: samples the whole process for a while and posts a flamegraph.
+
+use anyhow::Result;
+use std::thread;
+use std::time::Duration;
+
+use crate::bot::client::Client;
+use crate::bot::types::{Id, TokenBuf};
+
+pub const MAX_SECONDS: u64 = 120;
+const FREQUENCY: i32 = 99;
+
+/// Profiles on its own thread so the gateway loop keeps running (and shows up in the samples),
+/// then uploads the flamegraph to `channel`.
+pub fn spawn(token: TokenBuf, channel: Id, duration: Duration) {
+    thread::spawn(move || {
+        let client = Client::new(&token);
+        let result = match flamegraph(duration) {
+            Ok(svg) => async_io::block_on(client.upload_file(
+                channel,
+                &format!("🔥 {} seconds of CPU time", duration.as_secs()),
+                "flamegraph.svg",
+                &svg,
+            )),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            eprintln!("profile: {}", e);
+            let _ = async_io::block_on(
+                client.create_message(channel, &format!("Couldn't profile: {}", e)),
+            );
+        }
+    });
+}
+
+fn flamegraph(duration: Duration) -> Result<Vec<u8>> {
+    let guard = pprof::ProfilerGuardBuilder::default()
+        .frequency(FREQUENCY)
+        .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+        .build()?;
+    thread::sleep(duration);
+    let mut svg = Vec::new();
+    guard.report().build()?.flamegraph(&mut svg)?;
+    Ok(svg)
+}
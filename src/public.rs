@@ -0,0 +1,271 @@
+//! Public hosting, where every guild's data has to stay its own. These check that guild scopes,
+//! paths and keys never overlap, and that `eg!forget`, `eg!dataexport`, `eg!handoff` and
+//! `eg!follows` run in one guild never reach another's; run them with `cargo test public` after
+//! touching `Scope`, the store or those commands.
+
+use std::fs;
+
+use crate::bot::types::{Id, Message, TokenBuf};
+use crate::markov::Markov;
+use crate::persist::{guild_key, load_encrypted, save_encrypted_to};
+use crate::store::{is_valid_name, Scope};
+use crate::tokenizer::Tokenizer;
+use crate::{BotConfig, Data, GuildStickers, Handler, Job, Trace};
+
+fn id(id: u64) -> Id {
+    id.to_string().parse().expect("valid id")
+}
+
+fn guilds() -> [Id; 2] {
+    ["81384788765712384", "175928847299117063"].map(|g| g.parse().expect("valid id"))
+}
+
+/// Every scope a command run in `guild` can resolve to in public mode.
+fn guild_scopes(guild: Id) -> Vec<Scope> {
+    vec![
+        Scope::for_guild(Some(guild), true),
+        Scope::style(Some(guild), "lore", true),
+        Scope::style(Some(guild), "LORE", true),
+    ]
+}
+
+/// A channel in each of `guilds()`, in the same order.
+fn channels() -> [Id; 2] {
+    ["81384788765712385", "175928847299117064"].map(|c| c.parse().expect("valid id"))
+}
+
+/// A handler on whatever data is in the working directory, which never saves. `public` hosts
+/// publicly; otherwise every guild gets its own model on top of the shared one.
+fn handler(data: &mut Data, public: bool) -> Handler<'_> {
+    let cfg: BotConfig = serde_json::from_value(serde_json::json!({
+        "intents": [],
+        "admins": [],
+        "channel_blacklist": [],
+        "announcement_channels": [],
+        "guild_models": true,
+        "public": public,
+    }))
+    .expect("valid config");
+    let tokenizer = Tokenizer::new(cfg.tokenizer.clone(), &cfg.punctuation).expect("tokenizer");
+    Handler {
+        data,
+        rng: rand::thread_rng(),
+        id: None,
+        name: None,
+        cfg,
+        token: TokenBuf::from(""),
+        mastodon: None,
+        moderation: None,
+        tokenizer,
+        restart: false,
+        trace: Trace::default(),
+        sentry: None,
+        stickers: GuildStickers::default(),
+        replication: None,
+    }
+}
+
+/// Has `author` say `content` in the `nth` of `guilds()`, as message `id`.
+fn say(handler: &mut Handler<'_>, id: u64, nth: usize, author: u64, content: &str) {
+    let json = serde_json::json!({
+        "id": id.to_string(),
+        "channel_id": channels()[nth].to_string(),
+        "guild_id": guilds()[nth].to_string(),
+        "content": content,
+        "timestamp": "2026-01-01T00:00:00+00:00",
+        "author": { "id": author.to_string(), "username": "user", "discriminator": "0" },
+        "mentions": [],
+    })
+    .to_string();
+    let message: Message<'_> = serde_json::from_str(&json).expect("valid message");
+    handler.observe(&message);
+    handler.ingest(&message);
+}
+
+fn knows(handler: &mut Handler<'_>, scope: &Scope, word: &str) -> bool {
+    handler.data.models.get_mut(scope).words().contains(word)
+}
+
+fn shared_scopes() -> Vec<Scope> {
+    vec![
+        Scope::Global,
+        Scope::style(None, "lore", true),
+        Scope::Corpus(String::from("lore")),
+    ]
+}
+
+#[test]
+fn every_guild_scope_is_owned_by_its_guild() {
+    for &guild in &guilds() {
+        for scope in guild_scopes(guild) {
+            assert_eq!(scope.owner(), Some(guild), "{:?}", scope);
+        }
+    }
+}
+
+#[test]
+fn no_two_guilds_share_a_model_file() {
+    let [a, b] = guilds();
+    for x in guild_scopes(a) {
+        for y in guild_scopes(b).into_iter().chain(shared_scopes()) {
+            assert_ne!(x.path(), y.path(), "{:?} and {:?}", x, y);
+        }
+    }
+}
+
+#[test]
+fn no_two_style_names_share_a_model_file() {
+    let names = [
+        "lore", "my_lore", "my-lore", "my.lore", "my lore", "my/lore",
+    ];
+    let scopes: Vec<_> = names
+        .iter()
+        .filter(|n| is_valid_name(n))
+        .map(|n| Scope::style(Some(guilds()[0]), n, true))
+        .collect();
+    for (i, x) in scopes.iter().enumerate() {
+        for y in &scopes[i + 1..] {
+            assert_ne!(x.path(), y.path(), "{:?} and {:?}", x, y);
+        }
+    }
+}
+
+#[test]
+fn model_files_stay_in_their_directory() {
+    for scope in guild_scopes(guilds()[0]) {
+        let path = scope.path();
+        assert_eq!(
+            path.components().count(),
+            2,
+            "{:?} is saved to {}",
+            scope,
+            path.display()
+        );
+    }
+}
+
+#[test]
+fn no_two_guilds_share_a_key() {
+    let [a, b] = guilds();
+    let master = [7; 32];
+    assert_ne!(guild_key(&master, a), guild_key(&master, b));
+}
+
+#[test]
+fn a_guilds_model_cant_be_read_with_another_guilds_key() {
+    let [a, b] = guilds();
+    let master = [7; 32];
+    let (own, other) = (guild_key(&master, a), guild_key(&master, b));
+    let path = std::env::temp_dir().join(format!("taco_bot-isolation-{}.dat", a));
+    let mut model = Markov::new();
+    model.insert_sequence("only this server knows this".split(' ').map(String::from));
+    save_encrypted_to(&path, &model, &own).unwrap();
+    let stolen = load_encrypted::<Markov>(&path, &other);
+    let opened = load_encrypted::<Markov>(&path, &own);
+    let _ = fs::remove_file(&path);
+    assert!(stolen.is_err(), "the other guild's key opened it");
+    assert_eq!(opened.unwrap().entry_count(), model.entry_count());
+}
+
+#[test]
+fn forgetting_a_word_leaves_other_guilds_alone() {
+    let [a, b] = guilds();
+    let mut data = Data::load(None).unwrap();
+    let mut handler = handler(&mut data, false);
+    say(&mut handler, 1, 0, 1, "meet me by the secret tree");
+    say(&mut handler, 2, 1, 1, "meet me by the secret pond");
+
+    assert!(handler.forget_word(Some(a), false, "secret") > 0);
+    assert!(!knows(&mut handler, &Scope::Guild(a), "secret"));
+    assert!(knows(&mut handler, &Scope::Guild(b), "secret"));
+    assert!(knows(&mut handler, &Scope::Global, "secret"));
+
+    handler.forget_word(Some(a), true, "secret");
+    assert!(knows(&mut handler, &Scope::Guild(b), "secret"));
+    assert!(!knows(&mut handler, &Scope::Global, "secret"));
+}
+
+#[test]
+fn forgetting_a_user_leaves_other_guilds_alone() {
+    let [a, b] = guilds();
+    let mut data = Data::load(None).unwrap();
+    let mut handler = handler(&mut data, true);
+    say(&mut handler, 1, 0, 7, "my cat is called whiskers");
+    say(&mut handler, 2, 1, 7, "my dog is called rex");
+    say(&mut handler, 3, 0, 8, "my fish is called bubbles");
+
+    assert_eq!(handler.forget_recent(id(7), Some(a)), 1);
+    assert!(!knows(&mut handler, &Scope::Guild(a), "whiskers"));
+    assert!(knows(&mut handler, &Scope::Guild(a), "bubbles"));
+    assert!(knows(&mut handler, &Scope::Guild(b), "rex"));
+}
+
+#[test]
+fn an_export_only_holds_its_guilds_models() {
+    let [a, b] = guilds();
+    let mut data = Data::load(None).unwrap();
+    let mut handler = handler(&mut data, true);
+    handler.data.styles.tag(channels()[0], "lore").unwrap();
+    handler.data.styles.tag(channels()[1], "lore").unwrap();
+    say(&mut handler, 1, 0, 1, "the dragon sleeps under the hill");
+    say(&mut handler, 2, 1, 1, "the kraken sleeps under the sea");
+
+    let (filename, archive) = handler.export_archive(a, &channels()[..1]).unwrap();
+    let archive = String::from_utf8_lossy(&archive);
+    assert!(filename.contains(&a.to_string()));
+    assert!(archive.contains(&format!("guild-{}.dat", a)));
+    assert!(archive.contains("dragon"));
+    assert!(!archive.contains(&b.to_string()));
+    assert!(!archive.contains("kraken"));
+}
+
+#[test]
+fn a_handoff_only_takes_its_guilds_models_and_jobs() {
+    let [a, b] = guilds();
+    let mut data = Data::load(None).unwrap();
+    let mut handler = handler(&mut data, true);
+    handler.data.styles.tag(channels()[0], "lore").unwrap();
+    handler.data.styles.tag(channels()[1], "lore").unwrap();
+    say(&mut handler, 1, 0, 1, "the dragon sleeps under the hill");
+    say(&mut handler, 2, 1, 1, "the kraken sleeps under the sea");
+    for (nth, &guild) in guilds().iter().enumerate() {
+        let job = Job::Engagement {
+            guild: Some(guild),
+            channel: channels()[nth],
+            message: id(1),
+        };
+        handler.data.jobs.schedule(0, job);
+    }
+
+    let (handoff, _) = handler.pack_handoff(a, &channels()[..1]).unwrap();
+    assert!(!handoff.models.is_empty());
+    for (scope, bytes, _) in &handoff.models {
+        assert_eq!(scope.owner(), Some(a), "{:?}", scope);
+        let model: Markov = bincode::deserialize(bytes).unwrap();
+        assert!(!model.words().contains("kraken"), "{:?}", scope);
+    }
+    assert_eq!(handoff.jobs.len(), 1);
+    let left = |job: &Job| matches!(job, Job::Engagement { guild, .. } if *guild == Some(b));
+    assert!(handler.data.jobs.any(left));
+}
+
+#[test]
+fn follows_only_sees_its_guilds_model() {
+    let [a, _] = guilds();
+    let mut data = Data::load(None).unwrap();
+    let mut handler = handler(&mut data, true);
+    say(&mut handler, 1, 0, 1, "the dragon sleeps under the hill");
+    say(&mut handler, 2, 1, 1, "the kraken sleeps under the sea");
+
+    let scope = handler.scope(Some(a), channels()[0]);
+    let follows: Vec<_> = handler
+        .data
+        .models
+        .get_mut(&scope)
+        .what_follows(&["the"])
+        .into_iter()
+        .map(|(word, _)| word.to_string())
+        .collect();
+    assert!(follows.iter().any(|w| w == "dragon"), "{:?}", follows);
+    assert!(!follows.iter().any(|w| w == "kraken"), "{:?}", follows);
+}
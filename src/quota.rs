@@ -0,0 +1,209 @@
+use anyhow::{bail, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::time::Duration;
+
+use crate::bot::types::Id;
+
+/// What a guild is allowed to use, for hosting the bot publicly. `None` is unlimited.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+pub struct Limits {
+    /// Entries the guild's own model can grow to before it stops learning
+    #[serde(default)]
+    pub model_entries: Option<usize>,
+    #[serde(default)]
+    pub imports_per_day: Option<u32>,
+    /// Polls waiting for their results at once
+    #[serde(default)]
+    pub scheduled_posts: Option<usize>,
+    /// Compute the guild gets back every minute, for expensive commands. It can save up to a
+    /// minute's worth.
+    #[serde(default)]
+    pub compute_per_minute: Option<u32>,
+}
+
+impl Limits {
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        fn parse<T: std::str::FromStr>(value: &str) -> Result<Option<T>> {
+            match value {
+                "none" => Ok(None),
+                v => match v.parse() {
+                    Ok(n) => Ok(Some(n)),
+                    Err(_) => bail!("expected a number or `none`, got `{}`", v),
+                },
+            }
+        }
+        match key {
+            "model_entries" => self.model_entries = parse(value)?,
+            "imports_per_day" => self.imports_per_day = parse(value)?,
+            "scheduled_posts" => self.scheduled_posts = parse(value)?,
+            "compute_per_minute" => self.compute_per_minute = parse(value)?,
+            k => bail!(
+                "unknown quota `{}`, try `model_entries`, `imports_per_day`, `scheduled_posts` \
+                 or `compute_per_minute`",
+                k
+            ),
+        }
+        Ok(())
+    }
+}
+
+impl Display for Limits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn show(limit: Option<impl Display>) -> String {
+            limit.map_or_else(|| String::from("unlimited"), |l| l.to_string())
+        }
+        write!(
+            f,
+            "model entries: {}, imports per day: {}, scheduled posts: {}, compute per minute: {}",
+            show(self.model_entries),
+            show(self.imports_per_day),
+            show(self.scheduled_posts),
+            show(self.compute_per_minute)
+        )
+    }
+}
+
+/// Commands that take more than generating a sentence, out of a guild's compute budget.
+#[derive(Clone, Copy, Debug)]
+pub enum Work {
+    /// Several sentences, moderated
+    Story,
+    /// Paragraphs for every option of an adventure's first round
+    Adventure,
+    /// Looking through the whole model for where a prompt fits
+    Prompt,
+    /// Going through every entry of a model, like `eg!follows` or `eg!audit`
+    Scan,
+}
+
+impl Work {
+    /// Roughly how many sentences' worth of work it is.
+    pub fn cost(self) -> u32 {
+        match self {
+            Work::Story => 6,
+            Work::Adventure => 8,
+            Work::Prompt => 5,
+            Work::Scan => 10,
+        }
+    }
+}
+
+/// A guild's compute budget, as it was when it last changed.
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    compute: f64,
+    at: i64,
+}
+
+/// Per-guild overrides of the configured limits, and what each guild has used so far.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Quotas {
+    overrides: HashMap<Id, Limits>,
+    imports: HashMap<Id, (NaiveDate, u32)>,
+    /// The guild of every poll still waiting for its results, by poll message
+    polls: HashMap<Id, Id>,
+    /// Starts over full on a restart
+    #[serde(skip)]
+    budgets: HashMap<Id, Bucket>,
+}
+
+impl Quotas {
+    pub fn limits(&self, guild: Id, defaults: &Limits) -> Limits {
+        self.overrides.get(&guild).copied().unwrap_or(*defaults)
+    }
+
+    pub fn set_override(&mut self, guild: Id, limits: Limits) {
+        self.overrides.insert(guild, limits);
+    }
+
+    /// Goes back to the configured limits, returning `false` if there was no override.
+    pub fn clear_override(&mut self, guild: Id) -> bool {
+        self.overrides.remove(&guild).is_some()
+    }
+
+    pub fn imports_on(&self, guild: Id, date: NaiveDate) -> u32 {
+        match self.imports.get(&guild) {
+            Some(&(day, count)) if day == date => count,
+            _ => 0,
+        }
+    }
+
+    /// Fails if the guild's imports for `date` are used up.
+    pub fn check_import(&self, guild: Id, limits: &Limits, date: NaiveDate) -> Result<()> {
+        if limits
+            .imports_per_day
+            .is_some_and(|max| self.imports_on(guild, date) >= max)
+        {
+            bail!("this server is out of imports for today");
+        }
+        Ok(())
+    }
+
+    /// Counts an import against the guild's quota for `date`, once it's gone through.
+    pub fn count_import(&mut self, guild: Id, date: NaiveDate) {
+        let count = self.imports_on(guild, date);
+        self.imports.insert(guild, (date, count + 1));
+    }
+
+    /// How much compute the guild has at `now` (in milliseconds), or `None` if it's unlimited.
+    pub fn compute_left(&self, guild: Id, limits: &Limits, now: i64) -> Option<u32> {
+        let per_minute = limits.compute_per_minute?;
+        Some(self.refilled(guild, per_minute, now) as u32)
+    }
+
+    /// Takes `work` out of the guild's compute budget at `now`. If there isn't enough, nothing is
+    /// taken and it fails with how long until there will be. Work that costs more than a minute's
+    /// worth only needs the budget to be full.
+    pub fn spend(
+        &mut self,
+        guild: Id,
+        limits: &Limits,
+        work: Work,
+        now: i64,
+    ) -> std::result::Result<(), Duration> {
+        let per_minute = match limits.compute_per_minute {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+        let compute = self.refilled(guild, per_minute, now);
+        let cost = work.cost().min(per_minute) as f64;
+        if compute < cost {
+            let wait = (cost - compute) * 60_000.0 / per_minute.max(1) as f64;
+            return Err(Duration::from_millis(wait.ceil() as u64));
+        }
+        self.budgets.insert(
+            guild,
+            Bucket {
+                compute: compute - cost,
+                at: now,
+            },
+        );
+        Ok(())
+    }
+
+    fn refilled(&self, guild: Id, per_minute: u32, now: i64) -> f64 {
+        let per_minute = per_minute as f64;
+        match self.budgets.get(&guild) {
+            Some(bucket) => {
+                let elapsed = (now - bucket.at).max(0) as f64;
+                (bucket.compute + elapsed * per_minute / 60_000.0).min(per_minute)
+            }
+            None => per_minute,
+        }
+    }
+
+    pub fn polls(&self, guild: Id) -> usize {
+        self.polls.values().filter(|&&g| g == guild).count()
+    }
+
+    pub fn add_poll(&mut self, guild: Id, message: Id) {
+        self.polls.insert(message, guild);
+    }
+
+    pub fn finish_poll(&mut self, message: Id) {
+        self.polls.remove(&message);
+    }
+}
@@ -0,0 +1,441 @@
+//! Keeping a standby's models in step with the primary's, so it can take over when the primary's
+//! host dies. The primary appends every change it makes to its models to `replication.log`, and
+//! serves the log over TCP to standbys that know the secret. `taco_bot standby` tails it and makes
+//! the same changes to its own copy of the models.
+//!
+//! The log is frames of a sequence number, a length and a change, encrypted like the models when
+//! there's a key. A standby asks for everything after the last change it saved, so it picks up
+//! where it left off after a restart or a dropped connection.
+//!
+//! With a `lease`, a standby takes over by itself: when it loses the primary and the primary's
+//! lease has run out, it takes the lease and starts the bot, see `lease`.
+
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::convert::TryInto;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::lease::{Lease, LeaseConfig};
+use crate::markov::Markov;
+use crate::persist::{self, Key};
+use crate::store::{MarkovStore, Scope};
+
+const LOG_PATH: &str = "replication.log";
+/// The last change a standby saved
+const SEQ_PATH: &str = "replication.seq";
+const HEADER_LEN: usize = 12;
+/// Sent with nothing in it when there's been nothing to send for this long, so a standby can tell
+/// a quiet primary from a dead one
+const HEARTBEAT: Duration = Duration::from_secs(15);
+/// How long a standby waits to hear anything before it decides the primary is gone
+const TIMEOUT: Duration = Duration::from_secs(60);
+const POLL: Duration = Duration::from_millis(200);
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct ReplicationConfig {
+    /// On the primary, the `host:port` to serve the log on
+    #[serde(default)]
+    pub listen: Option<String>,
+    /// On a standby, the primary's `host:port`
+    #[serde(default)]
+    pub primary: Option<String>,
+    /// Can be kept out of `bot.json`, like the token
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// How often a standby saves what it's applied
+    #[serde(default = "default_save_seconds")]
+    pub save_seconds: u64,
+    /// For failing over by itself
+    #[serde(default)]
+    pub lease: Option<LeaseConfig>,
+}
+
+fn default_save_seconds() -> u64 {
+    60
+}
+
+/// Something that changed a model, made again the same way on a standby.
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Change {
+    Learn {
+        scopes: Vec<Scope>,
+        words: Vec<String>,
+        times: usize,
+    },
+    Unlearn {
+        scopes: Vec<Scope>,
+        words: Vec<String>,
+    },
+    Ensure {
+        scope: Scope,
+        words: Vec<String>,
+        weight: usize,
+    },
+    RemoveWords {
+        scope: Scope,
+        words: HashSet<String>,
+    },
+    RemoveContaining {
+        scope: Scope,
+        word: String,
+    },
+    /// A whole model, serialized, like one a handoff brought in
+    Replace {
+        scope: Scope,
+        model: Vec<u8>,
+    },
+    Remove {
+        scope: Scope,
+    },
+    /// Another model, serialized, merged in
+    Merge {
+        scope: Scope,
+        model: Vec<u8>,
+    },
+}
+
+impl Change {
+    pub fn apply(self, models: &mut MarkovStore) -> Result<()> {
+        match self {
+            Change::Learn {
+                scopes,
+                words,
+                times,
+            } => {
+                for scope in &scopes {
+                    let model = models.get_mut(scope);
+                    for _ in 0..times {
+                        model.insert_sequence(words.iter().cloned());
+                    }
+                }
+            }
+            Change::Unlearn { scopes, words } => {
+                for scope in &scopes {
+                    models.get_mut(scope).remove_sequence(words.iter().cloned());
+                }
+            }
+            Change::Ensure {
+                scope,
+                words,
+                weight,
+            } => models.get_mut(&scope).ensure_sequence(words, weight),
+            Change::RemoveWords { scope, words } => {
+                models.get_mut(&scope).remove_words(&words);
+            }
+            Change::RemoveContaining { scope, word } => {
+                models.get_mut(&scope).remove_containing(&word);
+            }
+            Change::Replace { scope, model } => {
+                let model: Markov = bincode::deserialize(&model)?;
+                models.replace(scope, model);
+            }
+            Change::Remove { scope } => models.remove(&scope)?,
+            Change::Merge { scope, model } => {
+                let model: Markov = bincode::deserialize(&model)?;
+                models.get_mut(&scope).merge(model)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The primary's end: the log being appended to.
+pub struct Log {
+    file: File,
+    seq: u64,
+    key: Option<Key>,
+}
+
+impl Log {
+    /// Opens the log to go on from the last change in it. Half a frame left at the end by a crash
+    /// is cut off first, or every frame after it would be read wrong.
+    pub fn open(key: Option<Key>) -> Result<Self> {
+        let (seq, len, torn) = match fs::read(LOG_PATH) {
+            Ok(bytes) => {
+                let (seq, len) = whole_frames(&bytes);
+                (seq, len, len < bytes.len())
+            }
+            Err(_) => (0, 0, false),
+        };
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(LOG_PATH)?;
+        if torn {
+            eprintln!("replication: cutting a torn change off the end of the log");
+            file.set_len(len as u64)?;
+            file.sync_all()?;
+        }
+        Ok(Log { file, seq, key })
+    }
+
+    pub fn append(&mut self, change: &Change) -> Result<()> {
+        let payload = match &self.key {
+            Some(key) => persist::seal(change, key)?,
+            None => bincode::serialize(change)?,
+        };
+        let mut bytes = header(self.seq + 1, payload.len()).to_vec();
+        bytes.extend_from_slice(&payload);
+        // one write, so a standby reading along never sees half a frame for long
+        self.file.write_all(&bytes)?;
+        self.seq += 1;
+        Ok(())
+    }
+}
+
+/// The sequence number of the last whole frame in `bytes`, and where it ends.
+fn whole_frames(bytes: &[u8]) -> (u64, usize) {
+    let mut seq = 0;
+    let mut rest = bytes;
+    while let Some((s, _, after)) = frame(rest) {
+        seq = s;
+        rest = after;
+    }
+    (seq, bytes.len() - rest.len())
+}
+
+/// The first whole frame in `bytes`: its sequence number, what's in it, and what comes after.
+fn frame(bytes: &[u8]) -> Option<(u64, &[u8], &[u8])> {
+    if bytes.len() < HEADER_LEN {
+        return None;
+    }
+    let seq = u64::from_be_bytes(bytes[..8].try_into().ok()?);
+    let len = u32::from_be_bytes(bytes[8..HEADER_LEN].try_into().ok()?) as usize;
+    let rest = &bytes[HEADER_LEN..];
+    if rest.len() < len {
+        return None;
+    }
+    Some((seq, &rest[..len], &rest[len..]))
+}
+
+fn header(seq: u64, len: usize) -> [u8; HEADER_LEN] {
+    let mut header = [0; HEADER_LEN];
+    header[..8].copy_from_slice(&seq.to_be_bytes());
+    header[8..].copy_from_slice(&(len as u32).to_be_bytes());
+    header
+}
+
+/// Serves the log to standbys on `listen`, each on its own thread.
+pub fn serve(listen: &str, secret: String) -> Result<()> {
+    let listener = TcpListener::bind(listen)?;
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let secret = secret.clone();
+            let stream = match stream {
+                Ok(s) => s,
+                Err(e) => {
+                    eprintln!("replication: {}", e);
+                    continue;
+                }
+            };
+            thread::spawn(move || {
+                let peer = stream.peer_addr().map(|a| a.to_string());
+                let peer = peer.as_deref().unwrap_or("a standby");
+                if let Err(e) = send_log(stream, &secret) {
+                    eprintln!("replication: {}: {}", peer, e);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+/// Checks the standby knows the secret, then sends it every change after the one it asks for,
+/// and every new one as it's appended, until the connection drops.
+fn send_log(mut stream: TcpStream, secret: &str) -> Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let mut hello = String::new();
+    BufReader::new((&stream).take(256)).read_line(&mut hello)?;
+    let after = match hello.split_whitespace().collect::<Vec<_>>()[..] {
+        ["TACO-REPL", "1", given, after] if same_secret(given, secret) => after.parse::<u64>()?,
+        _ => {
+            stream.write_all(b"DENIED\n")?;
+            bail!("denied, wrong secret or not a standby");
+        }
+    };
+    stream.write_all(b"OK\n")?;
+
+    let mut log = File::open(LOG_PATH)?;
+    let mut pending = Vec::new();
+    let mut last_sent = Instant::now();
+    loop {
+        let read = log.read_to_end(&mut pending)?;
+        let mut rest = &pending[..];
+        while let Some((seq, payload, after_frame)) = frame(rest) {
+            if seq > after {
+                stream.write_all(&header(seq, payload.len()))?;
+                stream.write_all(payload)?;
+                last_sent = Instant::now();
+            }
+            rest = after_frame;
+        }
+        pending = rest.to_vec();
+        if last_sent.elapsed() >= HEARTBEAT {
+            stream.write_all(&header(0, 0))?;
+            last_sent = Instant::now();
+        }
+        if read == 0 {
+            thread::sleep(POLL);
+        }
+    }
+}
+
+/// Compares secrets by their hashes, which doesn't give away how much of one matched.
+fn same_secret(given: &str, secret: &str) -> bool {
+    blake3::hash(given.as_bytes()) == blake3::hash(secret.as_bytes())
+}
+
+/// `taco_bot standby`: applies the primary's changes to `models` until it's stopped, reconnecting
+/// whenever the connection drops. What's applied is saved every `save_seconds`. Returns, with
+/// everything saved, once it's taken `lease` over from a primary that's gone.
+pub fn run_standby(
+    cfg: &ReplicationConfig,
+    secret: &str,
+    key: Option<Key>,
+    models: &mut MarkovStore,
+    lease: Option<&Lease>,
+) -> Result<()> {
+    const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+    let primary = cfg
+        .primary
+        .as_deref()
+        .ok_or_else(|| anyhow!("a standby needs `replication.primary`"))?;
+    let mut seq: u64 = match fs::read_to_string(SEQ_PATH) {
+        Ok(s) => s.trim().parse()?,
+        Err(_) => 0,
+    };
+    loop {
+        eprintln!("standby: following {} from change {}", primary, seq);
+        if let Err(e) = follow(cfg, primary, secret, key.as_ref(), models, &mut seq) {
+            eprintln!("standby: {}", e);
+        }
+        save(models, seq)?;
+        if let Some(lease) = lease {
+            match lease.try_acquire() {
+                Ok(true) => {
+                    eprintln!("standby: the primary's lease ran out, taking over");
+                    // it's not following anything now
+                    fs::remove_file(SEQ_PATH).ok();
+                    return Ok(());
+                }
+                Ok(false) => {}
+                Err(e) => eprintln!("standby: couldn't check the lease: {}", e),
+            }
+        }
+        thread::sleep(RECONNECT_DELAY);
+    }
+}
+
+fn follow(
+    cfg: &ReplicationConfig,
+    primary: &str,
+    secret: &str,
+    key: Option<&Key>,
+    models: &mut MarkovStore,
+    seq: &mut u64,
+) -> Result<()> {
+    let mut stream = TcpStream::connect(primary)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.write_all(format!("TACO-REPL 1 {} {}\n", secret, seq).as_bytes())?;
+    let mut reply = [0; 3];
+    stream.read_exact(&mut reply)?;
+    if &reply != b"OK\n" {
+        bail!("the primary turned this standby away, check the secret");
+    }
+
+    let save_every = Duration::from_secs(cfg.save_seconds);
+    let mut saved = Instant::now();
+    let mut header = [0; HEADER_LEN];
+    loop {
+        stream
+            .read_exact(&mut header)
+            .map_err(|e| anyhow!("lost the primary: {}", e))?;
+        let next = u64::from_be_bytes(header[..8].try_into()?);
+        let mut payload = vec![0; u32::from_be_bytes(header[8..].try_into()?) as usize];
+        stream.read_exact(&mut payload)?;
+        if next != 0 {
+            if next != *seq + 1 {
+                bail!("expected change {}, got {}", *seq + 1, next);
+            }
+            let change: Change = match key {
+                Some(key) => persist::unseal(&payload, key)?,
+                None => bincode::deserialize(&payload)?,
+            };
+            change.apply(models)?;
+            *seq = next;
+        }
+        if saved.elapsed() >= save_every {
+            save(models, *seq)?;
+            saved = Instant::now();
+        }
+    }
+}
+
+/// Saves the models, then that they're up to `seq`, so a change is never skipped. A crash between
+/// the two applies every change since the last save again on restart, up to `save_seconds` of
+/// them, so what was learned in that time counts twice.
+fn save(models: &mut MarkovStore, seq: u64) -> Result<()> {
+    models.save()?;
+    fs::write(SEQ_PATH, seq.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(payloads: &[&[u8]]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for (i, payload) in payloads.iter().enumerate() {
+            bytes.extend_from_slice(&header(i as u64 + 1, payload.len()));
+            bytes.extend_from_slice(payload);
+        }
+        bytes
+    }
+
+    #[test]
+    fn frames_read_back_as_written() {
+        let bytes = log(&[b"one", b"", b"three"]);
+        let (seq, payload, rest) = frame(&bytes).unwrap();
+        assert_eq!((seq, payload), (1, &b"one"[..]));
+        let (seq, payload, rest) = frame(rest).unwrap();
+        assert_eq!((seq, payload), (2, &b""[..]));
+        let (seq, payload, rest) = frame(rest).unwrap();
+        assert_eq!((seq, payload), (3, &b"three"[..]));
+        assert!(rest.is_empty());
+        assert_eq!(whole_frames(&bytes), (3, bytes.len()));
+    }
+
+    #[test]
+    fn changes_read_back_as_written() {
+        let change = Change::RemoveContaining {
+            scope: Scope::Global,
+            word: String::from("cat"),
+        };
+        let payload = bincode::serialize(&change).unwrap();
+        let bytes = log(&[&payload]);
+        let (_, payload, _) = frame(&bytes).unwrap();
+        match bincode::deserialize(payload).unwrap() {
+            Change::RemoveContaining { scope, word } => {
+                assert_eq!((scope, word.as_str()), (Scope::Global, "cat"))
+            }
+            c => panic!("read back {:?}", c),
+        }
+    }
+
+    #[test]
+    fn a_torn_tail_isnt_a_frame() {
+        let whole = log(&[b"one", b"two"]);
+        for cut in 1..HEADER_LEN + 3 {
+            let mut bytes = whole.clone();
+            bytes.extend_from_slice(&log(&[b"one", b"two", b"three"])[whole.len()..][..cut]);
+            assert_eq!(whole_frames(&bytes), (2, whole.len()), "cut at {}", cut);
+        }
+        assert_eq!(whole_frames(&whole[..5]), (0, 0));
+    }
+}
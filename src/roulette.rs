@@ -0,0 +1,216 @@
+//! Impersonation roulette for `eg!roulette`: once a week, in servers that turn it on, the bot says
+//! something one of the players might say, from what it remembers them saying, and everyone guesses
+//! who with buttons until it reveals who it was. Only people who join are ever impersonated.
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::bot::types::{ActionRow, Button, ButtonStyle, Id};
+
+pub const BUTTON_PREFIX: &str = "roulette:";
+/// Who it could be, whoever it is included
+pub const CHOICES: usize = 4;
+pub const WEEK_MILLIS: i64 = 7 * 24 * 60 * 60 * 1000;
+const SHOWN_SCORES: usize = 10;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Round {
+    channel: Id,
+    message: Option<Id>,
+    text: String,
+    /// Who it could be, with the names they're guessed by
+    choices: Vec<(Id, String)>,
+    /// Which of `choices` it is
+    answer: usize,
+    /// Who everyone guessed, by user
+    guesses: HashMap<Id, usize>,
+}
+
+impl Round {
+    fn quote(&self) -> String {
+        format!("🎭 **Who said it?**\n> {}", self.text)
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct Game {
+    /// Where rounds are posted, while it's on
+    channel: Option<Id>,
+    /// Everyone who can be impersonated, with the name they're guessed by
+    players: BTreeMap<Id, String>,
+    scores: HashMap<Id, u32>,
+    round: Option<Round>,
+}
+
+pub enum Guess<'a> {
+    Counted(&'a str),
+    Over,
+    /// They're the one being impersonated
+    Themselves,
+}
+
+/// Every server's game.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Roulette {
+    games: HashMap<Id, Game>,
+}
+
+impl Roulette {
+    /// Posts `guild`'s rounds in `channel` from now on. Returns whether it was off.
+    pub fn turn_on(&mut self, guild: Id, channel: Id) -> bool {
+        let game = self.games.entry(guild).or_default();
+        game.channel.replace(channel).is_none()
+    }
+
+    /// Returns whether it was on.
+    pub fn turn_off(&mut self, guild: Id) -> bool {
+        self.games
+            .get_mut(&guild)
+            .and_then(|g| g.channel.take())
+            .is_some()
+    }
+
+    /// Where `guild`'s rounds go, if it's on.
+    pub fn channel(&self, guild: Id) -> Option<Id> {
+        self.games.get(&guild)?.channel
+    }
+
+    /// Returns whether they weren't playing already.
+    pub fn join(&mut self, guild: Id, user: Id, name: &str) -> bool {
+        let players = &mut self.games.entry(guild).or_default().players;
+        players.insert(user, String::from(name)).is_none()
+    }
+
+    pub fn leave(&mut self, guild: Id, user: Id) -> bool {
+        self.games
+            .get_mut(&guild)
+            .is_some_and(|g| g.players.remove(&user).is_some())
+    }
+
+    pub fn players(&self, guild: Id) -> Vec<Id> {
+        self.games
+            .get(&guild)
+            .map_or_else(Vec::new, |g| g.players.keys().copied().collect())
+    }
+
+    pub fn is_running(&self, guild: Id) -> bool {
+        self.games.get(&guild).is_some_and(|g| g.round.is_some())
+    }
+
+    /// Starts a round of `guild`'s, if it's on, impersonating `target` with `text`, with other
+    /// players to choose from too. Returns what to post, or `None` if there's nobody else.
+    pub fn start(
+        &mut self,
+        guild: Id,
+        target: Id,
+        text: String,
+        rng: &mut impl Rng,
+    ) -> Option<(String, Vec<ActionRow>)> {
+        let game = self.games.get_mut(&guild)?;
+        let channel = game.channel?;
+        let others: Vec<_> = game.players.keys().filter(|&&p| p != target).collect();
+        let mut choices: Vec<_> = others
+            .choose_multiple(rng, CHOICES - 1)
+            .chain([&&target])
+            .map(|&&p| (p, game.players[&p].clone()))
+            .collect();
+        if choices.len() < 2 {
+            return None;
+        }
+        choices.shuffle(rng);
+        let answer = choices.iter().position(|&(p, _)| p == target)?;
+        let buttons = choices
+            .iter()
+            .enumerate()
+            .map(|(i, (_, name))| {
+                Button::new(
+                    ButtonStyle::Primary,
+                    name.as_str(),
+                    format!("{}{}", BUTTON_PREFIX, i),
+                )
+            })
+            .collect();
+        let round = Round {
+            channel,
+            message: None,
+            text,
+            choices,
+            answer,
+            guesses: HashMap::new(),
+        };
+        let text = round.quote() + "\nGuess with the buttons before it's revealed!";
+        game.round = Some(round);
+        Some((text, vec![ActionRow::new(buttons)]))
+    }
+
+    pub fn posted(&mut self, guild: Id, message: Id) {
+        if let Some(round) = self.games.get_mut(&guild).and_then(|g| g.round.as_mut()) {
+            round.message = Some(message);
+        }
+    }
+
+    /// Records `user`'s guess from a button on `message`, which they can change until the reveal.
+    pub fn guess(&mut self, guild: Id, message: Id, user: Id, choice: usize) -> Guess<'_> {
+        let round = match self.games.get_mut(&guild).and_then(|g| g.round.as_mut()) {
+            Some(r) if r.message == Some(message) && choice < r.choices.len() => r,
+            _ => return Guess::Over,
+        };
+        if round.choices[round.answer].0 == user {
+            return Guess::Themselves;
+        }
+        round.guesses.insert(user, choice);
+        Guess::Counted(&round.choices[choice].1)
+    }
+
+    /// Ends `guild`'s round on `message`, giving everyone who guessed right a point, or whoever
+    /// was impersonated one if nobody did. Returns the channel it's in and what it should say now.
+    pub fn reveal(&mut self, guild: Id, message: Id) -> Option<(Id, String)> {
+        let game = self.games.get_mut(&guild)?;
+        if game.round.as_ref()?.message != Some(message) {
+            return None;
+        }
+        let round = game.round.take()?;
+        let (target, name) = &round.choices[round.answer];
+        let mut right: Vec<_> = round
+            .guesses
+            .iter()
+            .filter(|&(_, &g)| g == round.answer)
+            .map(|(&user, _)| user)
+            .collect();
+        right.sort();
+        let mut text = format!("{}\nIt was **{}**!", round.quote(), name);
+        if right.is_empty() {
+            *game.scores.entry(*target).or_default() += 1;
+            text += " Nobody guessed it, so they get the point.";
+        } else {
+            for user in &right {
+                *game.scores.entry(*user).or_default() += 1;
+            }
+            let mentions: Vec<_> = right.iter().map(|u| u.mention_user()).collect();
+            text += &format!(" A point each for {}.", mentions.join(", "));
+        }
+        Some((round.channel, text))
+    }
+
+    /// Everyone with a point in `guild`, most first.
+    pub fn scores(&self, guild: Id) -> String {
+        let mut scores: Vec<_> = self
+            .games
+            .get(&guild)
+            .map(|g| g.scores.iter().map(|(&u, &s)| (u, s)).collect())
+            .unwrap_or_default();
+        if scores.is_empty() {
+            return String::from("Nobody has any points yet");
+        }
+        scores.sort_by_key(|&(user, score)| (std::cmp::Reverse(score), user));
+        let lines: Vec<_> = scores
+            .iter()
+            .take(SHOWN_SCORES)
+            .enumerate()
+            .map(|(i, (user, score))| format!("{}. {} — {}", i + 1, user.mention_user(), score))
+            .collect();
+        format!("🎭 **Impersonation roulette**\n{}", lines.join("\n"))
+    }
+}
@@ -0,0 +1,138 @@
+//! Picking an index at random in proportion to its weight, which every word a model generates
+//! comes from. Weights are kept as running sums, so changing one never fails the way rebuilding a
+//! `WeightedIndex` can, and everything weighing nothing is allowed: there's just nothing to pick.
+//!
+//! It picks exactly what `WeightedIndex` would from the same rng, so seeded output doesn't change.
+
+use rand::distributions::{Distribution, Uniform};
+use rand::Rng;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cumulative {
+    /// Each weight plus all the ones before it
+    sums: Vec<usize>,
+}
+
+impl Cumulative {
+    pub fn new(weights: impl IntoIterator<Item = usize>) -> Self {
+        let mut sums = Vec::new();
+        let mut total = 0;
+        for weight in weights {
+            total += weight;
+            sums.push(total);
+        }
+        Cumulative { sums }
+    }
+
+    pub fn len(&self) -> usize {
+        self.sums.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sums.is_empty()
+    }
+
+    /// All the weights added up.
+    pub fn total(&self) -> usize {
+        self.sums.last().copied().unwrap_or(0)
+    }
+
+    pub fn weight(&self, index: usize) -> usize {
+        match index {
+            0 => self.sums[0],
+            i => self.sums[i] - self.sums[i - 1],
+        }
+    }
+
+    /// An index, each as likely as its share of the total, or `None` if everything weighs nothing.
+    /// Indices weighing nothing are never picked.
+    pub fn sample(&self, rng: &mut impl Rng) -> Option<usize> {
+        let total = self.total();
+        if total == 0 {
+            return None;
+        }
+        let chosen = Uniform::new(0, total).sample(rng);
+        Some(self.sums.partition_point(|&sum| sum <= chosen))
+    }
+
+    /// Adds one more index at the end.
+    pub fn push(&mut self, weight: usize) {
+        self.sums.push(self.total() + weight);
+    }
+
+    /// Changes `index`'s weight to `weight`.
+    pub fn set(&mut self, index: usize, weight: usize) {
+        let old = self.weight(index);
+        for sum in &mut self.sums[index..] {
+            *sum = *sum - old + weight;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::distributions::WeightedIndex;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn keeps_weights_as_running_sums() {
+        let sums = Cumulative::new([3, 0, 5]);
+        assert_eq!(sums.len(), 3);
+        assert_eq!(sums.total(), 8);
+        assert_eq!(
+            (0..3).map(|i| sums.weight(i)).collect::<Vec<_>>(),
+            [3, 0, 5]
+        );
+    }
+
+    #[test]
+    fn picks_what_weighted_index_would() {
+        let weights = [1, 7, 0, 2, 11];
+        let sums = Cumulative::new(weights);
+        let dist = WeightedIndex::new(weights).unwrap();
+        let (mut a, mut b) = (StdRng::seed_from_u64(7), StdRng::seed_from_u64(7));
+        for _ in 0..1000 {
+            assert_eq!(sums.sample(&mut a), Some(dist.sample(&mut b)));
+        }
+    }
+
+    #[test]
+    fn never_picks_what_weighs_nothing() {
+        let sums = Cumulative::new([0, 4, 0, 1, 0]);
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..1000 {
+            let picked = sums.sample(&mut rng).unwrap();
+            assert!(picked == 1 || picked == 3, "picked {}", picked);
+        }
+    }
+
+    #[test]
+    fn picks_nothing_when_everything_weighs_nothing() {
+        let mut rng = StdRng::seed_from_u64(1);
+        assert_eq!(Cumulative::default().sample(&mut rng), None);
+        assert_eq!(Cumulative::new([0, 0]).sample(&mut rng), None);
+    }
+
+    #[test]
+    fn set_changes_one_weight() {
+        let mut sums = Cumulative::new([2, 3, 4]);
+        sums.set(1, 10);
+        assert_eq!(sums, Cumulative::new([2, 10, 4]));
+        sums.set(0, 0);
+        assert_eq!(sums, Cumulative::new([0, 10, 4]));
+        sums.set(2, 0);
+        sums.set(1, 0);
+        assert_eq!(sums.total(), 0);
+        assert_eq!(sums.sample(&mut StdRng::seed_from_u64(1)), None);
+    }
+
+    #[test]
+    fn push_adds_to_the_end() {
+        let mut sums = Cumulative::new([1]);
+        sums.push(0);
+        sums.push(6);
+        assert_eq!(sums, Cumulative::new([1, 0, 6]));
+    }
+}
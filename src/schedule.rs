@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Scheduler<J> {
+    jobs: Vec<(i64, J)>,
+}
+
+impl<J> Default for Scheduler<J> {
+    fn default() -> Self {
+        Scheduler { jobs: Vec::new() }
+    }
+}
+
+impl<J> Scheduler<J> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn schedule(&mut self, at_millis: i64, job: J) {
+        self.jobs.push((at_millis, job));
+    }
+
+    pub fn take_due(&mut self, now_millis: i64) -> Vec<J> {
+        let (mut due, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.jobs)
+            .into_iter()
+            .partition(|(at, _)| *at <= now_millis);
+        self.jobs = pending;
+        due.sort_by_key(|(at, _)| *at);
+        due.into_iter().map(|(_, job)| job).collect()
+    }
+
+    /// Takes out the jobs picked by `which`, with when they're due.
+    pub fn take_where(&mut self, which: impl Fn(&J) -> bool) -> Vec<(i64, J)> {
+        let (taken, kept) = std::mem::take(&mut self.jobs)
+            .into_iter()
+            .partition(|(_, j)| which(j));
+        self.jobs = kept;
+        taken
+    }
+
+    pub fn any(&self, f: impl Fn(&J) -> bool) -> bool {
+        self.jobs.iter().any(|(_, j)| f(j))
+    }
+}
+
+pub fn now_millis() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
@@ -0,0 +1,147 @@
+//! Per-guild rhai hooks. A script can define any of
+//!
+//! - `on_output(text)`: returns the text to send instead of something the bot generated
+//! - `on_message(author, text)`: returns a reply to send, or `()` for none
+
+use anyhow::{anyhow, bail, Result};
+use rhai::{Dynamic, Engine, AST};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::bot::types::Id;
+use crate::persist::load_or_default;
+
+pub const MAX_SCRIPTS: usize = 10;
+pub const MAX_SOURCE_LEN: usize = 4000;
+
+type Sources = HashMap<Id, BTreeMap<String, String>>;
+
+pub struct ScriptHooks {
+    engine: Engine,
+    sources: Sources,
+    compiled: HashMap<Id, BTreeMap<String, AST>>,
+}
+
+impl ScriptHooks {
+    pub fn load(path: &str) -> Self {
+        let mut engine = Engine::new();
+        engine.set_max_operations(50_000);
+        engine.set_max_call_levels(16);
+        engine.set_max_expr_depths(32, 16);
+        engine.set_max_string_size(4000);
+        engine.set_max_array_size(500);
+        engine.set_max_map_size(500);
+        engine.on_print(|_| ());
+        engine.on_debug(|_, _, _| ());
+
+        let sources: Sources = load_or_default(path);
+        let mut compiled: HashMap<_, BTreeMap<_, _>> = HashMap::new();
+        for (&guild, scripts) in &sources {
+            for (name, source) in scripts {
+                match engine.compile(source) {
+                    Ok(ast) => {
+                        compiled.entry(guild).or_default().insert(name.clone(), ast);
+                    }
+                    Err(e) => eprintln!("script {}: {}", name, e),
+                }
+            }
+        }
+        ScriptHooks {
+            engine,
+            sources,
+            compiled,
+        }
+    }
+
+    pub fn sources(&self) -> &Sources {
+        &self.sources
+    }
+
+    pub fn install(&mut self, guild: Id, name: &str, source: &str) -> Result<()> {
+        if source.len() > MAX_SOURCE_LEN {
+            bail!("scripts can be at most {} bytes", MAX_SOURCE_LEN);
+        }
+        let scripts = self.sources.entry(guild).or_default();
+        if !scripts.contains_key(name) && scripts.len() >= MAX_SCRIPTS {
+            bail!("a server can only have {} scripts", MAX_SCRIPTS);
+        }
+        let ast = self.engine.compile(source)?;
+        if !ast
+            .iter_functions()
+            .any(|f| f.name == "on_output" || f.name == "on_message")
+        {
+            bail!("scripts have to define `on_output` or `on_message`");
+        }
+        scripts.insert(String::from(name), String::from(source));
+        self.compiled
+            .entry(guild)
+            .or_default()
+            .insert(String::from(name), ast);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, guild: Id, name: &str) -> bool {
+        if let Some(compiled) = self.compiled.get_mut(&guild) {
+            compiled.remove(name);
+        }
+        self.sources
+            .get_mut(&guild)
+            .and_then(|s| s.remove(name))
+            .is_some()
+    }
+
+    pub fn names(&self, guild: Id) -> impl Iterator<Item = &str> {
+        self.sources
+            .get(&guild)
+            .into_iter()
+            .flat_map(|s| s.keys().map(String::as_str))
+    }
+
+    pub fn on_output(&self, guild: Id, text: String) -> String {
+        self.scripts(guild, "on_output")
+            .fold(text, |text, (name, ast)| {
+                match self.call(ast, "on_output", (text.clone(),)) {
+                    Ok(out) => out.into_string().unwrap_or(text),
+                    Err(e) => {
+                        eprintln!("script {}: {}", name, e);
+                        text
+                    }
+                }
+            })
+    }
+
+    pub fn on_message(&self, guild: Id, author: &str, text: &str) -> Vec<String> {
+        self.scripts(guild, "on_message")
+            .filter_map(|(name, ast)| {
+                match self.call(
+                    ast,
+                    "on_message",
+                    (String::from(author), String::from(text)),
+                ) {
+                    Ok(out) => out.into_string().ok().filter(|s| !s.trim().is_empty()),
+                    Err(e) => {
+                        eprintln!("script {}: {}", name, e);
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    fn scripts<'a>(
+        &'a self,
+        guild: Id,
+        hook: &'a str,
+    ) -> impl Iterator<Item = (&'a String, &'a AST)> + 'a {
+        self.compiled
+            .get(&guild)
+            .into_iter()
+            .flatten()
+            .filter(move |(_, ast)| ast.iter_functions().any(|f| f.name == hook))
+    }
+
+    fn call(&self, ast: &AST, hook: &str, args: impl rhai::FuncArgs) -> Result<Dynamic> {
+        self.engine
+            .call_fn(&mut rhai::Scope::new(), ast, hook, args)
+            .map_err(|e| anyhow!("{}", e))
+    }
+}
@@ -0,0 +1,98 @@
+//! Where secrets like the bot token come from, and keeping them out of the logs.
+//!
+//! A secret called `token` is looked for, in order, in the `TACO_BOT_TOKEN` environment variable,
+//! a systemd credential (`$CREDENTIALS_DIRECTORY/token`), a docker secret (`/run/secrets/token`),
+//! and finally `bot.json`.
+
+use anyhow::{bail, Context, Result};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::bot::types::TokenBuf;
+
+const DOCKER_SECRETS_DIR: &str = "/run/secrets";
+
+/// Every secret handed out so far, to scrub from anything that gets logged
+static KNOWN: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Finds the secret called `name`, falling back to `configured` if it isn't anywhere else.
+pub fn load(name: &str, configured: Option<&str>) -> Result<Option<String>> {
+    let var = format!("TACO_BOT_{}", name.to_uppercase());
+    let secret = match env::var(&var) {
+        Ok(s) => Some(s),
+        Err(env::VarError::NotPresent) => {
+            let files = env::var_os("CREDENTIALS_DIRECTORY")
+                .map(PathBuf::from)
+                .into_iter()
+                .chain(Some(PathBuf::from(DOCKER_SECRETS_DIR)))
+                .map(|dir| dir.join(name));
+            let mut found = None;
+            for path in files {
+                match fs::read_to_string(&path) {
+                    Ok(s) => {
+                        found = Some(s);
+                        break;
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e).with_context(|| path.display().to_string()),
+                }
+            }
+            found.or_else(|| configured.map(String::from))
+        }
+        Err(e) => return Err(e).context(var),
+    };
+    let secret = secret.map(|s| String::from(s.trim()));
+    if let Some(s) = &secret {
+        remember(s);
+    }
+    Ok(secret)
+}
+
+/// The bot token, wherever it's kept.
+pub fn token(configured: Option<&TokenBuf>) -> Result<TokenBuf> {
+    let token = match load("token", configured.map(|t| t.as_str()))? {
+        Some(t) => t,
+        None => {
+            bail!("no bot token: set TACO_BOT_TOKEN, add a `token` secret, or put it in bot.json")
+        }
+    };
+    validate_token(&token)?;
+    Ok(TokenBuf::from(token))
+}
+
+/// Catches the usual mistakes: a `Bot ` prefix, quotes, or something that isn't a token at all.
+pub fn validate_token(token: &str) -> Result<()> {
+    let parts: Vec<_> = token.split('.').collect();
+    let well_formed = parts.len() == 3
+        && parts.iter().all(|p| {
+            !p.is_empty()
+                && p.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        });
+    if !well_formed {
+        bail!("the bot token doesn't look like a Discord token (three dot-separated parts, without `Bot `)");
+    }
+    Ok(())
+}
+
+fn remember(secret: &str) {
+    // short secrets would redact half of every message
+    const MIN_LEN: usize = 8;
+    if secret.len() < MIN_LEN {
+        return;
+    }
+    let mut known = KNOWN.lock().unwrap_or_else(|e| e.into_inner());
+    if !known.iter().any(|k| k == secret) {
+        known.push(String::from(secret));
+    }
+}
+
+/// `text` with every secret loaded so far blanked out.
+pub fn redact(text: &str) -> String {
+    let known = KNOWN.lock().unwrap_or_else(|e| e.into_inner());
+    known.iter().fold(String::from(text), |text, secret| {
+        text.replace(secret.as_str(), "[redacted]")
+    })
+}
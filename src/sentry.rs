@@ -0,0 +1,192 @@
+//! Sending errors and panics to Sentry, or anything else that takes its envelopes, so whoever runs
+//! the bot hears about failures nobody reports. What's sent is scrubbed of anything personal first.
+
+use anyhow::{anyhow, bail, Result};
+use rand::Rng;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Once;
+
+use crate::bot::types::Id;
+use crate::secrets;
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct SentryConfig {
+    /// Like `https://PUBLIC_KEY@o0.ingest.sentry.io/PROJECT_ID`
+    pub dsn: String,
+    #[serde(default = "default_environment")]
+    pub environment: String,
+}
+
+fn default_environment() -> String {
+    String::from("production")
+}
+
+/// Where reports go, parsed from the DSN.
+#[derive(Clone, Debug)]
+pub struct Sentry {
+    dsn: String,
+    endpoint: String,
+    auth: String,
+    environment: String,
+    /// What's replaced in everything sent, and with what
+    scrubbers: Vec<(Regex, &'static str)>,
+}
+
+/// What a report is about.
+#[derive(Default, Debug)]
+pub struct Context<'a> {
+    pub guild: Option<Id>,
+    pub command: Option<&'a str>,
+    pub trace: Option<String>,
+}
+
+impl Sentry {
+    pub fn new(cfg: &SentryConfig) -> Result<Self> {
+        let invalid = || anyhow!("the sentry dsn should look like https://KEY@HOST/PROJECT");
+        let (scheme, rest) = cfg.dsn.split_once("://").ok_or_else(invalid)?;
+        let (key, rest) = rest.split_once('@').ok_or_else(invalid)?;
+        let (host, project) = rest.rsplit_once('/').ok_or_else(invalid)?;
+        let key = key.split(':').next().unwrap_or(key);
+        if key.is_empty() || host.is_empty() || project.is_empty() {
+            bail!(invalid());
+        }
+        Ok(Sentry {
+            dsn: cfg.dsn.clone(),
+            endpoint: format!("{}://{}/api/{}/envelope/", scheme, host, project),
+            auth: format!(
+                "Sentry sentry_version=7, sentry_key={}, sentry_client=taco_bot/{}",
+                key,
+                env!("CARGO_PKG_VERSION")
+            ),
+            environment: cfg.environment.clone(),
+            scrubbers: [
+                (r"<[@#][!&]?\d+>", "[mention]"),
+                (r"\b\d{15,20}\b", "[id]"),
+                (r"[\w.+-]+@[\w-]+\.[\w.-]+", "[email]"),
+                (r"\b\d{1,3}(\.\d{1,3}){3}\b", "[ip]"),
+                (r"`[^`]*`", "`[text]`"),
+                (r#""[^"]*""#, "\"[text]\""),
+            ]
+            .iter()
+            .map(|&(re, with)| Ok((Regex::new(re)?, with)))
+            .collect::<Result<_>>()?,
+        })
+    }
+
+    /// `text` without secrets, ids, mentions, email or IP addresses, or anything quoted, which is
+    /// usually what somebody said.
+    pub fn scrub(&self, text: &str) -> String {
+        self.scrubbers
+            .iter()
+            .fold(secrets::redact(text), |text, (re, with)| {
+                re.replace_all(&text, *with).into_owned()
+            })
+    }
+
+    /// Reports `error` in the background.
+    pub fn report(&self, error: &anyhow::Error, context: Context<'_>) {
+        let envelope = self.envelope("error", "error", &format!("{:#}", error), &context);
+        let sentry = self.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = sentry.send(envelope) {
+                eprintln!("couldn't report an error to sentry: {}", e);
+            }
+        });
+    }
+
+    /// Reports panics from here on, as they happen, before the usual panic message. Only the first
+    /// call does anything.
+    pub fn install_panic_hook(&self) {
+        static INSTALLED: Once = Once::new();
+        let sentry = self.clone();
+        INSTALLED.call_once(move || {
+            let previous = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                let envelope =
+                    sentry.envelope("panic", "fatal", &info.to_string(), &Context::default());
+                if let Err(e) = sentry.send(envelope) {
+                    eprintln!("couldn't report a panic to sentry: {}", e);
+                }
+                previous(info);
+            }));
+        });
+    }
+
+    fn envelope(&self, kind: &str, level: &str, text: &str, context: &Context<'_>) -> Vec<u8> {
+        #[derive(Serialize)]
+        struct Event<'a> {
+            event_id: &'a str,
+            timestamp: f64,
+            platform: &'static str,
+            level: &'a str,
+            release: String,
+            environment: &'a str,
+            exception: Exceptions<'a>,
+            tags: BTreeMap<&'static str, String>,
+        }
+        #[derive(Serialize)]
+        struct Exceptions<'a> {
+            values: [Exception<'a>; 1],
+        }
+        #[derive(Serialize)]
+        struct Exception<'a> {
+            #[serde(rename = "type")]
+            kind: &'a str,
+            value: String,
+        }
+
+        let event_id: String = (0..32)
+            .map(|_| std::char::from_digit(rand::thread_rng().gen_range(0, 16), 16).unwrap())
+            .collect();
+        let mut tags = BTreeMap::new();
+        if let Some(guild) = context.guild {
+            tags.insert("guild", guild.to_string());
+        }
+        if let Some(command) = context.command {
+            tags.insert("command", self.scrub(command));
+        }
+        if let Some(trace) = &context.trace {
+            tags.insert("trace", trace.clone());
+        }
+        let event = Event {
+            event_id: &event_id,
+            timestamp: chrono::Utc::now().timestamp_millis() as f64 / 1000.0,
+            platform: "other",
+            level,
+            release: format!("taco_bot@{}", env!("CARGO_PKG_VERSION")),
+            environment: &self.environment,
+            exception: Exceptions {
+                values: [Exception {
+                    kind,
+                    value: self.scrub(text),
+                }],
+            },
+            tags,
+        };
+        let header = serde_json::json!({ "event_id": event_id, "dsn": self.dsn });
+        let mut envelope = Vec::new();
+        for line in [
+            header,
+            serde_json::json!({ "type": "event" }),
+            serde_json::to_value(&event).expect("events serialize"),
+        ] {
+            envelope.extend(line.to_string().into_bytes());
+            envelope.push(b'\n');
+        }
+        envelope
+    }
+
+    fn send(&self, envelope: Vec<u8>) -> Result<()> {
+        let request = http::Request::post(&self.endpoint)
+            .header("X-Sentry-Auth", &self.auth)
+            .header("Content-Type", "application/x-sentry-envelope")
+            .body(envelope)?;
+        let response = isahc::send(request)?;
+        if !response.status().is_success() {
+            bail!("{} from sentry", response.status());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,395 @@
+//! Default generation settings for a whole guild, a category or a single channel. Each level only
+//! overrides what it sets, so a channel inherits the rest from its category and then its guild.
+//!
+//! Everything that affects generation cascades the same way, from the bot's defaults down to the
+//! user asking: defaults → guild → category → channel → user. What that comes out to for each
+//! place and user is cached in `Resolved` until something it came from changes.
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use crate::bot::types::Id;
+use crate::corpus::{self, Corpora, Mix};
+use crate::markov::Sampling;
+use crate::prefs::{UserPrefs, MAX_TEMPERATURE, MIN_TEMPERATURE};
+use crate::store::is_valid_name;
+use crate::transform::Transform;
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct Settings {
+    pub temperature: Option<f64>,
+    pub sampling: Option<Sampling>,
+    pub style: Option<String>,
+    pub mix: Option<Mix>,
+    pub transforms: Option<Vec<Transform>>,
+    /// Words (lowercase) generated less often, without unlearning them
+    pub suppress: Option<BTreeSet<String>>,
+    /// The chance of answering any message on its own, from 0 to 1
+    pub chatter: Option<f64>,
+    /// The chance when it's mentioned or named instead
+    pub chatter_mentioned: Option<f64>,
+    /// Seconds to wait after chattering in a channel before doing it there again
+    pub chatter_cooldown: Option<u64>,
+    /// Words a sentence is cut off at
+    pub max_words: Option<usize>,
+    /// Words a sentence needs, or it's generated again
+    pub min_words: Option<usize>,
+}
+
+const DEFAULT_CHATTER_COOLDOWN: u64 = 60;
+/// The most `max_words` and `min_words` can be
+pub const MAX_WORDS: usize = 500;
+
+impl Settings {
+    /// What a guild set with `eg!mix` and `eg!transforms`.
+    pub fn of_guild(mix: Option<&Mix>, transforms: &[Transform]) -> Self {
+        Settings {
+            mix: mix.cloned(),
+            transforms: Some(transforms.to_vec()).filter(|ts| !ts.is_empty()),
+            ..Settings::default()
+        }
+    }
+
+    /// What a user set with `eg!prefs`.
+    pub fn of_user(prefs: &UserPrefs) -> Self {
+        Settings {
+            temperature: prefs.temperature,
+            style: prefs.persona.clone(),
+            ..Settings::default()
+        }
+    }
+
+    /// `self` with anything `other` sets taking its place.
+    fn overlay(mut self, other: Option<&Settings>) -> Self {
+        if let Some(other) = other {
+            self.temperature = other.temperature.or(self.temperature);
+            self.sampling = other.sampling.or(self.sampling);
+            self.style = other.style.clone().or(self.style);
+            self.mix = other.mix.clone().or(self.mix);
+            self.transforms = other.transforms.clone().or(self.transforms);
+            self.suppress = other.suppress.clone().or(self.suppress);
+            self.chatter = other.chatter.or(self.chatter);
+            self.chatter_mentioned = other.chatter_mentioned.or(self.chatter_mentioned);
+            self.chatter_cooldown = other.chatter_cooldown.or(self.chatter_cooldown);
+            self.max_words = other.max_words.or(self.max_words);
+            self.min_words = other.min_words.or(self.min_words);
+        }
+        self
+    }
+
+    pub fn temperature(&self) -> f64 {
+        self.temperature.unwrap_or(1.0)
+    }
+
+    /// The chance of chattering, depending on whether the message mentions or names the bot.
+    pub fn chatter_chance(&self, mentioned: bool) -> f64 {
+        let chance = match mentioned {
+            true => self.chatter_mentioned.or(self.chatter),
+            false => self.chatter,
+        };
+        chance.unwrap_or(0.0)
+    }
+
+    pub fn chatter_cooldown(&self) -> u64 {
+        self.chatter_cooldown.unwrap_or(DEFAULT_CHATTER_COOLDOWN)
+    }
+
+    fn set(&mut self, key: &str, values: &[&str], corpora: &Corpora) -> Result<()> {
+        let cleared = values == ["none"];
+        match key {
+            "temperature" if cleared => self.temperature = None,
+            "temperature" => match values {
+                [t] => match t.parse::<f64>()? {
+                    t if (MIN_TEMPERATURE..=MAX_TEMPERATURE).contains(&t) => {
+                        self.temperature = Some(t)
+                    }
+                    _ => bail!(
+                        "temperature has to be between {} and {}",
+                        MIN_TEMPERATURE,
+                        MAX_TEMPERATURE
+                    ),
+                },
+                _ => bail!("expected one temperature"),
+            },
+            "sampling" if cleared => self.sampling = None,
+            "sampling" => match Sampling::parse(values) {
+                Some(s) => self.sampling = Some(s),
+                None => bail!("sampling is `weighted`, `greedy`, `top-k N` or `top-p P` (0 to 1)"),
+            },
+            "style" if cleared => self.style = None,
+            "style" => match values {
+                [s] if is_valid_name(&s.to_lowercase()) => self.style = Some(s.to_lowercase()),
+                [_] => bail!("style names can only have letters, numbers and `_`"),
+                _ => bail!("expected one style"),
+            },
+            "mix" if cleared => self.mix = None,
+            "mix" => {
+                let mut mix = Mix::new();
+                for arg in values {
+                    let (name, weight) = match arg.find('=') {
+                        Some(i) => (&arg[..i], arg[i + 1..].parse::<f64>()?),
+                        None => bail!("expected `NAME=WEIGHT`, got `{}`", arg),
+                    };
+                    mix.insert(String::from(name), weight);
+                }
+                corpus::validate_mix(&mix, corpora)?;
+                self.mix = Some(mix);
+            }
+            "transforms" if cleared => self.transforms = None,
+            "transforms" => {
+                let mut transforms = Vec::with_capacity(values.len());
+                for name in values {
+                    match Transform::parse(name) {
+                        Some(t) => transforms.push(t),
+                        None => bail!("there's no transform called `{}`", name),
+                    }
+                }
+                self.transforms = Some(transforms);
+            }
+            "suppress" if cleared => self.suppress = None,
+            "suppress" => match values {
+                [] => bail!("expected words to suppress"),
+                words => self.suppress = Some(words.iter().map(|w| w.to_lowercase()).collect()),
+            },
+            "chatter" if cleared => self.chatter = None,
+            "chatter" => self.chatter = Some(chance(values)?),
+            "chatter_mentioned" if cleared => self.chatter_mentioned = None,
+            "chatter_mentioned" => self.chatter_mentioned = Some(chance(values)?),
+            "chatter_cooldown" if cleared => self.chatter_cooldown = None,
+            "chatter_cooldown" => match values {
+                [s] => self.chatter_cooldown = Some(s.trim_end_matches('s').parse()?),
+                _ => bail!("expected a number of seconds"),
+            },
+            "max_words" if cleared => self.max_words = None,
+            "max_words" => self.max_words = Some(words(values)?),
+            "min_words" if cleared => self.min_words = None,
+            "min_words" => self.min_words = Some(words(values)?),
+            k => bail!("unknown setting `{}`", k),
+        }
+        Ok(())
+    }
+}
+
+/// A chance from 0 to 1, or a percentage like `5%`.
+fn chance(values: &[&str]) -> Result<f64> {
+    let chance = match values {
+        [c] => match c.strip_suffix('%') {
+            Some(percent) => percent.parse::<f64>()? / 100.0,
+            None => c.parse::<f64>()?,
+        },
+        _ => bail!("expected one chance"),
+    };
+    if !(0.0..=1.0).contains(&chance) {
+        bail!("a chance has to be between 0 and 1, or 0% and 100%");
+    }
+    Ok(chance)
+}
+
+/// A number of words for `max_words` or `min_words`.
+fn words(values: &[&str]) -> Result<usize> {
+    match values {
+        [n] => match n.parse()? {
+            n @ 1..=MAX_WORDS => Ok(n),
+            _ => bail!("a number of words has to be between 1 and {}", MAX_WORDS),
+        },
+        _ => bail!("expected a number of words"),
+    }
+}
+
+impl fmt::Display for Settings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.temperature {
+            Some(t) => write!(f, "temperature: {}", t)?,
+            None => write!(f, "temperature: default")?,
+        }
+        match self.sampling {
+            Some(s) => write!(f, ", sampling: {}", s)?,
+            None => write!(f, ", sampling: default")?,
+        }
+        write!(f, ", style: {}", self.style.as_deref().unwrap_or("default"))?;
+        match &self.mix {
+            Some(mix) => {
+                write!(f, ", mix:")?;
+                for (name, weight) in mix {
+                    write!(f, " {}={}", name, weight)?;
+                }
+            }
+            None => write!(f, ", mix: default")?,
+        }
+        match &self.transforms {
+            Some(ts) => {
+                let names: Vec<_> = ts.iter().map(|t| t.name()).collect();
+                write!(f, ", transforms: {}", names.join(" "))?
+            }
+            None => write!(f, ", transforms: default")?,
+        }
+        match &self.suppress {
+            Some(words) => {
+                let words: Vec<_> = words.iter().map(String::as_str).collect();
+                write!(f, ", suppressed: {}", words.join(" "))?
+            }
+            None => write!(f, ", suppressed: nothing")?,
+        }
+        let percent = |c: f64| format!("{}%", c * 100.0);
+        write!(
+            f,
+            ", chatter: {}",
+            self.chatter.map_or_else(|| String::from("off"), percent)
+        )?;
+        if let Some(c) = self.chatter_mentioned {
+            write!(f, " ({} when mentioned)", percent(c))?;
+        }
+        write!(f, ", chatter cooldown: {}s", self.chatter_cooldown())?;
+        if let Some(n) = self.max_words {
+            write!(f, ", max words: {}", n)?;
+        }
+        if let Some(n) = self.min_words {
+            write!(f, ", min words: {}", n)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Level {
+    Guild(Id),
+    Category(Id),
+    Channel(Id),
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct ChannelSettings {
+    guilds: HashMap<Id, Settings>,
+    categories: HashMap<Id, Settings>,
+    channels: HashMap<Id, Settings>,
+}
+
+impl ChannelSettings {
+    /// What's set for `guild`, `category` and `channel`, in the order they cascade.
+    pub fn layers(
+        &self,
+        guild: Id,
+        category: Option<Id>,
+        channel: Option<Id>,
+    ) -> [Option<&Settings>; 3] {
+        [
+            self.guilds.get(&guild),
+            category.and_then(|c| self.categories.get(&c)),
+            channel.and_then(|c| self.channels.get(&c)),
+        ]
+    }
+
+    /// Whether any category has settings, so it's worth finding out which category a channel is in.
+    pub fn has_categories(&self) -> bool {
+        !self.categories.is_empty()
+    }
+
+    /// Everything set in `guild`, where `channels` are its channels and categories.
+    pub fn of_guild(&self, guild: Id, channels: &[Id]) -> GuildSettings {
+        fn pick(map: &HashMap<Id, Settings>, ids: &[Id]) -> HashMap<Id, Settings> {
+            ids.iter()
+                .filter_map(|id| map.get(id).map(|s| (*id, s.clone())))
+                .collect()
+        }
+        GuildSettings {
+            server: self.guilds.get(&guild).cloned(),
+            categories: pick(&self.categories, channels),
+            channels: pick(&self.channels, channels),
+        }
+    }
+
+    /// Takes in what `of_guild` gave another instance of the bot.
+    pub fn restore(&mut self, guild: Id, settings: GuildSettings) {
+        if let Some(server) = settings.server {
+            self.guilds.insert(guild, server);
+        }
+        self.categories.extend(settings.categories);
+        self.channels.extend(settings.channels);
+    }
+
+    pub fn set(
+        &mut self,
+        level: Level,
+        key: &str,
+        values: &[&str],
+        corpora: &Corpora,
+    ) -> Result<()> {
+        let settings = match level {
+            Level::Guild(id) => self.guilds.entry(id),
+            Level::Category(id) => self.categories.entry(id),
+            Level::Channel(id) => self.channels.entry(id),
+        }
+        .or_default();
+        settings.set(key, values, corpora)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GuildSettings {
+    pub server: Option<Settings>,
+    pub categories: HashMap<Id, Settings>,
+    pub channels: HashMap<Id, Settings>,
+}
+
+/// Each layer in turn overriding whatever the ones before it set.
+pub fn cascade<'a>(layers: impl IntoIterator<Item = Option<&'a Settings>>) -> Settings {
+    layers
+        .into_iter()
+        .fold(Settings::default(), |settings, layer| {
+            settings.overlay(layer)
+        })
+}
+
+/// Where settings are resolved for, and for whom. Anything left out skips its layer.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Place {
+    pub guild: Option<Id>,
+    pub channel: Option<Id>,
+    pub user: Option<Id>,
+}
+
+impl Place {
+    /// Anywhere in `guild`, for no one in particular.
+    pub fn guild(guild: Option<Id>) -> Self {
+        Place {
+            guild,
+            ..Place::default()
+        }
+    }
+
+    /// Just `user`'s own settings, like in DMs.
+    pub fn user(user: Id) -> Self {
+        Place {
+            user: Some(user),
+            ..Place::default()
+        }
+    }
+}
+
+/// Settings already resolved, by place.
+#[derive(Default, Debug)]
+pub struct Resolved {
+    cache: HashMap<Place, Settings>,
+}
+
+impl Resolved {
+    pub fn get(&self, place: &Place) -> Option<&Settings> {
+        self.cache.get(place)
+    }
+
+    pub fn insert(&mut self, place: Place, settings: Settings) {
+        self.cache.insert(place, settings);
+    }
+
+    /// Forgets everything, for when any layer changes.
+    pub fn invalidate(&mut self) {
+        self.cache.clear();
+    }
+
+    /// Forgets what was resolved in `channel`, for when it moves to another category.
+    pub fn invalidate_channel(&mut self, channel: Id) {
+        self.cache.retain(|place, _| place.channel != Some(channel));
+    }
+}
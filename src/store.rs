@@ -0,0 +1,633 @@
+use crate::backend::{Files, MarkovBackend, Storage};
+use crate::bot::types::Id;
+use crate::markov::{Format, Markov, DEFAULT_ORDER};
+use crate::persist::{guild_key, save_sealed_to, Key};
+use crate::privacy::PrivacyConfig;
+use anyhow::{bail, Result};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const GLOBAL_PATH: &str = "markov.dat";
+const MODELS_DIR: &str = "models";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Scope {
+    Global,
+    Guild(Id),
+    Channel(Id),
+    /// A pseudo-guild for a channel on another platform, e.g. `irc.chat.twitch.tv/#somebody`.
+    Bridge(String),
+    /// Text that didn't come from a chat at all, like the `news` feeds.
+    Corpus(String),
+    /// Everything learned from channels tagged with a style, e.g. `lore`.
+    Style(String),
+    /// A style that belongs to one guild, for public hosting.
+    GuildStyle(Id, String),
+    /// Scheduled events and crossposted announcements, one guild's or everyone's.
+    Events(Option<Id>),
+}
+
+impl Scope {
+    /// The scope the model of a guild (or of DMs) lives in. Without `per_guild`, all of Discord
+    /// shares the global model.
+    pub fn for_guild(guild: Option<Id>, per_guild: bool) -> Scope {
+        match guild {
+            Some(guild) if per_guild => Scope::Guild(guild),
+            _ => Scope::Global,
+        }
+    }
+
+    /// The scope of a style, kept apart per guild when `public`.
+    pub fn style(guild: Option<Id>, name: &str, public: bool) -> Scope {
+        let name = name.to_lowercase();
+        match guild {
+            Some(guild) if public => Scope::GuildStyle(guild, name),
+            _ => Scope::Style(name),
+        }
+    }
+
+    /// Where a guild's events are learned, kept apart per guild when `per_guild`.
+    pub fn events(guild: Option<Id>, per_guild: bool) -> Scope {
+        Scope::Events(guild.filter(|_| per_guild))
+    }
+
+    /// The guild whose data this is, if it's only one guild's.
+    pub fn owner(&self) -> Option<Id> {
+        match self {
+            Scope::Guild(id) | Scope::GuildStyle(id, _) | Scope::Events(Some(id)) => Some(*id),
+            _ => None,
+        }
+    }
+
+    /// What the model's file is called, without `.dat`, like `channel-1234`.
+    pub fn name(&self) -> String {
+        self.path()
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// The scope whose `name` this is. Names made file safe come back as they were written.
+    pub fn from_name(name: &str) -> Option<Scope> {
+        let (kind, rest) = name.split_once('-').unwrap_or((name, ""));
+        let id = |id: &str| id.parse::<Id>().ok();
+        Some(match kind {
+            "markov" if rest.is_empty() => Scope::Global,
+            "events" if rest.is_empty() => Scope::Events(None),
+            "channel" => Scope::Channel(id(rest)?),
+            "bridge" => Scope::Bridge(String::from(rest)),
+            "corpus" => Scope::Corpus(String::from(rest)),
+            "style" => Scope::Style(String::from(rest)),
+            "guild" => match rest.split_once('-') {
+                None => Scope::Guild(id(rest)?),
+                Some((guild, "events")) => Scope::Events(Some(id(guild)?)),
+                Some((guild, style)) => {
+                    Scope::GuildStyle(id(guild)?, String::from(style.strip_prefix("style-")?))
+                }
+            },
+            _ => return None,
+        })
+    }
+
+    pub fn path(&self) -> PathBuf {
+        let name = match self {
+            Scope::Global => return PathBuf::from(GLOBAL_PATH),
+            Scope::Guild(id) => format!("guild-{}", id),
+            Scope::Channel(id) => format!("channel-{}", id),
+            Scope::Bridge(name) => format!("bridge-{}", file_safe(name)),
+            Scope::Corpus(name) => format!("corpus-{}", file_safe(name)),
+            Scope::Style(name) => format!("style-{}", file_safe(name)),
+            Scope::GuildStyle(id, name) => format!("guild-{}-style-{}", id, file_safe(name)),
+            Scope::Events(None) => String::from("events"),
+            Scope::Events(Some(id)) => format!("guild-{}-events", id),
+        };
+        PathBuf::from(MODELS_DIR).join(name + ".dat")
+    }
+}
+
+fn file_safe(name: &str) -> String {
+    name.replace(|c: char| !c.is_ascii_alphanumeric() && c != '.', "_")
+}
+
+pub struct MarkovStore {
+    models: HashMap<Scope, Markov>,
+    /// When each model was last asked for, kept after it's unloaded
+    used: HashMap<Scope, Instant>,
+    warming: Option<Receiver<(Scope, Result<Markov>)>>,
+    /// Models that only have their hot entries loaded (see `MarkovBackend::load_hot`), with the rest
+    /// on its way. They're only generated from until it comes: anything that changes one waits for
+    /// the whole model first, so nothing learned is lost when it's swapped in.
+    filling: HashMap<Scope, Receiver<Result<Markov>>>,
+    /// Models whose files are there but couldn't be read, e.g. with the wrong key. They're never
+    /// saved, so what's in the files isn't lost.
+    unreadable: HashSet<Scope>,
+    /// Models are encrypted when this is set, each guild's with its own key derived from it.
+    /// Models saved without encryption still load, and get encrypted the next time they're saved.
+    master_key: Option<Key>,
+    /// The order new models get, by name, and for any not named
+    orders: HashMap<String, usize>,
+    default_order: usize,
+    /// Never writes models, for mirrors of another instance's
+    read_only: bool,
+    storage: Storage,
+    backend: Arc<dyn MarkovBackend>,
+    /// The shared model as other servers get it with `shared_privacy`, and when it was made
+    shared_view: Option<(Instant, Markov)>,
+}
+
+impl MarkovStore {
+    /// Fails if the global model is there but can't be read, rather than start without it.
+    pub fn load(master_key: Option<Key>) -> Result<Self> {
+        let mut models = HashMap::new();
+        models.insert(
+            Scope::Global,
+            Self::load_model(
+                &Files,
+                &Scope::Global,
+                key_for(master_key.as_ref(), &Scope::Global),
+                DEFAULT_ORDER,
+            )?,
+        );
+        Ok(MarkovStore {
+            models,
+            used: HashMap::new(),
+            warming: None,
+            filling: HashMap::new(),
+            unreadable: HashSet::new(),
+            master_key,
+            orders: HashMap::new(),
+            default_order: DEFAULT_ORDER,
+            read_only: false,
+            storage: Storage::Files,
+            backend: Arc::new(Files),
+            shared_view: None,
+        })
+    }
+
+    /// Saves every model where they're kept now, then keeps them in `storage` from here on.
+    pub fn set_storage(&mut self, storage: Storage) -> Result<()> {
+        if storage == self.storage {
+            return Ok(());
+        }
+        let backend: Arc<dyn MarkovBackend> = match storage {
+            Storage::Files => Arc::new(Files),
+            #[cfg(feature = "sled")]
+            Storage::Sled => Arc::new(crate::backend::Sled::open()?),
+            #[cfg(not(feature = "sled"))]
+            Storage::Sled => bail!("sled storage needs the bot built with `--features sled`"),
+        };
+        self.save()?;
+        self.storage = storage;
+        self.backend = backend;
+        self.reload();
+        Ok(())
+    }
+
+    pub fn master_key(&self) -> Option<Key> {
+        self.master_key
+    }
+
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    /// Forgets every model, so each is read from disk again when it's next needed. The global
+    /// model is read right away, or its hot entries are.
+    pub fn reload(&mut self) {
+        self.warming = None;
+        self.filling.clear();
+        self.models.clear();
+        self.used.clear();
+        self.unreadable.clear();
+        self.ensure_loaded(&Scope::Global, true);
+    }
+
+    /// Sets the order models get when they're new. Models that already learned something keep
+    /// theirs, and empty ones already loaded take the new one.
+    pub fn set_orders(&mut self, default: usize, orders: HashMap<String, usize>) -> Result<()> {
+        if default == 0 || orders.values().any(|&o| o == 0) {
+            bail!("chain orders have to be at least 1");
+        }
+        self.default_order = default;
+        self.orders = orders;
+        self.fill_all();
+        for (scope, markov) in self.models.iter_mut() {
+            if markov.entry_count() == 0 {
+                let order = self.orders.get(&scope.name()).copied();
+                *markov = Markov::with_order(order.unwrap_or(self.default_order));
+            }
+        }
+        Ok(())
+    }
+
+    fn order(&self, scope: &Scope) -> usize {
+        self.orders
+            .get(&scope.name())
+            .copied()
+            .unwrap_or(self.default_order)
+    }
+
+    fn key(&self, scope: &Scope) -> Option<Key> {
+        key_for(self.master_key.as_ref(), scope)
+    }
+
+    /// Loads `scope`'s model, or makes a new one with `order` if it doesn't have one.
+    fn load_model(
+        backend: &dyn MarkovBackend,
+        scope: &Scope,
+        key: Option<Key>,
+        order: usize,
+    ) -> Result<Markov> {
+        Ok(match backend.load(scope, key.as_ref())? {
+            Some(markov) if markov.entry_count() > 0 => markov,
+            _ => Markov::with_order(order),
+        })
+    }
+
+    /// `model`, or an empty one with `order` if it couldn't be read, noting `scope` as unreadable.
+    fn or_unreadable(
+        unreadable: &mut HashSet<Scope>,
+        scope: &Scope,
+        model: Result<Markov>,
+        order: usize,
+    ) -> Markov {
+        model.unwrap_or_else(|e| {
+            eprintln!("couldn't load {}, it won't be saved: {:#}", scope.name(), e);
+            unreadable.insert(scope.clone());
+            Markov::with_order(order)
+        })
+    }
+
+    fn save_model(&self, scope: &Scope, markov: &Markov) -> Result<u64> {
+        if self.read_only || self.unreadable.contains(scope) {
+            return Ok(0);
+        }
+        self.backend.save(scope, markov, self.key(scope).as_ref())
+    }
+
+    /// Loads `scopes` in order on a background thread, so they're ready before anyone asks.
+    pub fn warm(&mut self, scopes: Vec<Scope>) {
+        let (sender, receiver) = mpsc::channel();
+        let scopes: Vec<_> = scopes
+            .into_iter()
+            .map(|s| {
+                let key = self.key(&s);
+                let order = self.order(&s);
+                (s, key, order)
+            })
+            .collect();
+        let backend = Arc::clone(&self.backend);
+        thread::spawn(move || {
+            for (scope, key, order) in scopes {
+                let model = Self::load_model(&*backend, &scope, key, order);
+                if sender.send((scope, model)).is_err() {
+                    break;
+                }
+            }
+        });
+        self.warming = Some(receiver);
+    }
+
+    fn adopt_warmed(&mut self) {
+        let warming = match &self.warming {
+            Some(w) => w,
+            None => return,
+        };
+        loop {
+            match warming.try_recv() {
+                // anything loaded in the meantime may have learned something since
+                Ok((scope, model)) if !self.used.contains_key(&scope) => {
+                    let order = self.order(&scope);
+                    let model = Self::or_unreadable(&mut self.unreadable, &scope, model, order);
+                    self.used.insert(scope.clone(), Instant::now());
+                    self.models.insert(scope, model);
+                }
+                Ok(_) => {}
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.warming = None;
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Swaps in the whole of `scope`'s model if it's only partly loaded, waiting for it when `wait`.
+    fn fill(&mut self, scope: &Scope, wait: bool) {
+        let filled = match self.filling.get(scope) {
+            Some(rest) if wait => rest.recv().map_err(|_| TryRecvError::Disconnected),
+            Some(rest) => rest.try_recv(),
+            None => return,
+        };
+        let model = match filled {
+            Ok(model) => model,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => Err(anyhow::anyhow!("loading it stopped")),
+        };
+        self.filling.remove(scope);
+        match model {
+            Ok(model) => {
+                self.models.insert(scope.clone(), model);
+            }
+            // the hot entries are all there is of it now, which mustn't be saved over the rest
+            Err(e) => {
+                eprintln!(
+                    "couldn't load the rest of {}, it won't be saved: {:#}",
+                    scope.name(),
+                    e
+                );
+                self.unreadable.insert(scope.clone());
+            }
+        }
+    }
+
+    /// Waits for every partly loaded model to be whole.
+    fn fill_all(&mut self) {
+        let filling: Vec<_> = self.filling.keys().cloned().collect();
+        for scope in filling {
+            self.fill(&scope, true);
+        }
+    }
+
+    /// Makes sure `scope`'s model is loaded. With `hot`, just its hot entries will do, when it has
+    /// any and it isn't loaded yet, and the rest is loaded in the background.
+    fn ensure_loaded(&mut self, scope: &Scope, hot: bool) {
+        self.adopt_warmed();
+        self.used.insert(scope.clone(), Instant::now());
+        self.fill(scope, !hot);
+        if self.models.contains_key(scope) {
+            return;
+        }
+        let key = self.key(scope);
+        let order = self.order(scope);
+        if hot {
+            match self.backend.load_hot(scope, key.as_ref()) {
+                Ok(Some(model)) => {
+                    let (sender, receiver) = mpsc::channel();
+                    let (backend, scope_) = (Arc::clone(&self.backend), scope.clone());
+                    thread::spawn(move || {
+                        let _ = sender.send(Self::load_model(&*backend, &scope_, key, order));
+                    });
+                    self.filling.insert(scope.clone(), receiver);
+                    self.models.insert(scope.clone(), model);
+                    return;
+                }
+                Ok(None) => {}
+                Err(e) => eprintln!("couldn't load {}'s hot entries: {:#}", scope.name(), e),
+            }
+        }
+        let model = Self::load_model(&*self.backend, scope, key, order);
+        let model = Self::or_unreadable(&mut self.unreadable, scope, model, order);
+        self.models.insert(scope.clone(), model);
+    }
+
+    /// Saves and forgets the models picked by `which` that nobody asked for in `max_idle`.
+    /// They're loaded again the next time they're needed. The global model always stays.
+    pub fn unload_idle(
+        &mut self,
+        max_idle: Duration,
+        which: impl Fn(&Scope) -> bool,
+    ) -> Result<usize> {
+        self.adopt_warmed();
+        let idle: Vec<_> = self
+            .models
+            .keys()
+            .filter(|s| **s != Scope::Global && which(s))
+            .filter(|s| self.used.get(s).is_none_or(|t| t.elapsed() >= max_idle))
+            .cloned()
+            .collect();
+        for scope in &idle {
+            // a partly loaded model hasn't changed, or it would have been filled
+            if self.filling.remove(scope).is_none() {
+                self.save_model(scope, &self.models[scope])?;
+            }
+            self.models.remove(scope);
+        }
+        Ok(idle.len())
+    }
+
+    pub fn save(&mut self) -> Result<u64> {
+        if self.read_only {
+            return Ok(0);
+        }
+        let mut size = 0;
+        for (scope, markov) in self.models.iter_mut() {
+            if self.unreadable.contains(scope) || self.filling.contains_key(scope) {
+                continue;
+            }
+            let key = key_for(self.master_key.as_ref(), scope);
+            size += self.backend.save(scope, markov, key.as_ref())?;
+            markov.mark_saved();
+        }
+        Ok(size)
+    }
+
+    /// Every model kept, loaded or not, by name.
+    pub fn names(&self) -> Result<BTreeSet<String>> {
+        let mut names: BTreeSet<_> = self.backend.names()?.into_iter().collect();
+        if Path::new(GLOBAL_PATH).exists() {
+            names.insert(Scope::Global.name());
+        }
+        for entry in fs::read_dir(MODELS_DIR).into_iter().flatten() {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "dat") {
+                names.extend(path.file_stem().map(|s| s.to_string_lossy().into_owned()));
+            }
+        }
+        Ok(names)
+    }
+
+    /// Writes every model kept under `dir` in `format`, laid out and encrypted like `Files` keeps
+    /// them, one at a time so they don't all have to fit in memory. Models `format` can't hold are
+    /// skipped. Returns how many were written.
+    pub fn export_in(&self, format: Format, dir: &Path) -> Result<usize> {
+        let mut count = 0;
+        for name in self.names()? {
+            let scope = match Scope::from_name(&name) {
+                Some(scope) => scope,
+                None => {
+                    eprintln!("skipping {}, which isn't a model's name", name);
+                    continue;
+                }
+            };
+            let key = self.key(&scope);
+            let unloaded;
+            let loaded = self.models.get(&scope);
+            let markov = match loaded.filter(|_| !self.filling.contains_key(&scope)) {
+                Some(markov) => markov,
+                None => match self.backend.load(&scope, key.as_ref())? {
+                    Some(markov) => {
+                        unloaded = markov;
+                        &unloaded
+                    }
+                    None => continue,
+                },
+            };
+            // the bot from back then couldn't have had it either
+            let markov = match markov.in_format(format) {
+                Ok(markov) => markov,
+                Err(e) => {
+                    eprintln!("skipping {}: {}", name, e);
+                    continue;
+                }
+            };
+            let path = dir.join(scope.path());
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            save_sealed_to(&path, &markov, key.as_ref())
+                .map_err(|e| anyhow::anyhow!("{}: {}", name, e))?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Loads the model for `scope` from disk the first time it is asked for, all of it.
+    pub fn get_mut(&mut self, scope: &Scope) -> &mut Markov {
+        self.ensure_loaded(scope, false);
+        self.models.get_mut(scope).expect("it was just loaded")
+    }
+
+    /// Whether `scope` has a model, loaded or on disk.
+    pub fn exists(&self, scope: &Scope) -> bool {
+        self.models.contains_key(scope) || self.backend.exists(scope)
+    }
+
+    /// Every model that's loaded now.
+    pub fn loaded(&self) -> impl Iterator<Item = &Markov> {
+        self.models.values()
+    }
+
+    /// Runs `change` on every model kept, loaded or not. Ones that weren't loaded are loaded one at
+    /// a time and saved straight after, so they don't all have to fit in memory at once. Ones that
+    /// can't be read are left alone.
+    pub fn change_all(
+        &mut self,
+        mut change: impl FnMut(&Scope, &mut Markov) -> Result<()>,
+    ) -> Result<()> {
+        self.adopt_warmed();
+        // anything still warming would come back as it was before the change
+        self.warming = None;
+        self.fill_all();
+        let mut scopes: Vec<_> = self.models.keys().cloned().collect();
+        for name in self.names()? {
+            match Scope::from_name(&name) {
+                Some(scope) if !self.models.contains_key(&scope) => scopes.push(scope),
+                Some(_) => {}
+                None => eprintln!("skipping {}, which isn't a model's name", name),
+            }
+        }
+        for scope in scopes {
+            if self.unreadable.contains(&scope) {
+                continue;
+            }
+            if let Some(markov) = self.models.get_mut(&scope) {
+                change(&scope, markov)?;
+                continue;
+            }
+            let (key, order) = (self.key(&scope), self.order(&scope));
+            let model = Self::load_model(&*self.backend, &scope, key, order);
+            let mut markov = match model {
+                Ok(markov) => markov,
+                Err(e) => {
+                    eprintln!("couldn't load {}, leaving it alone: {:#}", scope.name(), e);
+                    continue;
+                }
+            };
+            change(&scope, &mut markov)?;
+            self.save_model(&scope, &markov)?;
+        }
+        Ok(())
+    }
+
+    /// Every model that's loaded now, to change.
+    pub fn loaded_mut(&mut self) -> impl Iterator<Item = (&Scope, &mut Markov)> {
+        self.adopt_warmed();
+        self.fill_all();
+        self.models.iter_mut()
+    }
+
+    /// Like `get_mut`, for several models at once, to generate from. Models that aren't loaded yet
+    /// only have their hot entries at first.
+    pub fn get_all(&mut self, scopes: &[Scope]) -> Vec<&Markov> {
+        for scope in scopes {
+            self.ensure_loaded(scope, true);
+        }
+        let models = &self.models;
+        scopes.iter().map(|s| &models[s]).collect()
+    }
+
+    /// Like `get_all`, but with the shared model as `privacy` lets other servers have it, remade
+    /// every `refresh_minutes`.
+    pub fn get_all_shared(
+        &mut self,
+        scopes: &[Scope],
+        privacy: Option<&PrivacyConfig>,
+        rng: &mut impl Rng,
+    ) -> Vec<&Markov> {
+        let privacy = match privacy {
+            Some(p) if scopes.contains(&Scope::Global) => p,
+            _ => return self.get_all(scopes),
+        };
+        let refresh = Duration::from_secs(privacy.refresh_minutes * 60);
+        if self
+            .shared_view
+            .as_ref()
+            .is_none_or(|(made, _)| made.elapsed() >= refresh)
+        {
+            let view = privacy.apply(self.get_mut(&Scope::Global), rng);
+            self.shared_view = Some((Instant::now(), view));
+        }
+        for scope in scopes {
+            self.ensure_loaded(scope, true);
+        }
+        let (models, view) = (&self.models, self.shared_view.as_ref().map(|(_, v)| v));
+        scopes
+            .iter()
+            .map(|scope| match (scope, view) {
+                (Scope::Global, Some(view)) => view,
+                _ => &models[scope],
+            })
+            .collect()
+    }
+
+    /// Puts `markov` in place of whatever `scope` had.
+    pub fn replace(&mut self, scope: Scope, mut markov: Markov) {
+        markov.mark_all_changed();
+        self.adopt_warmed();
+        self.filling.remove(&scope);
+        self.unreadable.remove(&scope);
+        self.used.insert(scope.clone(), Instant::now());
+        self.models.insert(scope, markov);
+    }
+
+    /// Forgets a model and deletes it from disk.
+    pub fn remove(&mut self, scope: &Scope) -> Result<()> {
+        self.models.remove(scope);
+        self.filling.remove(scope);
+        self.unreadable.remove(scope);
+        self.backend.remove(scope)
+    }
+}
+
+/// The key `scope`'s model is encrypted with, derived from `master`.
+fn key_for(master: Option<&Key>, scope: &Scope) -> Option<Key> {
+    let master = master?;
+    Some(match scope.owner() {
+        Some(guild) => guild_key(master, guild),
+        None => *master,
+    })
+}
+
+/// Whether `name` can be used for a corpus or style. No `-`, since `file_safe` would save `a-b`
+/// in the same file as `a_b`.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
@@ -0,0 +1,129 @@
+//! Long output, like `eg!story`, sent a bit at a time by editing one message, as if it's being
+//! typed, with a button to stop it early.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::bot::client::Client;
+use crate::bot::types::{ActionRow, Button, ButtonStyle, Id, TokenBuf};
+
+pub const STOP_ID: &str = "stream:stop";
+/// Discord allows about 5 edits every 5 seconds in a channel
+const EDIT_INTERVAL: Duration = Duration::from_millis(1200);
+/// Roughly how many characters each edit adds
+const STEP: usize = 60;
+const CURSOR: &str = " ▌";
+
+/// How a stream ends up once it's all there.
+#[derive(Default)]
+pub struct Ending {
+    pub buttons: Vec<ActionRow>,
+    /// Whether to archive the thread it's in
+    pub archive: bool,
+}
+
+/// Streams that are still going, by their message.
+#[derive(Default)]
+pub struct Streams {
+    running: HashMap<Id, (Id, Arc<AtomicBool>)>,
+}
+
+impl Streams {
+    /// Starts streaming `text` into `message`, which already shows `first(text, &ending)`. Only
+    /// `requester` (or an admin) can stop it.
+    pub fn start(
+        &mut self,
+        token: TokenBuf,
+        channel: Id,
+        message: Id,
+        requester: Id,
+        text: String,
+        ending: Ending,
+    ) {
+        self.running
+            .retain(|_, (_, stopped)| Arc::strong_count(stopped) > 1);
+        let stopped = Arc::new(AtomicBool::new(false));
+        self.running.insert(message, (requester, stopped.clone()));
+        thread::spawn(move || {
+            let client = Client::new(&token);
+            let mut next_edit = Instant::now();
+            for cut in cuts(&text).into_iter().skip(1) {
+                next_edit = next_edit.max(Instant::now() + EDIT_INTERVAL);
+                thread::sleep(next_edit - Instant::now());
+                if stopped.load(Ordering::Relaxed) {
+                    return;
+                }
+                let done = cut == text.len();
+                let (content, buttons) = if done {
+                    (text.clone(), ending.buttons.clone())
+                } else {
+                    (String::from(&text[..cut]) + CURSOR, stop_button())
+                };
+                let edit = client.edit_message(channel, message, &content, &buttons);
+                match async_io::block_on(edit) {
+                    Ok(Some(limit_end)) => next_edit = limit_end,
+                    Ok(None) => {}
+                    Err(e) => {
+                        eprintln!("stream: {}", e);
+                        return;
+                    }
+                }
+                if done && ending.archive {
+                    if let Err(e) = async_io::block_on(client.archive_thread(channel)) {
+                        eprintln!("stream: {}", e);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Stops the stream in `message` if `user` is allowed to, returning whether they were. Streams
+    /// that already ended (or didn't survive a restart) are anyone's to stop.
+    pub fn stop(&mut self, message: Id, user: Id, is_admin: bool) -> bool {
+        match self.running.get(&message) {
+            Some((requester, _)) if *requester != user && !is_admin => false,
+            _ => {
+                if let Some((_, stopped)) = self.running.remove(&message) {
+                    stopped.store(true, Ordering::Relaxed);
+                }
+                true
+            }
+        }
+    }
+}
+
+/// What to show before the first edit, with the stop button if there's more to come, or else
+/// the ending's buttons.
+pub fn first(text: &str, ending: &Ending) -> (String, Vec<ActionRow>) {
+    match cuts(text).first() {
+        Some(&cut) if cut < text.len() => (String::from(&text[..cut]) + CURSOR, stop_button()),
+        _ => (String::from(text), ending.buttons.clone()),
+    }
+}
+
+fn stop_button() -> Vec<ActionRow> {
+    vec![ActionRow::new(vec![Button::new(
+        ButtonStyle::Secondary,
+        "Stop",
+        STOP_ID,
+    )])]
+}
+
+/// Where each edit cuts `text` off: every `STEP` characters or so, at the next space if there is
+/// one. The last is always the whole thing.
+fn cuts(text: &str) -> Vec<usize> {
+    let mut cuts = Vec::new();
+    let mut from = 0;
+    while from < text.len() {
+        let ahead = text[from..]
+            .char_indices()
+            .nth(STEP)
+            .map_or(text.len(), |(i, _)| from + i);
+        from = text[ahead..].find(' ').map_or(text.len(), |i| ahead + i);
+        cuts.push(from);
+    }
+    cuts
+}
@@ -0,0 +1,41 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::bot::types::Id;
+use crate::store::is_valid_name;
+
+/// Channels whose messages are also learned into a style's own model.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct StyleTags {
+    channels: HashMap<Id, String>,
+}
+
+impl StyleTags {
+    pub fn tag(&mut self, channel: Id, style: &str) -> Result<()> {
+        let style = style.to_lowercase();
+        if !is_valid_name(&style) {
+            bail!("style names can only have letters, numbers and `_`");
+        }
+        self.channels.insert(channel, style);
+        Ok(())
+    }
+
+    pub fn untag(&mut self, channel: Id) -> Option<String> {
+        self.channels.remove(&channel)
+    }
+
+    pub fn get(&self, channel: Id) -> Option<&str> {
+        self.channels.get(&channel).map(String::as_str)
+    }
+
+    pub fn list(&self) -> Vec<(Id, &str)> {
+        let mut list: Vec<_> = self
+            .channels
+            .iter()
+            .map(|(&c, s)| (c, s.as_str()))
+            .collect();
+        list.sort_by_key(|&(_, s)| s);
+        list
+    }
+}
@@ -0,0 +1,313 @@
+//! Turning text into the words a model learns and generated words back into text. Text goes
+//! through a pipeline of stages, in the order the config gives: `normalize` makes mentions, custom
+//! emoji and links all the same and folds case, `strip` leaves out the ones that are stripped,
+//! `segment` splits text into words by the language's punctuation rules (see `lang`),
+//! `phrase_merge` learns configured phrases as one word, and `filter` leaves out words nobody
+//! wants learned. Each server can have its own.
+
+use anyhow::{bail, Result};
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::bot::types::Id;
+use crate::lang::{Punctuation, Rules};
+
+/// What happens to mentions, custom emoji or links.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Normalize {
+    /// Learned as they are (mentions of people as their names)
+    #[default]
+    Keep,
+    Strip,
+    /// All learned as the same placeholder, like `` `@someone` ``, `:name:` or `` `link` ``
+    Canonical,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    Normalize,
+    Strip,
+    Segment,
+    PhraseMerge,
+    Filter,
+}
+
+impl Stage {
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Normalize => "normalize",
+            Stage::Strip => "strip",
+            Stage::Segment => "segment",
+            Stage::PhraseMerge => "phrase_merge",
+            Stage::Filter => "filter",
+        }
+    }
+}
+
+fn default_stages() -> Vec<Stage> {
+    vec![
+        Stage::Normalize,
+        Stage::Strip,
+        Stage::Segment,
+        Stage::PhraseMerge,
+        Stage::Filter,
+    ]
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct TokenizerConfig {
+    /// Whether English punctuation is split off words, so `world!` is `world` and `!`
+    #[serde(default)]
+    pub split_punctuation: bool,
+    #[serde(default)]
+    pub lowercase: bool,
+    #[serde(default)]
+    pub mentions: Normalize,
+    #[serde(default)]
+    pub emoji: Normalize,
+    #[serde(default)]
+    pub urls: Normalize,
+    /// The stages text goes through, in order. `segment` has to be one of them
+    #[serde(default = "default_stages")]
+    pub stages: Vec<Stage>,
+    /// Runs of words learned as one word, like `new york`
+    #[serde(default)]
+    pub phrases: Vec<String>,
+    /// Regexes (case-insensitive) for words to leave out, matching the whole word
+    #[serde(default)]
+    pub drop: Vec<String>,
+    /// Words longer than this many characters are left out
+    #[serde(default)]
+    pub max_word_len: Option<usize>,
+    /// Servers' own settings, instead of these, for what's learned into their own models
+    #[serde(default)]
+    pub guilds: HashMap<Id, TokenizerConfig>,
+}
+
+impl Default for TokenizerConfig {
+    fn default() -> Self {
+        TokenizerConfig {
+            split_punctuation: false,
+            lowercase: false,
+            mentions: Normalize::default(),
+            emoji: Normalize::default(),
+            urls: Normalize::default(),
+            stages: default_stages(),
+            phrases: Vec::new(),
+            drop: Vec::new(),
+            max_word_len: None,
+            guilds: HashMap::new(),
+        }
+    }
+}
+
+pub struct Tokenizer {
+    default: Pipeline,
+    guilds: HashMap<Id, Pipeline>,
+}
+
+impl Tokenizer {
+    /// Splits English punctuation off when `split_punctuation` is on, unless `overrides` has its
+    /// own rules for English.
+    pub fn new(cfg: TokenizerConfig, overrides: &HashMap<String, Rules>) -> Result<Self> {
+        let guilds = cfg
+            .guilds
+            .iter()
+            .map(|(&guild, cfg)| match Pipeline::new(cfg, overrides) {
+                Ok(pipeline) => Ok((guild, pipeline)),
+                Err(e) => bail!("the tokenizer for {}: {}", guild, e),
+            })
+            .collect::<Result<_>>()?;
+        Ok(Tokenizer {
+            default: Pipeline::new(&cfg, overrides)?,
+            guilds,
+        })
+    }
+
+    fn pipeline(&self, guild: Option<Id>) -> &Pipeline {
+        guild
+            .and_then(|g| self.guilds.get(&g))
+            .unwrap_or(&self.default)
+    }
+
+    /// Whether mentions of people should be learned as their names, which only the message they
+    /// were in knows.
+    pub fn keeps_mentions(&self, guild: Option<Id>) -> bool {
+        self.pipeline(guild).cfg.mentions == Normalize::Keep
+    }
+
+    /// Splits `text` into the words a model of `guild`'s (or a shared one) learns.
+    pub fn tokenize(&self, guild: Option<Id>, text: &str) -> Vec<String> {
+        let pipeline = self.pipeline(guild);
+        pipeline
+            .cfg
+            .stages
+            .iter()
+            .fold(vec![String::from(text)], |pieces, &stage| {
+                pipeline.run(stage, pieces)
+            })
+    }
+
+    /// What `tokenize` makes of `text` after each stage, for seeing where a word went wrong.
+    pub fn debug(&self, guild: Option<Id>, text: &str) -> Vec<(Stage, Vec<String>)> {
+        let pipeline = self.pipeline(guild);
+        let mut pieces = vec![String::from(text)];
+        let mut stages = Vec::new();
+        for &stage in &pipeline.cfg.stages {
+            pieces = pipeline.run(stage, pieces);
+            stages.push((stage, pieces.clone()));
+        }
+        stages
+    }
+
+    /// Joins words generated from a model of `guild`'s (or a shared one) back into text,
+    /// punctuation and all.
+    pub fn detokenize(&self, guild: Option<Id>, words: impl IntoIterator<Item = String>) -> String {
+        self.pipeline(guild).punctuation.join(words)
+    }
+}
+
+/// One server's stages, or the default ones, ready to run.
+struct Pipeline {
+    cfg: TokenizerConfig,
+    punctuation: Punctuation,
+    /// Each kind of thing that can be normalized, how, and what it's replaced with if canonical
+    replacements: Vec<(Regex, Normalize, &'static str)>,
+    /// Lowercase, a word at a time
+    phrases: Vec<Vec<String>>,
+    drop: Vec<Regex>,
+}
+
+impl Pipeline {
+    fn new(cfg: &TokenizerConfig, overrides: &HashMap<String, Rules>) -> Result<Self> {
+        if cfg.stages.iter().filter(|&&s| s == Stage::Segment).count() != 1 {
+            bail!("`stages` has to have `segment` once");
+        }
+        for (i, stage) in cfg.stages.iter().enumerate() {
+            if cfg.stages[..i].contains(stage) {
+                bail!("`stages` has `{}` twice", stage.name());
+            }
+        }
+        let mut overrides = overrides.clone();
+        if cfg.split_punctuation {
+            overrides
+                .entry(String::from("en"))
+                .or_insert_with(|| Rules {
+                    openers: String::from("([{"),
+                    closers: String::from(".,!?;:)]}"),
+                    ..Rules::default()
+                });
+        }
+        let replacements = [
+            (r"<(@[!&]?|#)\d+>", cfg.mentions, "`@someone`"),
+            (r"<a?:(\w+):\d+>", cfg.emoji, ":$1:"),
+            (r"https?://\S+", cfg.urls, "`link`"),
+        ]
+        .iter()
+        .filter(|(_, normalize, _)| *normalize != Normalize::Keep)
+        .map(|&(re, normalize, with)| Ok((Regex::new(re)?, normalize, with)))
+        .collect::<Result<_>>()?;
+        let phrases = cfg
+            .phrases
+            .iter()
+            .map(|p| p.split_whitespace().map(str::to_lowercase).collect())
+            .filter(|p: &Vec<_>| p.len() > 1)
+            .collect();
+        let drop = cfg
+            .drop
+            .iter()
+            .map(|re| {
+                RegexBuilder::new(&format!("^(?:{})$", re))
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|e| anyhow::anyhow!("`{}` in `drop` isn't a regex: {}", re, e))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Pipeline {
+            punctuation: Punctuation::new(&overrides)?,
+            cfg: TokenizerConfig {
+                guilds: HashMap::new(),
+                ..cfg.clone()
+            },
+            replacements,
+            phrases,
+            drop,
+        })
+    }
+
+    /// `pieces` after `stage`. Before `segment` they're the whole text.
+    fn run(&self, stage: Stage, pieces: Vec<String>) -> Vec<String> {
+        match stage {
+            Stage::Normalize => pieces
+                .into_iter()
+                .map(|piece| self.replace(piece, Normalize::Canonical))
+                .map(|piece| match self.cfg.lowercase {
+                    true => piece.to_lowercase(),
+                    false => piece,
+                })
+                .collect(),
+            Stage::Strip => pieces
+                .into_iter()
+                .map(|piece| self.replace(piece, Normalize::Strip))
+                .filter(|piece| !piece.trim().is_empty())
+                .collect(),
+            Stage::Segment => pieces
+                .iter()
+                .flat_map(|piece| self.punctuation.tokenize(piece))
+                .collect(),
+            Stage::PhraseMerge => self.merge_phrases(pieces),
+            Stage::Filter => pieces
+                .into_iter()
+                .filter(|word| {
+                    self.cfg
+                        .max_word_len
+                        .is_none_or(|max| word.chars().count() <= max)
+                        && !self.drop.iter().any(|re| re.is_match(word))
+                })
+                .collect(),
+        }
+    }
+
+    /// `text` with whatever's set to be `normalized` that way replaced.
+    fn replace(&self, text: String, normalized: Normalize) -> String {
+        self.replacements
+            .iter()
+            .filter(|(_, normalize, _)| *normalize == normalized)
+            .fold(text, |text, (re, normalize, with)| {
+                let with = match normalize {
+                    Normalize::Canonical => *with,
+                    _ => "",
+                };
+                re.replace_all(&text, with).into_owned()
+            })
+    }
+
+    /// Joins each run of words that's a phrase into one word, the longest phrase first.
+    fn merge_phrases(&self, words: Vec<String>) -> Vec<String> {
+        if self.phrases.is_empty() {
+            return words;
+        }
+        let mut merged = Vec::with_capacity(words.len());
+        let mut i = 0;
+        while i < words.len() {
+            let len = self
+                .phrases
+                .iter()
+                .filter(|p| {
+                    words.len() - i >= p.len()
+                        && p.iter()
+                            .zip(&words[i..])
+                            .all(|(p, w)| w.to_lowercase() == *p)
+                })
+                .map(Vec::len)
+                .max()
+                .unwrap_or(1);
+            merged.push(words[i..i + len].join(" "));
+            i += len;
+        }
+        merged
+    }
+}
@@ -0,0 +1,41 @@
+//! Correlation ids, so everything logged about one event, from learning through generation and
+//! moderation to sending, can be picked out of the logs together. The id is also the event's trace
+//! id when spans are exported, see `otel`, and what's logged is its first 8 hex digits.
+
+use rand::Rng;
+use std::fmt::{self, Display};
+
+use crate::bot::types::Id;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Trace {
+    id: u128,
+    guild: Option<Id>,
+}
+
+impl Trace {
+    pub fn new(rng: &mut impl Rng, guild: Option<Id>) -> Self {
+        Trace {
+            id: rng.gen(),
+            guild,
+        }
+    }
+
+    pub fn guild(&self) -> Option<Id> {
+        self.guild
+    }
+
+    pub fn id(&self) -> u128 {
+        self.id
+    }
+}
+
+impl Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:08x}", (self.id >> 96) as u32)?;
+        if let Some(guild) = self.guild {
+            write!(f, "@{}", guild)?;
+        }
+        Ok(())
+    }
+}
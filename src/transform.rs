@@ -0,0 +1,199 @@
+use crate::bot::types::Id;
+use crate::emoji::{self, EmojiAssociations};
+use anyhow::{bail, Result};
+use rand::seq::SliceRandom;
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Novelty rewrites of generated text. Several can be stacked, and run in the order given.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transform {
+    /// Swaps every word for an emoji the server reacts to it with, dropping words with none.
+    Emojify,
+    /// Puts a 👏 between every word.
+    Clap,
+    /// aLtErNaTiNg CaSe, like the spongebob meme.
+    Mock,
+    /// Owoifies it, with a face on the end.
+    Owo,
+    /// Talks like a pirate.
+    Pirate,
+}
+
+pub const ALL: [Transform; 5] = [
+    Transform::Emojify,
+    Transform::Clap,
+    Transform::Mock,
+    Transform::Owo,
+    Transform::Pirate,
+];
+
+const FACES: [&str; 5] = ["uwu", "owo", ">w<", "^w^", "(・`ω´・)"];
+
+const PIRATE: [(&str, &str); 16] = [
+    ("hello", "ahoy"),
+    ("hi", "ahoy"),
+    ("hey", "ahoy"),
+    ("my", "me"),
+    ("you", "ye"),
+    ("your", "yer"),
+    ("you're", "ye be"),
+    ("is", "be"),
+    ("are", "be"),
+    ("am", "be"),
+    ("the", "th'"),
+    ("yes", "aye"),
+    ("no", "nay"),
+    ("friend", "matey"),
+    ("friends", "hearties"),
+    ("money", "doubloons"),
+];
+
+/// What a transform can draw on besides the text itself.
+pub struct Context<'a, R> {
+    pub emoji: &'a EmojiAssociations,
+    pub guild: Option<Id>,
+    pub rng: &'a mut R,
+}
+
+impl Transform {
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.trim_start_matches("--").to_lowercase();
+        ALL.iter().copied().find(|t| t.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Transform::Emojify => "emojify",
+            Transform::Clap => "clap",
+            Transform::Mock => "mock",
+            Transform::Owo => "owo",
+            Transform::Pirate => "pirate",
+        }
+    }
+
+    pub fn apply<R: Rng>(self, text: String, ctx: &mut Context<'_, R>) -> String {
+        match self {
+            Transform::Emojify => {
+                let guild = match ctx.guild {
+                    Some(g) => g,
+                    None => return text,
+                };
+                text.split_whitespace()
+                    .filter_map(|w| ctx.emoji.pick(guild, w, ctx.rng))
+                    .map(emoji::display)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            }
+            Transform::Clap => text.split_whitespace().collect::<Vec<_>>().join(" 👏 "),
+            Transform::Mock => mock(&text),
+            Transform::Owo => owoify(&text, ctx.rng),
+            Transform::Pirate => text
+                .split_whitespace()
+                .map(pirate_word)
+                .collect::<Vec<_>>()
+                .join(" "),
+        }
+    }
+}
+
+pub fn apply_all<R: Rng>(
+    transforms: &[Transform],
+    text: String,
+    ctx: &mut Context<'_, R>,
+) -> String {
+    transforms.iter().fold(text, |text, t| t.apply(text, ctx))
+}
+
+/// Transforms each guild has on everything the bot says there.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct GuildDefaults {
+    guilds: HashMap<Id, Vec<Transform>>,
+}
+
+impl GuildDefaults {
+    pub fn get(&self, guild: Option<Id>) -> &[Transform] {
+        guild
+            .and_then(|g| self.guilds.get(&g))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    pub fn set(&mut self, guild: Id, names: &[&str]) -> Result<()> {
+        let mut transforms = Vec::with_capacity(names.len());
+        for name in names {
+            match Transform::parse(name) {
+                Some(t) => transforms.push(t),
+                None => bail!("there's no transform called `{}`", name),
+            }
+        }
+        self.guilds.insert(guild, transforms);
+        Ok(())
+    }
+
+    pub fn reset(&mut self, guild: Id) {
+        self.guilds.remove(&guild);
+    }
+}
+
+fn mock(text: &str) -> String {
+    let mut upper = false;
+    text.chars()
+        .map(|c| {
+            if !c.is_alphabetic() {
+                return c.to_string();
+            }
+            upper = !upper;
+            if upper {
+                c.to_uppercase().to_string()
+            } else {
+                c.to_lowercase().to_string()
+            }
+        })
+        .collect()
+}
+
+fn owoify(text: &str, rng: &mut dyn RngCore) -> String {
+    let mut out = String::with_capacity(text.len() + 8);
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            'r' | 'l' => out.push('w'),
+            'R' | 'L' => out.push('W'),
+            'n' | 'N' if chars.peek().is_some_and(|n| "aeiou".contains(*n)) => {
+                out.push(c);
+                out.push('y');
+            }
+            c => out.push(c),
+        }
+    }
+    out.push(' ');
+    out.push_str(FACES.choose(rng).unwrap());
+    out
+}
+
+fn pirate_word(word: &str) -> String {
+    let core = word.trim_end_matches(|c: char| !(c.is_alphanumeric() || c == '\''));
+    let rest = &word[core.len()..];
+    let lower = core.to_lowercase();
+    let swapped = match PIRATE.iter().find(|(from, _)| *from == lower) {
+        Some((_, to)) => String::from(*to),
+        None => match lower.strip_suffix("ing") {
+            Some(stem) if stem.len() > 1 => format!("{}in'", stem),
+            _ => return String::from(word),
+        },
+    };
+    if core.starts_with(char::is_uppercase) {
+        capitalize(&swapped) + rest
+    } else {
+        swapped + rest
+    }
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
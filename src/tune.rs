@@ -0,0 +1,130 @@
+//! `eg!tune`, a panel of select menus for a server's generation defaults. Picking something sets it
+//! straight away, the same as `eg!settings server`, and the panel shows what the bot would say with
+//! it.
+
+use crate::bot::types::{ActionRow, Button, ButtonStyle, SelectMenu};
+use crate::settings::Settings;
+
+pub const PREFIX: &str = "tune:";
+pub const PREVIEW_ID: &str = "tune:preview";
+pub const DONE_ID: &str = "tune:done";
+
+/// A setting the panel has a menu for, with what it offers: labels and the values they set.
+struct Knob {
+    key: &'static str,
+    name: &'static str,
+    choices: &'static [(&'static str, &'static str)],
+}
+
+const KNOBS: &[Knob] = &[
+    Knob {
+        key: "temperature",
+        name: "Temperature",
+        choices: &[
+            ("0.5, plays it safe", "0.5"),
+            ("0.8", "0.8"),
+            ("1, as learned", "1"),
+            ("1.2", "1.2"),
+            ("1.5", "1.5"),
+            ("2, unhinged", "2"),
+        ],
+    },
+    Knob {
+        key: "max_words",
+        name: "Max length",
+        choices: &[
+            ("10 words", "10"),
+            ("20 words", "20"),
+            ("30 words", "30"),
+            ("50 words", "50"),
+            ("100 words", "100"),
+        ],
+    },
+    Knob {
+        key: "min_words",
+        name: "Min length",
+        choices: &[
+            ("1 word", "1"),
+            ("3 words", "3"),
+            ("5 words", "5"),
+            ("8 words", "8"),
+        ],
+    },
+    Knob {
+        key: "chatter",
+        name: "Reply chance",
+        choices: &[
+            ("Never", "0"),
+            ("1%", "0.01"),
+            ("2%", "0.02"),
+            ("5%", "0.05"),
+            ("10%", "0.1"),
+            ("25%", "0.25"),
+        ],
+    },
+];
+
+/// What unsets a knob, so it goes back to the default
+const DEFAULT: &str = "none";
+
+/// What `settings` has for `knob`, written like its choices' values.
+fn current(settings: &Settings, knob: &Knob) -> Option<String> {
+    match knob.key {
+        "temperature" => settings.temperature.map(|t| t.to_string()),
+        "max_words" => settings.max_words.map(|n| n.to_string()),
+        "min_words" => settings.min_words.map(|n| n.to_string()),
+        "chatter" => settings.chatter.map(|c| c.to_string()),
+        _ => None,
+    }
+}
+
+/// How `knob` is set in `settings`, as its choice's label if it's one of them.
+fn shown(settings: &Settings, knob: &Knob) -> String {
+    match current(settings, knob) {
+        Some(value) => match knob.choices.iter().find(|&&(_, v)| v == value) {
+            Some((label, _)) => String::from(*label),
+            None => value,
+        },
+        None => String::from("default"),
+    }
+}
+
+/// Every knob and how it's set, for the top of the panel and once it's done with.
+pub fn summary(settings: &Settings) -> String {
+    let knobs: Vec<_> = KNOBS
+        .iter()
+        .map(|knob| format!("{}: **{}**", knob.name, shown(settings, knob)))
+        .collect();
+    format!("🎛️ **This server's defaults**\n{}", knobs.join(" · "))
+}
+
+/// Whether `key` is one of the panel's.
+pub fn is_knob(key: &str) -> bool {
+    KNOBS.iter().any(|k| k.key == key)
+}
+
+/// The panel for a server that set `server`, with `preview` generated from it.
+pub fn panel(server: &Settings, preview: Option<&str>) -> (String, Vec<ActionRow>) {
+    let mut rows = Vec::new();
+    for knob in KNOBS {
+        let current = current(server, knob);
+        let mut menu = SelectMenu::new(format!("{}{}", PREFIX, knob.key), knob.name);
+        for &(label, value) in knob.choices {
+            let picked = current.as_deref() == Some(value);
+            menu = menu.option(format!("{}: {}", knob.name, label), value, picked);
+        }
+        let menu = menu.option(
+            format!("{}: default", knob.name),
+            DEFAULT,
+            current.is_none(),
+        );
+        rows.push(ActionRow::select(menu));
+    }
+    rows.push(ActionRow::new(vec![
+        Button::new(ButtonStyle::Primary, "Another preview", PREVIEW_ID),
+        Button::new(ButtonStyle::Secondary, "Done", DONE_ID),
+    ]));
+    let preview = preview.unwrap_or("*(nothing came out)*");
+    let text = format!("{}\n\nWith these, I'd say:\n> {}", summary(server), preview);
+    (text, rows)
+}
@@ -0,0 +1,147 @@
+//! Admin-uploaded filters, sandboxed in WebAssembly.
+//!
+//! A filter is a module without imports that exports `memory`, `alloc(len: i32) -> i32`, and one
+//! or both of `ingest(ptr: i32, len: i32) -> i64` and `output(ptr: i32, len: i32) -> i64`. Text is
+//! passed in and out as UTF-8; the result packs the pointer in the high 32 bits and the length in
+//! the low ones, and a negative one drops the text (only meaningful for `ingest`).
+
+use anyhow::{anyhow, bail, Result};
+use std::fs;
+use std::path::PathBuf;
+use wasmtime::{Config, Engine, Instance, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+const DIR: &str = "wasm";
+const FUEL: u64 = 10_000_000;
+const MAX_MEMORY: usize = 16 * 1024 * 1024;
+pub const MAX_MODULE_SIZE: u64 = 1024 * 1024;
+
+pub struct WasmFilters {
+    engine: Engine,
+    filters: Vec<(String, Module)>,
+}
+
+impl WasmFilters {
+    pub fn load() -> Self {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config).expect("couldn't start the wasm engine");
+        let mut filters = Vec::new();
+        for entry in fs::read_dir(DIR).into_iter().flatten().flatten() {
+            let path = entry.path();
+            let name = match path.file_stem().and_then(|s| s.to_str()) {
+                Some(n) if path.extension().is_some_and(|e| e == "wasm") => String::from(n),
+                _ => continue,
+            };
+            match Module::from_file(&engine, &path) {
+                Ok(module) => filters.push((name, module)),
+                Err(e) => eprintln!("wasm filter {}: {}", name, e),
+            }
+        }
+        filters.sort_by(|a, b| a.0.cmp(&b.0));
+        WasmFilters { engine, filters }
+    }
+
+    pub fn install(&mut self, name: &str, bytes: &[u8]) -> Result<()> {
+        if !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            bail!("filter names can only have letters, numbers, `-` and `_`");
+        }
+        let module = Module::new(&self.engine, bytes)?;
+        if module.imports().next().is_some() {
+            bail!("filters can't import anything");
+        }
+        if module.get_export("memory").is_none() || module.get_export("alloc").is_none() {
+            bail!("filters have to export `memory` and `alloc`");
+        }
+        fs::create_dir_all(DIR)?;
+        fs::write(path(name), bytes)?;
+        self.filters.retain(|(n, _)| n != name);
+        self.filters.push((String::from(name), module));
+        self.filters.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<bool> {
+        let len = self.filters.len();
+        self.filters.retain(|(n, _)| n != name);
+        if self.filters.len() == len {
+            return Ok(false);
+        }
+        fs::remove_file(path(name))?;
+        Ok(true)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.filters.iter().map(|(n, _)| n.as_str())
+    }
+
+    pub fn ingest(&self, text: String) -> Option<String> {
+        self.filters.iter().try_fold(text, |text, (name, module)| {
+            match self.run(module, "ingest", &text) {
+                Ok(Some(Some(t))) => Some(t),
+                Ok(Some(None)) => None,
+                Ok(None) => Some(text),
+                Err(e) => {
+                    eprintln!("wasm filter {}: {}", name, e);
+                    Some(text)
+                }
+            }
+        })
+    }
+
+    pub fn output(&self, text: String) -> String {
+        self.filters.iter().fold(text, |text, (name, module)| {
+            match self.run(module, "output", &text) {
+                Ok(Some(Some(t))) => t,
+                Ok(_) => text,
+                Err(e) => {
+                    eprintln!("wasm filter {}: {}", name, e);
+                    text
+                }
+            }
+        })
+    }
+
+    /// `None` if the module doesn't export `export`, `Some(None)` if it dropped the text.
+    fn run(&self, module: &Module, export: &str, text: &str) -> Result<Option<Option<String>>> {
+        if module.get_export(export).is_none() {
+            return Ok(None);
+        }
+        // a fresh store every call, so filters can't keep state or leak memory between runs
+        let mut store = Store::new(
+            &self.engine,
+            StoreLimitsBuilder::new()
+                .memory_size(MAX_MEMORY)
+                .instances(1)
+                .build(),
+        );
+        store.limiter(|limits: &mut StoreLimits| limits);
+        store.set_fuel(FUEL)?;
+        let instance = Instance::new(&mut store, module, &[])?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| anyhow!("no exported memory"))?;
+        let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc")?;
+        let filter = instance.get_typed_func::<(i32, i32), i64>(&mut store, export)?;
+
+        let len = text.len() as i32;
+        let ptr = alloc.call(&mut store, len)?;
+        memory.write(&mut store, ptr as usize, text.as_bytes())?;
+        let result = filter.call(&mut store, (ptr, len))?;
+        if result < 0 {
+            return Ok(Some(None));
+        }
+        let (ptr, len) = ((result >> 32) as usize, (result & 0xffff_ffff) as usize);
+        let bytes = memory
+            .data(&store)
+            .get(ptr..ptr + len)
+            .ok_or_else(|| anyhow!("result out of bounds"))?;
+        Ok(Some(Some(String::from_utf8(bytes.to_vec())?)))
+    }
+}
+
+fn path(name: &str) -> PathBuf {
+    [DIR, &format!("{}.wasm", name)].iter().collect()
+}
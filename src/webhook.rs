@@ -0,0 +1,132 @@
+//! Telling other services when long jobs end: training on or forgetting a channel's history,
+//! `eg!import` and `eg!dataexport`. Each webhook in the config gets the event as a JSON POST,
+//! signed with its secret so it can tell the bot really sent it.
+//!
+//! The signature, in `X-Taco-Signature`, is the hex BLAKE3 keyed hash of the body, keyed with the
+//! BLAKE3 hash of the secret.
+
+use anyhow::{anyhow, bail, Result};
+use isahc::config::Configurable;
+use isahc::HttpClient;
+use serde::{Deserialize, Serialize};
+use std::thread;
+use std::time::Duration;
+
+use crate::bot::types::Id;
+use crate::schedule::now_millis;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// Tries at sending each event before giving up on it
+const ATTEMPTS: u32 = 3;
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// What requests are signed with. Unsigned if not set
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// The jobs it hears about, like `["train", "import"]`. Every one if empty
+    #[serde(default)]
+    pub jobs: Vec<String>,
+}
+
+/// A job that's over, as sent.
+#[derive(Serialize, Debug)]
+pub struct Event {
+    /// `train`, `forget`, `import` or `dataexport`
+    pub job: &'static str,
+    /// `done` or `failed`
+    pub status: &'static str,
+    pub guild: Option<Id>,
+    pub channel: Option<Id>,
+    /// What was said about it in Discord, or what went wrong
+    pub detail: String,
+    /// In milliseconds since the epoch
+    pub timestamp: i64,
+}
+
+impl Event {
+    pub fn done(job: &'static str, guild: Option<Id>, channel: Option<Id>, detail: &str) -> Self {
+        Event::new(job, "done", guild, channel, String::from(detail))
+    }
+
+    pub fn failed(
+        job: &'static str,
+        guild: Option<Id>,
+        channel: Option<Id>,
+        error: &anyhow::Error,
+    ) -> Self {
+        Event::new(job, "failed", guild, channel, format!("{:#}", error))
+    }
+
+    fn new(
+        job: &'static str,
+        status: &'static str,
+        guild: Option<Id>,
+        channel: Option<Id>,
+        detail: String,
+    ) -> Self {
+        Event {
+            job,
+            status,
+            guild,
+            channel,
+            detail,
+            timestamp: now_millis(),
+        }
+    }
+}
+
+/// Sends `event` to every webhook that wants it, in the background.
+pub fn notify(hooks: &[WebhookConfig], event: Event) {
+    let hooks: Vec<_> = hooks
+        .iter()
+        .filter(|h| h.jobs.is_empty() || h.jobs.iter().any(|j| j == event.job))
+        .cloned()
+        .collect();
+    if hooks.is_empty() {
+        return;
+    }
+    let body = match serde_json::to_vec(&event) {
+        Ok(body) => body,
+        Err(e) => return eprintln!("webhook: {}", e),
+    };
+    thread::spawn(move || {
+        for hook in hooks {
+            if let Err(e) = send(&hook, &body) {
+                eprintln!("webhook: {}: {}", hook.url, e);
+            }
+        }
+    });
+}
+
+/// Posts `body` to `hook`, trying again a little later if it doesn't take it.
+fn send(hook: &WebhookConfig, body: &[u8]) -> Result<()> {
+    let http = HttpClient::builder().timeout(TIMEOUT).build()?;
+    let mut attempt = 1;
+    loop {
+        let mut request = http::Request::post(hook.url.as_str())
+            .header("Content-Type", "application/json")
+            .header("User-Agent", "taco_bot");
+        if let Some(secret) = &hook.secret {
+            request = request.header("X-Taco-Signature", sign(secret, body));
+        }
+        let error = match http.send(request.body(body.to_vec())?) {
+            Ok(response) if response.status().is_success() => return Ok(()),
+            Ok(response) => anyhow!("{}", response.status()),
+            Err(e) => e.into(),
+        };
+        if attempt >= ATTEMPTS {
+            bail!("gave up after {} tries: {}", ATTEMPTS, error);
+        }
+        thread::sleep(Duration::from_secs(2u64.pow(attempt)));
+        attempt += 1;
+    }
+}
+
+fn sign(secret: &str, body: &[u8]) -> String {
+    let key = blake3::hash(secret.as_bytes());
+    blake3::keyed_hash(key.as_bytes(), body)
+        .to_hex()
+        .to_string()
+}